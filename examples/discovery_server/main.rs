@@ -0,0 +1,130 @@
+//! Standalone discovery server for `RustDDS`
+//!
+//! Runs a DomainParticipant in discovery server mode and periodically prints
+//! the participants it knows. Clients are configured with
+//! `DiscoveryMode::Client`, listing this server as one of their servers.
+
+#![deny(clippy::all)]
+
+use std::{io, net::IpAddr, thread, time::Duration};
+
+use log::LevelFilter;
+use log4rs::{
+  append::console::ConsoleAppender,
+  config::{Appender, Root},
+  Config,
+};
+use rustdds::{DiscoveryMode, DomainParticipantBuilder, RTPSEntity};
+use clap::{Arg, ArgMatches, Command}; // command line argument processing
+
+fn main() {
+  configure_logging();
+  let matches = get_matches();
+
+  let domain_id = *matches.get_one::<u16>("domain_id").unwrap();
+  let participant_id = *matches.get_one::<u16>("participant_id").unwrap();
+  let interval = Duration::from_secs(*matches.get_one::<u64>("interval").unwrap());
+
+  let mut builder = DomainParticipantBuilder::new(domain_id)
+    .participant_id(participant_id)
+    .discovery_mode(DiscoveryMode::Server)
+    .entity_name("RustDDS discovery server");
+  if let Some(bind_address) = matches.get_one::<IpAddr>("bind_address") {
+    builder = builder.bind_address(*bind_address);
+  }
+  if matches.get_flag("no_multicast") {
+    builder = builder.multicast(false);
+  }
+
+  let domain_participant = builder
+    .build()
+    .unwrap_or_else(|e| panic!("DomainParticipant construction failed: {e:?}"));
+
+  println!(
+    "Discovery server running: domain_id={} participant_id={} GUID={:?}",
+    domain_participant.domain_id(),
+    domain_participant.participant_id(),
+    domain_participant.guid(),
+  );
+
+  loop {
+    thread::sleep(interval);
+    let participants = domain_participant.discovered_participants();
+    println!("{} participants known:", participants.len());
+    for p in participants {
+      println!(
+        "  {:?} name={:?} locators={:?}",
+        p.participant_guid.prefix,
+        p.entity_name.as_deref().unwrap_or(""),
+        p.metatraffic_unicast_locators,
+      );
+    }
+  }
+}
+
+fn configure_logging() {
+  // initialize logging, preferably from config file
+  log4rs::init_file(
+    "logging-config.yaml",
+    log4rs::config::Deserializers::default(),
+  )
+  .unwrap_or_else(|e| {
+    match e.downcast_ref::<io::Error>() {
+      // Config file did not work. If it is a simple "No such file or directory", then
+      // substitute some default config.
+      Some(os_err) if os_err.kind() == io::ErrorKind::NotFound => {
+        let stdout = ConsoleAppender::builder().build();
+        let conf = Config::builder()
+          .appender(Appender::builder().build("stdout", Box::new(stdout)))
+          .build(Root::builder().appender("stdout").build(LevelFilter::Error))
+          .unwrap();
+        log4rs::init_config(conf).unwrap();
+      }
+      // Give up.
+      other_error => panic!("Config problem: {other_error:?}"),
+    }
+  });
+}
+
+fn get_matches() -> ArgMatches {
+  Command::new("RustDDS discovery server")
+    .about("Relays participant and endpoint discovery data for discovery clients.")
+    .arg(
+      Arg::new("domain_id")
+        .short('d')
+        .value_name("id")
+        .value_parser(clap::value_parser!(u16))
+        .default_value("0")
+        .help("Sets the DDS domain id number"),
+    )
+    .arg(
+      Arg::new("participant_id")
+        .short('p')
+        .value_name("id")
+        .value_parser(clap::value_parser!(u16))
+        .default_value("0")
+        .help("Sets the participant id, which determines the listening ports"),
+    )
+    .arg(
+      Arg::new("bind_address")
+        .short('b')
+        .value_name("address")
+        .value_parser(clap::value_parser!(IpAddr))
+        .help("Local address to listen on. Default is all interfaces."),
+    )
+    .arg(
+      Arg::new("no_multicast")
+        .short('M')
+        .help("Disable multicast, i.e. serve only discovery clients")
+        .action(clap::ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("interval")
+        .short('i')
+        .value_name("seconds")
+        .value_parser(clap::value_parser!(u64))
+        .default_value("5")
+        .help("How often to print known participants"),
+    )
+    .get_matches()
+}
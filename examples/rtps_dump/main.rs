@@ -0,0 +1,285 @@
+//! RTPS traffic decoder
+//!
+//! Decodes RTPS messages from a pcap/pcapng capture file, or listens on the
+//! ports of a DDS domain, and prints them in human-readable form or as JSON,
+//! one message per line. Useful for debugging interoperability without
+//! Wireshark.
+//!
+//! Live listening receives the multicast traffic of the domain. Unicast
+//! traffic is received only for the participant ids given with `-p`, and only
+//! if no local participant is already using those ports.
+
+#![deny(clippy::all)]
+
+use std::{
+  fs, io,
+  net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+  process,
+  sync::mpsc,
+  thread,
+  time::{Duration, SystemTime},
+};
+
+use log::LevelFilter;
+use log4rs::{
+  append::console::ConsoleAppender,
+  config::{Appender, Root},
+  Config,
+};
+use rustdds::{debug, PortMapping};
+use socket2::{Domain, Protocol, Socket, Type};
+use clap::{Arg, ArgAction, ArgMatches, Command}; // command line argument processing
+
+const MULTICAST_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 0, 1);
+
+struct Packet {
+  // Since the Unix epoch
+  timestamp: Duration,
+  source: SocketAddr,
+  destination: SocketAddr,
+  payload: Vec<u8>,
+}
+
+fn main() {
+  configure_logging();
+  let matches = get_matches();
+  let json = matches.get_flag("json");
+
+  match matches.get_one::<String>("read") {
+    Some(file_name) => {
+      let capture = fs::read(file_name).unwrap_or_else(|e| {
+        eprintln!("Cannot read {file_name}: {e}");
+        process::exit(1)
+      });
+      let datagrams = debug::read_capture(&capture).unwrap_or_else(|e| {
+        eprintln!("{file_name}: {e}");
+        process::exit(1)
+      });
+      for datagram in datagrams {
+        print_packet(
+          &Packet {
+            timestamp: datagram.timestamp,
+            source: datagram.source,
+            destination: datagram.destination,
+            payload: datagram.payload.to_vec(),
+          },
+          json,
+        );
+      }
+    }
+    None => {
+      let receiver = listen(&matches);
+      for packet in receiver {
+        print_packet(&packet, json);
+      }
+    }
+  }
+}
+
+fn print_packet(packet: &Packet, json: bool) {
+  // Other UDP traffic may be in the capture.
+  if !packet.payload.starts_with(b"RTPS") {
+    return;
+  }
+  let time = chrono::DateTime::from_timestamp(
+    packet.timestamp.as_secs() as i64,
+    packet.timestamp.subsec_nanos(),
+  )
+  .unwrap_or_default()
+  .to_rfc3339();
+  let decoded = debug::decode_message(&packet.payload);
+
+  if json {
+    let mut line = serde_json::json!({
+      "time": time,
+      "source": packet.source.to_string(),
+      "destination": packet.destination.to_string(),
+      "length": packet.payload.len(),
+    });
+    match decoded {
+      Ok(message) => line["message"] = serde_json::to_value(message).unwrap(),
+      Err(e) => line["error"] = e.to_string().into(),
+    }
+    println!("{line}");
+  } else {
+    println!(
+      "{time} {} -> {} {} bytes",
+      packet.source,
+      packet.destination,
+      packet.payload.len()
+    );
+    match decoded {
+      Ok(message) => println!("{message}"),
+      Err(e) => println!("  {e}\n"),
+    }
+  }
+}
+
+// Receives packets on the domain ports, until interrupted
+fn listen(matches: &ArgMatches) -> mpsc::Receiver<Packet> {
+  let domain_id = *matches.get_one::<u16>("domain_id").unwrap();
+  let port_mapping = PortMapping::default();
+
+  let mut ports = vec![
+    (port_mapping.spdp_well_known_multicast_port(domain_id), true),
+    (port_mapping.user_traffic_multicast_port(domain_id), true),
+  ];
+  for participant_id in matches
+    .get_many::<u16>("participant_id")
+    .into_iter()
+    .flatten()
+  {
+    ports.push((
+      port_mapping.spdp_well_known_unicast_port(domain_id, *participant_id),
+      false,
+    ));
+    ports.push((
+      port_mapping.user_traffic_unicast_port(domain_id, *participant_id),
+      false,
+    ));
+  }
+
+  let (sender, receiver) = mpsc::channel();
+  for (port, multicast) in ports {
+    let port = match port {
+      Some(port) => port,
+      None => {
+        eprintln!("Port number of domain {domain_id} overflows");
+        continue;
+      }
+    };
+    let socket = match bind(port, multicast) {
+      Ok(socket) => socket,
+      Err(e) => {
+        eprintln!("Cannot listen on port {port}: {e}");
+        continue;
+      }
+    };
+    eprintln!(
+      "Listening on {} port {port}",
+      if multicast { "multicast" } else { "unicast" }
+    );
+    let destination = SocketAddr::from((
+      if multicast {
+        MULTICAST_GROUP
+      } else {
+        Ipv4Addr::UNSPECIFIED
+      },
+      port,
+    ));
+    let sender = sender.clone();
+    thread::spawn(move || {
+      let mut buffer = vec![0; 64 * 1024];
+      loop {
+        let (length, source) = match socket.recv_from(&mut buffer) {
+          Ok(received) => received,
+          Err(e) => {
+            eprintln!("Receive on port {port} failed: {e}");
+            return;
+          }
+        };
+        let packet = Packet {
+          timestamp: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default(),
+          source,
+          destination,
+          payload: buffer[..length].to_vec(),
+        };
+        if sender.send(packet).is_err() {
+          return;
+        }
+      }
+    });
+  }
+  receiver
+}
+
+// Multicast ports are shared with participants on this host, like RustDDS
+// participants share them with each other.
+fn bind(port: u16, multicast: bool) -> io::Result<UdpSocket> {
+  let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+  if multicast {
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+  }
+  socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port).into())?;
+  if multicast {
+    let mut interfaces: Vec<Ipv4Addr> = if_addrs::get_if_addrs()?
+      .into_iter()
+      .filter_map(|interface| match interface.ip() {
+        IpAddr::V4(address) => Some(address),
+        IpAddr::V6(_) => None,
+      })
+      .collect();
+    if interfaces.is_empty() {
+      interfaces.push(Ipv4Addr::UNSPECIFIED);
+    }
+    for interface in interfaces {
+      if let Err(e) = socket.join_multicast_v4(&MULTICAST_GROUP, &interface) {
+        eprintln!("Cannot join multicast group on {interface}: {e}");
+      }
+    }
+  }
+  Ok(socket.into())
+}
+
+fn configure_logging() {
+  // initialize logging, preferably from config file
+  log4rs::init_file(
+    "logging-config.yaml",
+    log4rs::config::Deserializers::default(),
+  )
+  .unwrap_or_else(|e| {
+    match e.downcast_ref::<io::Error>() {
+      // Config file did not work. If it is a simple "No such file or directory", then
+      // substitute some default config.
+      Some(os_err) if os_err.kind() == io::ErrorKind::NotFound => {
+        let stdout = ConsoleAppender::builder().build();
+        let conf = Config::builder()
+          .appender(Appender::builder().build("stdout", Box::new(stdout)))
+          .build(Root::builder().appender("stdout").build(LevelFilter::Error))
+          .unwrap();
+        log4rs::init_config(conf).unwrap();
+      }
+      // Give up.
+      other_error => panic!("Config problem: {other_error:?}"),
+    }
+  });
+}
+
+fn get_matches() -> ArgMatches {
+  Command::new("RustDDS RTPS decoder")
+    .about("Decodes RTPS messages from a capture file or the network.")
+    .arg(
+      Arg::new("read")
+        .short('r')
+        .value_name("FILE")
+        .help("Read pcap or pcapng file instead of listening to the network"),
+    )
+    .arg(
+      Arg::new("domain_id")
+        .short('d')
+        .value_name("id")
+        .value_parser(clap::value_parser!(u16))
+        .default_value("0")
+        .help("DDS domain to listen to"),
+    )
+    .arg(
+      Arg::new("participant_id")
+        .short('p')
+        .value_name("id")
+        .value_parser(clap::value_parser!(u16))
+        .action(ArgAction::Append)
+        .help("Listen also to unicast ports of this participant id. Can be repeated."),
+    )
+    .arg(
+      Arg::new("json")
+        .short('j')
+        .long("json")
+        .action(ArgAction::SetTrue)
+        .help("Print one JSON object per message"),
+    )
+    .get_matches()
+}
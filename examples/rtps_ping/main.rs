@@ -0,0 +1,166 @@
+//! RTPS ping tool for `RustDDS`
+//!
+//! Checks DDS-level reachability of participants with RTPS PING, and prints
+//! the round-trip times. Pings either the given address, or all participants
+//! discovered in a domain.
+
+#![deny(clippy::all)]
+
+use std::{io, net::SocketAddr, thread, time::Duration};
+
+use log::LevelFilter;
+use log4rs::{
+  append::console::ConsoleAppender,
+  config::{Appender, Root},
+  Config,
+};
+use rustdds::{ping, DomainParticipant, Locator, RTPSEntity};
+use clap::{Arg, ArgMatches, Command}; // command line argument processing
+
+fn main() {
+  configure_logging();
+  let matches = get_matches();
+
+  let count = *matches.get_one::<u32>("count").unwrap();
+  let timeout = Duration::from_millis(*matches.get_one::<u64>("timeout").unwrap());
+
+  let targets: Vec<(String, SocketAddr)> = match matches.get_one::<SocketAddr>("address") {
+    Some(address) => vec![(address.to_string(), *address)],
+    None => discovered_targets(&matches),
+  };
+  if targets.is_empty() {
+    println!("Nothing to ping.");
+    return;
+  }
+
+  for (name, address) in targets {
+    println!("PING {name} ({address})");
+    let mut received = 0;
+    for i in 0..count {
+      if i > 0 {
+        thread::sleep(Duration::from_secs(1));
+      }
+      match ping::ping(address, timeout) {
+        Ok(reply) => {
+          received += 1;
+          println!(
+            "  reply from {}: guid_prefix={:?} vendor={:?} version={}.{} time={:.3} ms",
+            reply.source,
+            reply.guid_prefix,
+            reply.vendor_id.as_bytes(),
+            reply.protocol_version.major,
+            reply.protocol_version.minor,
+            reply.round_trip_time.as_secs_f64() * 1000.0,
+          );
+        }
+        Err(e) => println!("  {e}"),
+      }
+    }
+    println!("  {count} sent, {received} answered");
+  }
+}
+
+// Unicast UDP discovery locators of the participants discovered in a domain
+fn discovered_targets(matches: &ArgMatches) -> Vec<(String, SocketAddr)> {
+  let domain_id = *matches.get_one::<u16>("domain_id").unwrap();
+  let wait = Duration::from_secs(*matches.get_one::<u64>("wait").unwrap());
+
+  let domain_participant = DomainParticipant::new(domain_id)
+    .unwrap_or_else(|e| panic!("DomainParticipant construction failed: {e:?}"));
+  println!("Discovering participants in domain {domain_id} for {wait:?}");
+  thread::sleep(wait);
+
+  let mut targets = Vec::new();
+  // Skip ourselves. Our participant is gone by the time pings are sent.
+  for participant in domain_participant
+    .discovered_participants()
+    .into_iter()
+    .filter(|p| p.participant_guid != domain_participant.guid())
+  {
+    let name = format!(
+      "{:?} {}",
+      participant.participant_guid.prefix,
+      participant.entity_name.as_deref().unwrap_or(""),
+    );
+    targets.extend(
+      participant
+        .metatraffic_unicast_locators
+        .iter()
+        .filter_map(|locator| match locator {
+          Locator::UdpV4(address) => Some(SocketAddr::from(*address)),
+          Locator::UdpV6(address) => Some(SocketAddr::from(*address)),
+          _ => None,
+        })
+        .map(|address| (name.clone(), address)),
+    );
+  }
+  targets
+}
+
+fn configure_logging() {
+  // initialize logging, preferably from config file
+  log4rs::init_file(
+    "logging-config.yaml",
+    log4rs::config::Deserializers::default(),
+  )
+  .unwrap_or_else(|e| {
+    match e.downcast_ref::<io::Error>() {
+      // Config file did not work. If it is a simple "No such file or directory", then
+      // substitute some default config.
+      Some(os_err) if os_err.kind() == io::ErrorKind::NotFound => {
+        let stdout = ConsoleAppender::builder().build();
+        let conf = Config::builder()
+          .appender(Appender::builder().build("stdout", Box::new(stdout)))
+          .build(Root::builder().appender("stdout").build(LevelFilter::Error))
+          .unwrap();
+        log4rs::init_config(conf).unwrap();
+      }
+      // Give up.
+      other_error => panic!("Config problem: {other_error:?}"),
+    }
+  });
+}
+
+fn get_matches() -> ArgMatches {
+  Command::new("RustDDS ping")
+    .about("Checks reachability of RTPS participants with RTPS PING.")
+    .arg(
+      Arg::new("address")
+        .value_name("address:port")
+        .value_parser(clap::value_parser!(SocketAddr))
+        .help("Unicast locator to ping. Default is to ping discovered participants."),
+    )
+    .arg(
+      Arg::new("domain_id")
+        .short('d')
+        .value_name("id")
+        .value_parser(clap::value_parser!(u16))
+        .default_value("0")
+        .help("Sets the DDS domain id number, where participants are discovered"),
+    )
+    .arg(
+      Arg::new("wait")
+        .short('w')
+        .value_name("seconds")
+        .value_parser(clap::value_parser!(u64))
+        .default_value("3")
+        .help("How long to discover participants before pinging them"),
+    )
+    .arg(
+      Arg::new("count")
+        .short('c')
+        .value_name("count")
+        .value_parser(clap::value_parser!(u32))
+        .default_value("3")
+        .help("Number of pings to each address, one per second"),
+    )
+    .arg(
+      Arg::new("timeout")
+        .short('t')
+        .value_name("milliseconds")
+        .value_parser(clap::value_parser!(u64))
+        .default_value("2000")
+        .help("How long to wait for each answer"),
+    )
+    .get_matches()
+}
@@ -0,0 +1,254 @@
+//! UDP throughput benchmark for `RustDDS`
+//!
+//! Sends small and large samples between two participants in this process over
+//! UDP on the loopback interface, first with plain system calls, then with
+//! batched UDP system calls (`DomainParticipantBuilder::batched_udp_io`).
+//! Reports wall clock time and process CPU time of each run.
+//!
+//! Samples are sent best effort, and the writer waits while a few samples are
+//! in flight, so that the socket receive buffer does not overflow. Large
+//! samples are fragmented with an Ethernet sized fragment size by default.
+
+#![deny(clippy::all)]
+
+use std::{
+  net::{IpAddr, Ipv4Addr},
+  sync::atomic::{AtomicU32, Ordering},
+  thread,
+  time::{Duration, Instant},
+};
+
+use rustdds::{
+  no_key::{DataReader, DataWriter},
+  policy, CDRDeserializerAdapter, CDRSerializerAdapter, DomainParticipant,
+  DomainParticipantBuilder, QosPolicies, QosPolicyBuilder, TopicKind,
+};
+use serde::{Deserialize, Serialize};
+use clap::{Arg, ArgMatches, Command}; // command line argument processing
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Payload {
+  seq: u32,
+  data: Vec<u8>,
+}
+
+type Writer = DataWriter<Payload, CDRSerializerAdapter<Payload>>;
+type Reader = DataReader<Payload, CDRDeserializerAdapter<Payload>>;
+
+fn main() {
+  let matches = get_matches();
+  let domain_id = *matches.get_one::<u16>("domain_id").unwrap();
+  let small_count = *matches.get_one::<u32>("small_samples").unwrap();
+  let large_count = *matches.get_one::<u32>("large_samples").unwrap();
+  let large_size = *matches.get_one::<usize>("large_size").unwrap();
+  let fragment_size = *matches.get_one::<usize>("fragment_size").unwrap();
+
+  let runs = [
+    ("small", small_count, 64),
+    ("large", large_count, large_size),
+  ];
+  for batched in [false, true] {
+    // Separate domains, so that the runs do not see each other.
+    let domain_id = domain_id + u16::from(batched);
+    let (writer, mut reader, _participants) = connect(domain_id, batched, fragment_size);
+    for (name, count, size) in runs {
+      let cpu_start = cpu_time();
+      let start = Instant::now();
+      let received = transfer(&writer, &mut reader, count, size);
+      let wall = start.elapsed();
+      let cpu = cpu_time().zip(cpu_start).map(|(end, start)| end - start);
+
+      let megabytes = (received as f64) * (size as f64) / 1e6;
+      println!(
+        "batched_udp_io={batched:5} {name}: {received}/{count} samples of {size} bytes in \
+         {:.2} s, {:.1} MB/s, CPU time {}",
+        wall.as_secs_f64(),
+        megabytes / wall.as_secs_f64(),
+        cpu.map_or("not available".to_string(), |cpu| format!(
+          "{:.2} s, {:.1} us per sample",
+          cpu.as_secs_f64(),
+          cpu.as_secs_f64() * 1e6 / f64::from(received.max(1))
+        )),
+      );
+    }
+  }
+}
+
+// Creates the participants, and waits until the reader receives samples.
+fn connect(
+  domain_id: u16,
+  batched: bool,
+  fragment_size: usize,
+) -> (Writer, Reader, Vec<DomainParticipant>) {
+  let participant = |participant_id, peer: &str| {
+    DomainParticipantBuilder::new(domain_id)
+      .participant_id(participant_id)
+      .bind_address(IpAddr::V4(Ipv4Addr::LOCALHOST))
+      .multicast(false)
+      .shared_memory(false)
+      .batched_udp_io(batched)
+      .max_fragment_size(fragment_size)
+      .initial_peers(vec![peer.parse().unwrap()])
+      .spdp_announcement_period(Duration::from_millis(100))
+      .build()
+      .unwrap_or_else(|e| panic!("DomainParticipant construction failed: {e:?}"))
+  };
+  let writer_participant = participant(0, "127.0.0.1[1-1]");
+  let reader_participant = participant(1, "127.0.0.1[0-0]");
+
+  let qos: QosPolicies = QosPolicyBuilder::new()
+    .reliability(policy::Reliability::BestEffort)
+    .history(policy::History::KeepLast { depth: 1024 })
+    .build();
+  let topic = |participant: &DomainParticipant| {
+    participant
+      .create_topic(
+        "udp_throughput".to_string(),
+        "Payload".to_string(),
+        &qos,
+        TopicKind::NoKey,
+      )
+      .unwrap()
+  };
+  let writer = writer_participant
+    .create_publisher(&qos)
+    .unwrap()
+    .create_datawriter_no_key(&topic(&writer_participant), None)
+    .unwrap();
+  let mut reader = reader_participant
+    .create_subscriber(&qos)
+    .unwrap()
+    .create_datareader_no_key(&topic(&reader_participant), None)
+    .unwrap();
+
+  loop {
+    writer
+      .write(
+        Payload {
+          seq: 0,
+          data: vec![],
+        },
+        None,
+      )
+      .unwrap();
+    thread::sleep(Duration::from_millis(100));
+    if reader.take_next_sample().unwrap().is_some() {
+      break;
+    }
+  }
+  // Samples written while matching may still arrive
+  thread::sleep(Duration::from_millis(200));
+  while reader.take_next_sample().unwrap().is_some() {}
+
+  (writer, reader, vec![writer_participant, reader_participant])
+}
+
+// Writes `count` samples of `size` bytes, and returns how many were received.
+// A sample that does not arrive in time is counted as lost.
+fn transfer(writer: &Writer, reader: &mut Reader, count: u32, size: usize) -> u32 {
+  const RECEIVE_TIMEOUT: Duration = Duration::from_millis(200);
+  // Samples in flight, about 128 kB, which fits in the default socket receive
+  // buffer.
+  let window = u32::try_from(128 * 1024 / (size + 1024))
+    .unwrap_or(1)
+    .clamp(1, 128);
+
+  let received = AtomicU32::new(0);
+  let last_seq = AtomicU32::new(0);
+  thread::scope(|scope| {
+    scope.spawn(|| {
+      let mut last_progress = Instant::now();
+      while last_seq.load(Ordering::Acquire) < count && last_progress.elapsed() < RECEIVE_TIMEOUT {
+        match reader.take_next_sample() {
+          Ok(Some(sample)) if sample.value().seq > 0 => {
+            received.fetch_add(1, Ordering::Release);
+            last_seq.store(sample.value().seq, Ordering::Release);
+            last_progress = Instant::now();
+          }
+          Ok(_) => thread::sleep(Duration::from_micros(50)),
+          Err(e) => panic!("Reading failed: {e:?}"),
+        }
+      }
+    });
+
+    for seq in 1..=count {
+      let wait_start = Instant::now();
+      while seq > last_seq.load(Ordering::Acquire) + window
+        && wait_start.elapsed() < RECEIVE_TIMEOUT
+      {
+        thread::sleep(Duration::from_micros(50));
+      }
+      let payload = Payload {
+        seq,
+        data: vec![seq as u8; size],
+      };
+      // Writing fails when the command queue to the event loop is full.
+      while writer.write(payload.clone(), None).is_err() {
+        thread::sleep(Duration::from_micros(50));
+      }
+    }
+  });
+  received.into_inner()
+}
+
+// CPU time used by this process, user and system
+#[cfg(target_os = "linux")]
+fn cpu_time() -> Option<Duration> {
+  let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+  // SAFETY: getrusage fills in the struct, if it succeeds.
+  if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+    return None;
+  }
+  let usage = unsafe { usage.assume_init() };
+  let duration = |t: libc::timeval| {
+    Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+  };
+  Some(duration(usage.ru_utime) + duration(usage.ru_stime))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cpu_time() -> Option<Duration> {
+  None
+}
+
+fn get_matches() -> ArgMatches {
+  Command::new("RustDDS UDP throughput benchmark")
+    .version("0.1")
+    .about("Compares plain and batched UDP system calls.")
+    .arg(
+      Arg::new("domain_id")
+        .short('d')
+        .help("DDS domain ids to use: this and the next one")
+        .value_parser(clap::value_parser!(u16))
+        .default_value("90"),
+    )
+    .arg(
+      Arg::new("small_samples")
+        .short('s')
+        .help("Number of small samples")
+        .value_parser(clap::value_parser!(u32))
+        .default_value("20000"),
+    )
+    .arg(
+      Arg::new("large_samples")
+        .short('l')
+        .help("Number of large samples")
+        .value_parser(clap::value_parser!(u32))
+        .default_value("200"),
+    )
+    .arg(
+      Arg::new("large_size")
+        .short('z')
+        .help("Size of a large sample in bytes")
+        .value_parser(clap::value_parser!(usize))
+        .default_value("100000"),
+    )
+    .arg(
+      Arg::new("fragment_size")
+        .short('f')
+        .help("Fragment size in bytes")
+        .value_parser(clap::value_parser!(usize))
+        .default_value("1400"),
+    )
+    .get_matches()
+}
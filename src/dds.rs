@@ -5,14 +5,16 @@
 mod helpers;
 
 pub(crate) mod participant;
-pub use participant::DomainParticipant;
+pub use participant::{DomainParticipant, DomainParticipantBuilder};
 
 pub(crate) mod dds_entity;
 pub(crate) mod ddsdata;
 pub(crate) mod pubsub;
 pub(crate) mod readcondition;
+pub(crate) mod send_buffer_pool;
 pub(crate) mod topic;
 pub(crate) mod typedesc;
+pub(crate) mod waitset;
 
 pub mod result;
 pub use result::{Error, Result};
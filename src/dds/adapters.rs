@@ -7,7 +7,7 @@
 /// for WITH_KEY topics, we need to be able to (de)serailize the key in addition
 /// to data.
 pub mod no_key {
-  use bytes::Bytes;
+  use bytes::{Bytes, BytesMut};
 
   use crate::{serialization::error::Result, RepresentationIdentifier};
 
@@ -43,6 +43,17 @@ pub mod no_key {
     fn output_encoding() -> RepresentationIdentifier;
 
     fn to_bytes(value: &D) -> Result<Bytes>;
+
+    /// Serialize data by appending it to `buffer`. DataWriters pass in
+    /// recycled buffers, so that there is no allocation per sample.
+    ///
+    /// This method has a default implementation, but the default calls
+    /// to_bytes() and copies the result to the buffer.
+    // In order to avoid the allocation and copy, implement also this method.
+    fn to_buffer(value: &D, buffer: &mut BytesMut) -> Result<()> {
+      buffer.extend_from_slice(&Self::to_bytes(value)?);
+      Ok(())
+    }
   }
 }
 
@@ -1,8 +1,9 @@
 use std::thread;
 
-use mio_extras::channel::{SyncSender, TrySendError};
-
-use crate::structure::duration::Duration;
+use crate::{
+  mio_source::channel::{SyncSender, TrySendError},
+  structure::duration::Duration,
+};
 
 const TIMEOUT_EPSILON: Duration = Duration::from_nanos(1000);
 
@@ -1,10 +1,13 @@
 use std::{
   io,
   pin::Pin,
+  sync::Arc,
   task::{Context, Poll},
 };
 
+#[cfg(feature = "mio_06")]
 use mio_06::{self, Evented};
+use enumflags2::BitFlags;
 use futures::stream::{FusedStream, Stream};
 
 use crate::{
@@ -14,7 +17,8 @@ use crate::{
     qos::{HasQoSPolicy, QosPolicies},
     readcondition::ReadCondition,
     result::Result,
-    statusevents::DataReaderStatus,
+    statusevents::{DataReaderStatus, StatusCondition, StatusKind},
+    waitset::ReadConditionSource,
     with_key::{
       datareader as datareader_with_key,
       datasample::{DataSample as WithKeyDataSample, Sample},
@@ -22,6 +26,7 @@ use crate::{
       DataReaderStream as WithKeyDataReaderStream,
     },
   },
+  mio_source::TaskWaker,
   serialization::CDRDeserializerAdapter,
   structure::entity::RTPSEntity,
   StatusEvented, GUID,
@@ -429,6 +434,7 @@ where
 //  TODO: test
 // This is  not part of DDS spec. We implement mio Eventd so that the
 // application can asynchronously poll DataReader(s).
+#[cfg(feature = "mio_06")]
 impl<D, DA> Evented for DataReader<D, DA>
 where
   DA: DeserializerAdapter<D>,
@@ -462,6 +468,15 @@ where
   }
 }
 
+impl<D, DA> ReadConditionSource for DataReader<D, DA>
+where
+  DA: DeserializerAdapter<D>,
+{
+  fn task_waker(&self) -> Arc<TaskWaker> {
+    self.keyed_datareader.task_waker()
+  }
+}
+
 /// WARNING! UNTESTED
 //  TODO: test
 impl<D, DA> mio_08::event::Source for DataReader<D, DA>
@@ -512,6 +527,7 @@ impl<D, DA> StatusEvented<DataReaderStatus> for DataReader<D, DA>
 where
   DA: DeserializerAdapter<D>,
 {
+  #[cfg(feature = "mio_06")]
   fn as_status_evented(&mut self) -> &dyn Evented {
     self.keyed_datareader.as_status_evented()
   }
@@ -523,6 +539,10 @@ where
   fn try_recv_status(&self) -> Option<DataReaderStatus> {
     self.keyed_datareader.try_recv_status()
   }
+
+  fn status_condition(&mut self, mask: BitFlags<StatusKind>) -> io::Result<StatusCondition> {
+    self.keyed_datareader.status_condition(mask)
+  }
 }
 
 impl<D, DA> HasQoSPolicy for DataReader<D, DA>
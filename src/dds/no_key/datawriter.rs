@@ -1,6 +1,8 @@
-use std::time::Duration;
+use std::{io, time::Duration};
 
+#[cfg(feature = "mio_06")]
 use mio_06::Evented;
+use enumflags2::BitFlags;
 
 use crate::{
   dds::{
@@ -8,7 +10,7 @@ use crate::{
     dds_entity::DDSEntity,
     pubsub::Publisher,
     qos::{HasQoSPolicy, QosPolicies},
-    statusevents::{DataWriterStatus, StatusReceiverStream},
+    statusevents::{DataWriterStatus, StatusCondition, StatusKind, StatusReceiverStream},
     topic::Topic,
     with_key::datawriter as datawriter_with_key,
     Result,
@@ -401,6 +403,7 @@ impl<D, SA> StatusEvented<DataWriterStatus> for DataWriter<D, SA>
 where
   SA: SerializerAdapter<D>,
 {
+  #[cfg(feature = "mio_06")]
   fn as_status_evented(&mut self) -> &dyn Evented {
     self.keyed_datawriter.as_status_evented()
   }
@@ -412,6 +415,10 @@ where
   fn try_recv_status(&self) -> Option<DataWriterStatus> {
     self.keyed_datawriter.try_recv_status()
   }
+
+  fn status_condition(&mut self, mask: BitFlags<StatusKind>) -> io::Result<StatusCondition> {
+    self.keyed_datawriter.status_condition(mask)
+  }
 }
 
 impl<D, SA: SerializerAdapter<D>> RTPSEntity for DataWriter<D, SA> {
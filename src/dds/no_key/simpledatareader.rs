@@ -1,8 +1,10 @@
 use std::{io, task::Waker};
 
+use enumflags2::BitFlags;
 use futures::stream::{FusedStream, Stream, StreamExt};
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
+#[cfg(feature = "mio_06")]
 use mio_06::{self, Evented};
 use mio_08;
 
@@ -101,6 +103,7 @@ where
 
 // This is  not part of DDS spec. We implement mio Eventd so that the
 // application can asynchronously poll DataReader(s).
+#[cfg(feature = "mio_06")]
 impl<D, DA> Evented for SimpleDataReader<D, DA>
 where
   DA: DeserializerAdapter<D>,
@@ -170,6 +173,7 @@ impl<D, DA> StatusEvented<DataReaderStatus> for SimpleDataReader<D, DA>
 where
   DA: DeserializerAdapter<D>,
 {
+  #[cfg(feature = "mio_06")]
   fn as_status_evented(&mut self) -> &dyn Evented {
     self.keyed_simpledatareader.as_status_evented()
   }
@@ -181,6 +185,10 @@ where
   fn try_recv_status(&self) -> Option<DataReaderStatus> {
     self.keyed_simpledatareader.try_recv_status()
   }
+
+  fn status_condition(&mut self, mask: BitFlags<StatusKind>) -> io::Result<StatusCondition> {
+    self.keyed_simpledatareader.status_condition(mask)
+  }
 }
 
 impl<D, DA> RTPSEntity for SimpleDataReader<D, DA>
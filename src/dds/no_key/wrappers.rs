@@ -1,6 +1,6 @@
 use std::{marker::PhantomData, ops::Deref};
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 
 use crate::{
   dds::adapters::*, messages::submessages::submessages::RepresentationIdentifier,
@@ -54,6 +54,10 @@ where
   fn to_bytes(value: &NoKeyWrapper<D>) -> Result<Bytes> {
     SA::to_bytes(&value.d)
   }
+
+  fn to_buffer(value: &NoKeyWrapper<D>, buffer: &mut BytesMut) -> Result<()> {
+    SA::to_buffer(&value.d, buffer)
+  }
 }
 
 // This is the point of wrapping. Implement dummy key serialization
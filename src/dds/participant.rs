@@ -2,34 +2,51 @@
 use std::{
   collections::HashMap,
   io::ErrorKind,
-  net::Ipv4Addr,
+  net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
   sync::{atomic, Arc, Mutex, RwLock, Weak},
   thread,
   thread::JoinHandle,
   time::{Duration, Instant},
 };
 
-use mio_extras::channel as mio_channel;
-use mio_06::Token;
+use mio_08::Token;
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 
 use crate::{
   dds::{pubsub::*, qos::*, result::*, topic::*, typedesc::TypeDesc},
   discovery::{
-    discovery::{Discovery, DiscoveryCommand},
+    discovery::{Discovery, DiscoveryCommand, DiscoveryConfig, DiscoveryMode},
     discovery_db::DiscoveryDB,
+    initial_peers::InitialPeer,
     sedp_messages::DiscoveredTopicData,
+    spdp_participant_data::SpdpDiscoveredParticipantData,
   },
   log_and_err_internal,
-  network::{constant::*, udp_listener::UDPListener},
+  mio_source::channel as mio_channel,
+  network::{
+    constant::*,
+    interface_filter::{InterfaceFilter, InterfaceSelector},
+    tcp_transport::{tcp_locator, tcp_locator_address, TCPSender, TCPTransport},
+    transport::{Transport, TransportContext, TransportFactory, TransportLocators, Transports},
+    udp_listener::UDPListener,
+    udp_sender::UDPSender,
+    udp_transport::UDPTransport,
+    util::{
+      get_interface_mtu, get_interface_name, get_local_multicast_ip_addrs,
+      get_local_unicast_locators,
+    },
+  },
   rtps::{
     dp_event_loop::{DPEventLoop, DomainInfo},
+    flow_controller::FlowControllerSettings,
     reader::*,
-    writer::WriterIngredients,
+    writer::{WriterIngredients, MAX_FRAGMENT_SIZE, MIN_FRAGMENT_SIZE},
   },
   structure::{dds_cache::DDSCache, entity::RTPSEntity, guid::*, locator::Locator},
 };
+#[cfg(target_os = "linux")]
+use crate::network::shm_transport::ShmTransport;
 
 /// DDS DomainParticipant
 ///
@@ -59,84 +76,7 @@ impl DomainParticipant {
   /// let domain_participant = DomainParticipant::new(0).unwrap();
   /// ```
   pub fn new(domain_id: u16) -> Result<Self> {
-    trace!("DomainParticipant construct start");
-
-    // Discovery join channel is used to just send a join handle into the inner
-    // participant, so its .drop() can wait until discovery has had a chance to
-    // stop.
-    let (djh_sender, djh_receiver) = mio_channel::channel();
-
-    // Channel is used to notify Discovery of (duplicate) SPDP messages from the
-    // wire.
-    let (spdp_liveness_sender, spdp_liveness_receiver) = mio_channel::sync_channel(8);
-
-    // Discovery thread receives and decodes updates from the wire.
-    // It updates data to DiscoveryDB, and sends notifications to dp_event_loop,
-    // which owns the Readers and Writers and notifies them also.
-    let (discovery_updated_sender, discovery_update_notification_receiver) =
-      mio_channel::sync_channel::<DiscoveryNotificationType>(32);
-
-    // This channel is used to:
-    // * local DataReader and DataWriter notify Discovery on drop() so that
-    // Discovery knows we no longer have them.
-    // * Participant commands Discovery to assert liveness, i.e. send liveness
-    // message to remote participants.
-    // * Discovery commands Discovery (thread) to terminate on exit.
-    let (discovery_command_sender, discovery_command_receiver) =
-      mio_channel::sync_channel::<DiscoveryCommand>(64);
-
-    // intermediate DP wrapper
-    let dp = DomainParticipantDisc::new(
-      domain_id,
-      djh_receiver,
-      discovery_update_notification_receiver,
-      discovery_command_sender,
-      spdp_liveness_sender,
-    )?;
-    let self_locators = dp.self_locators();
-
-    // outer DP wrapper
-    let dp = Self {
-      dpi: Arc::new(Mutex::new(dp)),
-    };
-
-    let (discovery_started_sender, discovery_started_receiver) =
-      std::sync::mpsc::channel::<Result<()>>();
-
-    // Construct and start background thread
-    let dp_clone = dp.weak_clone();
-    let disc_db_clone = dp.discovery_db();
-    let discovery_handle = thread::Builder::new()
-      .name("RustDDS discovery thread".to_string())
-      .spawn(move || {
-        if let Ok(mut discovery) = Discovery::new(
-          dp_clone,
-          disc_db_clone,
-          discovery_started_sender,
-          discovery_updated_sender,
-          discovery_command_receiver,
-          spdp_liveness_receiver,
-          self_locators,
-        ) {
-          discovery.discovery_event_loop(); // run the event loop
-        }
-      })?;
-
-    djh_sender.send(discovery_handle).unwrap_or(()); // send join handle to inner participant
-
-    debug!("Waiting for discovery to start"); // blocking until discovery answers
-    match discovery_started_receiver.recv_timeout(Duration::from_secs(10)) {
-      Ok(Ok(())) => {
-        // normal case
-        info!("Discovery started. Participant constructed.");
-        Ok(dp)
-      }
-      Ok(Err(e)) => {
-        std::mem::drop(dp);
-        log_and_err_internal!("Failed to start discovery thread: {e:?}")
-      }
-      Err(e) => log_and_err_internal!("Discovery thread channel error: {e:?}"),
-    }
+    DomainParticipantBuilder::new(domain_id).build()
   }
 
   /// Creates DDS Publisher
@@ -245,6 +185,22 @@ impl DomainParticipant {
     self.dpi.lock().unwrap().participant_id()
   }
 
+  /// Entity name announced in SPDP, if one was set with
+  /// [`DomainParticipantBuilder::entity_name`].
+  pub fn entity_name(&self) -> Option<String> {
+    self.dpi.lock().unwrap().entity_name()
+  }
+
+  /// Default fragment size of DataWriters and DataReaders. See
+  /// [`DomainParticipantBuilder::max_fragment_size`].
+  pub fn max_fragment_size(&self) -> usize {
+    self.dpi.lock().unwrap().max_fragment_size()
+  }
+
+  pub(crate) fn has_flow_controller(&self, name: &str) -> bool {
+    self.dpi.lock().unwrap().has_flow_controller(name)
+  }
+
   /// Gets all DiscoveredTopics from DDS network
   ///
   /// # Examples
@@ -262,6 +218,23 @@ impl DomainParticipant {
     self.dpi.lock().unwrap().discovered_topics()
   }
 
+  /// Gets all DomainParticipants discovered from DDS network, including this
+  /// one.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use rustdds::DomainParticipant;
+  ///
+  /// let domain_participant = DomainParticipant::new(0).unwrap();
+  /// for participant in domain_participant.discovered_participants() {
+  ///   println!("{:?}", participant.participant_guid);
+  /// }
+  /// ```
+  pub fn discovered_participants(&self) -> Vec<SpdpDiscoveredParticipantData> {
+    self.dpi.lock().unwrap().discovered_participants()
+  }
+
   /// Manually asserts liveliness, affecting all writers with
   /// LIVELINESS QoS of MANUAL_BY_PARTICIPANT created by
   /// this particular participant.
@@ -307,6 +280,663 @@ impl DomainParticipant {
   }
 } // end impl DomainParticipant
 
+/// Builder for [`DomainParticipant`]
+///
+/// [`DomainParticipant::new`] is a shorthand for
+/// `DomainParticipantBuilder::new(domain_id).build()`, i.e. all settings at
+/// their defaults.
+///
+/// # Examples
+/// ```
+/// # use std::net::{IpAddr, Ipv4Addr};
+/// # use rustdds::DomainParticipantBuilder;
+///
+/// let domain_participant = DomainParticipantBuilder::new(0)
+///   .participant_id(7)
+///   .announce_addresses(vec![IpAddr::V4(Ipv4Addr::LOCALHOST)])
+///   .entity_name("robot_arm")
+///   .build()
+///   .unwrap();
+/// assert_eq!(domain_participant.participant_id(), 7);
+/// ```
+#[derive(Clone, Debug)]
+pub struct DomainParticipantBuilder {
+  domain_id: u16,
+  participant_id: Option<u16>,
+  guid_prefix: Option<GuidPrefix>,
+  bind_address: IpAddr,
+  announce_addresses: Option<Vec<IpAddr>>,
+  interface_filter: InterfaceFilter,
+  udp: bool,
+  batched_udp_io: bool,
+  multicast: bool,
+  ipv4: bool,
+  ipv6: bool,
+  ipv6_multicast_group: Ipv6Addr,
+  shared_memory: bool,
+  shared_memory_segment_size: usize,
+  tcp_listen_port: Option<u16>,
+  transports: Vec<TransportFactory>,
+  max_fragment_size: Option<usize>,
+  flow_controllers: Vec<(String, FlowControllerSettings)>,
+  event_loop_threads: usize,
+  port_mapping: PortMapping,
+  lease_duration: crate::Duration,
+  spdp_announcement_period: Duration,
+  entity_name: Option<String>,
+  initial_peers: Vec<InitialPeer>,
+  discovery_mode: DiscoveryMode,
+}
+
+impl DomainParticipantBuilder {
+  // Magic value 120 is from RTPS spec 2.5 Section "9.6.2.3 Default Port
+  // Numbers"
+  const MAX_PARTICIPANT_ID: u16 = 120;
+
+  const MULTICAST_GROUP_IPV4: Ipv4Addr = Ipv4Addr::new(239, 255, 0, 1);
+
+  /// IPv6 multicast group `ff02::ffff:239.255.0.1`, which has link-local scope.
+  /// This is the default.
+  pub const IPV6_MULTICAST_GROUP_LINK_LOCAL: Ipv6Addr =
+    Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0xffff, 0xefff, 0x0001);
+
+  /// IPv6 multicast group `ff05::ffff:239.255.0.1`, which has site-local scope,
+  /// i.e. it may be routed beyond the local link.
+  pub const IPV6_MULTICAST_GROUP_SITE_LOCAL: Ipv6Addr =
+    Ipv6Addr::new(0xff05, 0, 0, 0, 0, 0xffff, 0xefff, 0x0001);
+
+  const SHARED_MEMORY_SEGMENT_SIZE: usize = 1024 * 1024;
+
+  // MTU assumed for interfaces whose MTU is not known
+  const DEFAULT_MTU: usize = 1500;
+
+  // Room for IP and UDP headers, and RTPS headers, submessages and inline QoS
+  // that go into the same datagram as a DATA or DATA_FRAG.
+  const DATAGRAM_OVERHEAD: usize = 200;
+
+  pub fn new(domain_id: u16) -> Self {
+    let spdp_announcement_period = Discovery::SEND_PARTICIPANT_INFO_PERIOD;
+    Self {
+      domain_id,
+      participant_id: None,
+      guid_prefix: None,
+      bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+      announce_addresses: None,
+      interface_filter: InterfaceFilter::default(),
+      udp: true,
+      batched_udp_io: false,
+      multicast: true,
+      ipv4: true,
+      ipv6: false,
+      ipv6_multicast_group: Self::IPV6_MULTICAST_GROUP_LINK_LOCAL,
+      shared_memory: true,
+      shared_memory_segment_size: Self::SHARED_MEMORY_SEGMENT_SIZE,
+      tcp_listen_port: None,
+      transports: Vec::new(),
+      max_fragment_size: None,
+      flow_controllers: Vec::new(),
+      event_loop_threads: 0,
+      port_mapping: PortMapping::default(),
+      // 5 times the announcement period, so lease doesn't break if update fails
+      // once or twice
+      lease_duration: 5.0 * crate::Duration::from(spdp_announcement_period),
+      spdp_announcement_period,
+      entity_name: None,
+      initial_peers: Vec::new(),
+      discovery_mode: DiscoveryMode::Simple,
+    }
+  }
+
+  /// Use a fixed participant id instead of probing for the first free one.
+  /// Building fails if the ports of this participant id are already in use.
+  #[must_use]
+  pub fn participant_id(mut self, participant_id: u16) -> Self {
+    self.participant_id = Some(participant_id);
+    self
+  }
+
+  /// Use a fixed GUID prefix instead of a random one.
+  #[must_use]
+  pub fn guid_prefix(mut self, guid_prefix: GuidPrefix) -> Self {
+    self.guid_prefix = Some(guid_prefix);
+    self
+  }
+
+  /// Local address the unicast listening sockets are bound to. Default is the
+  /// unspecified address, i.e. all interfaces.
+  ///
+  /// The address applies to the sockets of its own IP version. If the other IP
+  /// version is also enabled, its sockets are bound to all interfaces.
+  #[must_use]
+  pub fn bind_address(mut self, bind_address: IpAddr) -> Self {
+    self.bind_address = bind_address;
+    self
+  }
+
+  /// Addresses announced to remote participants in our locators. These are
+  /// also the interfaces on which multicast groups are joined.
+  ///
+  /// Default is the bind address, if it is specified, or else all
+  /// non-loopback interfaces.
+  #[must_use]
+  pub fn announce_addresses(mut self, announce_addresses: Vec<IpAddr>) -> Self {
+    self.announce_addresses = Some(announce_addresses);
+    self
+  }
+
+  /// Use only these network interfaces. Default is all non-loopback
+  /// interfaces.
+  ///
+  /// The allow and deny lists limit the interfaces that are announced in
+  /// locators, on which multicast groups are joined and multicast is sent,
+  /// and that the [bind address](Self::bind_address) may be on. An interface
+  /// address is used, if the allow list is empty or selects it, and the
+  /// [deny list](Self::deny_interfaces) does not select it. Explicit
+  /// [announce addresses](Self::announce_addresses) are used as given.
+  ///
+  /// Unicast listening sockets bound to the unspecified address still receive
+  /// from all interfaces, but remote participants learn only the permitted
+  /// addresses.
+  ///
+  /// # Examples
+  /// ```
+  /// # use rustdds::DomainParticipantBuilder;
+  /// let builder = DomainParticipantBuilder::new(0)
+  ///   .allow_interfaces(vec!["192.168.1.0/24".parse().unwrap()])
+  ///   .deny_interfaces(vec!["docker0".parse().unwrap(), "tun0".parse().unwrap()]);
+  /// ```
+  #[must_use]
+  pub fn allow_interfaces(mut self, interfaces: Vec<InterfaceSelector>) -> Self {
+    self.interface_filter.allow = interfaces;
+    self
+  }
+
+  /// Do not use these network interfaces, e.g. container bridges or VPN
+  /// tunnels. See [`allow_interfaces`](Self::allow_interfaces).
+  #[must_use]
+  pub fn deny_interfaces(mut self, interfaces: Vec<InterfaceSelector>) -> Self {
+    self.interface_filter.deny = interfaces;
+    self
+  }
+
+  /// Enable or disable the UDP transport. Default is enabled.
+  ///
+  /// With UDP disabled, the participant opens no UDP sockets, and needs some
+  /// other transport, e.g. a
+  /// [`LoopbackNetwork`](crate::transport::LoopbackNetwork) added with
+  /// [`transport`](Self::transport). The participant id is then not probed, but
+  /// is the one given with [`participant_id`](Self::participant_id), or 0.
+  #[must_use]
+  pub fn udp(mut self, enable: bool) -> Self {
+    self.udp = enable;
+    self
+  }
+
+  /// Enable or disable multicast listening. Default is enabled.
+  ///
+  /// Note that with multicast disabled, SPDP announcements have no default
+  /// destination, so remote participants must discover us some other way.
+  #[must_use]
+  pub fn multicast(mut self, enable: bool) -> Self {
+    self.multicast = enable;
+    self
+  }
+
+  /// Enable or disable IPv4. Default is enabled.
+  #[must_use]
+  pub fn ipv4(mut self, enable: bool) -> Self {
+    self.ipv4 = enable;
+    self
+  }
+
+  /// Enable or disable IPv6. Default is disabled.
+  ///
+  /// With IPv6 enabled, the participant listens also on IPv6 unicast and
+  /// multicast, announces its IPv6 locators in discovery, and sends to IPv6
+  /// locators of remote participants. IPv4 can be disabled for IPv6-only
+  /// networks.
+  ///
+  /// IPv6 link-local unicast addresses are not announced, because locators
+  /// cannot carry the interface (scope) id that is needed to reach them.
+  #[must_use]
+  pub fn ipv6(mut self, enable: bool) -> Self {
+    self.ipv6 = enable;
+    self
+  }
+
+  /// Multicast group for IPv6 discovery and user traffic. Default is
+  /// [`IPV6_MULTICAST_GROUP_LINK_LOCAL`](Self::IPV6_MULTICAST_GROUP_LINK_LOCAL).
+  #[must_use]
+  pub fn ipv6_multicast_group(mut self, group: Ipv6Addr) -> Self {
+    self.ipv6_multicast_group = group;
+    self
+  }
+
+  /// Enable or disable the shared memory transport. Default is enabled.
+  ///
+  /// The participant creates a shared memory segment and announces it in
+  /// discovery next to its UDP locators. Participants on the same host then
+  /// send to each other through shared memory instead of UDP. If the segment of
+  /// a remote participant cannot be used, e.g. because it runs in a different
+  /// container or as a different user, UDP is used instead.
+  ///
+  /// Each participant creates a POSIX shared memory segment
+  /// `/dev/shm/rustdds_shm_*` of
+  /// [`shared_memory_segment_size`](Self::shared_memory_segment_size) bytes
+  /// (1 MiB by default), and a Unix socket of the same name in the temporary
+  /// directory, usually `/tmp`. When the participant is created, `/dev/shm`
+  /// is scanned for segments left behind by crashed processes, and those are
+  /// removed.
+  ///
+  /// A segment is considered left behind if no process with its owner's pid
+  /// exists and nobody listens on its socket. Containers that share `/dev/shm`
+  /// must also share the PID namespace and the temporary directory, or else
+  /// they may remove each other's segments. Disable shared memory in such
+  /// setups.
+  ///
+  /// Shared memory transport is available only on Linux. On other platforms
+  /// this setting has no effect.
+  #[must_use]
+  pub fn shared_memory(mut self, enable: bool) -> Self {
+    self.shared_memory = enable;
+    self
+  }
+
+  /// Size of the ring buffer in our shared memory segment, in bytes. Default is
+  /// 1 MiB. If the buffer is full, senders fall back to UDP.
+  #[must_use]
+  pub fn shared_memory_segment_size(mut self, size: usize) -> Self {
+    self.shared_memory_segment_size = size;
+    self
+  }
+
+  /// Enable or disable batched UDP system calls. Default is disabled.
+  ///
+  /// When enabled, the UDP transport receives several datagrams with one
+  /// `recvmmsg` call, and sends the fragments of a large sample, or a message
+  /// to several locators, with one `sendmmsg` call. If the kernel supports UDP
+  /// generic segmentation and receive offload (GSO and GRO), fragments to the
+  /// same locator are handed to the kernel as one large buffer. This reduces
+  /// the CPU time spent per datagram, especially with large samples.
+  ///
+  /// Batched system calls are available only on Linux. On other platforms this
+  /// setting has no effect.
+  #[must_use]
+  pub fn batched_udp_io(mut self, enable: bool) -> Self {
+    self.batched_udp_io = enable;
+    self
+  }
+
+  /// Enable RTPS over TCP, listening for connections on the given port on the
+  /// bind address. Port 0 lets the operating system choose. Default is
+  /// disabled.
+  ///
+  /// TCP locators are announced in discovery next to the UDP locators.
+  /// Remote participants that also have TCP enabled then send unicast traffic
+  /// to us over TCP. A participant that can only make outgoing connections,
+  /// e.g. because it is behind NAT, is replied to through its own connection.
+  /// Remote TCP participants are found by listing them as initial peers, e.g.
+  /// `"tcp://host:port"`, or by multicast discovery.
+  ///
+  /// The connection control protocol does not interoperate with other DDS
+  /// implementations.
+  #[must_use]
+  pub fn tcp(mut self, listen_port: u16) -> Self {
+    self.tcp_listen_port = Some(listen_port);
+    self
+  }
+
+  /// Add a user-supplied transport, e.g. an in-memory transport for tests or a
+  /// serial or radio link. The function is called to create the transport
+  /// when the participant is built, and building fails if it returns an error.
+  ///
+  /// The locators of the transport are announced in discovery next to the
+  /// built-in ones. User-supplied transports are preferred over the built-in
+  /// transports, in the order they were added. See
+  /// [`Transport`](crate::transport::Transport).
+  #[must_use]
+  pub fn transport<F>(mut self, factory: F) -> Self
+  where
+    F: Fn(&TransportContext) -> std::io::Result<Box<dyn Transport>> + Send + Sync + 'static,
+  {
+    self.transports.push(TransportFactory(Arc::new(factory)));
+    self
+  }
+
+  /// Largest serialized sample, in bytes, that DataWriters send in one DATA
+  /// submessage. Larger samples are sent as DATA_FRAG fragments of this size.
+  /// DataReaders announce this as the largest sample or fragment they want to
+  /// receive. The fragment size of a DataWriter does not change after it is
+  /// created, so it only warns about matched readers with a lower limit. The
+  /// value is limited to the range 256 ..= 64000. Individual DataWriters
+  /// and DataReaders can use a different value with
+  /// [`MaxFragmentSize`](crate::policy::MaxFragmentSize) QoS. Samples that are
+  /// sent together after
+  /// [`resume_publications`](crate::Publisher::resume_publications) are packed
+  /// into messages of at most this size.
+  ///
+  /// The default is derived from the smallest MTU of the network interfaces
+  /// in use, so that a DATA or DATA_FRAG fits into one UDP datagram without IP
+  /// fragmentation. If an MTU is not known, 1500 bytes is assumed. If UDP is
+  /// disabled, the default is the maximum.
+  #[must_use]
+  pub fn max_fragment_size(mut self, size: usize) -> Self {
+    self.max_fragment_size = Some(size);
+    self
+  }
+
+  /// Define a named flow controller, which limits the sending rate of the
+  /// DataWriters assigned to it with
+  /// [`FlowController`](crate::policy::FlowController) QoS. Fresh data and
+  /// repairs of these DataWriters wait in a send queue until the limit allows
+  /// sending them. When a DataWriter has about one second of traffic queued,
+  /// `write` blocks for at most the `max_blocking_time` of Reliability QoS,
+  /// and fails if the queue does not shrink. Defining a name again replaces
+  /// the earlier settings.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use rustdds::*;
+  /// let participant = DomainParticipantBuilder::new(0)
+  ///   .flow_controller("radio", FlowControllerSettings::token_bucket(125_000, 8_000))
+  ///   .build()
+  ///   .unwrap();
+  /// let qos = QosPolicyBuilder::new()
+  ///   .flow_controller(policy::FlowController {
+  ///     name: "radio".to_string(),
+  ///   })
+  ///   .build();
+  /// ```
+  #[must_use]
+  pub fn flow_controller(
+    mut self,
+    name: impl Into<String>,
+    settings: FlowControllerSettings,
+  ) -> Self {
+    let name = name.into();
+    self.flow_controllers.retain(|(n, _)| *n != name);
+    self.flow_controllers.push((name, settings));
+    self
+  }
+
+  /// Number of threads that process user-defined DataReaders and DataWriters.
+  /// Default is 0, i.e. they are processed by the event loop thread of the
+  /// participant.
+  ///
+  /// Otherwise the endpoints are assigned to the threads by topic name, so
+  /// that a busy topic, e.g. one repairing large samples, does not delay the
+  /// other topics. The submessages of an endpoint are still processed in
+  /// order. The participant event loop thread receives from the network,
+  /// dispatches the received submessages, and runs discovery.
+  ///
+  /// Sample batches and flow controller send queues are separate for each
+  /// thread, but the threads share the limit of a named flow controller.
+  #[must_use]
+  pub fn event_loop_threads(mut self, threads: usize) -> Self {
+    self.event_loop_threads = threads;
+    self
+  }
+
+  /// Parameters used to compute port numbers from domain and participant ids.
+  /// [`build`](Self::build) fails with
+  /// [`BadParameter`](crate::dds::result::Error::BadParameter) if a port
+  /// number of the participant or of an initial peer does not fit in `u16`.
+  #[must_use]
+  pub fn port_mapping(mut self, port_mapping: PortMapping) -> Self {
+    self.port_mapping = port_mapping;
+    self
+  }
+
+  /// Participant lease duration announced to remote participants.
+  #[must_use]
+  pub fn lease_duration(mut self, lease_duration: crate::Duration) -> Self {
+    self.lease_duration = lease_duration;
+    self
+  }
+
+  /// How often SPDP participant announcements are sent.
+  #[must_use]
+  pub fn spdp_announcement_period(mut self, period: Duration) -> Self {
+    self.spdp_announcement_period = period;
+    self
+  }
+
+  /// Participant name announced in SPDP (PID_ENTITY_NAME).
+  #[must_use]
+  pub fn entity_name(mut self, entity_name: impl Into<String>) -> Self {
+    self.entity_name = Some(entity_name.into());
+    self
+  }
+
+  /// Peers that SPDP announcements are sent to by unicast. This allows
+  /// discovery on networks without multicast. Participants discovered from
+  /// received SPDP data are added to the announcement destinations
+  /// automatically.
+  #[must_use]
+  pub fn initial_peers(mut self, initial_peers: Vec<InitialPeer>) -> Self {
+    self.initial_peers = initial_peers;
+    self
+  }
+
+  /// Act as a discovery server or client. Default is
+  /// [`DiscoveryMode::Simple`], i.e. standard SPDP and SEDP.
+  #[must_use]
+  pub fn discovery_mode(mut self, discovery_mode: DiscoveryMode) -> Self {
+    self.discovery_mode = discovery_mode;
+    self
+  }
+
+  pub fn build(self) -> Result<DomainParticipant> {
+    trace!("DomainParticipant construct start");
+
+    for (name, settings) in &self.flow_controllers {
+      if let Some(reason) = settings.invalid_reason() {
+        return Error::bad_parameter(format!("Flow controller {name:?}: {reason}"));
+      }
+    }
+
+    // Discovery join channel is used to just send a join handle into the inner
+    // participant, so its .drop() can wait until discovery has had a chance to
+    // stop.
+    let (djh_sender, djh_receiver) = mio_channel::channel();
+
+    // Channel is used to notify Discovery of (duplicate) SPDP messages from the
+    // wire.
+    let (spdp_liveness_sender, spdp_liveness_receiver) = mio_channel::sync_channel(8);
+
+    // Discovery thread receives and decodes updates from the wire.
+    // It updates data to DiscoveryDB, and sends notifications to dp_event_loop,
+    // which owns the Readers and Writers and notifies them also.
+    let (discovery_updated_sender, discovery_update_notification_receiver) =
+      mio_channel::sync_channel::<DiscoveryNotificationType>(32);
+
+    // This channel is used to:
+    // * local DataReader and DataWriter notify Discovery on drop() so that
+    // Discovery knows we no longer have them.
+    // * Participant commands Discovery to assert liveness, i.e. send liveness
+    // message to remote participants.
+    // * Discovery commands Discovery (thread) to terminate on exit.
+    let (discovery_command_sender, discovery_command_receiver) =
+      mio_channel::sync_channel::<DiscoveryCommand>(64);
+
+    let resolve = |peers: &[InitialPeer]| -> Result<Vec<Locator>> {
+      let mut locators = Vec::new();
+      for peer in peers {
+        locators.extend(peer.to_locators(self.domain_id, &self.port_mapping)?);
+      }
+      locators.retain(|loc| match loc {
+        Locator::UdpV4(_) => self.udp && self.ipv4,
+        Locator::UdpV6(_) => self.udp && self.ipv6,
+        _ => match tcp_locator_address(loc) {
+          Some((address, _)) if self.tcp_listen_port.is_some() => {
+            if address.is_ipv4() {
+              self.ipv4
+            } else {
+              self.ipv6
+            }
+          }
+          _ => false,
+        },
+      });
+      Ok(locators)
+    };
+    let discovery_servers = match &self.discovery_mode {
+      DiscoveryMode::Client { servers } => Some(resolve(servers)?),
+      DiscoveryMode::Simple | DiscoveryMode::Server => None,
+    };
+    let discovery_config = DiscoveryConfig {
+      spdp_announcement_period: self.spdp_announcement_period,
+      participant_lease_duration: self.lease_duration,
+      initial_peers: resolve(&self.initial_peers)?,
+      is_server: self.discovery_mode == DiscoveryMode::Server,
+      discovery_servers: discovery_servers.clone(),
+    };
+
+    // intermediate DP wrapper
+    let dp = DomainParticipantDisc::new(
+      &self,
+      discovery_servers,
+      djh_receiver,
+      discovery_update_notification_receiver,
+      discovery_command_sender,
+      spdp_liveness_sender,
+    )?;
+    let self_locators = dp.self_locators();
+
+    // outer DP wrapper
+    let dp = DomainParticipant {
+      dpi: Arc::new(Mutex::new(dp)),
+    };
+
+    let (discovery_started_sender, discovery_started_receiver) =
+      std::sync::mpsc::channel::<Result<()>>();
+
+    // Construct and start background thread
+    let dp_clone = dp.weak_clone();
+    let disc_db_clone = dp.discovery_db();
+    let discovery_handle = thread::Builder::new()
+      .name("RustDDS discovery thread".to_string())
+      .spawn(move || {
+        if let Ok(mut discovery) = Discovery::new(
+          dp_clone,
+          disc_db_clone,
+          discovery_started_sender,
+          discovery_updated_sender,
+          discovery_command_receiver,
+          spdp_liveness_receiver,
+          self_locators,
+          discovery_config,
+        ) {
+          discovery.discovery_event_loop(); // run the event loop
+        }
+      })?;
+
+    djh_sender.send(discovery_handle).unwrap_or(()); // send join handle to inner participant
+
+    debug!("Waiting for discovery to start"); // blocking until discovery answers
+    match discovery_started_receiver.recv_timeout(Duration::from_secs(10)) {
+      Ok(Ok(())) => {
+        // normal case
+        info!("Discovery started. Participant constructed.");
+        Ok(dp)
+      }
+      Ok(Err(e)) => {
+        std::mem::drop(dp);
+        log_and_err_internal!("Failed to start discovery thread: {e:?}")
+      }
+      Err(e) => log_and_err_internal!("Discovery thread channel error: {e:?}"),
+    }
+  }
+
+  // Listener parameters for each enabled IP version
+  fn ip_versions(&self) -> Vec<IpVersionListeners> {
+    let mut ip_versions = Vec::with_capacity(2);
+    if self.ipv4 {
+      ip_versions.push(IpVersionListeners {
+        bind_host: match self.bind_address {
+          IpAddr::V4(a) => a.to_string(),
+          IpAddr::V6(_) => Ipv4Addr::UNSPECIFIED.to_string(),
+        },
+        any_host: Ipv4Addr::UNSPECIFIED.to_string(),
+        multicast_group: IpAddr::V4(Self::MULTICAST_GROUP_IPV4),
+        discovery_token: DISCOVERY_LISTENER_TOKEN,
+        discovery_multicast_token: DISCOVERY_MUL_LISTENER_TOKEN,
+        user_traffic_token: USER_TRAFFIC_LISTENER_TOKEN,
+        user_traffic_multicast_token: USER_TRAFFIC_MUL_LISTENER_TOKEN,
+      });
+    }
+    if self.ipv6 {
+      ip_versions.push(IpVersionListeners {
+        bind_host: match self.bind_address {
+          IpAddr::V6(a) => a.to_string(),
+          IpAddr::V4(_) => Ipv6Addr::UNSPECIFIED.to_string(),
+        },
+        any_host: Ipv6Addr::UNSPECIFIED.to_string(),
+        multicast_group: IpAddr::V6(self.ipv6_multicast_group),
+        discovery_token: DISCOVERY_LISTENER_V6_TOKEN,
+        discovery_multicast_token: DISCOVERY_MUL_LISTENER_V6_TOKEN,
+        user_traffic_token: USER_TRAFFIC_LISTENER_V6_TOKEN,
+        user_traffic_multicast_token: USER_TRAFFIC_MUL_LISTENER_V6_TOKEN,
+      });
+    }
+    ip_versions
+  }
+
+  // Configured fragment size, or one derived from the MTU of the interfaces.
+  fn fragment_size_for(&self, interfaces: &[IpAddr]) -> usize {
+    let size = match self.max_fragment_size {
+      Some(size) => size,
+      None if !self.udp => MAX_FRAGMENT_SIZE,
+      None => {
+        let mtu = interfaces
+          .iter()
+          .map(|ip| get_interface_mtu(ip).unwrap_or(Self::DEFAULT_MTU))
+          .min()
+          .unwrap_or(Self::DEFAULT_MTU);
+        mtu.saturating_sub(Self::DATAGRAM_OVERHEAD)
+      }
+    };
+    let clamped = size.clamp(MIN_FRAGMENT_SIZE, MAX_FRAGMENT_SIZE);
+    if clamped != size {
+      warn!("Fragment size {size} is out of range. Using {clamped}.");
+    }
+    clamped
+  }
+
+  // Addresses on which we join multicast groups. None means all interfaces.
+  fn interface_addresses(&self) -> Option<Vec<IpAddr>> {
+    match &self.announce_addresses {
+      Some(addrs) => Some(addrs.clone()),
+      None if !self.bind_address.is_unspecified() => Some(vec![self.bind_address]),
+      None => None,
+    }
+  }
+}
+
+// Listening addresses and poll tokens of one IP version
+struct IpVersionListeners {
+  bind_host: String, // unicast
+  any_host: String,  // multicast
+  multicast_group: IpAddr,
+  discovery_token: Token,
+  discovery_multicast_token: Token,
+  user_traffic_token: Token,
+  user_traffic_multicast_token: Token,
+}
+
+// IPv6 listeners are announced together with the corresponding IPv4 listeners,
+// so the rest of the participant only needs to know about the IPv4 tokens.
+fn announced_listener_token(token: Token) -> Token {
+  match token {
+    DISCOVERY_LISTENER_V6_TOKEN => DISCOVERY_LISTENER_TOKEN,
+    DISCOVERY_MUL_LISTENER_V6_TOKEN => DISCOVERY_MUL_LISTENER_TOKEN,
+    USER_TRAFFIC_LISTENER_V6_TOKEN => USER_TRAFFIC_LISTENER_TOKEN,
+    USER_TRAFFIC_MUL_LISTENER_V6_TOKEN => USER_TRAFFIC_MUL_LISTENER_TOKEN,
+    other => other,
+  }
+}
+
 impl PartialEq for DomainParticipant {
   fn eq(&self, other: &Self) -> bool {
     self.guid() == other.guid()
@@ -425,14 +1055,16 @@ pub(crate) struct DomainParticipantDisc {
 
 impl DomainParticipantDisc {
   pub fn new(
-    domain_id: u16,
+    config: &DomainParticipantBuilder,
+    discovery_servers: Option<Vec<Locator>>,
     discovery_join_handle: mio_channel::Receiver<JoinHandle<()>>,
     discovery_update_notification_receiver: mio_channel::Receiver<DiscoveryNotificationType>,
     discovery_command_sender: mio_channel::SyncSender<DiscoveryCommand>,
     spdp_liveness_sender: mio_channel::SyncSender<GuidPrefix>,
   ) -> Result<Self> {
     let dpi = DomainParticipantInner::new(
-      domain_id,
+      config,
+      discovery_servers,
       discovery_update_notification_receiver,
       spdp_liveness_sender,
     )?;
@@ -512,10 +1144,32 @@ impl DomainParticipantDisc {
     self.dpi.lock().unwrap().participant_id()
   }
 
+  pub fn entity_name(&self) -> Option<String> {
+    self.dpi.lock().unwrap().entity_name.clone()
+  }
+
+  pub fn max_fragment_size(&self) -> usize {
+    self.dpi.lock().unwrap().max_fragment_size
+  }
+
+  pub fn has_flow_controller(&self, name: &str) -> bool {
+    self
+      .dpi
+      .lock()
+      .unwrap()
+      .flow_controller_names
+      .iter()
+      .any(|n| n == name)
+  }
+
   pub fn discovered_topics(&self) -> Vec<DiscoveredTopicData> {
     self.dpi.lock().unwrap().discovered_topics()
   }
 
+  pub fn discovered_participants(&self) -> Vec<SpdpDiscoveredParticipantData> {
+    self.dpi.lock().unwrap().discovered_participants()
+  }
+
   pub(crate) fn dds_cache(&self) -> Arc<RwLock<DDSCache>> {
     self.dpi.lock().unwrap().dds_cache()
   }
@@ -588,6 +1242,15 @@ pub(crate) struct DomainParticipantInner {
 
   // RTPS locators describing how to reach this DP
   self_locators: HashMap<Token, Vec<Locator>>,
+
+  // Name announced in SPDP
+  entity_name: Option<String>,
+
+  // Default for DataWriters and DataReaders
+  max_fragment_size: usize,
+
+  // Flow controllers defined in the event loop
+  flow_controller_names: Vec<String>,
 }
 
 impl Drop for DomainParticipantInner {
@@ -616,94 +1279,322 @@ impl Drop for DomainParticipantInner {
 #[allow(clippy::new_without_default)]
 impl DomainParticipantInner {
   fn new(
-    domain_id: u16,
+    config: &DomainParticipantBuilder,
+    discovery_servers: Option<Vec<Locator>>,
     discovery_update_notification_receiver: mio_channel::Receiver<DiscoveryNotificationType>,
     spdp_liveness_sender: mio_channel::SyncSender<GuidPrefix>,
   ) -> Result<Self> {
-    let mut listeners = HashMap::new();
-
-    match UDPListener::new_multicast(
-      "0.0.0.0",
-      spdp_well_known_multicast_port(domain_id),
-      Ipv4Addr::new(239, 255, 0, 1),
-    ) {
-      Ok(l) => {
-        listeners.insert(DISCOVERY_MUL_LISTENER_TOKEN, l);
+    let domain_id = config.domain_id;
+    let ports = config.port_mapping;
+    let overflow = |what: String| -> Error {
+      Error::BadParameter {
+        reason: format!("{what} port number of domain {domain_id} overflows with {ports:?}"),
+      }
+    };
+    let spdp_multicast_port = ports
+      .spdp_well_known_multicast_port(domain_id)
+      .ok_or_else(|| overflow("SPDP multicast".to_string()))?;
+    let user_traffic_multicast_port = ports
+      .user_traffic_multicast_port(domain_id)
+      .ok_or_else(|| overflow("User traffic multicast".to_string()))?;
+    // Metatraffic and user traffic unicast ports of a participant id
+    let unicast_ports = |participant_id| -> Result<(u16, u16)> {
+      match (
+        ports.spdp_well_known_unicast_port(domain_id, participant_id),
+        ports.user_traffic_unicast_port(domain_id, participant_id),
+      ) {
+        (Some(metatraffic_port), Some(user_traffic_port)) => {
+          Ok((metatraffic_port, user_traffic_port))
+        }
+        _ => Err(overflow(format!("ParticipantId {participant_id} unicast"))),
       }
-      Err(e) => warn!("Cannot get multicast discovery listener: {e:?}"),
+    };
+    let ip_versions = config.ip_versions();
+    if ip_versions.is_empty() {
+      return log_and_err_internal!("Both IPv4 and IPv6 are disabled");
     }
+    if !config.bind_address.is_unspecified()
+      && (config.bind_address.is_ipv4() && !config.ipv4
+        || config.bind_address.is_ipv6() && !config.ipv6)
+    {
+      return log_and_err_internal!(
+        "Cannot bind to {}: IP version is disabled",
+        config.bind_address
+      );
+    }
+    if !config.bind_address.is_unspecified() {
+      let name = get_interface_name(&config.bind_address).unwrap_or_default();
+      if !config.interface_filter.permits(&name, &config.bind_address) {
+        return log_and_err_internal!(
+          "Cannot bind to {}: interface is not allowed",
+          config.bind_address
+        );
+      }
+    }
+    let filter = &config.interface_filter;
+    let interfaces = match config.interface_addresses() {
+      Some(addrs) => addrs,
+      None if config.udp => get_local_multicast_ip_addrs(filter)?,
+      None => Vec::new(),
+    };
+    if config.udp && config.multicast && interfaces.is_empty() {
+      warn!("No network interfaces for multicast");
+    }
+    let max_fragment_size = config.fragment_size_for(&interfaces);
+    debug!("Fragment size {max_fragment_size}");
 
-    let mut participant_id = 0;
-
-    let mut discovery_listener = None;
+    let mut listeners = HashMap::new();
 
-    // Magic value 120 below is from RTPS spec 2.5 Section "9.6.2.3 Default Port
-    // Numbers"
-    while discovery_listener.is_none() && participant_id < 120 {
-      discovery_listener = UDPListener::new_unicast(
-        "0.0.0.0",
-        spdp_well_known_unicast_port(domain_id, participant_id),
-      )
-      .ok();
-      if discovery_listener.is_none() {
-        participant_id += 1;
+    if config.udp && config.multicast {
+      for ipv in &ip_versions {
+        match UDPListener::new_multicast_on_interfaces(
+          &ipv.any_host,
+          spdp_multicast_port,
+          ipv.multicast_group,
+          &interfaces,
+        ) {
+          Ok(l) => {
+            listeners.insert(ipv.discovery_multicast_token, l);
+          }
+          Err(e) => warn!(
+            "Cannot get multicast discovery listener {:?}: {e:?}",
+            ipv.multicast_group
+          ),
+        }
       }
     }
 
-    info!("ParticipantId {} selected.", participant_id);
+    // Either try the one participant id we were given, or probe for a free one.
+    // The id must be free for all enabled IP versions.
+    // Probing stops at the first id whose ports would overflow.
+    unicast_ports(config.participant_id.unwrap_or(0))?;
+    let participant_ids: Vec<u16> = match config.participant_id {
+      Some(id) => vec![id],
+      None => (0..DomainParticipantBuilder::MAX_PARTICIPANT_ID)
+        .take_while(|id| unicast_ports(*id).is_ok())
+        .collect(),
+    };
+    let mut discovery_listeners = None;
+    if !config.udp {
+      discovery_listeners = Some((config.participant_id.unwrap_or(0), Vec::new()));
+    }
+    for participant_id in participant_ids.into_iter().filter(|_| config.udp) {
+      let (port, _) = unicast_ports(participant_id)?;
+      let ls: std::io::Result<Vec<_>> = ip_versions
+        .iter()
+        .map(|ipv| UDPListener::new_unicast(&ipv.bind_host, port).map(|l| (ipv.discovery_token, l)))
+        .collect();
+      if let Ok(ls) = ls {
+        discovery_listeners = Some((participant_id, ls));
+        break;
+      }
+    }
 
-    // here discovery_listener is redefined (shadowed)
-    let discovery_listener = match discovery_listener {
-      Some(dl) => dl,
-      None => return log_and_err_internal!("Could not find free ParticipantId"),
+    let (participant_id, discovery_listeners) = match discovery_listeners {
+      Some(pl) => pl,
+      None => match config.participant_id {
+        Some(id) => return log_and_err_internal!("ParticipantId {id} is not available"),
+        None => return log_and_err_internal!("Could not find free ParticipantId"),
+      },
     };
-    listeners.insert(DISCOVERY_LISTENER_TOKEN, discovery_listener);
+    info!("ParticipantId {} selected.", participant_id);
+    listeners.extend(discovery_listeners);
+    let (metatraffic_port, user_traffic_port) = unicast_ports(participant_id)?;
 
     // Now the user traffic listeners
 
-    match UDPListener::new_multicast(
-      "0.0.0.0",
-      user_traffic_multicast_port(domain_id),
-      Ipv4Addr::new(239, 255, 0, 1),
-    ) {
-      Ok(l) => {
-        listeners.insert(USER_TRAFFIC_MUL_LISTENER_TOKEN, l);
+    for ipv in ip_versions.iter().filter(|_| config.udp) {
+      if config.multicast {
+        match UDPListener::new_multicast_on_interfaces(
+          &ipv.any_host,
+          user_traffic_multicast_port,
+          ipv.multicast_group,
+          &interfaces,
+        ) {
+          Ok(l) => {
+            listeners.insert(ipv.user_traffic_multicast_token, l);
+          }
+          Err(e) => warn!(
+            "Cannot get multicast user traffic listener {:?}: {e:?}",
+            ipv.multicast_group
+          ),
+        }
       }
-      Err(e) => warn!("Cannot get multicast user traffic listener: {e:?}"),
+
+      let user_traffic_listener = UDPListener::new_unicast(&ipv.bind_host, user_traffic_port)
+        .or_else(|e| {
+          if matches!(e.kind(), ErrorKind::AddrInUse) {
+            // If we do not get the preferred listening port,
+            // try again, with "any" port number.
+            UDPListener::new_unicast(&ipv.bind_host, 0).or_else(|e| {
+              log_and_err_internal!(
+                "Could not open unicast user traffic listener, any port number: {:?}",
+                e
+              )
+            })
+          } else {
+            log_and_err_internal!("Could not open unicast user traffic listener: {e:?}")
+          }
+        })?;
+
+      listeners.insert(ipv.user_traffic_token, user_traffic_listener);
+    }
+
+    // construct our own Locators
+    let mut udp_locators: HashMap<Token, Vec<Locator>> = HashMap::new();
+    for (t, l) in &listeners {
+      let announced_token = announced_listener_token(*t);
+      let is_ipv6 = announced_token != *t;
+      let locators = match (&config.announce_addresses, l.to_locator_address(filter)) {
+        // Unicast listeners announce only the configured addresses
+        (Some(addrs), Ok(_))
+          if announced_token == DISCOVERY_LISTENER_TOKEN
+            || announced_token == USER_TRAFFIC_LISTENER_TOKEN =>
+        {
+          addrs
+            .iter()
+            .filter(|a| a.is_ipv6() == is_ipv6)
+            .map(|a| Locator::from(SocketAddr::new(*a, l.port())))
+            .collect()
+        }
+        (_, Ok(locs)) => locs,
+        (_, Err(e)) => {
+          error!("No local network address for token {:?}: {:?}", t, e);
+          vec![]
+        }
+      };
+      udp_locators
+        .entry(announced_token)
+        .or_default()
+        .extend(locators);
+    }
+    let mut udp_locators_of = |token| udp_locators.remove(&token).unwrap_or_default();
+    let udp_locators = TransportLocators {
+      metatraffic_unicast: udp_locators_of(DISCOVERY_LISTENER_TOKEN),
+      metatraffic_multicast: udp_locators_of(DISCOVERY_MUL_LISTENER_TOKEN),
+      default_unicast: udp_locators_of(USER_TRAFFIC_LISTENER_TOKEN),
+      default_multicast: udp_locators_of(USER_TRAFFIC_MUL_LISTENER_TOKEN),
+    };
+
+    // Transports in order of preference: user-supplied, shared memory, TCP and
+    // UDP.
+    let context = TransportContext {
+      domain_id,
+      participant_id,
+    };
+    let mut transports: Vec<Box<dyn Transport>> = Vec::new();
+    for factory in &config.transports {
+      let transport = (factory.0)(&context)
+        .or_else(|e| log_and_err_internal!("Could not create transport: {e:?}"))?;
+      transports.push(transport);
     }
 
-    let user_traffic_listener = UDPListener::new_unicast(
-      "0.0.0.0",
-      user_traffic_unicast_port(domain_id, participant_id),
-    )
-    .or_else(|e| {
-      if matches!(e.kind(), ErrorKind::AddrInUse) {
-        // If we do not get the preferred listening port,
-        // try again, with "any" port number.
-        UDPListener::new_unicast("0.0.0.0", 0).or_else(|e| {
-          log_and_err_internal!(
-            "Could not open unicast user traffic listener, any port number: {:?}",
-            e
-          )
-        })
-      } else {
-        log_and_err_internal!("Could not open unicast user traffic listener: {e:?}")
+    // Shared memory segment for participants on the same host
+    #[cfg(target_os = "linux")]
+    if config.shared_memory {
+      match ShmTransport::new(config.shared_memory_segment_size) {
+        Ok(shm_transport) => transports.push(Box::new(shm_transport)),
+        Err(e) => warn!("Shared memory transport not available: {e:?}"),
       }
-    })?;
+    }
 
-    listeners.insert(USER_TRAFFIC_LISTENER_TOKEN, user_traffic_listener);
+    // RTPS over TCP. The logical ports are the UDP port numbers of the same
+    // traffic, and the SPDP multicast port, because TCP initial peers do not
+    // know our participant id.
+    if let Some(port) = config.tcp_listen_port {
+      let ip = match config.bind_address {
+        IpAddr::V4(_) if !config.ipv4 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        ip => ip,
+      };
+      let announced_ips: Vec<IpAddr> = match &config.announce_addresses {
+        Some(addrs) => addrs
+          .iter()
+          .filter(|a| a.is_ipv6() == ip.is_ipv6())
+          .copied()
+          .collect(),
+        None if !ip.is_unspecified() => vec![ip],
+        None => get_local_unicast_locators(0, ip.is_ipv6(), filter)
+          .into_iter()
+          .map(|l| SocketAddr::from(l).ip())
+          .collect(),
+      };
+      let (tcp_sender, tcp_receiver) = TCPSender::start(
+        SocketAddr::new(ip, port),
+        &announced_ips,
+        vec![spdp_multicast_port, metatraffic_port, user_traffic_port],
+      )
+      .or_else(|e| log_and_err_internal!("Could not start TCP transport: {e:?}"))?;
+      let tcp_locators = |logical_port| -> Vec<Locator> {
+        announced_ips
+          .iter()
+          .map(|ip| tcp_locator(SocketAddr::new(*ip, tcp_sender.listen_port()), logical_port))
+          .collect()
+      };
+      let locators = TransportLocators {
+        metatraffic_unicast: tcp_locators(metatraffic_port),
+        default_unicast: tcp_locators(user_traffic_port),
+        ..TransportLocators::default()
+      };
+      transports.push(Box::new(TCPTransport::new(
+        tcp_sender,
+        tcp_receiver,
+        locators,
+      )));
+    }
 
-    // construct our own Locators
-    let self_locators: HashMap<Token, Vec<Locator>> = listeners
-      .iter()
-      .map(|(t, l)| match l.to_locator_address() {
-        Ok(locs) => (*t, locs),
-        Err(e) => {
-          error!("No local network address for token {:?}: {:?}", t, e);
-          (*t, vec![])
+    if config.udp {
+      // port number 0 means OS chooses an available port number.
+      #[allow(unused_mut)] // only Linux has batched UDP I/O
+      let mut udp_sender = UDPSender::new_on_interfaces(0, &interfaces, config.ipv4, config.ipv6)?;
+      #[cfg(target_os = "linux")]
+      if config.batched_udp_io {
+        for listener in listeners.values_mut() {
+          listener.enable_batched_receive();
         }
-      })
-      .collect();
+        udp_sender.enable_batched_send();
+      }
+      transports.push(Box::new(UDPTransport::new(
+        listeners,
+        udp_sender,
+        udp_locators,
+      )));
+    }
+
+    if transports.is_empty() {
+      return log_and_err_internal!("No transports enabled");
+    }
+    if transports.len() > Transports::MAX_TRANSPORTS {
+      return log_and_err_internal!(
+        "Too many transports: {}, maximum is {}",
+        transports.len(),
+        Transports::MAX_TRANSPORTS
+      );
+    }
+    let mut announced_locators = TransportLocators::default();
+    for transport in &transports {
+      announced_locators.extend(transport.announced_locators());
+    }
+    let self_locators: HashMap<Token, Vec<Locator>> = [
+      (
+        DISCOVERY_LISTENER_TOKEN,
+        announced_locators.metatraffic_unicast,
+      ),
+      (
+        DISCOVERY_MUL_LISTENER_TOKEN,
+        announced_locators.metatraffic_multicast,
+      ),
+      (
+        USER_TRAFFIC_LISTENER_TOKEN,
+        announced_locators.default_unicast,
+      ),
+      (
+        USER_TRAFFIC_MUL_LISTENER_TOKEN,
+        announced_locators.default_multicast,
+      ),
+    ]
+    .into_iter()
+    .filter(|(_, locators)| !locators.is_empty())
+    .collect();
 
     // Adding readers
     let (sender_add_reader, receiver_add_reader) =
@@ -715,11 +1606,15 @@ impl DomainParticipantInner {
       mio_channel::sync_channel::<WriterIngredients>(10);
     let (remove_writer_sender, remove_writer_receiver) = mio_channel::sync_channel::<GUID>(10);
 
-    let new_guid = GUID::new_participant_guid();
+    let new_guid = match config.guid_prefix {
+      Some(prefix) => GUID::new(prefix, EntityId::PARTICIPANT),
+      None => GUID::new_participant_guid(),
+    };
     let domain_info = DomainInfo {
       domain_participant_guid: new_guid,
       domain_id,
       participant_id,
+      discovery_servers,
     };
 
     let dds_cache = Arc::new(RwLock::new(DDSCache::new()));
@@ -733,6 +1628,9 @@ impl DomainParticipantInner {
 
     let (stop_poll_sender, stop_poll_receiver) = mio_channel::channel::<()>();
 
+    let flow_controllers = config.flow_controllers.clone();
+    let event_loop_threads = config.event_loop_threads;
+
     // Launch the background thread for DomainParticipant
     let dds_cache_clone = dds_cache.clone();
     let disc_db_clone = discovery_db.clone();
@@ -741,7 +1639,8 @@ impl DomainParticipantInner {
       .spawn(move || {
         let dp_event_loop = DPEventLoop::new(
           domain_info,
-          listeners,
+          Transports::new(transports),
+          &flow_controllers,
           dds_cache_clone,
           disc_db_clone,
           new_guid.prefix,
@@ -764,6 +1663,7 @@ impl DomainParticipantInner {
           stop_poll_receiver,
           discovery_update_notification_receiver,
           spdp_liveness_sender,
+          event_loop_threads,
         );
         dp_event_loop.event_loop();
       })?;
@@ -786,6 +1686,13 @@ impl DomainParticipantInner {
       discovery_db,
       discovery_db_event_receiver,
       self_locators,
+      entity_name: config.entity_name.clone(),
+      max_fragment_size,
+      flow_controller_names: config
+        .flow_controllers
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect(),
     })
   }
 
@@ -874,36 +1781,21 @@ impl DomainParticipantInner {
     name: &str,
     timeout: Duration,
   ) -> Result<Option<Topic>> {
-    use mio_06 as mio;
-
-    let poll = mio::Poll::new()?;
-    let mut events = mio::Events::with_capacity(1);
-    // Should be register before the check and use level trigger to avoid missing
-    // event
-    poll.register(
-      &self.discovery_db_event_receiver,
-      mio::Token(0),
-      mio::Ready::readable(),
-      mio::PollOpt::level(),
-    )?;
-
     let find_end = Instant::now() + timeout;
     loop {
+      // Events from before the check are not interesting.
+      while self.discovery_db_event_receiver.try_recv().is_ok() {}
       if let Some(topic) = self.find_topic_in_discovery_db(domain_participant_weak, name)? {
         return Ok(Some(topic));
       }
-      let timeout = find_end - Instant::now();
-      poll.poll(&mut events, Some(timeout))?;
-
-      if let Some(_event) = events.iter().next() {
-        if self.discovery_db_event_receiver.try_recv().is_ok() {
-          continue;
-        }
-      }
-
-      if Instant::now() > find_end {
+      let timeout = find_end.saturating_duration_since(Instant::now());
+      if timeout.is_zero() {
         break;
       }
+      match self.discovery_db_event_receiver.recv_timeout(timeout) {
+        Ok(()) | Err(mio_channel::RecvTimeoutError::Timeout) => (),
+        Err(mio_channel::RecvTimeoutError::Disconnected) => break,
+      }
     }
 
     Ok(None)
@@ -976,6 +1868,15 @@ impl DomainParticipantInner {
 
     db.all_user_topics().cloned().collect()
   }
+
+  pub fn discovered_participants(&self) -> Vec<SpdpDiscoveredParticipantData> {
+    let db = self
+      .discovery_db
+      .read()
+      .unwrap_or_else(|e| panic!("DiscoveryDB is poisoned. {e:?}"));
+
+    db.all_participants().cloned().collect()
+  }
 } // impl
 
 impl RTPSEntity for DomainParticipant {
@@ -1008,7 +1909,8 @@ impl std::fmt::Debug for DomainParticipant {
 mod tests {
   use std::{
     collections::BTreeSet,
-    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
   };
 
   use enumflags2::BitFlags;
@@ -1017,7 +1919,8 @@ mod tests {
   use byteorder::LittleEndian;
 
   use crate::{
-    dds::{qos::QosPolicies, topic::TopicKind},
+    dds::{qos::QosPolicies, result::Error, topic::TopicKind},
+    discovery::InitialPeer,
     messages::{
       header::Header,
       protocol_id::ProtocolId,
@@ -1025,17 +1928,257 @@ mod tests {
       submessages::submessages::{AckNack, SubmessageHeader, SubmessageKind, *},
       vendor_id::VendorId,
     },
-    network::{constant::user_traffic_unicast_port, udp_sender::UDPSender},
+    network::{
+      constant::{
+        PortMapping, DISCOVERY_LISTENER_TOKEN, DISCOVERY_MUL_LISTENER_TOKEN,
+        USER_TRAFFIC_LISTENER_TOKEN, USER_TRAFFIC_MUL_LISTENER_TOKEN,
+      },
+      interface_filter::{InterfaceFilter, InterfaceSelector},
+      udp_sender::UDPSender,
+      util::get_local_unicast_locators,
+    },
     rtps::{submessage::*, Message, Submessage},
     serialization::cdr_serializer::CDRSerializerAdapter,
     structure::{
-      guid::{EntityId, GUID},
+      entity::RTPSEntity,
+      guid::{EntityId, GuidPrefix, GUID},
       locator::Locator,
       sequence_number::{SequenceNumber, SequenceNumberSet},
     },
     test::random_data::RandomData,
   };
-  use super::DomainParticipant;
+  use super::{DomainParticipant, DomainParticipantBuilder};
+
+  #[test]
+  fn dp_builder_settings() {
+    let port_mapping = PortMapping {
+      pb: 17400,
+      ..PortMapping::default()
+    };
+    let guid_prefix = GuidPrefix::new(b"BuilderTest1");
+    let localhost = Ipv4Addr::LOCALHOST;
+
+    let dp = DomainParticipantBuilder::new(41)
+      .participant_id(3)
+      .guid_prefix(guid_prefix)
+      .bind_address(localhost.into())
+      .multicast(false)
+      .shared_memory(false)
+      .port_mapping(port_mapping)
+      .entity_name("builder_test")
+      .build()
+      .expect("Failed to create participant");
+
+    assert_eq!(dp.domain_id(), 41);
+    assert_eq!(dp.participant_id(), 3);
+    assert_eq!(dp.guid(), GUID::new(guid_prefix, EntityId::PARTICIPANT));
+    assert_eq!(dp.entity_name(), Some("builder_test".to_string()));
+
+    let locators = dp.self_locators();
+    assert_eq!(
+      locators[&DISCOVERY_LISTENER_TOKEN],
+      vec![Locator::from(SocketAddr::new(
+        localhost.into(),
+        port_mapping.spdp_well_known_unicast_port(41, 3).unwrap()
+      ))]
+    );
+    assert_eq!(
+      locators[&USER_TRAFFIC_LISTENER_TOKEN],
+      vec![Locator::from(SocketAddr::new(
+        localhost.into(),
+        port_mapping.user_traffic_unicast_port(41, 3).unwrap()
+      ))]
+    );
+    assert!(!locators.contains_key(&DISCOVERY_MUL_LISTENER_TOKEN));
+    assert!(!locators.contains_key(&USER_TRAFFIC_MUL_LISTENER_TOKEN));
+  }
+
+  #[test]
+  fn dp_builder_port_overflow() {
+    let build_error = |builder: DomainParticipantBuilder| match builder.multicast(false).build() {
+      Err(Error::BadParameter { .. }) => {}
+      Err(e) => panic!("Unexpected error {e:?}"),
+      Ok(_) => panic!("Participant with overflowing ports was built"),
+    };
+    // 7400 + 250 * 233 does not fit in u16.
+    build_error(DomainParticipantBuilder::new(233));
+    // 7400 + 250 * 200 + 11 + 2 * 5000 does not fit either.
+    build_error(DomainParticipantBuilder::new(200).participant_id(5000));
+    build_error(DomainParticipantBuilder::new(0).initial_peers(vec![
+      InitialPeer::with_participant_ids("127.0.0.1", 0..=60000),
+    ]));
+    build_error(DomainParticipantBuilder::new(0).port_mapping(PortMapping {
+      pb: 65530,
+      ..PortMapping::default()
+    }));
+  }
+
+  #[test]
+  fn dp_builder_unicast_discovery() {
+    // Discovery over unicast only: a knows b from initial peers, b must learn a
+    // from the SPDP data it receives.
+    let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let builder = |participant_id| {
+      DomainParticipantBuilder::new(43)
+        .participant_id(participant_id)
+        .bind_address(localhost)
+        .multicast(false)
+        .spdp_announcement_period(Duration::from_millis(100))
+    };
+    let dp_b = builder(1).build().expect("Failed to create participant");
+    let dp_a = builder(0)
+      .initial_peers(vec!["127.0.0.1[1-1]".parse().unwrap()])
+      .build()
+      .expect("Failed to create participant");
+
+    let knows = |dp: &DomainParticipant, other: &DomainParticipant| {
+      dp.discovery_db()
+        .read()
+        .unwrap()
+        .find_participant_proxy(other.guid().prefix)
+        .is_some()
+    };
+    let mut discovered = false;
+    for _ in 0..50 {
+      if knows(&dp_a, &dp_b) && knows(&dp_b, &dp_a) {
+        discovered = true;
+        break;
+      }
+      std::thread::sleep(Duration::from_millis(100));
+    }
+    assert!(discovered);
+  }
+
+  #[test]
+  fn dp_builder_initial_peers_with_multicast() {
+    // a uses multicast, b does not. b can learn a only from SPDP data that a
+    // sends to its initial peers by unicast.
+    let dp_b = DomainParticipantBuilder::new(44)
+      .participant_id(1)
+      .bind_address(IpAddr::V4(Ipv4Addr::LOCALHOST))
+      .multicast(false)
+      .spdp_announcement_period(Duration::from_millis(100))
+      .build()
+      .expect("Failed to create participant");
+    let dp_a = DomainParticipantBuilder::new(44)
+      .participant_id(0)
+      .multicast(true)
+      .initial_peers(vec!["127.0.0.1[1-1]".parse().unwrap()])
+      .spdp_announcement_period(Duration::from_millis(100))
+      .build()
+      .expect("Failed to create participant");
+    assert!(dp_a
+      .self_locators()
+      .contains_key(&DISCOVERY_MUL_LISTENER_TOKEN));
+
+    let mut discovered = false;
+    for _ in 0..50 {
+      if dp_b
+        .discovery_db()
+        .read()
+        .unwrap()
+        .find_participant_proxy(dp_a.guid().prefix)
+        .is_some()
+      {
+        discovered = true;
+        break;
+      }
+      std::thread::sleep(Duration::from_millis(100));
+    }
+    assert!(discovered);
+  }
+
+  #[test]
+  fn dp_builder_fragment_size() {
+    let builder = DomainParticipantBuilder::new(55);
+    assert_eq!(builder.clone().fragment_size_for(&[]), 1300);
+    assert_eq!(builder.clone().udp(false).fragment_size_for(&[]), 64_000);
+    assert_eq!(
+      builder
+        .clone()
+        .max_fragment_size(100)
+        .fragment_size_for(&[]),
+      256
+    );
+    assert_eq!(builder.max_fragment_size(4000).fragment_size_for(&[]), 4000);
+  }
+
+  #[test]
+  fn dp_builder_ip_version_errors() {
+    assert!(DomainParticipantBuilder::new(49)
+      .ipv4(false)
+      .build()
+      .is_err());
+    assert!(DomainParticipantBuilder::new(49)
+      .bind_address(IpAddr::V6(Ipv6Addr::LOCALHOST))
+      .build()
+      .is_err());
+  }
+
+  #[test]
+  fn dp_interface_filter() {
+    let builder = || {
+      DomainParticipantBuilder::new(60)
+        .shared_memory(false)
+        .spdp_announcement_period(Duration::from_millis(100))
+    };
+    let udp_addresses = |dp: &DomainParticipant, token| -> Vec<IpAddr> {
+      dp.self_locators()
+        .get(&token)
+        .into_iter()
+        .flatten()
+        .map(|l| SocketAddr::from(*l).ip())
+        .collect()
+    };
+    let local_addresses: Vec<IpAddr> =
+      get_local_unicast_locators(0, false, &InterfaceFilter::default())
+        .into_iter()
+        .map(|l| SocketAddr::from(l).ip())
+        .collect();
+
+    // Nothing allowed: no unicast addresses to announce, but the multicast
+    // group is announced.
+    let dp = builder()
+      .deny_interfaces(vec!["0.0.0.0/0".parse().unwrap()])
+      .build()
+      .expect("Failed to create participant");
+    assert!(udp_addresses(&dp, DISCOVERY_LISTENER_TOKEN).is_empty());
+    assert!(udp_addresses(&dp, USER_TRAFFIC_LISTENER_TOKEN).is_empty());
+    assert_eq!(
+      udp_addresses(&dp, DISCOVERY_MUL_LISTENER_TOKEN),
+      vec![IpAddr::V4(Ipv4Addr::new(239, 255, 0, 1))]
+    );
+    drop(dp);
+
+    if let Some(address) = local_addresses.first() {
+      let dp = builder()
+        .allow_interfaces(vec![InterfaceSelector::address(*address)])
+        .build()
+        .expect("Failed to create participant");
+      for token in [DISCOVERY_LISTENER_TOKEN, USER_TRAFFIC_LISTENER_TOKEN] {
+        assert_eq!(udp_addresses(&dp, token), vec![*address]);
+      }
+    }
+
+    // The bind address must be allowed.
+    assert!(builder()
+      .bind_address(IpAddr::V4(Ipv4Addr::LOCALHOST))
+      .deny_interfaces(vec!["127.0.0.0/8".parse().unwrap()])
+      .build()
+      .is_err());
+  }
+
+  #[test]
+  fn dp_builder_participant_id_in_use() {
+    let builder = DomainParticipantBuilder::new(42)
+      .participant_id(5)
+      .multicast(false);
+    let _dp = builder
+      .clone()
+      .build()
+      .expect("Failed to create participant");
+    assert!(builder.build().is_err());
+  }
 
   // TODO: improve basic test when more or the structure is known
   #[test]
@@ -1098,7 +2241,9 @@ mod tests {
       .create_datawriter::<RandomData, CDRSerializerAdapter<RandomData, LittleEndian>>(&topic, None)
       .expect("Failed to create datawriter");
 
-    let port_number: u16 = user_traffic_unicast_port(5, 0);
+    let port_number: u16 = PortMapping::default()
+      .user_traffic_unicast_port(5, 0)
+      .unwrap();
     let sender = UDPSender::new(1234).unwrap();
     let mut m: Message = Message::default();
 
@@ -1,11 +1,14 @@
 use std::{
+  collections::BTreeMap,
   fmt::Debug,
-  sync::{Arc, Mutex, MutexGuard, RwLock},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex, MutexGuard, RwLock,
+  },
   time::Duration,
 };
 
 use serde::{Deserialize, Serialize};
-use mio_extras::channel as mio_channel;
 use byteorder::LittleEndian;
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
@@ -32,9 +35,10 @@ use crate::{
     discovery::DiscoveryCommand, discovery_db::DiscoveryDB, sedp_messages::DiscoveredWriterData,
   },
   log_and_err_internal, log_and_err_precondition_not_met, mio_source,
+  mio_source::channel as mio_channel,
   rtps::{
     reader::ReaderIngredients,
-    writer::{WriterCommand, WriterIngredients},
+    writer::{max_fragment_size, WriterCommand, WriterIngredients},
   },
   serialization::{cdr_deserializer::CDRDeserializerAdapter, cdr_serializer::CDRSerializerAdapter},
   structure::{
@@ -296,19 +300,45 @@ impl Publisher {
   // lookup datawriter: maybe not necessary? App should remember datawriters it
   // has created.
 
-  // Suspend and resume publications are preformance optimization methods.
-  // The minimal correct implementation is to do nothing. See DDS spec 2.2.2.4.1.8
-  // and .9
-  /// **NOT IMPLEMENTED. DO NOT USE**
-  #[deprecated(note = "unimplemented")]
+  /// Suspend sending of data from all DataWriters of this Publisher.
+  ///
+  /// This is a performance optimization for writing many samples in one go.
+  /// Samples written while suspended are stored to the history caches of the
+  /// writers, but sending them is deferred until
+  /// [`resume_publications`](Publisher::resume_publications). Then they are
+  /// sent so that several samples are packed into each UDP datagram, instead
+  /// of one datagram per sample.
+  ///
+  /// Suspending an already suspended Publisher does nothing. Heartbeats sent
+  /// while suspended announce only the samples written before suspension.
+  ///
+  /// See DDS spec 2.2.2.4.1.8.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # use rustdds::*;
+  /// # let domain_participant = DomainParticipant::new(0).unwrap();
+  /// # let qos = QosPolicyBuilder::new().build();
+  /// let publisher = domain_participant.create_publisher(&qos).unwrap();
+  ///
+  /// publisher.suspend_publications().unwrap();
+  /// // write samples with several DataWriters ...
+  /// publisher.resume_publications().unwrap();
+  /// ```
   pub fn suspend_publications(&self) -> Result<()> {
-    unimplemented!();
+    self.inner_lock().suspend_publications()
   }
 
-  /// **NOT IMPLEMENTED. DO NOT USE**
-  #[deprecated(note = "unimplemented")]
+  /// Resume sending of data suspended by
+  /// [`suspend_publications`](Publisher::suspend_publications). All samples
+  /// written while suspended are sent out.
+  ///
+  /// Returns `PreconditionNotMet` if publications were not suspended.
+  ///
+  /// See DDS spec 2.2.2.4.1.9.
   pub fn resume_publications(&self) -> Result<()> {
-    unimplemented!();
+    self.inner_lock().resume_publications()
   }
 
   // coherent change set
@@ -430,6 +460,10 @@ struct InnerPublisher {
   add_writer_sender: mio_channel::SyncSender<WriterIngredients>,
   remove_writer_sender: mio_channel::SyncSender<GUID>,
   discovery_command: mio_channel::SyncSender<DiscoveryCommand>,
+  // Shared with our Writers
+  publications_suspended: Arc<AtomicBool>,
+  // Command channels to our Writers, for resume.
+  writer_command_senders: BTreeMap<GUID, mio_channel::SyncSender<WriterCommand>>,
 }
 
 // public interface for Publisher
@@ -457,11 +491,13 @@ impl InnerPublisher {
       add_writer_sender,
       remove_writer_sender,
       discovery_command,
+      publications_suspended: Arc::new(AtomicBool::new(false)),
+      writer_command_senders: BTreeMap::new(),
     }
   }
 
   pub fn create_datawriter<D, SA>(
-    &self,
+    &mut self,
     outer: &Publisher,
     entity_id_opt: Option<EntityId>,
     topic: &Topic,
@@ -497,6 +533,12 @@ impl InnerPublisher {
       .ok_or("upgrade fail")
       .or_else(|e| log_and_err_internal!("Where is my DomainParticipant? {}", e))?;
 
+    if let Some(policy::FlowController { name }) = writer_qos.flow_controller() {
+      if !dp.has_flow_controller(name) {
+        return Error::bad_parameter(format!("Unknown flow controller {name:?}"));
+      }
+    }
+
     // Create a new topic to DDScache if it doesn't exist and get a handle to it
     let topic_cache_handle = match dp.dds_cache().write() {
       Ok(mut dds_cache) => dds_cache.add_new_topic(topic.name(), topic.get_type(), &writer_qos),
@@ -513,12 +555,18 @@ impl InnerPublisher {
       topic_cache_handle,
       qos_policies: writer_qos.clone(),
       status_sender,
+      publications_suspended: Arc::clone(&self.publications_suspended),
+      data_max_size_serialized: max_fragment_size(&writer_qos, dp.max_fragment_size()),
+      max_message_size: dp.max_fragment_size(),
     };
 
     self
       .add_writer_sender
       .send(new_writer)
       .or_else(|e| log_and_err_internal!("Adding a new writer failed: {}", e))?;
+    self
+      .writer_command_senders
+      .insert(guid, dwcc_upload.clone());
 
     let data_writer = WithKeyDataWriter::<D, SA>::new(
       outer.clone(),
@@ -542,7 +590,7 @@ impl InnerPublisher {
   }
 
   pub fn create_datawriter_no_key<D, SA>(
-    &self,
+    &mut self,
     outer: &Publisher,
     entity_id_opt: Option<EntityId>,
     topic: &Topic,
@@ -584,7 +632,31 @@ impl InnerPublisher {
     entity_id_opt.unwrap_or_else(|| self.participant().unwrap().new_entity_id(entity_kind))
   }
 
-  pub(crate) fn remove_writer(&self, guid: GUID) {
+  // Writers check the shared flag, so suspending does not need to wait for
+  // them.
+  fn suspend_publications(&mut self) -> Result<()> {
+    self.publications_suspended.store(true, Ordering::Release);
+    Ok(())
+  }
+
+  fn resume_publications(&mut self) -> Result<()> {
+    if !self.publications_suspended.swap(false, Ordering::AcqRel) {
+      return log_and_err_precondition_not_met!("Publications are not suspended.");
+    }
+    // Wake up the Writers. This must not block, as a Writer that is held back
+    // by its send queue does not take commands. A full command channel is
+    // fine: the Writer sends the suspended samples before the queued ones.
+    for (guid, sender) in &self.writer_command_senders {
+      match sender.try_send(WriterCommand::ResumePublications) {
+        Ok(()) | Err(mio_channel::TrySendError::Full(_)) => (),
+        Err(e) => debug!("Cannot send resume to Writer {:?}: {:?}", guid, e),
+      }
+    }
+    Ok(())
+  }
+
+  pub(crate) fn remove_writer(&mut self, guid: GUID) {
+    self.writer_command_senders.remove(&guid);
     self
       .remove_writer_sender
       .try_send(guid)
@@ -1136,4 +1208,167 @@ impl InnerSubscriber {
 // -------------------------------------------------------------------
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+  use std::{
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+  };
+
+  use serde::{Deserialize, Serialize};
+
+  use crate::{
+    dds::{
+      participant::DomainParticipantBuilder,
+      qos::{policy, QosPolicies, QosPolicyBuilder},
+      result::Error,
+      topic::TopicKind,
+    },
+    serialization::{
+      cdr_deserializer::CDRDeserializerAdapter, cdr_serializer::CDRSerializerAdapter,
+    },
+  };
+  use super::{mio_channel, EntityKind, WriterCommand, GUID};
+
+  #[test]
+  fn publisher_resume_without_suspend() {
+    let dp = DomainParticipantBuilder::new(46)
+      .multicast(false)
+      .build()
+      .unwrap();
+    let publisher = dp.create_publisher(&QosPolicies::qos_none()).unwrap();
+
+    assert!(matches!(
+      publisher.resume_publications(),
+      Err(Error::PreconditionNotMet { .. })
+    ));
+    publisher.suspend_publications().unwrap();
+    publisher.suspend_publications().unwrap();
+    publisher.resume_publications().unwrap();
+    assert!(publisher.resume_publications().is_err());
+  }
+
+  #[test]
+  fn publisher_resume_does_not_block() {
+    let dp = DomainParticipantBuilder::new(46)
+      .multicast(false)
+      .build()
+      .unwrap();
+    let publisher = dp.create_publisher(&QosPolicies::qos_none()).unwrap();
+
+    // A Writer that does not take commands, e.g. because its send queue is
+    // full.
+    let (sender, _receiver) = mio_channel::sync_channel(1);
+    sender.try_send(WriterCommand::ResumePublications).unwrap();
+    publisher.inner_lock().writer_command_senders.insert(
+      GUID::dummy_test_guid(EntityKind::WRITER_NO_KEY_USER_DEFINED),
+      sender,
+    );
+
+    publisher.suspend_publications().unwrap();
+    publisher.resume_publications().unwrap();
+  }
+
+  #[test]
+  fn publisher_suspend_and_resume() {
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Sample {
+      a: i32,
+      padding: Vec<u8>,
+    }
+
+    let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let builder = |participant_id, peer: &str| {
+      DomainParticipantBuilder::new(47)
+        .participant_id(participant_id)
+        .bind_address(localhost)
+        .multicast(false)
+        .initial_peers(vec![peer.parse().unwrap()])
+        .spdp_announcement_period(Duration::from_millis(100))
+    };
+    let dp_w = builder(0, "127.0.0.1[1-1]").build().unwrap();
+    let dp_r = builder(1, "127.0.0.1[0-0]").build().unwrap();
+
+    let qos = QosPolicyBuilder::new()
+      .reliability(policy::Reliability::Reliable {
+        max_blocking_time: Duration::from_millis(100).into(),
+      })
+      .history(policy::History::KeepAll)
+      .build();
+    let topic_w = dp_w
+      .create_topic(
+        "suspend_resume".to_string(),
+        "Sample".to_string(),
+        &qos,
+        TopicKind::NoKey,
+      )
+      .unwrap();
+    let topic_r = dp_r
+      .create_topic(
+        "suspend_resume".to_string(),
+        "Sample".to_string(),
+        &qos,
+        TopicKind::NoKey,
+      )
+      .unwrap();
+    let publisher = dp_w.create_publisher(&qos).unwrap();
+    let writer = publisher
+      .create_datawriter_no_key::<Sample, CDRSerializerAdapter<Sample>>(&topic_w, None)
+      .unwrap();
+    let mut reader = dp_r
+      .create_subscriber(&qos)
+      .unwrap()
+      .create_datareader_no_key::<Sample, CDRDeserializerAdapter<Sample>>(&topic_r, None)
+      .unwrap();
+
+    // wait for matching
+    let mut matched = false;
+    for _ in 0..100 {
+      writer
+        .write(
+          Sample {
+            a: -1,
+            padding: vec![],
+          },
+          None,
+        )
+        .unwrap();
+      std::thread::sleep(Duration::from_millis(100));
+      if reader.take_next_sample().unwrap().is_some() {
+        matched = true;
+        break;
+      }
+    }
+    assert!(matched);
+
+    // Samples of various sizes, some of them too large to batch.
+    publisher.suspend_publications().unwrap();
+    let count = 50;
+    for a in 0..count {
+      let padding = vec![0xaa; if a % 10 == 9 { 2000 } else { a as usize * 10 }];
+      writer.write(Sample { a, padding }, None).unwrap();
+    }
+    // Long enough for a periodic heartbeat. It must not announce the
+    // suspended samples, or the reader would request them. Only repairs of
+    // the matching samples may arrive.
+    std::thread::sleep(Duration::from_millis(1500));
+    while let Some(sample) = reader.take_next_sample().unwrap() {
+      assert_eq!(sample.into_value().a, -1);
+    }
+
+    publisher.resume_publications().unwrap();
+    let mut received = Vec::new();
+    for _ in 0..50 {
+      std::thread::sleep(Duration::from_millis(100));
+      while let Some(sample) = reader.take_next_sample().unwrap() {
+        let a = sample.into_value().a;
+        if a >= 0 {
+          received.push(a);
+        }
+      }
+      if received.len() >= count as usize {
+        break;
+      }
+    }
+    assert_eq!(received, (0..count).collect::<Vec<_>>());
+  }
+}
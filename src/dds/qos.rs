@@ -71,6 +71,11 @@ pub struct QosPolicyBuilder {
   history: Option<policy::History>,
   resource_limits: Option<policy::ResourceLimits>,
   lifespan: Option<policy::Lifespan>,
+  max_fragment_size: Option<policy::MaxFragmentSize>,
+  flow_controller: Option<policy::FlowController>,
+  publish_mode: Option<policy::PublishMode>,
+  batching: Option<policy::Batching>,
+  reliability_timing: Option<policy::ReliabilityTiming>,
 }
 
 impl QosPolicyBuilder {
@@ -150,7 +155,37 @@ impl QosPolicyBuilder {
     self
   }
 
-  pub const fn build(self) -> QosPolicies {
+  #[must_use]
+  pub const fn max_fragment_size(mut self, max_fragment_size: policy::MaxFragmentSize) -> Self {
+    self.max_fragment_size = Some(max_fragment_size);
+    self
+  }
+
+  #[must_use]
+  pub fn flow_controller(mut self, flow_controller: policy::FlowController) -> Self {
+    self.flow_controller = Some(flow_controller);
+    self
+  }
+
+  #[must_use]
+  pub const fn publish_mode(mut self, publish_mode: policy::PublishMode) -> Self {
+    self.publish_mode = Some(publish_mode);
+    self
+  }
+
+  #[must_use]
+  pub const fn batching(mut self, batching: policy::Batching) -> Self {
+    self.batching = Some(batching);
+    self
+  }
+
+  #[must_use]
+  pub const fn reliability_timing(mut self, reliability_timing: policy::ReliabilityTiming) -> Self {
+    self.reliability_timing = Some(reliability_timing);
+    self
+  }
+
+  pub fn build(self) -> QosPolicies {
     QosPolicies {
       durability: self.durability,
       presentation: self.presentation,
@@ -164,6 +199,11 @@ impl QosPolicyBuilder {
       history: self.history,
       resource_limits: self.resource_limits,
       lifespan: self.lifespan,
+      max_fragment_size: self.max_fragment_size,
+      flow_controller: self.flow_controller,
+      publish_mode: self.publish_mode,
+      batching: self.batching,
+      reliability_timing: self.reliability_timing,
     }
   }
 }
@@ -186,6 +226,16 @@ pub struct QosPolicies {
   pub(crate) history: Option<policy::History>,
   pub(crate) resource_limits: Option<policy::ResourceLimits>,
   pub(crate) lifespan: Option<policy::Lifespan>,
+  // RustDDS extension. Not sent in discovery.
+  pub(crate) max_fragment_size: Option<policy::MaxFragmentSize>,
+  // RustDDS extension. Not sent in discovery.
+  pub(crate) flow_controller: Option<policy::FlowController>,
+  // RustDDS extension. Not sent in discovery.
+  pub(crate) publish_mode: Option<policy::PublishMode>,
+  // RustDDS extension. Not sent in discovery.
+  pub(crate) batching: Option<policy::Batching>,
+  // RustDDS extension. Not sent in discovery.
+  pub(crate) reliability_timing: Option<policy::ReliabilityTiming>,
 }
 
 impl QosPolicies {
@@ -254,6 +304,26 @@ impl QosPolicies {
     self.lifespan
   }
 
+  pub const fn max_fragment_size(&self) -> Option<policy::MaxFragmentSize> {
+    self.max_fragment_size
+  }
+
+  pub const fn flow_controller(&self) -> Option<&policy::FlowController> {
+    self.flow_controller.as_ref()
+  }
+
+  pub const fn publish_mode(&self) -> Option<policy::PublishMode> {
+    self.publish_mode
+  }
+
+  pub const fn batching(&self) -> Option<policy::Batching> {
+    self.batching
+  }
+
+  pub const fn reliability_timing(&self) -> Option<policy::ReliabilityTiming> {
+    self.reliability_timing
+  }
+
   /// Merge two QosPolicies
   ///
   /// Constructs a QosPolicy, where each policy is taken from `self`,
@@ -273,6 +343,14 @@ impl QosPolicies {
       history: other.history.or(self.history),
       resource_limits: other.resource_limits.or(self.resource_limits),
       lifespan: other.lifespan.or(self.lifespan),
+      max_fragment_size: other.max_fragment_size.or(self.max_fragment_size),
+      flow_controller: other
+        .flow_controller
+        .clone()
+        .or_else(|| self.flow_controller.clone()),
+      publish_mode: other.publish_mode.or(self.publish_mode),
+      batching: other.batching.or(self.batching),
+      reliability_timing: other.reliability_timing.or(self.reliability_timing),
     }
   }
 
@@ -401,6 +479,11 @@ impl QosPolicies {
       history,
       resource_limits,
       lifespan,
+      max_fragment_size: _,  // not serialized
+      flow_controller: _,    // not serialized
+      publish_mode: _,       // not serialized
+      batching: _,           // not serialized
+      reliability_timing: _, // not serialized
     } = self;
 
     macro_rules! emit {
@@ -560,6 +643,11 @@ impl QosPolicies {
       history,
       resource_limits,
       lifespan,
+      max_fragment_size: None,
+      flow_controller: None,
+      publish_mode: None,
+      batching: None,
+      reliability_timing: None,
     })
   }
 }
@@ -626,6 +714,126 @@ pub mod policy {
   }
   */
 
+  /// Largest serialized sample, in bytes, that a DataWriter sends in one DATA
+  /// submessage. Larger samples are sent as DATA_FRAG fragments of this size.
+  /// For a DataReader, this is the largest sample or fragment it wants to
+  /// receive. Values are limited to the range 256 ..= 64000.
+  ///
+  /// This is a RustDDS extension, not defined in the DDS specification. The
+  /// default is given by
+  /// [`DomainParticipantBuilder::max_fragment_size`](crate::DomainParticipantBuilder::max_fragment_size).
+  ///
+  /// The fragment size of a DataWriter is fixed when it is created. DataReaders
+  /// announce their effective value to other RustDDS participants in discovery,
+  /// and a DataWriter logs a warning when it matches a DataReader with a lower
+  /// limit.
+  #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+  pub struct MaxFragmentSize {
+    pub bytes: u32,
+  }
+
+  /// Name of the flow controller that limits the sending rate of a
+  /// DataWriter. The flow controller must be defined with
+  /// [`DomainParticipantBuilder::flow_controller`](crate::DomainParticipantBuilder::flow_controller).
+  /// Without this policy, a DataWriter sends as fast as it can.
+  ///
+  /// This is a RustDDS extension, not defined in the DDS specification. It is
+  /// not sent in discovery.
+  #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+  pub struct FlowController {
+    pub name: String,
+  }
+
+  /// Where the messages of a DataWriter are sent from.
+  ///
+  /// In [`Synchronous`](PublishMode::Synchronous) mode, which is the default,
+  /// a written sample is sent completely by the participant event loop before
+  /// it handles anything else. A large sample then delays discovery and all
+  /// other DataWriters of the participant.
+  ///
+  /// In [`Asynchronous`](PublishMode::Asynchronous) mode, the event loop
+  /// prepares the fragments of a large sample a few at a time, taking turns
+  /// with the other asynchronous DataWriters, and a separate sender thread of
+  /// the participant sends them. Repairs are queued the same way. If the
+  /// sender falls behind, the DataWriter stops accepting new samples, and
+  /// `write` blocks up to the `max_blocking_time` of
+  /// [`Reliability`] QoS.
+  ///
+  /// This is a RustDDS extension, similar to the publish mode of some other
+  /// DDS implementations. It is not sent in discovery.
+  #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+  pub enum PublishMode {
+    #[default]
+    Synchronous,
+    Asynchronous,
+  }
+
+  /// Send several small samples of a DataWriter in one RTPS message.
+  ///
+  /// DATA submessages are collected until the message would grow over
+  /// `max_bytes`, or it has `max_samples` samples, or the first sample has
+  /// waited for the duration of [`LatencyBudget`] QoS. Without a latency
+  /// budget, the samples that were written while the participant was busy
+  /// sending are sent together. Samples larger than the fragment size are not
+  /// batched.
+  ///
+  /// With `across_writers`, a batch may also share a message with batches of
+  /// other DataWriters of the participant that go to the same locators. Batches
+  /// of DataWriters with a [`FlowController`] or in
+  /// [`Asynchronous`](PublishMode::Asynchronous) mode are not combined.
+  ///
+  /// This is a RustDDS extension, not defined in the DDS specification. It is
+  /// not sent in discovery, and Readers need no support for it.
+  #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+  pub struct Batching {
+    pub max_bytes: u32,
+    pub max_samples: u32,
+    pub across_writers: bool,
+  }
+
+  /// Protocol tuning of reliable communication. These are the timing
+  /// parameters of RTPS spec sections 8.4.7.1 (Writer) and 8.4.10.1
+  /// (Reader).
+  ///
+  /// A Writer announces its samples with HEARTBEAT every `heartbeat_period`.
+  /// While some reliable Reader has not acknowledged all samples, the period
+  /// is `fast_heartbeat_period` instead, so that lost samples are repaired
+  /// sooner. The Writer repairs samples `nack_response_delay` after an
+  /// ACKNACK, and ignores requests for samples that it has sent to the Reader
+  /// less than `nack_suppression_duration` ago. Samples that all Readers have
+  /// acknowledged are removed every `cache_cleaning_period`.
+  ///
+  /// A Reader responds to HEARTBEAT after `heartbeat_response_delay`. Until it
+  /// has received something from a matched Writer, it sends a preemptive
+  /// ACKNACK to the Writer every `preemptive_acknack_period`.
+  ///
+  /// This is a RustDDS extension, not defined in the DDS specification. It is
+  /// not sent in discovery.
+  #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+  pub struct ReliabilityTiming {
+    pub heartbeat_period: Duration,
+    pub fast_heartbeat_period: Duration,
+    pub nack_response_delay: Duration,
+    pub nack_suppression_duration: Duration,
+    pub cache_cleaning_period: Duration,
+    pub heartbeat_response_delay: Duration,
+    pub preemptive_acknack_period: Duration,
+  }
+
+  impl Default for ReliabilityTiming {
+    fn default() -> Self {
+      Self {
+        heartbeat_period: Duration::from_secs(1),
+        fast_heartbeat_period: Duration::from_secs(1),
+        nack_response_delay: Duration::from_millis(200),
+        nack_suppression_duration: Duration::DURATION_ZERO,
+        cache_cleaning_period: Duration::from_secs(2 * 60),
+        heartbeat_response_delay: Duration::DURATION_ZERO,
+        preemptive_acknack_period: Duration::from_secs(5),
+      }
+    }
+  }
+
   /// DDS 2.2.3.16 LIFESPAN
   #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Readable, Writable)]
   pub struct Lifespan {
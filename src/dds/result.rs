@@ -1,4 +1,4 @@
-use mio_extras::channel::TrySendError;
+use crate::mio_source::channel::TrySendError;
 
 /// This is a specialized Result, similar to [`std::io::Result`]
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,105 @@
+//! Recycled serialization buffers of a DataWriter.
+//!
+//! A sample is serialized into a buffer from the pool, and the frozen buffer
+//! becomes the payload in the history cache. Messages are sent with gather
+//! I/O, so the buffer holds just the payload, and the RTPS headers are written
+//! elsewhere. The pool keeps a reference to the payload, and takes the buffer
+//! back when it holds the only reference, i.e. the sample has been sent and
+//! removed from the history cache.
+
+use std::collections::VecDeque;
+
+use bytes::{Bytes, BytesMut};
+
+// Buffers of larger samples are not recycled, so that the pool does not keep
+// large allocations alive.
+const MAX_POOLED_BUFFER_SIZE: usize = 64 * 1024;
+// Capacity of new buffers
+const INITIAL_BUFFER_SIZE: usize = 256;
+
+pub(crate) struct SendBufferPool {
+  // Payloads handed out, oldest first
+  in_use: VecDeque<Bytes>,
+  free: Vec<BytesMut>,
+  max_buffers: usize,
+}
+
+impl SendBufferPool {
+  pub fn new(max_buffers: usize) -> Self {
+    Self {
+      in_use: VecDeque::new(),
+      free: Vec::new(),
+      max_buffers,
+    }
+  }
+
+  /// An empty buffer to serialize a sample into
+  pub fn take(&mut self) -> BytesMut {
+    self.reclaim();
+    self
+      .free
+      .pop()
+      .unwrap_or_else(|| BytesMut::with_capacity(INITIAL_BUFFER_SIZE))
+  }
+
+  /// Freezes a serialized buffer to a payload. The buffer is recycled when the
+  /// payload is no longer referenced elsewhere.
+  pub fn freeze(&mut self, buffer: BytesMut) -> Bytes {
+    let payload = buffer.freeze();
+    if payload.len() <= MAX_POOLED_BUFFER_SIZE && self.in_use.len() < self.max_buffers {
+      self.in_use.push_back(payload.clone());
+    }
+    payload
+  }
+
+  // Samples are usually released in the order they were written, so this
+  // stops at the first payload that is still referenced.
+  fn reclaim(&mut self) {
+    while self.in_use.front().map_or(false, Bytes::is_unique) {
+      let payload = self.in_use.pop_front().unwrap(); // front() was Some
+      if let Ok(mut buffer) = payload.try_into_mut() {
+        if self.free.len() < self.max_buffers {
+          buffer.clear();
+          self.free.push(buffer);
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn buffers_are_recycled_when_released() {
+    let mut pool = SendBufferPool::new(4);
+
+    let mut buffer = pool.take();
+    buffer.extend_from_slice(&[1; 100]);
+    let first = pool.freeze(buffer);
+    let first_ptr = first.as_ptr();
+
+    // Still referenced, so a new buffer is allocated.
+    let mut buffer = pool.take();
+    assert_ne!(buffer.as_ptr(), first_ptr);
+    buffer.extend_from_slice(&[2; 100]);
+    let second = pool.freeze(buffer);
+    let second_ptr = second.as_ptr();
+
+    drop(first);
+    let buffer = pool.take();
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.as_ptr(), first_ptr);
+
+    // Large buffers are not kept.
+    let mut buffer = pool.take();
+    buffer.resize(MAX_POOLED_BUFFER_SIZE + 1, 3);
+    let _large = pool.freeze(buffer);
+    assert_eq!(pool.in_use.len(), 1);
+
+    drop(second);
+    assert_eq!(pool.take().as_ptr(), second_ptr);
+    assert!(pool.in_use.is_empty());
+  }
+}
@@ -9,28 +9,55 @@
 use std::{
   io,
   pin::Pin,
-  sync::{Arc, Mutex},
+  sync::{Arc, Mutex, Weak},
   task::{Context, Poll, Waker},
 };
 
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
+use enumflags2::{bitflags, BitFlags};
 use futures::stream::{FusedStream, Stream};
+#[cfg(feature = "mio_06")]
 use mio_06::Evented;
-use mio_extras::channel as mio_channel;
 use mio_08::{self, event, Interest, Registry, Token};
 
-use crate::{dds::qos::QosPolicyId, mio_source::*};
+use crate::{
+  dds::qos::QosPolicyId,
+  mio_source::{channel as mio_channel, *},
+};
 
 /// This trait corresponds to set_listener() of the Entity class in DDS spec.
 /// Types implementing this trait can be registered to a poll and
 /// polled for status events.
 pub trait StatusEvented<E> {
+  #[cfg(feature = "mio_06")]
   fn as_status_evented(&mut self) -> &dyn Evented; // This is for polling with mio-0.6.x
   fn as_status_source(&mut self) -> &mut dyn mio_08::event::Source; // This is for polling with mio-0.8.x
                                                                     //fn as_async_receiver(&self) -> dyn Stream<E>;
 
   fn try_recv_status(&self) -> Option<E>;
+
+  /// Creates a [`StatusCondition`] that triggers when a status whose kind is
+  /// included in `mask` is reported by this Entity. The condition can be
+  /// attached to a [`WaitSet`](crate::WaitSet). Triggering does not consume
+  /// the status, so it is still available from
+  /// [`try_recv_status`](StatusEvented::try_recv_status).
+  ///
+  /// [`StatusKind::DataAvailable`], [`StatusKind::DataOnReaders`] and
+  /// [`StatusKind::InconsistentTopic`] are not reported as statuses, so they
+  /// never trigger the condition. A DataReader signals available data by
+  /// itself being readable. A `mask` with only these kinds is an
+  /// [`InvalidInput`](io::ErrorKind::InvalidInput) error.
+  ///
+  /// The default implementation returns an
+  /// [`Unsupported`](io::ErrorKind::Unsupported) error.
+  fn status_condition(&mut self, mask: BitFlags<StatusKind>) -> io::Result<StatusCondition> {
+    let _ = mask;
+    Err(io::Error::new(
+      io::ErrorKind::Unsupported,
+      "Status conditions are not supported",
+    ))
+  }
 }
 
 // Helper object for various DDS Entities
@@ -56,7 +83,20 @@ impl<E> StatusReceiver<E> {
   }
 }
 
+impl<E> StatusReceiver<E> {
+  // This is not in the StatusEvented impl, so that the impl does not require
+  // HasStatusKind.
+  pub fn status_condition(&mut self, mask: BitFlags<StatusKind>) -> io::Result<StatusCondition>
+  where
+    E: HasStatusKind,
+  {
+    self.enabled = true;
+    self.channel_receiver.status_condition(mask)
+  }
+}
+
 impl<E> StatusEvented<E> for StatusReceiver<E> {
+  #[cfg(feature = "mio_06")]
   fn as_status_evented(&mut self) -> &dyn Evented {
     self.enabled = true;
     &self.channel_receiver.actual_receiver
@@ -80,6 +120,65 @@ impl<E> StatusEvented<E> for StatusReceiver<E> {
 // -------------------------------------------------------------------------------
 // -------------------------------------------------------------------------------
 
+/// Condition that is triggered by status changes of a DDS Entity.
+///
+/// Created by [`StatusEvented::status_condition`]. See DDS Spec v1.4 Section
+/// "2.2.2.1.9 StatusCondition Class".
+pub struct StatusCondition {
+  mask: BitFlags<StatusKind>,
+  signal_receiver: PollEventSource,
+  // The sending side stops signalling when this is dropped.
+  _alive: Arc<()>,
+}
+
+impl StatusCondition {
+  /// The set of status kinds that this condition is interested in
+  pub fn enabled_statuses(&self) -> BitFlags<StatusKind> {
+    self.mask
+  }
+
+  pub(crate) fn drain(&self) {
+    self.signal_receiver.drain();
+  }
+
+  pub(crate) fn task_waker(&self) -> Arc<TaskWaker> {
+    self.signal_receiver.task_waker()
+  }
+}
+
+impl event::Source for StatusCondition {
+  fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+    self.signal_receiver.register(registry, token, interests)
+  }
+
+  fn reregister(
+    &mut self,
+    registry: &Registry,
+    token: Token,
+    interests: Interest,
+  ) -> io::Result<()> {
+    self.signal_receiver.reregister(registry, token, interests)
+  }
+
+  fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+    self.signal_receiver.deregister(registry)
+  }
+}
+
+// StatusWatcher is the sending side of a StatusCondition. It lives in the
+// status channel, and is checked on every status sent.
+struct StatusWatcher<T> {
+  filter: Box<dyn Fn(&T) -> bool + Send>,
+  signal_sender: PollEventSender,
+  alive: Weak<()>,
+}
+
+type StatusWatchers<T> = Arc<Mutex<Vec<StatusWatcher<T>>>>;
+
+// -------------------------------------------------------------------------------
+// -------------------------------------------------------------------------------
+// -------------------------------------------------------------------------------
+
 // a wrapper(s) for mio_channel augmented with
 // poll channel, so that it can be used together with mio-0.8
 // This is only used so that a mio-0.6 channel can pose as a
@@ -91,16 +190,19 @@ pub(crate) fn sync_status_channel<T>(
   let (signal_receiver, signal_sender) = make_poll_channel()?;
   let (actual_sender, actual_receiver) = mio_channel::sync_channel(capacity);
   let waker = Arc::new(Mutex::new(None));
+  let watchers = Arc::new(Mutex::new(Vec::new()));
   Ok((
     StatusChannelSender {
       actual_sender,
       signal_sender,
       waker: Arc::clone(&waker),
+      watchers: Arc::clone(&watchers),
     },
     StatusChannelReceiver {
       actual_receiver,
       signal_receiver,
       waker,
+      watchers,
     },
   ))
 }
@@ -110,19 +212,31 @@ pub struct StatusChannelSender<T> {
   actual_sender: mio_channel::SyncSender<T>,
   signal_sender: PollEventSender,
   waker: Arc<Mutex<Option<Waker>>>,
+  watchers: StatusWatchers<T>,
 }
 
 pub struct StatusChannelReceiver<T> {
   actual_receiver: mio_channel::Receiver<T>,
   signal_receiver: PollEventSource,
   waker: Arc<Mutex<Option<Waker>>>,
+  watchers: StatusWatchers<T>,
 }
 
 impl<T> StatusChannelSender<T> {
   pub fn try_send(&self, t: T) -> Result<(), mio_channel::TrySendError<T>> {
+    // StatusConditions are triggered only if the status is actually
+    // delivered, so they must be checked before the status is moved.
+    let mut watchers = self.watchers.lock().unwrap();
+    watchers.retain(|w| w.alive.strong_count() > 0);
+    let interested: Vec<bool> = watchers.iter().map(|w| (w.filter)(&t)).collect();
+
     let mut w = self.waker.lock().unwrap(); // lock already at the beginning
     match self.actual_sender.try_send(t) {
       Ok(()) => {
+        // Kick the StatusConditions that are interested in this status.
+        for (watcher, _) in watchers.iter().zip(interested).filter(|(_, i)| *i) {
+          watcher.signal_sender.send();
+        }
         self.signal_sender.send();
         w.as_ref().map(|w| w.wake_by_ref());
         *w = None;
@@ -149,6 +263,7 @@ impl<T> StatusChannelReceiver<T> {
     self.signal_receiver.drain();
     self.actual_receiver.try_recv()
   }
+  #[cfg(feature = "mio_06")]
   pub fn as_evented(&self) -> &dyn Evented {
     &self.actual_receiver
   }
@@ -157,6 +272,30 @@ impl<T> StatusChannelReceiver<T> {
       sync_receiver: self,
     }
   }
+
+  pub(crate) fn status_condition(&self, mask: BitFlags<StatusKind>) -> io::Result<StatusCondition>
+  where
+    T: HasStatusKind,
+  {
+    if (mask & !StatusKind::never_reported()).is_empty() {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Status kinds {mask:?} are never reported"),
+      ));
+    }
+    let (signal_receiver, signal_sender) = make_poll_channel()?;
+    let alive = Arc::new(());
+    self.watchers.lock().unwrap().push(StatusWatcher {
+      filter: Box::new(move |t: &T| mask.contains(t.status_kind())),
+      signal_sender,
+      alive: Arc::downgrade(&alive),
+    });
+    Ok(StatusCondition {
+      mask,
+      signal_receiver,
+      _alive: alive,
+    })
+  }
 }
 
 impl<T> event::Source for StatusChannelReceiver<T> {
@@ -218,6 +357,47 @@ impl<'a, T> FusedStream for StatusReceiverStream<'a, T> {
 // -------------------------------------------------------------------------------
 // -------------------------------------------------------------------------------
 
+/// Kinds of communication statuses. These are used as a mask to select the
+/// statuses a [`StatusCondition`] reacts to.
+///
+/// See DDS Spec v1.4 Section "2.2.4.1 Communication Status". The bit values
+/// are from Section "2.3.3 DCPS PSM : IDL".
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[bitflags]
+#[repr(u32)]
+pub enum StatusKind {
+  InconsistentTopic = 0x0001 << 0,
+  OfferedDeadlineMissed = 0x0001 << 1,
+  RequestedDeadlineMissed = 0x0001 << 2,
+  OfferedIncompatibleQos = 0x0001 << 5,
+  RequestedIncompatibleQos = 0x0001 << 6,
+  SampleLost = 0x0001 << 7,
+  SampleRejected = 0x0001 << 8,
+  DataOnReaders = 0x0001 << 9,
+  DataAvailable = 0x0001 << 10,
+  LivelinessLost = 0x0001 << 11,
+  LivelinessChanged = 0x0001 << 12,
+  PublicationMatched = 0x0001 << 13,
+  SubscriptionMatched = 0x0001 << 14,
+}
+
+impl StatusKind {
+  /// Set that contains all status kinds
+  pub fn any() -> BitFlags<Self> {
+    BitFlags::<Self>::all()
+  }
+
+  // Kinds that no Entity reports as a status
+  fn never_reported() -> BitFlags<Self> {
+    Self::DataAvailable | Self::DataOnReaders | Self::InconsistentTopic
+  }
+}
+
+/// Status types that can be classified by a [`StatusKind`]
+pub trait HasStatusKind {
+  fn status_kind(&self) -> StatusKind;
+}
+
 #[derive(Debug, Clone)]
 pub enum DomainParticipantStatus {
   PublisherStatus(PublisherStatus),
@@ -288,6 +468,19 @@ pub enum DataReaderStatus {
   },
 }
 
+impl HasStatusKind for DataReaderStatus {
+  fn status_kind(&self) -> StatusKind {
+    match self {
+      Self::SampleRejected { .. } => StatusKind::SampleRejected,
+      Self::LivelinessChanged { .. } => StatusKind::LivelinessChanged,
+      Self::RequestedDeadlineMissed { .. } => StatusKind::RequestedDeadlineMissed,
+      Self::RequestedIncompatibleQos { .. } => StatusKind::RequestedIncompatibleQos,
+      Self::SampleLost { .. } => StatusKind::SampleLost,
+      Self::SubscriptionMatched { .. } => StatusKind::SubscriptionMatched,
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
 pub enum DataWriterStatus {
   LivelinessLost {
@@ -309,6 +502,17 @@ pub enum DataWriterStatus {
   },
 }
 
+impl HasStatusKind for DataWriterStatus {
+  fn status_kind(&self) -> StatusKind {
+    match self {
+      Self::LivelinessLost { .. } => StatusKind::LivelinessLost,
+      Self::OfferedDeadlineMissed { .. } => StatusKind::OfferedDeadlineMissed,
+      Self::OfferedIncompatibleQos { .. } => StatusKind::OfferedIncompatibleQos,
+      Self::PublicationMatched { .. } => StatusKind::PublicationMatched,
+    }
+  }
+}
+
 /// Helper to contain same count actions across statuses
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct CountWithChange {
@@ -363,3 +567,21 @@ pub struct QosPolicyCount {
   policy_id: QosPolicyId,
   count: i32,
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn status_condition_rejects_never_reported_kinds() {
+    let (_sender, receiver) = sync_status_channel::<DataReaderStatus>(4).unwrap();
+    let mut receiver = StatusReceiver::new(receiver);
+    let result =
+      receiver.status_condition(StatusKind::DataAvailable | StatusKind::InconsistentTopic);
+    assert!(matches!(result, Err(e) if e.kind() == io::ErrorKind::InvalidInput));
+    // A mask with some reportable kind is fine.
+    receiver
+      .status_condition(StatusKind::DataAvailable | StatusKind::SubscriptionMatched)
+      .unwrap();
+  }
+}
@@ -0,0 +1,630 @@
+// WaitSet, GuardCondition and friends.
+//
+// These implement the "Conditions and Wait-sets" part of DDS Spec v1.4
+// Section 2.2.4, for applications that do not want to run their own mio
+// event loop. Internally a WaitSet is a mio-0.8 Poll, and the conditions
+// attached to it are event::Sources built from the poll channels in
+// mio_source.rs.
+use std::{
+  collections::BTreeMap,
+  future, io,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex, Weak,
+  },
+  task,
+  time::{Duration, Instant},
+};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use mio_08::{event::Source, Events, Interest, Poll, Token};
+
+use crate::{
+  dds::{readcondition::ReadCondition, result::*, statusevents::StatusCondition},
+  mio_source::*,
+};
+
+/// Identifies a condition attached to a [`WaitSet`].
+///
+/// These are returned when a condition is attached, and reported back by
+/// [`WaitSet::wait`] when the condition has triggered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConditionId(usize);
+
+impl ConditionId {
+  fn token(self) -> Token {
+    Token(self.0)
+  }
+}
+
+/// Entities that can be attached to a [`WaitSet`] with a [`ReadCondition`],
+/// i.e. DataReaders.
+pub trait ReadConditionSource: Source {
+  // Lets WaitSet::async_wait be woken up directly by the DataReader.
+  #[doc(hidden)]
+  fn task_waker(&self) -> Arc<TaskWaker>;
+}
+
+/// A condition whose trigger value is completely under the control of the
+/// application.
+///
+/// GuardConditions are cheap to clone. All clones refer to the same
+/// condition, so one clone can be attached to a [`WaitSet`] and another one
+/// triggered from a different thread.
+///
+/// See DDS Spec v1.4 Section "2.2.2.1.8 GuardCondition Class".
+#[derive(Clone)]
+pub struct GuardCondition {
+  inner: Arc<GuardConditionInner>,
+}
+
+struct GuardConditionInner {
+  trigger_value: AtomicBool,
+  // One sender per WaitSet this condition is attached to.
+  signal_senders: Mutex<Vec<(Weak<()>, PollEventSender)>>,
+}
+
+impl GuardCondition {
+  pub fn new() -> Self {
+    Self {
+      inner: Arc::new(GuardConditionInner {
+        trigger_value: AtomicBool::new(false),
+        signal_senders: Mutex::new(Vec::new()),
+      }),
+    }
+  }
+
+  /// Sets the trigger value. Setting it to `true` wakes up all WaitSets this
+  /// condition is attached to. The value stays set until it is set back to
+  /// `false`.
+  pub fn set_trigger_value(&self, value: bool) {
+    self.inner.trigger_value.store(value, Ordering::SeqCst);
+    if value {
+      let mut senders = self.inner.signal_senders.lock().unwrap();
+      senders.retain(|(alive, _)| alive.strong_count() > 0);
+      for (_, sender) in senders.iter() {
+        sender.send();
+      }
+    }
+  }
+
+  pub fn trigger_value(&self) -> bool {
+    self.inner.trigger_value.load(Ordering::SeqCst)
+  }
+
+  fn new_signal(&self) -> io::Result<(PollEventSource, Arc<()>)> {
+    let (source, sender) = make_poll_channel()?;
+    let alive = Arc::new(());
+    self
+      .inner
+      .signal_senders
+      .lock()
+      .unwrap()
+      .push((Arc::downgrade(&alive), sender));
+    Ok((source, alive))
+  }
+}
+
+impl Default for GuardCondition {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+enum AttachedCondition {
+  Read {
+    read_condition: ReadCondition,
+    task_waker: Arc<TaskWaker>,
+  },
+  Status {
+    status_condition: StatusCondition,
+  },
+  Guard {
+    guard_condition: GuardCondition,
+    signal: PollEventSource,
+    _alive: Arc<()>, // GuardCondition stops signalling us when this is dropped
+  },
+}
+
+/// Blocks the calling thread (or async task) until at least one of the
+/// attached conditions has triggered.
+///
+/// Three kinds of conditions can be attached:
+/// * [`ReadCondition`]s on a specific DataReader. These trigger when new data
+///   arrives to the reader.
+/// * [`StatusCondition`]s, created from any Entity that implements
+///   [`StatusEvented`](crate::StatusEvented). These trigger when the Entity
+///   reports a status that is enabled in the condition's mask.
+/// * [`GuardCondition`]s, which are triggered by the application.
+///
+/// Conditions are reported by their [`ConditionId`]. After a condition is
+/// reported, the application should act on it, e.g. read or take the data
+/// with the attached `ReadCondition`, or call
+/// [`try_recv_status`](crate::StatusEvented::try_recv_status) until it
+/// returns `None`.
+///
+/// A DataReader attached to a WaitSet should not be registered to another
+/// mio-0.8 `Poll` at the same time.
+///
+/// See DDS Spec v1.4 Section "2.2.2.1.6 WaitSet Class".
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// use rustdds::*;
+///
+/// let mut wait_set = WaitSet::new().unwrap();
+/// let guard = GuardCondition::new();
+/// let guard_id = wait_set.attach_guard_condition(guard.clone()).unwrap();
+///
+/// guard.set_trigger_value(true);
+/// let triggered = wait_set.wait(Some(Duration::from_secs(1))).unwrap();
+/// assert_eq!(triggered, vec![guard_id]);
+/// ```
+pub struct WaitSet {
+  poll: Poll,
+  conditions: BTreeMap<ConditionId, AttachedCondition>,
+  next_condition_id: usize,
+  // Conditions to be reported without waiting for an event.
+  pending: Vec<ConditionId>,
+}
+
+impl WaitSet {
+  pub fn new() -> Result<Self> {
+    Ok(Self {
+      poll: Poll::new()?,
+      conditions: BTreeMap::new(),
+      next_condition_id: 0,
+      pending: Vec::new(),
+    })
+  }
+
+  fn new_condition_id(&mut self) -> ConditionId {
+    let id = ConditionId(self.next_condition_id);
+    self.next_condition_id += 1;
+    id
+  }
+
+  fn register(&self, source: &mut dyn Source, id: ConditionId) -> Result<()> {
+    self
+      .poll
+      .registry()
+      .register(source, id.token(), Interest::READABLE)?;
+    Ok(())
+  }
+
+  /// Attaches a `ReadCondition` on the given DataReader.
+  ///
+  /// The condition triggers whenever new samples arrive to the DataReader.
+  /// It is also reported by the first `wait` after attaching, since the reader
+  /// may already contain data. The states in `read_condition` are not
+  /// evaluated by the WaitSet; use the same condition when reading, e.g. with
+  /// [`read`](crate::with_key::DataReader::read).
+  pub fn attach_read_condition<R: ReadConditionSource>(
+    &mut self,
+    reader: &mut R,
+    read_condition: ReadCondition,
+  ) -> Result<ConditionId> {
+    let id = self.new_condition_id();
+    self.register(reader, id)?;
+    self.conditions.insert(
+      id,
+      AttachedCondition::Read {
+        read_condition,
+        task_waker: reader.task_waker(),
+      },
+    );
+    self.pending.push(id);
+    Ok(id)
+  }
+
+  /// Attaches a [`StatusCondition`], which is created using
+  /// [`StatusEvented::status_condition`](crate::StatusEvented::status_condition).
+  pub fn attach_status_condition(
+    &mut self,
+    mut status_condition: StatusCondition,
+  ) -> Result<ConditionId> {
+    let id = self.new_condition_id();
+    self.register(&mut status_condition, id)?;
+    self
+      .conditions
+      .insert(id, AttachedCondition::Status { status_condition });
+    Ok(id)
+  }
+
+  /// Attaches a [`GuardCondition`]. The same condition may be attached to
+  /// several WaitSets.
+  pub fn attach_guard_condition(&mut self, guard_condition: GuardCondition) -> Result<ConditionId> {
+    let id = self.new_condition_id();
+    let (mut signal, alive) = guard_condition.new_signal()?;
+    self.register(&mut signal, id)?;
+    self.conditions.insert(
+      id,
+      AttachedCondition::Guard {
+        guard_condition,
+        signal,
+        _alive: alive,
+      },
+    );
+    Ok(id)
+  }
+
+  /// Detaches a condition. Returns `false` if there was no such condition.
+  ///
+  /// A DataReader that was attached with a `ReadCondition` must be detached
+  /// with [`detach_read_condition`](Self::detach_read_condition) instead, so
+  /// that it can be registered elsewhere later.
+  pub fn detach_condition(&mut self, id: ConditionId) -> Result<bool> {
+    let registry = self.poll.registry();
+    self.pending.retain(|p| *p != id);
+    match self.conditions.remove(&id) {
+      None => Ok(false),
+      Some(AttachedCondition::Read { .. }) => Ok(true), // reader is not available to deregister
+      Some(AttachedCondition::Status {
+        mut status_condition,
+      }) => {
+        registry.deregister(&mut status_condition)?;
+        Ok(true)
+      }
+      Some(AttachedCondition::Guard { mut signal, .. }) => {
+        registry.deregister(&mut signal)?;
+        Ok(true)
+      }
+    }
+  }
+
+  /// Detaches a `ReadCondition` and deregisters the DataReader from this
+  /// WaitSet.
+  pub fn detach_read_condition<R: ReadConditionSource>(
+    &mut self,
+    id: ConditionId,
+    reader: &mut R,
+  ) -> Result<bool> {
+    match self.conditions.get(&id) {
+      Some(AttachedCondition::Read { .. }) => {
+        self.conditions.remove(&id);
+        self.pending.retain(|p| *p != id);
+        self.poll.registry().deregister(reader)?;
+        Ok(true)
+      }
+      Some(_) => Error::bad_parameter("Not a ReadCondition"),
+      None => Ok(false),
+    }
+  }
+
+  /// The `ReadCondition` that was attached with the given id, if any.
+  pub fn read_condition(&self, id: ConditionId) -> Option<ReadCondition> {
+    match self.conditions.get(&id) {
+      Some(AttachedCondition::Read { read_condition, .. }) => Some(*read_condition),
+      _ => None,
+    }
+  }
+
+  /// Ids of all currently attached conditions.
+  pub fn conditions(&self) -> Vec<ConditionId> {
+    self.conditions.keys().copied().collect()
+  }
+
+  /// Waits until at least one attached condition triggers, or `timeout`
+  /// expires. `None` means waiting indefinitely.
+  ///
+  /// Returns the ids of the triggered conditions. If the timeout expires, the
+  /// result is empty.
+  pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Vec<ConditionId>> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut events = Events::with_capacity(16);
+    loop {
+      let poll_timeout = if self.pending.is_empty() && self.triggered_guards().is_empty() {
+        deadline.map(|d| d.saturating_duration_since(Instant::now()))
+      } else {
+        Some(Duration::ZERO) // just collect whatever else is there
+      };
+      self.poll.poll(&mut events, poll_timeout).or_else(|e| {
+        if e.kind() == io::ErrorKind::Interrupted {
+          Ok(())
+        } else {
+          Err(e)
+        }
+      })?;
+
+      let triggered = self.collect_triggered(events.iter().map(|e| e.token()));
+      if !triggered.is_empty() || deadline.map_or(false, |d| Instant::now() >= d) {
+        return Ok(triggered);
+      }
+      // Otherwise the wakeup was stale, e.g. a GuardCondition that was
+      // set and cleared again. Keep waiting.
+    }
+  }
+
+  /// Async version of [`wait`](Self::wait) without timeout.
+  ///
+  /// The future is woken up directly by the attached conditions, so this can
+  /// be used with any async runtime. Timeouts can be implemented by racing
+  /// this against a timer future.
+  pub async fn async_wait(&mut self) -> Result<Vec<ConditionId>> {
+    future::poll_fn(|cx| {
+      // Wakers are set before checking, so that a condition triggering in
+      // between is not missed.
+      for task_waker in self.conditions.values().map(AttachedCondition::task_waker) {
+        task_waker.set(cx.waker());
+      }
+      match self.wait(Some(Duration::ZERO)) {
+        Ok(triggered) if triggered.is_empty() => task::Poll::Pending,
+        result => task::Poll::Ready(result),
+      }
+    })
+    .await
+  }
+
+  fn triggered_guards(&self) -> Vec<ConditionId> {
+    self
+      .conditions
+      .iter()
+      .filter_map(|(id, c)| match c {
+        AttachedCondition::Guard {
+          guard_condition, ..
+        } if guard_condition.trigger_value() => Some(*id),
+        _ => None,
+      })
+      .collect()
+  }
+
+  // Turn poll tokens into a sorted list of ids. Signals are drained so that
+  // the next signals are seen by the edge-triggered poll.
+  fn collect_triggered(&mut self, tokens: impl Iterator<Item = Token>) -> Vec<ConditionId> {
+    let mut triggered = self.triggered_guards();
+    triggered.append(&mut self.pending);
+    for token in tokens {
+      let id = ConditionId(token.0);
+      match self.conditions.get(&id) {
+        Some(AttachedCondition::Read { .. }) => triggered.push(id),
+        Some(AttachedCondition::Status { status_condition }) => {
+          status_condition.drain();
+          triggered.push(id);
+        }
+        Some(AttachedCondition::Guard { signal, .. }) => signal.drain(),
+        None => trace!("Event for detached condition {id:?}"),
+      }
+    }
+    triggered.sort();
+    triggered.dedup();
+    triggered
+  }
+}
+
+impl AttachedCondition {
+  fn task_waker(&self) -> Arc<TaskWaker> {
+    match self {
+      Self::Read { task_waker, .. } => Arc::clone(task_waker),
+      Self::Status { status_condition } => status_condition.task_waker(),
+      Self::Guard { signal, .. } => signal.task_waker(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use enumflags2::BitFlags;
+  use serde::{Deserialize, Serialize};
+
+  use super::*;
+  use crate::{
+    dds::{
+      qos::{policy, QosPolicyBuilder},
+      statusevents::{sync_status_channel, DataReaderStatus, StatusKind},
+      topic::TopicKind,
+    },
+    CDRDeserializerAdapter, CDRSerializerAdapter, DomainParticipant, StatusEvented,
+  };
+
+  #[test]
+  fn waitset_guard_condition() {
+    let mut wait_set = WaitSet::new().unwrap();
+    let guard = GuardCondition::new();
+    let id = wait_set.attach_guard_condition(guard.clone()).unwrap();
+
+    assert!(wait_set
+      .wait(Some(Duration::from_millis(10)))
+      .unwrap()
+      .is_empty());
+
+    guard.set_trigger_value(true);
+    assert_eq!(wait_set.wait(Some(Duration::ZERO)).unwrap(), vec![id]);
+    // GuardCondition stays triggered until reset
+    assert_eq!(wait_set.wait(Some(Duration::ZERO)).unwrap(), vec![id]);
+
+    guard.set_trigger_value(false);
+    assert!(wait_set
+      .wait(Some(Duration::from_millis(10)))
+      .unwrap()
+      .is_empty());
+
+    assert!(wait_set.detach_condition(id).unwrap());
+    guard.set_trigger_value(true);
+    assert!(wait_set
+      .wait(Some(Duration::from_millis(10)))
+      .unwrap()
+      .is_empty());
+  }
+
+  #[test]
+  fn waitset_guard_from_other_thread() {
+    let mut wait_set = WaitSet::new().unwrap();
+    let guard = GuardCondition::new();
+    let id = wait_set.attach_guard_condition(guard.clone()).unwrap();
+
+    let trigger_thread = std::thread::spawn(move || {
+      std::thread::sleep(Duration::from_millis(50));
+      guard.set_trigger_value(true);
+    });
+    assert_eq!(
+      wait_set.wait(Some(Duration::from_secs(5))).unwrap(),
+      vec![id]
+    );
+    trigger_thread.join().unwrap();
+  }
+
+  #[test]
+  fn waitset_status_condition_mask() {
+    let (status_sender, status_receiver) = sync_status_channel::<DataReaderStatus>(4).unwrap();
+    let mut status_receiver = crate::dds::statusevents::StatusReceiver::new(status_receiver);
+    let mut wait_set = WaitSet::new().unwrap();
+    let condition = status_receiver
+      .status_condition(BitFlags::from(StatusKind::SubscriptionMatched))
+      .unwrap();
+    let id = wait_set.attach_status_condition(condition).unwrap();
+
+    // Not in the mask
+    status_sender
+      .try_send(DataReaderStatus::SampleLost {
+        count: crate::dds::statusevents::CountWithChange::new(1, 1),
+      })
+      .unwrap();
+    assert!(wait_set
+      .wait(Some(Duration::from_millis(10)))
+      .unwrap()
+      .is_empty());
+
+    status_sender
+      .try_send(DataReaderStatus::SubscriptionMatched {
+        total: crate::dds::statusevents::CountWithChange::new(1, 1),
+        current: crate::dds::statusevents::CountWithChange::new(1, 1),
+      })
+      .unwrap();
+    assert_eq!(
+      wait_set.wait(Some(Duration::from_secs(1))).unwrap(),
+      vec![id]
+    );
+
+    // Statuses are still available from the Entity
+    assert!(matches!(
+      status_receiver.try_recv_status(),
+      Some(DataReaderStatus::SampleLost { .. })
+    ));
+    assert!(matches!(
+      status_receiver.try_recv_status(),
+      Some(DataReaderStatus::SubscriptionMatched { .. })
+    ));
+  }
+
+  #[test]
+  fn waitset_status_condition_channel_full() {
+    let (status_sender, status_receiver) = sync_status_channel::<DataReaderStatus>(1).unwrap();
+    let mut status_receiver = crate::dds::statusevents::StatusReceiver::new(status_receiver);
+    let mut wait_set = WaitSet::new().unwrap();
+    let condition = status_receiver
+      .status_condition(BitFlags::from(StatusKind::SampleLost))
+      .unwrap();
+    let id = wait_set.attach_status_condition(condition).unwrap();
+    let sample_lost = || DataReaderStatus::SampleLost {
+      count: crate::dds::statusevents::CountWithChange::new(1, 1),
+    };
+
+    status_sender.try_send(sample_lost()).unwrap();
+    assert_eq!(
+      wait_set.wait(Some(Duration::from_secs(1))).unwrap(),
+      vec![id]
+    );
+
+    // A status that does not fit in the channel does not trigger the condition.
+    assert!(status_sender.try_send(sample_lost()).is_err());
+    assert!(wait_set
+      .wait(Some(Duration::from_millis(10)))
+      .unwrap()
+      .is_empty());
+  }
+
+  #[test]
+  fn waitset_async_wait() {
+    let mut wait_set = WaitSet::new().unwrap();
+    let guard = GuardCondition::new();
+    let id = wait_set.attach_guard_condition(guard.clone()).unwrap();
+
+    let trigger_thread = std::thread::spawn(move || {
+      std::thread::sleep(Duration::from_millis(50));
+      guard.set_trigger_value(true);
+    });
+    let triggered = futures::executor::block_on(wait_set.async_wait()).unwrap();
+    assert_eq!(triggered, vec![id]);
+    trigger_thread.join().unwrap();
+  }
+
+  #[test]
+  fn waitset_async_wait_status_condition() {
+    let (status_sender, status_receiver) = sync_status_channel::<DataReaderStatus>(4).unwrap();
+    let mut status_receiver = crate::dds::statusevents::StatusReceiver::new(status_receiver);
+    let mut wait_set = WaitSet::new().unwrap();
+    let condition = status_receiver
+      .status_condition(BitFlags::from(StatusKind::SampleLost))
+      .unwrap();
+    let id = wait_set.attach_status_condition(condition).unwrap();
+
+    let send_thread = std::thread::spawn(move || {
+      std::thread::sleep(Duration::from_millis(50));
+      status_sender
+        .try_send(DataReaderStatus::SampleLost {
+          count: crate::dds::statusevents::CountWithChange::new(1, 1),
+        })
+        .unwrap();
+    });
+    let triggered = futures::executor::block_on(wait_set.async_wait()).unwrap();
+    assert_eq!(triggered, vec![id]);
+    send_thread.join().unwrap();
+  }
+
+  #[test]
+  fn waitset_read_condition() {
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Sample {
+      a: i32,
+    }
+
+    let dp = DomainParticipant::new(0).unwrap();
+    let qos = QosPolicyBuilder::new()
+      .reliability(policy::Reliability::Reliable {
+        max_blocking_time: crate::Duration::DURATION_ZERO,
+      })
+      .history(policy::History::KeepAll)
+      .build();
+    let topic = dp
+      .create_topic(
+        "waitset_read_condition".to_string(),
+        "Sample".to_string(),
+        &qos,
+        TopicKind::NoKey,
+      )
+      .unwrap();
+    let subscriber = dp.create_subscriber(&qos).unwrap();
+    let publisher = dp.create_publisher(&qos).unwrap();
+    let mut reader = subscriber
+      .create_datareader_no_key::<Sample, CDRDeserializerAdapter<Sample>>(&topic, None)
+      .unwrap();
+    let writer = publisher
+      .create_datawriter_no_key::<Sample, CDRSerializerAdapter<Sample>>(&topic, None)
+      .unwrap();
+
+    let mut wait_set = WaitSet::new().unwrap();
+    let id = wait_set
+      .attach_read_condition(&mut reader, ReadCondition::not_read())
+      .unwrap();
+    // Reported once right after attaching, because the reader may have data.
+    assert_eq!(wait_set.wait(Some(Duration::ZERO)).unwrap(), vec![id]);
+
+    writer.write(Sample { a: 1 }, None).unwrap();
+    assert_eq!(
+      wait_set.wait(Some(Duration::from_secs(5))).unwrap(),
+      vec![id]
+    );
+    let rc = wait_set.read_condition(id).unwrap();
+    let samples = reader.take(10, rc).unwrap();
+    assert_eq!(samples.len(), 1);
+    assert_eq!(*samples[0].value(), Sample { a: 1 });
+
+    assert!(wait_set.detach_read_condition(id, &mut reader).unwrap());
+    assert!(wait_set.conditions().is_empty());
+  }
+}
@@ -7,8 +7,10 @@ use std::{
 
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
+#[cfg(feature = "mio_06")]
 use mio_06::{self, Evented};
 use mio_08;
+use enumflags2::BitFlags;
 use futures::stream::{FusedStream, Stream};
 
 use super::datasample_cache::DataSampleCache;
@@ -20,9 +22,11 @@ use crate::{
     readcondition::*,
     result::*,
     statusevents::*,
+    waitset::ReadConditionSource,
     with_key::{datasample::*, simpledatareader::*},
   },
   discovery::sedp_messages::PublicationBuiltinTopicData,
+  mio_source::TaskWaker,
   serialization::CDRDeserializerAdapter,
   structure::{duration::Duration, entity::RTPSEntity, guid::GUID, time::Timestamp},
 };
@@ -740,6 +744,7 @@ where
 
 // -------------------
 
+#[cfg(feature = "mio_06")]
 impl<D, DA> Evented for DataReader<D, DA>
 where
   D: Keyed,
@@ -777,6 +782,17 @@ where
   }
 }
 
+impl<D, DA> ReadConditionSource for DataReader<D, DA>
+where
+  D: Keyed,
+  <D as Keyed>::K: Key,
+  DA: DeserializerAdapter<D>,
+{
+  fn task_waker(&self) -> Arc<TaskWaker> {
+    self.simple_data_reader.task_waker()
+  }
+}
+
 impl<D, DA> mio_08::event::Source for DataReader<D, DA>
 where
   D: Keyed,
@@ -827,6 +843,7 @@ where
   <D as Keyed>::K: Key,
   DA: DeserializerAdapter<D>,
 {
+  #[cfg(feature = "mio_06")]
   fn as_status_evented(&mut self) -> &dyn Evented {
     self.simple_data_reader.as_status_evented()
   }
@@ -838,6 +855,10 @@ where
   fn try_recv_status(&self) -> Option<DataReaderStatus> {
     self.simple_data_reader.try_recv_status()
   }
+
+  fn status_condition(&mut self, mask: BitFlags<StatusKind>) -> io::Result<StatusCondition> {
+    self.simple_data_reader.status_condition(mask)
+  }
 }
 
 impl<D, DA> HasQoSPolicy for DataReader<D, DA>
@@ -1005,14 +1026,10 @@ where
 
 #[cfg(test)]
 mod tests {
-  use std::rc::Rc;
-
   use bytes::Bytes;
-  use mio_extras::channel as mio_channel;
   use log::info;
   use byteorder::LittleEndian;
 
-  use super::*;
   use crate::{
     dds::{
       participant::DomainParticipant,
@@ -1022,7 +1039,8 @@ mod tests {
       data::Data, elements::serialized_payload::SerializedPayload, submessage_flag::*,
     },
     mio_source,
-    network::udp_sender::UDPSender,
+    mio_source::channel as mio_channel,
+    network::transport::Transports,
     rtps::{
       message_receiver::*,
       reader::{Reader, ReaderIngredients},
@@ -1035,6 +1053,7 @@ mod tests {
     test::random_data::*,
     Keyed, RepresentationIdentifier,
   };
+  use super::*;
 
   #[test]
   fn read_and_take() {
@@ -1089,8 +1108,8 @@ mod tests {
 
     let mut reader = Reader::new(
       reader_ing,
-      Rc::new(UDPSender::new_with_random_port().unwrap()),
-      mio_extras::timer::Builder::default().build(),
+      Arc::new(Transports::new(Vec::new())),
+      crate::mio_source::timer::Timer::default(),
     );
 
     // Create the corresponding matching DataReader
@@ -1243,8 +1262,8 @@ mod tests {
 
     let mut reader = Reader::new(
       reader_ing,
-      Rc::new(UDPSender::new_with_random_port().unwrap()),
-      mio_extras::timer::Builder::default().build(),
+      Arc::new(Transports::new(Vec::new())),
+      crate::mio_source::timer::Timer::default(),
     );
 
     // Create the corresponding matching DataReader
@@ -1,4 +1,5 @@
 use std::{
+  io,
   marker::PhantomData,
   pin::Pin,
   sync::{
@@ -9,11 +10,14 @@ use std::{
   time::{Duration, Instant},
 };
 
+use enumflags2::BitFlags;
 use futures::{Future, Stream};
-use mio_06::{self, Evented, Events, PollOpt, Ready, Token};
-use mio_extras::channel::{self as mio_channel, SendError, TrySendError};
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
+#[cfg(feature = "mio_06")]
+use mio_06::Evented;
+use mio_08::{Events, Interest, Token};
+use bytes::Bytes;
 
 use crate::{
   dds::{
@@ -28,12 +32,14 @@ use crate::{
       HasQoSPolicy, QosPolicies,
     },
     result::{Error, Result},
+    send_buffer_pool::SendBufferPool,
     statusevents::*,
     topic::Topic,
   },
   discovery::{discovery::DiscoveryCommand, sedp_messages::SubscriptionBuiltinTopicData},
   log_and_err_internal,
   messages::submessages::elements::serialized_payload::SerializedPayload,
+  mio_source::channel::{self as mio_channel, SendError, TrySendError},
   rtps::writer::WriterCommand,
   serialization::CDRSerializerAdapter,
   structure::{
@@ -43,6 +49,10 @@ use crate::{
   Key, Keyed, TopicDescription,
 };
 
+// Serialization buffers of recent samples that are recycled. See
+// SendBufferPool.
+const SEND_BUFFER_POOL_SIZE: usize = 64;
+
 // TODO: Move the write options and the builder type to some lower-level module
 // to avoid circular dependencies.
 #[derive(Debug, Default)]
@@ -156,6 +166,7 @@ pub struct DataWriter<D: Keyed, SA: SerializerAdapter<D> = CDRSerializerAdapter<
   discovery_command: mio_channel::SyncSender<DiscoveryCommand>,
   status_receiver: StatusReceiver<DataWriterStatus>,
   available_sequence_number: AtomicI64,
+  send_buffers: Mutex<SendBufferPool>,
 }
 
 impl<D, SA> Drop for DataWriter<D, SA>
@@ -226,9 +237,17 @@ where
       discovery_command,
       status_receiver: StatusReceiver::new(status_receiver_rec),
       available_sequence_number: AtomicI64::new(1), // valid numbering starts from 1
+      send_buffers: Mutex::new(SendBufferPool::new(SEND_BUFFER_POOL_SIZE)),
     })
   }
 
+  // Serialize into a recycled buffer
+  fn serialize(&self, data: &D) -> crate::serialization::Result<Bytes> {
+    let mut buffer = self.send_buffers.lock().unwrap().take();
+    SA::to_buffer(data, &mut buffer)?;
+    Ok(self.send_buffers.lock().unwrap().freeze(buffer))
+  }
+
   fn next_sequence_number(&self) -> SequenceNumber {
     SequenceNumber::from(
       self
@@ -333,7 +352,7 @@ where
   }
 
   pub fn write_with_options(&self, data: D, write_options: WriteOptions) -> Result<SampleIdentity> {
-    let send_buffer = SA::to_bytes(&data)?; // serialize
+    let send_buffer = self.serialize(&data)?;
 
     let ddsdata = DDSData::new(SerializedPayload::new_from_bytes(
       SA::output_encoding(),
@@ -416,14 +435,11 @@ where
     match &self.qos_policy.reliability {
       None | Some(Reliability::BestEffort) => Ok(true),
       Some(Reliability::Reliable { .. }) => {
-        let (acked_sender, acked_receiver) = sync_status_channel::<()>(1)?;
-        let poll = mio_06::Poll::new()?;
-        poll.register(
-          acked_receiver.as_evented(),
-          Token(0),
-          Ready::readable(),
-          PollOpt::edge(),
-        )?;
+        let (acked_sender, mut acked_receiver) = sync_status_channel::<()>(1)?;
+        let mut poll = mio_08::Poll::new()?;
+        poll
+          .registry()
+          .register(&mut acked_receiver, Token(0), Interest::READABLE)?;
         self
           .cc_upload
           .try_send(WriterCommand::WaitForAcknowledgments {
@@ -905,6 +921,7 @@ where
   D: Keyed,
   SA: SerializerAdapter<D>,
 {
+  #[cfg(feature = "mio_06")]
   fn as_status_evented(&mut self) -> &dyn Evented {
     self.status_receiver.as_status_evented()
   }
@@ -916,6 +933,10 @@ where
   fn try_recv_status(&self) -> Option<DataWriterStatus> {
     self.status_receiver.try_recv_status()
   }
+
+  fn status_condition(&mut self, mask: BitFlags<StatusKind>) -> io::Result<StatusCondition> {
+    self.status_receiver.status_condition(mask)
+  }
 }
 
 impl<D, SA> RTPSEntity for DataWriter<D, SA>
@@ -1145,7 +1166,7 @@ where
   ) -> Result<SampleIdentity> {
     // Construct a future for an async write operation and await for its completion
 
-    let send_buffer = match SA::to_bytes(&data) {
+    let send_buffer = match self.serialize(&data) {
       Ok(s) => s,
       Err(e) => return Err(e.into()),
     };
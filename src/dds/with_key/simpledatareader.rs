@@ -4,17 +4,18 @@ use std::{
   io,
   marker::PhantomData,
   pin::Pin,
-  sync::{Arc, Mutex, MutexGuard},
+  sync::{Arc, Mutex, RwLock, RwLockReadGuard},
   task::{Context, Poll, Waker},
 };
 
 use futures::stream::{FusedStream, Stream};
 use serde::de::DeserializeOwned;
-use mio_extras::channel as mio_channel;
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
+#[cfg(feature = "mio_06")]
 use mio_06::{self, Evented};
 use mio_08;
+use enumflags2::BitFlags;
 
 use crate::{
   dds::{
@@ -26,11 +27,12 @@ use crate::{
     result::*,
     statusevents::*,
     topic::{Topic, TopicDescription},
+    waitset::ReadConditionSource,
     with_key::datasample::{DeserializedCacheChange, Sample},
   },
   discovery::discovery::DiscoveryCommand,
   log_and_err_internal, log_and_err_precondition_not_met,
-  mio_source::PollEventSource,
+  mio_source::{channel as mio_channel, PollEventSource, TaskWaker},
   serialization::CDRDeserializerAdapter,
   structure::{
     cache_change::CacheChange,
@@ -104,7 +106,7 @@ where
   pub(crate) notification_receiver: mio_channel::Receiver<()>,
 
   // SimpleDataReader stores a pointer to a mutex on the topic cache
-  topic_cache: Arc<Mutex<TopicCache>>,
+  topic_cache: Arc<RwLock<TopicCache>>,
 
   read_state: Mutex<ReadState<<D as Keyed>::K>>,
 
@@ -162,7 +164,7 @@ where
     qos_policy: QosPolicies,
     // Each notification sent to this channel must be try_recv'd
     notification_receiver: mio_channel::Receiver<()>,
-    topic_cache: Arc<Mutex<TopicCache>>,
+    topic_cache: Arc<RwLock<TopicCache>>,
     discovery_command: mio_channel::SyncSender<DiscoveryCommand>,
     status_channel_rec: StatusChannelReceiver<DataReaderStatus>,
     reader_command: mio_channel::SyncSender<ReaderCommand>,
@@ -181,7 +183,7 @@ where
     let my_guid = GUID::new_with_prefix_and_id(dp.guid_prefix(), my_id);
 
     // Verify that the topic cache corresponds to the topic of the Reader
-    let topic_cache_name = topic_cache.lock().unwrap().topic_name();
+    let topic_cache_name = topic_cache.read().unwrap().topic_name();
     if topic.name() != topic_cache_name {
       return log_and_err_internal!(
         "Topic name = {} and topic cache name = {} not equal when creating a SimpleDataReader",
@@ -314,24 +316,24 @@ where
       Some(policy::Reliability::Reliable { .. })
     );
 
-    let topic_cache = self.acquire_the_topic_cache_guard();
-
     let mut read_state_ref = self.read_state.lock().unwrap();
     let latest_instant = read_state_ref.latest_instant;
     let (last_read_sn, hash_to_key_map) = read_state_ref.get_sn_map_and_hash_map();
+    // The topic cache is locked only to find the change. The copy shares the
+    // payload, and is deserialized without blocking the event loop.
     let (timestamp, cc) = match Self::try_take_undecoded(
       is_reliable,
-      &topic_cache,
+      &self.acquire_the_topic_cache_guard(),
       latest_instant,
       last_read_sn,
     )
     .next()
     {
       None => return Ok(None),
-      Some((ts, cc)) => (ts, cc),
+      Some((ts, cc)) => (ts, cc.clone()),
     };
 
-    match Self::deserialize(timestamp, cc, hash_to_key_map) {
+    match Self::deserialize(timestamp, &cc, hash_to_key_map) {
       Ok(dcc) => {
         read_state_ref.latest_instant = max(read_state_ref.latest_instant, timestamp);
         read_state_ref
@@ -372,8 +374,8 @@ where
     }
   }
 
-  fn acquire_the_topic_cache_guard(&self) -> MutexGuard<TopicCache> {
-    self.topic_cache.lock().unwrap_or_else(|e| {
+  fn acquire_the_topic_cache_guard(&self) -> RwLockReadGuard<TopicCache> {
+    self.topic_cache.read().unwrap_or_else(|e| {
       panic!(
         "The topic cache of topic {} is poisoned. Error: {}",
         &self.my_topic.name(),
@@ -385,6 +387,7 @@ where
 
 // This is  not part of DDS spec. We implement mio Eventd so that the
 // application can asynchronously poll DataReader(s).
+#[cfg(feature = "mio_06")]
 impl<D, DA> Evented for SimpleDataReader<D, DA>
 where
   D: Keyed,
@@ -451,12 +454,24 @@ where
   }
 }
 
+impl<D, DA> ReadConditionSource for SimpleDataReader<D, DA>
+where
+  D: Keyed,
+  <D as Keyed>::K: Key,
+  DA: DeserializerAdapter<D>,
+{
+  fn task_waker(&self) -> Arc<TaskWaker> {
+    self.event_source.task_waker()
+  }
+}
+
 impl<D, DA> StatusEvented<DataReaderStatus> for SimpleDataReader<D, DA>
 where
   D: Keyed,
   <D as Keyed>::K: Key,
   DA: DeserializerAdapter<D>,
 {
+  #[cfg(feature = "mio_06")]
   fn as_status_evented(&mut self) -> &dyn Evented {
     self.status_receiver.as_status_evented()
   }
@@ -468,6 +483,10 @@ where
   fn try_recv_status(&self) -> Option<DataReaderStatus> {
     self.status_receiver.try_recv_status()
   }
+
+  fn status_condition(&mut self, mask: BitFlags<StatusKind>) -> io::Result<StatusCondition> {
+    self.status_receiver.status_condition(mask)
+  }
 }
 
 impl<D, DA> RTPSEntity for SimpleDataReader<D, DA>
@@ -3,8 +3,12 @@ pub(crate) mod content_filter_property;
 #[allow(clippy::module_inception)]
 pub(crate) mod discovery;
 pub(crate) mod discovery_db;
+pub(crate) mod initial_peers;
 pub(crate) mod sedp_messages;
 pub(crate) mod spdp_participant_data;
 
+pub use initial_peers::{InitialPeer, InitialPeerParseError};
 pub use sedp_messages::*;
 pub use spdp_participant_data::*;
+
+pub use self::discovery::DiscoveryMode;
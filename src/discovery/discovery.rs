@@ -6,8 +6,7 @@ use std::{
 
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
-use mio_06::{Events, Poll, PollOpt, Ready, Token};
-use mio_extras::{channel as mio_channel, timer::Timer};
+use mio_08::{Events, Interest, Poll, Token};
 
 use crate::{
   dds::{
@@ -30,6 +29,7 @@ use crate::{
   },
   discovery::{
     discovery_db::{DiscoveredVia, DiscoveryDB},
+    initial_peers::InitialPeer,
     sedp_messages::{
       DiscoveredReaderData, DiscoveredTopicData, DiscoveredWriterData, Endpoint_GUID,
       ParticipantMessageData, ParticipantMessageDataKind, PublicationBuiltinTopicData, ReaderProxy,
@@ -37,6 +37,7 @@ use crate::{
     },
     spdp_participant_data::{Participant_GUID, SpdpDiscoveredParticipantData},
   },
+  mio_source::{channel as mio_channel, timer::Timer},
   network::constant::*,
   serialization::pl_cdr_adapters::{PlCdrDeserializerAdapter, PlCdrSerializerAdapter},
   structure::{
@@ -48,6 +49,41 @@ use crate::{
   },
 };
 
+/// Role of a DomainParticipant in discovery
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum DiscoveryMode {
+  /// Standard SPDP and SEDP: Participants are announced by multicast and to
+  /// initial peers, and endpoint data is exchanged directly with every
+  /// discovered participant.
+  #[default]
+  Simple,
+  /// Discovery server: Works as `Simple`, but also relays participant and
+  /// endpoint discovery data received from other participants. This way
+  /// clients need to communicate only with the server.
+  Server,
+  /// Discovery client: Participant announcements are sent only to the given
+  /// servers, and built-in endpoints are matched only with the servers.
+  /// Everything else is learned from data relayed by the servers.
+  ///
+  /// A participant is recognized as a server if it announces one of the
+  /// server locators in its metatraffic unicast locators.
+  Client { servers: Vec<InitialPeer> },
+}
+
+// Settings for the Discovery thread, given by DomainParticipantBuilder
+pub(crate) struct DiscoveryConfig {
+  // How often we announce our participant via SPDP, and how long the
+  // announcement is valid at remote participants.
+  pub spdp_announcement_period: StdDuration,
+  pub participant_lease_duration: Duration,
+  // Statically configured unicast SPDP destinations
+  pub initial_peers: Vec<Locator>,
+  // Do we relay discovery data from other participants
+  pub is_server: bool,
+  // If we are a discovery client, these are the server locators
+  pub discovery_servers: Option<Vec<Locator>>,
+}
+
 #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum DiscoveryCommand {
   StopDiscovery,
@@ -93,6 +129,7 @@ pub(crate) struct Discovery {
 
   liveliness_state: LivelinessState,
   self_locators: HashMap<Token, Vec<Locator>>,
+  config: DiscoveryConfig,
 
   // DDS Subsciber and Publisher for Discovery
   // ...but these are not actually used after initialization
@@ -151,7 +188,7 @@ pub(crate) struct Discovery {
 impl Discovery {
   const PARTICIPANT_CLEANUP_PERIOD: StdDuration = StdDuration::from_secs(2);
   const TOPIC_CLEANUP_PERIOD: StdDuration = StdDuration::from_secs(60); // timer for cleaning up inactive topics
-  const SEND_PARTICIPANT_INFO_PERIOD: StdDuration = StdDuration::from_secs(2);
+  pub(crate) const SEND_PARTICIPANT_INFO_PERIOD: StdDuration = StdDuration::from_secs(2);
   const SEND_READERS_INFO_PERIOD: StdDuration = StdDuration::from_secs(2);
   const SEND_WRITERS_INFO_PERIOD: StdDuration = StdDuration::from_secs(2);
   const SEND_TOPIC_INFO_PERIOD: StdDuration = StdDuration::from_secs(10);
@@ -172,16 +209,23 @@ impl Discovery {
     history: Some(History::KeepLast { depth: 1 }),
     resource_limits: None,
     lifespan: None,
+    max_fragment_size: None,
+    flow_controller: None,
+    publish_mode: None,
+    batching: None,
+    reliability_timing: None,
   };
 
+  #[allow(clippy::too_many_arguments)]
   pub fn new(
     domain_participant: DomainParticipantWeak,
     discovery_db: Arc<RwLock<DiscoveryDB>>,
     discovery_started_sender: std::sync::mpsc::Sender<Result<()>>,
     discovery_updated_sender: mio_channel::SyncSender<DiscoveryNotificationType>,
-    discovery_command_receiver: mio_channel::Receiver<DiscoveryCommand>,
-    spdp_liveness_receiver: mio_channel::Receiver<GuidPrefix>,
+    mut discovery_command_receiver: mio_channel::Receiver<DiscoveryCommand>,
+    mut spdp_liveness_receiver: mio_channel::Receiver<GuidPrefix>,
     self_locators: HashMap<Token, Vec<Locator>>,
+    config: DiscoveryConfig,
   ) -> Result<Self> {
     // helper macro to handle initialization failures.
     macro_rules! try_construct {
@@ -199,27 +243,22 @@ impl Discovery {
       };
     }
 
-    let poll = try_construct!(
-      mio_06::Poll::new(),
-      "Failed to allocate discovery poll. {:?}"
-    );
+    let poll = try_construct!(Poll::new(), "Failed to allocate discovery poll. {:?}");
 
     try_construct!(
-      poll.register(
-        &discovery_command_receiver,
+      poll.registry().register(
+        &mut discovery_command_receiver,
         DISCOVERY_COMMAND_TOKEN,
-        Ready::readable(),
-        PollOpt::edge(),
+        Interest::READABLE
       ),
       "Failed to register Discovery poll. {:?}"
     );
 
     try_construct!(
-      poll.register(
-        &spdp_liveness_receiver,
+      poll.registry().register(
+        &mut spdp_liveness_receiver,
         SPDP_LIVENESS_TOKEN,
-        Ready::readable(),
-        PollOpt::edge(),
+        Interest::READABLE
       ),
       "Failed to register Discovery poll. {:?}"
     );
@@ -249,7 +288,7 @@ impl Discovery {
       "Unable to create DCPSParticipant topic. {:?}"
     );
 
-    let dcps_participant_reader = try_construct!( discovery_subscriber
+    let mut dcps_participant_reader = try_construct!( discovery_subscriber
       .create_datareader_with_entityid
         ::<SpdpDiscoveredParticipantData,PlCdrDeserializerAdapter<SpdpDiscoveredParticipantData>>(
         &dcps_participant_topic,
@@ -269,11 +308,10 @@ impl Discovery {
 
     // register participant reader
     try_construct!(
-      poll.register(
-        &dcps_participant_reader,
+      poll.registry().register(
+        &mut dcps_participant_reader,
         DISCOVERY_PARTICIPANT_DATA_TOKEN,
-        Ready::readable(),
-        PollOpt::edge(),
+        Interest::READABLE
       ),
       "Failed to register participant reader to poll. {:?}"
     );
@@ -282,25 +320,23 @@ impl Discovery {
     let mut participant_cleanup_timer: Timer<()> = Timer::default();
     participant_cleanup_timer.set_timeout(Self::PARTICIPANT_CLEANUP_PERIOD, ());
     try_construct!(
-      poll.register(
-        &participant_cleanup_timer,
+      poll.registry().register(
+        &mut participant_cleanup_timer,
         DISCOVERY_PARTICIPANT_CLEANUP_TOKEN,
-        Ready::readable(),
-        PollOpt::edge(),
+        Interest::READABLE
       ),
       "Unable to create participant cleanup timer. {:?}"
     );
 
     // creating timer for sending out own participant data
     let mut participant_send_info_timer: Timer<()> = Timer::default();
-    participant_send_info_timer.set_timeout(Self::SEND_PARTICIPANT_INFO_PERIOD, ());
+    participant_send_info_timer.set_timeout(config.spdp_announcement_period, ());
 
     try_construct!(
-      poll.register(
-        &participant_send_info_timer,
+      poll.registry().register(
+        &mut participant_send_info_timer,
         DISCOVERY_SEND_PARTICIPANT_INFO_TOKEN,
-        Ready::readable(),
-        PollOpt::edge(),
+        Interest::READABLE
       ),
       "Unable to register participant info sender. {:?}"
     );
@@ -318,7 +354,7 @@ impl Discovery {
       "Unable to create DCPSSubscription topic. {:?}"
     );
 
-    let dcps_subscription_reader = try_construct!( discovery_subscriber
+    let mut dcps_subscription_reader = try_construct!( discovery_subscriber
       .create_datareader_with_entityid::<DiscoveredReaderData, PlCdrDeserializerAdapter<DiscoveredReaderData>>(
         &dcps_subscription_topic,
         EntityId::SEDP_BUILTIN_SUBSCRIPTIONS_READER,
@@ -326,11 +362,10 @@ impl Discovery {
       ) ,"Unable to create DataReader for DCPSSubscription. {:?}");
 
     try_construct!(
-      poll.register(
-        &dcps_subscription_reader,
+      poll.registry().register(
+        &mut dcps_subscription_reader,
         DISCOVERY_READER_DATA_TOKEN,
-        Ready::readable(),
-        PollOpt::edge(),
+        Interest::READABLE
       ),
       "Unable to register subscription reader. {:?}"
     );
@@ -350,11 +385,10 @@ impl Discovery {
     readers_send_info_timer.set_timeout(Self::SEND_READERS_INFO_PERIOD, ());
 
     try_construct!(
-      poll.register(
-        &readers_send_info_timer,
+      poll.registry().register(
+        &mut readers_send_info_timer,
         DISCOVERY_SEND_READERS_INFO_TOKEN,
-        Ready::readable(),
-        PollOpt::edge(),
+        Interest::READABLE
       ),
       "Unable to register readers info sender. {:?}"
     );
@@ -371,7 +405,7 @@ impl Discovery {
       "Unable to create DCPSPublication topic. {:?}"
     );
 
-    let dcps_publication_reader = try_construct!( discovery_subscriber
+    let mut dcps_publication_reader = try_construct!( discovery_subscriber
       .create_datareader_with_entityid
         ::<DiscoveredWriterData, PlCdrDeserializerAdapter<DiscoveredWriterData>>(
         &dcps_publication_topic,
@@ -380,11 +414,10 @@ impl Discovery {
       ) ,"Unable to create DataReader for DCPSPublication. {:?}");
 
     try_construct!(
-      poll.register(
-        &dcps_publication_reader,
+      poll.registry().register(
+        &mut dcps_publication_reader,
         DISCOVERY_WRITER_DATA_TOKEN,
-        Ready::readable(),
-        PollOpt::edge(),
+        Interest::READABLE
       ),
       "Unable to register writers info sender. {:?}"
     );
@@ -404,11 +437,10 @@ impl Discovery {
     writers_send_info_timer.set_timeout(Self::SEND_WRITERS_INFO_PERIOD, ());
 
     try_construct!(
-      poll.register(
-        &writers_send_info_timer,
+      poll.registry().register(
+        &mut writers_send_info_timer,
         DISCOVERY_SEND_WRITERS_INFO_TOKEN,
-        Ready::readable(),
-        PollOpt::edge(),
+        Interest::READABLE
       ),
       "Unable to register readers info sender. {:?}"
     );
@@ -425,7 +457,7 @@ impl Discovery {
       "Unable to create DCPSTopic topic. {:?}"
     );
 
-    let dcps_topic_reader = try_construct!( discovery_subscriber
+    let mut dcps_topic_reader = try_construct!( discovery_subscriber
       .create_datareader_with_entityid
         ::<DiscoveredTopicData, PlCdrDeserializerAdapter<DiscoveredTopicData>>(
         &dcps_topic_topic,
@@ -434,11 +466,10 @@ impl Discovery {
       ) ,"Unable to create DataReader for DCPSTopic. {:?}");
 
     try_construct!(
-      poll.register(
-        &dcps_topic_reader,
+      poll.registry().register(
+        &mut dcps_topic_reader,
         DISCOVERY_TOPIC_DATA_TOKEN,
-        Ready::readable(),
-        PollOpt::edge(),
+        Interest::READABLE
       ),
       "Unable to register topic reader. {:?}"
     );
@@ -456,11 +487,10 @@ impl Discovery {
     let mut topic_info_send_timer: Timer<()> = Timer::default();
     topic_info_send_timer.set_timeout(Self::SEND_TOPIC_INFO_PERIOD, ());
     try_construct!(
-      poll.register(
-        &topic_info_send_timer,
+      poll.registry().register(
+        &mut topic_info_send_timer,
         DISCOVERY_SEND_TOPIC_INFO_TOKEN,
-        Ready::readable(),
-        PollOpt::edge(),
+        Interest::READABLE
       ),
       "Unable to register topic info sender. {:?}"
     );
@@ -469,11 +499,10 @@ impl Discovery {
     let mut topic_cleanup_timer: Timer<()> = Timer::default();
     topic_cleanup_timer.set_timeout(Self::TOPIC_CLEANUP_PERIOD, ());
     try_construct!(
-      poll.register(
-        &topic_cleanup_timer,
+      poll.registry().register(
+        &mut topic_cleanup_timer,
         DISCOVERY_TOPIC_CLEANUP_TOKEN,
-        Ready::readable(),
-        PollOpt::edge(),
+        Interest::READABLE
       ),
       "Unable to register topic cleanup timer. {:?}"
     );
@@ -490,7 +519,7 @@ impl Discovery {
       "Unable to create DCPSParticipantMessage topic. {:?}"
     );
 
-    let dcps_participant_message_reader = try_construct!(
+    let mut dcps_participant_message_reader = try_construct!(
       discovery_subscriber.create_datareader_cdr_with_entityid::<ParticipantMessageData>(
         &participant_message_topic,
         EntityId::P2P_BUILTIN_PARTICIPANT_MESSAGE_READER,
@@ -500,11 +529,10 @@ impl Discovery {
     );
 
     try_construct!(
-      poll.register(
-        &dcps_participant_message_reader,
+      poll.registry().register(
+        &mut dcps_participant_message_reader,
         DISCOVERY_PARTICIPANT_MESSAGE_TOKEN,
-        Ready::readable(),
-        PollOpt::edge(),
+        Interest::READABLE
       ),
       "Unable to register DCPSParticipantMessage reader. {:?}"
     );
@@ -521,11 +549,10 @@ impl Discovery {
     let mut dcps_participant_message_timer = Timer::default();
     dcps_participant_message_timer.set_timeout(Self::CHECK_PARTICIPANT_MESSAGES, ());
     try_construct!(
-      poll.register(
-        &dcps_participant_message_timer,
+      poll.registry().register(
+        &mut dcps_participant_message_timer,
         DISCOVERY_PARTICIPANT_MESSAGE_TIMER_TOKEN,
-        Ready::readable(),
-        PollOpt::edge(),
+        Interest::READABLE
       ),
       "Unable to register DCPSParticipantMessage timer. {:?}"
     );
@@ -539,6 +566,7 @@ impl Discovery {
       discovery_command_receiver,
       spdp_liveness_receiver,
       self_locators,
+      config,
 
       liveliness_state: LivelinessState::new(),
 
@@ -595,7 +623,7 @@ impl Discovery {
         }
       }
 
-      for event in events.into_iter() {
+      for event in &events {
         match event.token() {
           DISCOVERY_COMMAND_TOKEN => {
             while let Ok(command) = self.discovery_command_receiver.try_recv() {
@@ -688,7 +716,7 @@ impl Discovery {
             // setting next cleanup timeout
             self
               .participant_cleanup_timer
-              .set_timeout(Self::PARTICIPANT_CLEANUP_PERIOD, ());
+              .reset_timeout(Self::PARTICIPANT_CLEANUP_PERIOD, ());
           }
 
           DISCOVERY_SEND_PARTICIPANT_INFO_TOKEN => {
@@ -699,12 +727,10 @@ impl Discovery {
               return;
             };
 
-            // setting 5 times the duration so lease doesn't break if update fails once or
-            // twice
             let data = SpdpDiscoveredParticipantData::from_local_participant(
               &strong_dp,
               &self.self_locators,
-              5.0 * Duration::from(Self::SEND_PARTICIPANT_INFO_PERIOD),
+              self.config.participant_lease_duration,
             );
 
             self
@@ -716,7 +742,7 @@ impl Discovery {
             // reschedule timer
             self
               .participant_send_info_timer
-              .set_timeout(Self::SEND_PARTICIPANT_INFO_PERIOD, ());
+              .reset_timeout(self.config.spdp_announcement_period, ());
           }
           DISCOVERY_READER_DATA_TOKEN => {
             self.handle_subscription_reader(None);
@@ -725,7 +751,7 @@ impl Discovery {
             self.write_readers_info();
             self
               .readers_send_info_timer
-              .set_timeout(Self::SEND_READERS_INFO_PERIOD, ());
+              .reset_timeout(Self::SEND_READERS_INFO_PERIOD, ());
           }
           DISCOVERY_WRITER_DATA_TOKEN => {
             self.handle_publication_reader(None);
@@ -734,7 +760,7 @@ impl Discovery {
             self.write_writers_info();
             self
               .writers_send_info_timer
-              .set_timeout(Self::SEND_WRITERS_INFO_PERIOD, ());
+              .reset_timeout(Self::SEND_WRITERS_INFO_PERIOD, ());
           }
           DISCOVERY_TOPIC_DATA_TOKEN => {
             self.handle_topic_reader(None);
@@ -744,13 +770,13 @@ impl Discovery {
 
             self
               .topic_cleanup_timer
-              .set_timeout(Self::TOPIC_CLEANUP_PERIOD, ());
+              .reset_timeout(Self::TOPIC_CLEANUP_PERIOD, ());
           }
           DISCOVERY_SEND_TOPIC_INFO_TOKEN => {
             self.write_topic_info();
             self
               .topic_info_send_timer
-              .set_timeout(Self::SEND_TOPIC_INFO_PERIOD, ());
+              .reset_timeout(Self::SEND_TOPIC_INFO_PERIOD, ());
           }
           DISCOVERY_PARTICIPANT_MESSAGE_TOKEN => {
             self.handle_participant_message_reader();
@@ -759,7 +785,7 @@ impl Discovery {
             self.write_participant_message();
             self
               .dcps_participant_message_timer
-              .set_timeout(Self::CHECK_PARTICIPANT_MESSAGES, ());
+              .reset_timeout(Self::CHECK_PARTICIPANT_MESSAGES, ());
           }
           SPDP_LIVENESS_TOKEN => {
             while let Ok(guid_prefix) = self.spdp_liveness_receiver.try_recv() {
@@ -812,45 +838,6 @@ impl Discovery {
       guid_prefix: dp.guid().prefix,
     });
 
-    // insert a (fake) reader proxy as multicast address, so discovery notifications
-    // are sent somewhere
-    let reader_guid = GUID::new(
-      GuidPrefix::UNKNOWN,
-      EntityId::SPDP_BUILTIN_PARTICIPANT_READER,
-    );
-
-    // Do we expect inlineQos in every incoming DATA message?
-    let rustdds_expects_inline_qos = false;
-
-    let reader_proxy = ReaderProxy::new(
-      reader_guid,
-      rustdds_expects_inline_qos,
-      self
-        .self_locators
-        .get(&DISCOVERY_LISTENER_TOKEN)
-        .cloned()
-        .unwrap_or_default(),
-      self
-        .self_locators
-        .get(&DISCOVERY_MUL_LISTENER_TOKEN)
-        .cloned()
-        .unwrap_or_default(),
-    );
-
-    let sub_topic_data = SubscriptionBuiltinTopicData::new(
-      reader_guid,
-      Some(dp.guid()),
-      String::from("DCPSParticipant"),
-      String::from("SPDPDiscoveredParticipantData"),
-      &Self::create_spdp_patricipant_qos(),
-      None, // <<---------------TODO: None here means we advertise no EndpointSecurityInfo
-    );
-    let drd = DiscoveredReaderData {
-      reader_proxy,
-      subscription_topic_data: sub_topic_data,
-      content_filter: None,
-    };
-
     let writer_guid = GUID::new(dp.guid().prefix, EntityId::SPDP_BUILTIN_PARTICIPANT_WRITER);
 
     let writer_proxy = WriterProxy::new(
@@ -883,55 +870,151 @@ impl Discovery {
     // Notify local Readers and Writers in dp_event_loop
     // so that they will create WriterProxies and ReaderProxies
     // and know to communicate with them.
+    // The reader proxy is a fake one with multicast address and initial peers, so
+    // discovery notifications are sent somewhere.
     info!("Creating DCPSParticipant reader proxy.");
-    self.send_discovery_notification(DiscoveryNotificationType::ReaderUpdated {
-      discovered_reader_data: drd,
-    });
+    self.update_spdp_destinations();
     info!("Creating DCPSParticipant writer proxy for self.");
     self.send_discovery_notification(DiscoveryNotificationType::WriterUpdated {
       discovered_writer_data: dwd,
     });
   }
 
+  // The SPDP writer sends to a (fake) reader proxy with unknown GUID prefix.
+  // Its unicast locators are the initial peers and all participants that we
+  // know of, so that discovery works also without multicast. The writer sends
+  // to both the multicast and the unicast locators of this proxy.
+  // Discovery clients send only to their servers.
+  fn update_spdp_destinations(&self) {
+    let reader_guid = GUID::new(
+      GuidPrefix::UNKNOWN,
+      EntityId::SPDP_BUILTIN_PARTICIPANT_READER,
+    );
+
+    let (unicast_locators, multicast_locators) = match &self.config.discovery_servers {
+      Some(servers) => (servers.clone(), vec![]),
+      None => {
+        let mut unicast_locators = self.config.initial_peers.clone();
+        unicast_locators.extend(
+          self
+            .discovery_db_read()
+            .all_participants()
+            .flat_map(|p| p.metatraffic_unicast_locators.iter().cloned())
+            // This list mixes the locators of many participants, so shared
+            // memory would not replace the right UDP locators. SPDP goes over
+            // UDP.
+            .filter(|l| !l.is_shm()),
+        );
+        unicast_locators.sort();
+        unicast_locators.dedup();
+        let multicast_locators = self
+          .self_locators
+          .get(&DISCOVERY_MUL_LISTENER_TOKEN)
+          .cloned()
+          .unwrap_or_default();
+        (unicast_locators, multicast_locators)
+      }
+    };
+
+    // Do we expect inlineQos in every incoming DATA message?
+    let rustdds_expects_inline_qos = false;
+
+    let reader_proxy = ReaderProxy::new(
+      reader_guid,
+      rustdds_expects_inline_qos,
+      unicast_locators,
+      multicast_locators,
+    );
+
+    let sub_topic_data = SubscriptionBuiltinTopicData::new(
+      reader_guid,
+      Some(self.domain_participant.guid()),
+      String::from("DCPSParticipant"),
+      String::from("SPDPDiscoveredParticipantData"),
+      &Self::create_spdp_patricipant_qos(),
+      None, // <<---------------TODO: None here means we advertise no EndpointSecurityInfo
+    );
+    let drd = DiscoveredReaderData {
+      reader_proxy,
+      subscription_topic_data: sub_topic_data,
+      content_filter: None,
+    };
+
+    debug!("Updating SPDP destinations: {:?}", drd.reader_proxy);
+    self.send_discovery_notification(DiscoveryNotificationType::ReaderUpdated {
+      discovered_reader_data: drd,
+    });
+  }
+
+  // A discovery server relays data that it has received first-hand, i.e. from
+  // the participant that the data is about. Relaying anything else would
+  // make servers echo each other's (and their own) relayed data forever.
+  fn relays_from(&self, writer_prefix: GuidPrefix, data_prefix: GuidPrefix) -> bool {
+    self.config.is_server
+      && writer_prefix == data_prefix
+      && data_prefix != self.domain_participant.guid().prefix
+  }
+
   pub fn handle_participant_reader(&mut self) {
     loop {
       let s = self.dcps_participant_reader.take_next_sample();
       debug!("handle_participant_reader read {:?}", &s);
       match s {
-        Ok(Some(d)) => match d.value {
-          Sample::Value(participant_data) => {
-            debug!(
-              "handle_participant_reader discovered {:?}",
-              &participant_data
-            );
-            let was_new = self
-              .discovery_db_write()
-              .update_participant(&participant_data);
-            let guid_prefix = participant_data.participant_guid.prefix;
-            self.send_discovery_notification(DiscoveryNotificationType::ParticipantUpdated {
-              guid_prefix,
-            });
-            if was_new {
-              // This may be a rediscovery of a previously seen participant that
-              // was temporarily lost due to network outage. Check if we already know
-              // what it has (readers, writers, topics).
-              debug!("Participant rediscovery start");
-              self.handle_topic_reader(Some(guid_prefix));
-              self.handle_subscription_reader(Some(guid_prefix));
-              self.handle_publication_reader(Some(guid_prefix));
-              debug!("Participant rediscovery finished");
+        Ok(Some(d)) => {
+          let writer_prefix = d.sample_info().writer_guid().prefix;
+          match d.into_value() {
+            Sample::Value(participant_data) => {
+              debug!(
+                "handle_participant_reader discovered {:?}",
+                &participant_data
+              );
+              let was_new = self
+                .discovery_db_write()
+                .update_participant(&participant_data);
+              let guid_prefix = participant_data.participant_guid.prefix;
+              if was_new {
+                // Learn a new unicast SPDP destination
+                self.update_spdp_destinations();
+              }
+              if self.relays_from(writer_prefix, guid_prefix) {
+                self
+                  .dcps_participant_writer
+                  .write(participant_data.clone(), None)
+                  .unwrap_or_else(|e| error!("Relaying participant data failed: {e:?}"));
+              }
+              self.send_discovery_notification(DiscoveryNotificationType::ParticipantUpdated {
+                guid_prefix,
+              });
+              if was_new {
+                // This may be a rediscovery of a previously seen participant that
+                // was temporarily lost due to network outage. Check if we already know
+                // what it has (readers, writers, topics).
+                debug!("Participant rediscovery start");
+                self.handle_topic_reader(Some(guid_prefix));
+                self.handle_subscription_reader(Some(guid_prefix));
+                self.handle_publication_reader(Some(guid_prefix));
+                debug!("Participant rediscovery finished");
+              }
+            }
+            // Sample::Dispose means that DomainParticipant was disposed
+            Sample::Dispose(participant_guid) => {
+              let removed = self
+                .discovery_db_write()
+                .remove_participant(participant_guid.0.prefix, true); // true = actively removed
+              self.update_spdp_destinations();
+              if self.relays_from(writer_prefix, participant_guid.0.prefix) {
+                self
+                  .dcps_participant_writer
+                  .dispose(&participant_guid, None)
+                  .unwrap_or_else(|e| error!("Relaying participant dispose failed: {e:?}"));
+              }
+              self.send_discovery_notification(DiscoveryNotificationType::ParticipantLost {
+                guid_prefix: participant_guid.0.prefix,
+                locators: removed.map_or_else(Vec::new, |p| p.unicast_locators()),
+              });
             }
           }
-          // Sample::Dispose means that DomainParticipant was disposed
-          Sample::Dispose(participant_guid) => {
-            self
-              .discovery_db_write()
-              .remove_participant(participant_guid.0.prefix, true); // true = actively removed
-            self.send_discovery_notification(DiscoveryNotificationType::ParticipantLost {
-              guid_prefix: participant_guid.0.prefix,
-            });
-          }
-        },
+        }
         Ok(None) => {
           trace!("handle_participant_reader: no more data");
           return;
@@ -946,11 +1029,20 @@ impl Discovery {
 
   // Check if there are messages about new Readers
   pub fn handle_subscription_reader(&mut self, read_history: Option<GuidPrefix>) {
-    let drds: Vec<Sample<DiscoveredReaderData, GUID>> =
-      match self.dcps_subscription_reader.into_iterator() {
-        Ok(ds) => ds
-          .map(|d| d.map_dispose(|g| g.0)) // map_dispose removes Endpoint_GUID wrapper around GUID
-          .filter(|d|
+    // Pairs of (writer GUID prefix, sample). Writer prefix is needed for relaying.
+    let drds: Vec<(GuidPrefix, Sample<DiscoveredReaderData, GUID>)> = match self
+      .dcps_subscription_reader
+      .take(usize::MAX, ReadCondition::not_read())
+    {
+      Ok(ds) => ds
+        .into_iter()
+        .map(|d| {
+          (
+            d.sample_info().writer_guid().prefix,
+            d.into_value().map_dispose(|g| g.0), // map_dispose removes Endpoint_GUID wrapper
+          )
+        })
+        .filter(|(_, d)|
               // If a particiapnt was specified, we must match its GUID prefix.
               match (read_history, d) {
                 (None, _) => true, // Not asked to filter by participant
@@ -959,16 +1051,22 @@ impl Discovery {
                 (Some(participant_to_update), Sample::Dispose(guid)) =>
                   guid.prefix == participant_to_update,
               })
-          .collect(),
-        Err(e) => {
-          error!("handle_subscription_reader: {e:?}");
-          return;
-        }
-      };
+        .collect(),
+      Err(e) => {
+        error!("handle_subscription_reader: {e:?}");
+        return;
+      }
+    };
 
-    for d in drds {
+    for (writer_prefix, d) in drds {
       match d {
         Sample::Value(d) => {
+          if self.relays_from(writer_prefix, d.reader_proxy.remote_reader_guid.prefix) {
+            self
+              .dcps_subscription_writer
+              .write(d.clone(), None)
+              .unwrap_or_else(|e| error!("Relaying reader data failed: {e:?}"));
+          }
           let drd = self.discovery_db_write().update_subscription(&d);
           debug!(
             "handle_subscription_reader - send_discovery_notification ReaderUpdated  {:?}",
@@ -987,6 +1085,12 @@ impl Discovery {
         }
         Sample::Dispose(reader_key) => {
           info!("Dispose Reader {:?}", reader_key);
+          if self.relays_from(writer_prefix, reader_key.prefix) {
+            self
+              .dcps_subscription_writer
+              .dispose(&Endpoint_GUID(reader_key), None)
+              .unwrap_or_else(|e| error!("Relaying reader dispose failed: {e:?}"));
+          }
           self.discovery_db_write().remove_topic_reader(reader_key);
           self.send_discovery_notification(DiscoveryNotificationType::ReaderLost {
             reader_guid: reader_key,
@@ -997,34 +1101,49 @@ impl Discovery {
   }
 
   pub fn handle_publication_reader(&mut self, read_history: Option<GuidPrefix>) {
-    let dwds: Vec<Sample<DiscoveredWriterData, GUID>> =
-      match self.dcps_publication_reader.into_iterator() {
-        // a lot of cloning here, but we must copy the data out of the
-        // reader before we can use self again, as .read() returns references to within
-        // a reader and thus self
-        Ok(ds) => ds
-          .map(|d| d.map_dispose(|g| g.0)) // map_dispose removes Endpoint_GUID wrapper around GUID
-          // If a particiapnt was specified, we must match its GUID prefix.
-          .filter(|d| match (read_history, d) {
-            (None, _) => true, // Not asked to filter by participant
-            (Some(participant_to_update), Sample::Value(dwd)) => {
-              dwd.writer_proxy.remote_writer_guid.prefix == participant_to_update
-            }
-            (Some(participant_to_update), Sample::Dispose(guid)) => {
-              guid.prefix == participant_to_update
-            }
-          })
-          .collect(),
-        Err(e) => {
-          error!("handle_publication_reader: {e:?}");
-          return;
-        }
-      };
+    // Pairs of (writer GUID prefix, sample). Writer prefix is needed for relaying.
+    let dwds: Vec<(GuidPrefix, Sample<DiscoveredWriterData, GUID>)> = match self
+      .dcps_publication_reader
+      .take(usize::MAX, ReadCondition::not_read())
+    {
+      // a lot of cloning here, but we must copy the data out of the
+      // reader before we can use self again, as .read() returns references to within
+      // a reader and thus self
+      Ok(ds) => ds
+        .into_iter()
+        .map(|d| {
+          (
+            d.sample_info().writer_guid().prefix,
+            d.into_value().map_dispose(|g| g.0), // map_dispose removes Endpoint_GUID wrapper
+          )
+        })
+        // If a particiapnt was specified, we must match its GUID prefix.
+        .filter(|(_, d)| match (read_history, d) {
+          (None, _) => true, // Not asked to filter by participant
+          (Some(participant_to_update), Sample::Value(dwd)) => {
+            dwd.writer_proxy.remote_writer_guid.prefix == participant_to_update
+          }
+          (Some(participant_to_update), Sample::Dispose(guid)) => {
+            guid.prefix == participant_to_update
+          }
+        })
+        .collect(),
+      Err(e) => {
+        error!("handle_publication_reader: {e:?}");
+        return;
+      }
+    };
 
-    for d in dwds {
+    for (writer_prefix, d) in dwds {
       match d {
         Sample::Value(dwd) => {
           trace!("handle_publication_reader discovered {:?}", &dwd);
+          if self.relays_from(writer_prefix, dwd.writer_proxy.remote_writer_guid.prefix) {
+            self
+              .dcps_publication_writer
+              .write(dwd.clone(), None)
+              .unwrap_or_else(|e| error!("Relaying writer data failed: {e:?}"));
+          }
           let discovered_writer_data = self.discovery_db_write().update_publication(&dwd);
           self.send_discovery_notification(DiscoveryNotificationType::WriterUpdated {
             discovered_writer_data,
@@ -1032,6 +1151,12 @@ impl Discovery {
           debug!("Discovered Writer {:?}", &dwd);
         }
         Sample::Dispose(writer_key) => {
+          if self.relays_from(writer_prefix, writer_key.prefix) {
+            self
+              .dcps_publication_writer
+              .dispose(&Endpoint_GUID(writer_key), None)
+              .unwrap_or_else(|e| error!("Relaying writer dispose failed: {e:?}"));
+          }
           self.discovery_db_write().remove_topic_writer(writer_key);
           self.send_discovery_notification(DiscoveryNotificationType::WriterLost {
             writer_guid: writer_key,
@@ -1233,10 +1358,17 @@ impl Discovery {
   }
 
   pub fn participant_cleanup(&self) {
-    let removed_guid_prefixes = self.discovery_db_write().participant_cleanup();
-    for guid_prefix in removed_guid_prefixes {
+    let removed_participants = self.discovery_db_write().participant_cleanup();
+    if !removed_participants.is_empty() {
+      self.update_spdp_destinations();
+    }
+    for participant in removed_participants {
+      let guid_prefix = participant.participant_guid.prefix;
       debug!("participant cleanup - timeout for {:?}", guid_prefix);
-      self.send_discovery_notification(DiscoveryNotificationType::ParticipantLost { guid_prefix });
+      self.send_discovery_notification(DiscoveryNotificationType::ParticipantLost {
+        guid_prefix,
+        locators: participant.unicast_locators(),
+      });
     }
   }
 
@@ -1398,7 +1530,6 @@ mod tests {
 
   use chrono::Utc;
   //use bytes::Bytes;
-  use mio_06::Token;
   use speedy::{Endianness, Writable};
 
   use super::*;
@@ -1422,22 +1553,20 @@ mod tests {
 
   #[test]
   fn discovery_participant_data_test() {
-    let poll = Poll::new().unwrap();
+    let mut poll = Poll::new().unwrap();
     let mut udp_listener = UDPListener::new_unicast("127.0.0.1", 11000).unwrap();
     poll
-      .register(
-        udp_listener.mio_socket(),
-        Token(0),
-        Ready::readable(),
-        PollOpt::edge(),
-      )
+      .registry()
+      .register(udp_listener.mio_socket(), Token(0), Interest::READABLE)
       .unwrap();
 
     // sending participant data to discovery
     let udp_sender = UDPSender::new_with_random_port().expect("failed to create UDPSender");
     let addresses = vec![SocketAddr::new(
       "127.0.0.1".parse().unwrap(),
-      spdp_well_known_unicast_port(0, 0),
+      PortMapping::default()
+        .spdp_well_known_unicast_port(0, 0)
+        .unwrap(),
     )];
 
     let tdata = spdp_participant_msg_mod(11000);
@@ -1485,21 +1614,19 @@ mod tests {
     let _reader =
       subscriber.create_datareader::<ShapeType, CDRDeserializerAdapter<ShapeType>>(&topic, None);
 
-    let poll = Poll::new().unwrap();
+    let mut poll = Poll::new().unwrap();
     let mut udp_listener = UDPListener::new_unicast("127.0.0.1", 11001).unwrap();
     poll
-      .register(
-        udp_listener.mio_socket(),
-        Token(0),
-        Ready::readable(),
-        PollOpt::edge(),
-      )
+      .registry()
+      .register(udp_listener.mio_socket(), Token(0), Interest::READABLE)
       .unwrap();
 
     let udp_sender = UDPSender::new_with_random_port().expect("failed to create UDPSender");
     let addresses = vec![SocketAddr::new(
       "127.0.0.1".parse().unwrap(),
-      spdp_well_known_unicast_port(14, 0),
+      PortMapping::default()
+        .spdp_well_known_unicast_port(14, 0)
+        .unwrap(),
     )];
 
     let mut tdata = spdp_subscription_msg();
@@ -1575,21 +1702,19 @@ mod tests {
     let _reader =
       subscriber.create_datareader::<ShapeType, CDRDeserializerAdapter<ShapeType>>(&topic, None);
 
-    let poll = Poll::new().unwrap();
+    let mut poll = Poll::new().unwrap();
     let mut udp_listener = UDPListener::new_unicast("127.0.0.1", 0).unwrap();
     poll
-      .register(
-        udp_listener.mio_socket(),
-        Token(0),
-        Ready::readable(),
-        PollOpt::edge(),
-      )
+      .registry()
+      .register(udp_listener.mio_socket(), Token(0), Interest::READABLE)
       .unwrap();
 
     let udp_sender = UDPSender::new_with_random_port().expect("failed to create UDPSender");
     let addresses = vec![SocketAddr::new(
       "127.0.0.1".parse().unwrap(),
-      spdp_well_known_unicast_port(15, 0),
+      PortMapping::default()
+        .spdp_well_known_unicast_port(15, 0)
+        .unwrap(),
     )];
 
     let mut tdata = spdp_publication_msg();
@@ -1661,7 +1786,9 @@ mod tests {
     let udp_sender = UDPSender::new_with_random_port().expect("failed to create UDPSender");
     let addresses = vec![SocketAddr::new(
       "127.0.0.1".parse().unwrap(),
-      spdp_well_known_unicast_port(16, 0),
+      PortMapping::default()
+        .spdp_well_known_unicast_port(16, 0)
+        .unwrap(),
     )];
 
     let rr = rtps_message
@@ -10,6 +10,7 @@ use crate::{
     qos::HasQoSPolicy,
     topic::{Topic, TopicDescription},
   },
+  messages::vendor_id::VendorId,
   rtps::{
     reader::ReaderIngredients, rtps_reader_proxy::RtpsReaderProxy,
     rtps_writer_proxy::RtpsWriterProxy,
@@ -62,7 +63,7 @@ pub(crate) struct DiscoveryDB {
   topics: BTreeMap<String, BTreeMap<GuidPrefix, (DiscoveredVia, DiscoveredTopicData)>>,
 
   // sender for notifying (potential) waiters in participant.find_topic() call
-  topic_updated_sender: mio_extras::channel::SyncSender<()>,
+  topic_updated_sender: crate::mio_source::channel::SyncSender<()>,
 }
 
 // How did we discover this topic
@@ -85,7 +86,10 @@ fn move_by_guid_prefix<D>(
 }
 
 impl DiscoveryDB {
-  pub fn new(my_guid: GUID, topic_updated_sender: mio_extras::channel::SyncSender<()>) -> Self {
+  pub fn new(
+    my_guid: GUID,
+    topic_updated_sender: crate::mio_source::channel::SyncSender<()>,
+  ) -> Self {
     Self {
       my_guid,
       participant_proxies: BTreeMap::new(),
@@ -176,9 +180,14 @@ impl DiscoveryDB {
   // active_disposal means that we received a discovery message announcing the
   // disposal of the participant. active_disposal=false means that the
   // participant timed out.
-  pub fn remove_participant(&mut self, guid_prefix: GuidPrefix, active_disposal: bool) {
+  // Returns the removed participant, if it was known.
+  pub fn remove_participant(
+    &mut self,
+    guid_prefix: GuidPrefix,
+    active_disposal: bool,
+  ) -> Option<SpdpDiscoveredParticipantData> {
     info!("removing participant {:?}", guid_prefix);
-    self.participant_proxies.remove(&guid_prefix);
+    let removed = self.participant_proxies.remove(&guid_prefix);
     self.participant_last_life_signs.remove(&guid_prefix);
 
     if active_disposal {
@@ -197,6 +206,11 @@ impl DiscoveryDB {
         &mut self.external_topic_writers_attic,
       );
     }
+    removed
+  }
+
+  pub fn all_participants(&self) -> impl Iterator<Item = &SpdpDiscoveredParticipantData> {
+    self.participant_proxies.values()
   }
 
   pub fn find_participant_proxy(
@@ -243,7 +257,8 @@ impl DiscoveryDB {
 
   // Delete participant proxies, if we have not heard of them within
   // lease_duration
-  pub fn participant_cleanup(&mut self) -> Vec<GuidPrefix> {
+  // Returns the removed participants.
+  pub fn participant_cleanup(&mut self) -> Vec<SpdpDiscoveredParticipantData> {
     let inow = Instant::now();
 
     let mut to_remove = Vec::new();
@@ -271,10 +286,10 @@ impl DiscoveryDB {
         }
       } // match
     } // for
-    for guid in &to_remove {
-      self.remove_participant(*guid, false); // false = removed due to timeout
-    }
     to_remove
+      .into_iter()
+      .filter_map(|guid| self.remove_participant(guid, false)) // false = removed due to timeout
+      .collect()
   }
 
   fn topic_has_writers_or_readers(&self, topic_name: &str) -> bool {
@@ -380,6 +395,12 @@ impl DiscoveryDB {
       });
     debug!("External reader: {:?}", data);
 
+    // Vendor-specific parameters are meaningful only from our own vendor. The
+    // participant is the one that owns the Reader, even if the data was relayed.
+    let from_rustdds = self
+      .find_participant_proxy(guid.prefix)
+      .map_or(false, |pp| pp.vendor_id == VendorId::THIS_IMPLEMENTATION);
+
     // Now the topic update:
     let dtd = data.subscription_topic_data.to_topic_data();
     self.update_topic_data(
@@ -394,11 +415,14 @@ impl DiscoveryDB {
 
     // Return DiscoveredReaderData with possibly updated locators.
     DiscoveredReaderData {
-      reader_proxy: ReaderProxy::from(RtpsReaderProxy::from_discovered_reader_data(
-        data,
-        &default_locator_lists.0,
-        &default_locator_lists.1,
-      )),
+      reader_proxy: ReaderProxy {
+        max_fragment_size: data.reader_proxy.max_fragment_size.filter(|_| from_rustdds),
+        ..ReaderProxy::from(RtpsReaderProxy::from_discovered_reader_data(
+          data,
+          &default_locator_lists.0,
+          &default_locator_lists.1,
+        ))
+      },
       ..data.clone()
     }
   }
@@ -446,11 +470,14 @@ impl DiscoveryDB {
     );
 
     DiscoveredWriterData {
-      writer_proxy: WriterProxy::from(RtpsWriterProxy::from_discovered_writer_data(
-        data,
-        &default_locator_lists.0,
-        &default_locator_lists.1,
-      )),
+      writer_proxy: WriterProxy {
+        data_max_size_serialized: data.writer_proxy.data_max_size_serialized,
+        ..WriterProxy::from(RtpsWriterProxy::from_discovered_writer_data(
+          data,
+          &default_locator_lists.0,
+          &default_locator_lists.1,
+        ))
+      },
       ..data.clone()
     }
   }
@@ -679,9 +706,7 @@ mod tests {
   };
 
   use byteorder::LittleEndian;
-  use mio_extras::channel as mio_channel;
 
-  use super::*;
   use crate::{
     dds::{
       qos::QosPolicies,
@@ -690,6 +715,7 @@ mod tests {
       with_key::simpledatareader::ReaderCommand,
     },
     mio_source,
+    mio_source::channel as mio_channel,
     serialization::cdr_serializer::CDRSerializerAdapter,
     structure::guid::*,
     test::{
@@ -697,6 +723,7 @@ mod tests {
       test_data::{reader_proxy_data, spdp_participant_data, subscription_builtin_topic_data},
     },
   };
+  use super::*;
 
   #[test]
   fn discdb_participant_operations() {
@@ -714,12 +741,48 @@ mod tests {
     assert_eq!(discoverydb.participant_proxies.len(), 1);
 
     std::thread::sleep(StdDuration::from_secs(2));
-    discoverydb.participant_cleanup();
+    assert_eq!(discoverydb.participant_cleanup().len(), 1);
     assert!(discoverydb.participant_proxies.is_empty());
 
     // TODO: more operations tests
   }
 
+  #[test]
+  fn discdb_rustdds_max_fragment_size() {
+    let (discovery_db_event_sender, _discovery_db_event_receiver) =
+      mio_channel::sync_channel::<()>(4);
+    let mut discovery_db =
+      DiscoveryDB::new(GUID::new_participant_guid(), discovery_db_event_sender);
+
+    let reader_proxy = reader_proxy_data().unwrap();
+    let dreader = DiscoveredReaderData {
+      reader_proxy: reader_proxy.clone(),
+      subscription_topic_data: subscription_builtin_topic_data().unwrap(),
+      content_filter: None,
+    };
+    let announced = |discovery_db: &mut DiscoveryDB| {
+      discovery_db
+        .update_subscription(&dreader)
+        .reader_proxy
+        .max_fragment_size
+    };
+    // Participant not known yet
+    assert_eq!(announced(&mut discovery_db), None);
+
+    let mut participant = spdp_participant_data().unwrap();
+    participant.participant_guid = GUID::new(
+      reader_proxy.remote_reader_guid.prefix,
+      EntityId::PARTICIPANT,
+    );
+    participant.vendor_id = VendorId::VENDOR_UNKNOWN;
+    discovery_db.update_participant(&participant);
+    assert_eq!(announced(&mut discovery_db), None);
+
+    participant.vendor_id = VendorId::THIS_IMPLEMENTATION;
+    discovery_db.update_participant(&participant);
+    assert_eq!(announced(&mut discovery_db), Some(24000));
+  }
+
   #[test]
   fn discdb_writer_proxies() {
     let (discovery_db_event_sender, _discovery_db_event_receiver) =
@@ -834,14 +897,15 @@ mod tests {
     let mut discoverydb = DiscoveryDB::new(GUID::new_participant_guid(), discovery_db_event_sender);
 
     // Create reader ingredients
-    let (notification_sender1, _notification_receiver1) = mio_extras::channel::sync_channel(100);
+    let (notification_sender1, _notification_receiver1) =
+      crate::mio_source::channel::sync_channel(100);
     let (_notification_event_source1, notification_event_sender1) =
       mio_source::make_poll_channel().unwrap();
     let data_reader_waker1 = Arc::new(Mutex::new(None));
 
     let (status_sender1, _status_receiver1) = sync_status_channel::<DataReaderStatus>(4).unwrap();
     let (_reader_commander1, reader_command_receiver1) =
-      mio_extras::channel::sync_channel::<ReaderCommand>(100);
+      crate::mio_source::channel::sync_channel::<ReaderCommand>(100);
 
     let topic_cache =
       dp.dds_cache()
@@ -872,14 +936,15 @@ mod tests {
     assert_eq!(discoverydb.get_local_topic_readers(&topic).len(), 1);
 
     // Create second reader ingredients for the same topic
-    let (notification_sender2, _notification_receiver2) = mio_extras::channel::sync_channel(100);
+    let (notification_sender2, _notification_receiver2) =
+      crate::mio_source::channel::sync_channel(100);
     let (_notification_event_source2, notification_event_sender2) =
       mio_source::make_poll_channel().unwrap();
     let data_reader_waker2 = Arc::new(Mutex::new(None));
 
     let (status_sender2, _status_receiver2) = sync_status_channel::<DataReaderStatus>(4).unwrap();
     let (_reader_commander2, reader_command_receiver2) =
-      mio_extras::channel::sync_channel::<ReaderCommand>(100);
+      crate::mio_source::channel::sync_channel::<ReaderCommand>(100);
 
     let mut guid2 = GUID::dummy_test_guid(EntityKind::READER_NO_KEY_USER_DEFINED);
     guid2.prefix = GuidPrefix::new(b"Another fake"); // GUID needs to be different in order to be added
@@ -0,0 +1,341 @@
+use std::{
+  fmt,
+  net::{Ipv6Addr, SocketAddr, ToSocketAddrs},
+  ops::RangeInclusive,
+  str::FromStr,
+};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::{
+  dds::result::{self, Error},
+  network::{constant::PortMapping, tcp_transport::tcp_locator},
+  structure::locator::Locator,
+};
+
+/// A statically configured peer for unicast SPDP discovery.
+///
+/// SPDP announcements are sent to initial peers in addition to (or, with
+/// multicast disabled, instead of) the SPDP multicast group. Peers that are
+/// discovered from received SPDP data are added automatically, so only some
+/// of the participants need to be listed.
+///
+/// A peer can be given either as an exact address, or as a host and a range
+/// of participant ids, in which case the SPDP unicast port of each
+/// participant id in the range is used.
+///
+/// The string form accepts
+/// * `"host"`: participant ids 0 to 4 on the host,
+/// * `"host:port"`: exactly the given UDP port,
+/// * `"host[first-last]"`: the given range of participant ids,
+/// * `"tcp://host:port"`: a participant with TCP enabled, listening on the
+///   given TCP port.
+///
+/// `host` is either an IP address or a name that is resolved when the
+/// participant is built. IPv6 addresses are written in brackets, e.g.
+/// `"[fd00::1]:7410"`, except that a bare address without port or range can
+/// also be given as is. Peer addresses of an IP version that is not enabled
+/// in the participant are ignored.
+///
+/// # Examples
+/// ```
+/// # use rustdds::InitialPeer;
+/// let peer: InitialPeer = "192.168.1.10[0-9]".parse().unwrap();
+/// let peer: InitialPeer = "robot-base.local:7410".parse().unwrap();
+/// let peer: InitialPeer = "[fd00::1][0-3]".parse().unwrap();
+/// let peer: InitialPeer = "tcp://gateway.example.com:7650".parse().unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InitialPeer {
+  host: String,
+  ports: PeerPorts,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PeerPorts {
+  Port(u16),
+  ParticipantIds(RangeInclusive<u16>),
+  Tcp(u16),
+}
+
+impl InitialPeer {
+  // Same default as some other vendors use.
+  const DEFAULT_PARTICIPANT_IDS: RangeInclusive<u16> = 0..=4;
+
+  /// Peer at an exact UDP port.
+  pub fn with_port(host: impl Into<String>, port: u16) -> Self {
+    Self {
+      host: host.into(),
+      ports: PeerPorts::Port(port),
+    }
+  }
+
+  /// Peer at SPDP unicast ports of the given participant ids.
+  pub fn with_participant_ids(
+    host: impl Into<String>,
+    participant_ids: RangeInclusive<u16>,
+  ) -> Self {
+    Self {
+      host: host.into(),
+      ports: PeerPorts::ParticipantIds(participant_ids),
+    }
+  }
+
+  /// Peer with TCP enabled, listening on the given TCP port.
+  pub fn tcp(host: impl Into<String>, port: u16) -> Self {
+    Self {
+      host: host.into(),
+      ports: PeerPorts::Tcp(port),
+    }
+  }
+
+  // Resolve to Locators. Failures to resolve are only logged, because an
+  // unreachable peer should not prevent us from starting. Port numbers that do
+  // not fit in u16 are an error.
+  pub(crate) fn to_locators(
+    &self,
+    domain_id: u16,
+    port_mapping: &PortMapping,
+  ) -> result::Result<Vec<Locator>> {
+    let overflow = || Error::BadParameter {
+      reason: format!(
+        "Initial peer {:?}: port numbers of domain {domain_id} overflow with {port_mapping:?}",
+        self.host
+      ),
+    };
+    let ports: Vec<u16> = match &self.ports {
+      PeerPorts::Port(port) | PeerPorts::Tcp(port) => vec![*port],
+      PeerPorts::ParticipantIds(ids) => ids
+        .clone()
+        .map(|id| port_mapping.spdp_well_known_unicast_port(domain_id, id))
+        .collect::<Option<_>>()
+        .ok_or_else(overflow)?,
+    };
+    // We do not know the participant id of a TCP peer, so SPDP is sent to the
+    // logical port that all participants of the domain listen to.
+    let tcp_logical_port = match self.ports {
+      PeerPorts::Tcp(_) => Some(
+        port_mapping
+          .spdp_well_known_multicast_port(domain_id)
+          .ok_or_else(overflow)?,
+      ),
+      _ => None,
+    };
+
+    let ip_addrs: Vec<_> = match (self.host.as_str(), 0).to_socket_addrs() {
+      Ok(addrs) => addrs.map(|sa| sa.ip()).collect(),
+      Err(e) => {
+        warn!("Cannot resolve initial peer {:?}: {e:?}", self.host);
+        vec![]
+      }
+    };
+
+    let to_locator = |address| match tcp_logical_port {
+      Some(logical_port) => tcp_locator(address, logical_port),
+      None => Locator::from(address),
+    };
+
+    Ok(
+      ip_addrs
+        .iter()
+        .flat_map(|ip| {
+          ports
+            .iter()
+            .map(|port| to_locator(SocketAddr::new(*ip, *port)))
+        })
+        .collect(),
+    )
+  }
+}
+
+/// Error from parsing an [`InitialPeer`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InitialPeerParseError(String);
+
+impl fmt::Display for InitialPeerParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Invalid initial peer {:?}", self.0)
+  }
+}
+
+impl std::error::Error for InitialPeerParseError {}
+
+impl FromStr for InitialPeer {
+  type Err = InitialPeerParseError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let err = || InitialPeerParseError(s.to_string());
+
+    if let Some(address) = s.strip_prefix("tcp://") {
+      return match address.parse::<Self>() {
+        Ok(Self {
+          host,
+          ports: PeerPorts::Port(port),
+        }) => Ok(Self::tcp(host, port)),
+        _ => Err(err()),
+      };
+    }
+
+    // Bare IPv6 address. Its colons would otherwise be taken as a port separator.
+    if s.parse::<Ipv6Addr>().is_ok() {
+      return Ok(Self::with_participant_ids(s, Self::DEFAULT_PARTICIPANT_IDS));
+    }
+
+    // Split "[ipv6-address]rest" or "host-or-ipv4rest", so that rest is "",
+    // ":port" or "[first-last]".
+    let (host, rest) = match s.strip_prefix('[') {
+      Some(bracketed) => {
+        let (host, rest) = bracketed.split_once(']').ok_or_else(err)?;
+        host.parse::<Ipv6Addr>().map_err(|_| err())?;
+        (host, rest)
+      }
+      None => match s.find([':', '[']) {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+      },
+    };
+    if host.is_empty() {
+      return Err(err());
+    }
+
+    if let Some(range) = rest.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+      let (first, last) = range.split_once('-').ok_or_else(err)?;
+      let first = first.trim().parse().map_err(|_| err())?;
+      let last = last.trim().parse().map_err(|_| err())?;
+      if first > last {
+        return Err(err());
+      }
+      Ok(Self::with_participant_ids(host, first..=last))
+    } else if let Some(port) = rest.strip_prefix(':') {
+      let port = port.parse().map_err(|_| err())?;
+      Ok(Self::with_port(host, port))
+    } else if rest.is_empty() {
+      Ok(Self::with_participant_ids(
+        host,
+        Self::DEFAULT_PARTICIPANT_IDS,
+      ))
+    } else {
+      Err(err())
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::Ipv4Addr;
+
+  use super::*;
+
+  #[test]
+  fn initial_peer_parse() {
+    assert_eq!(
+      "10.0.0.5".parse(),
+      Ok(InitialPeer::with_participant_ids("10.0.0.5", 0..=4))
+    );
+    assert_eq!(
+      "10.0.0.5:7410".parse(),
+      Ok(InitialPeer::with_port("10.0.0.5", 7410))
+    );
+    assert_eq!(
+      "somehost[2-3]".parse(),
+      Ok(InitialPeer::with_participant_ids("somehost", 2..=3))
+    );
+    assert_eq!(
+      "::1".parse(),
+      Ok(InitialPeer::with_participant_ids("::1", 0..=4))
+    );
+    assert_eq!(
+      "[fd00::1]:7410".parse(),
+      Ok(InitialPeer::with_port("fd00::1", 7410))
+    );
+    assert_eq!(
+      "[fd00::1][1-2]".parse(),
+      Ok(InitialPeer::with_participant_ids("fd00::1", 1..=2))
+    );
+    assert_eq!(
+      "[fd00::1]".parse(),
+      Ok(InitialPeer::with_participant_ids("fd00::1", 0..=4))
+    );
+    assert!("".parse::<InitialPeer>().is_err());
+    assert!("[somehost]:7410".parse::<InitialPeer>().is_err());
+    assert!("[fd00::1".parse::<InitialPeer>().is_err());
+    assert!("10.0.0.5:7410x".parse::<InitialPeer>().is_err());
+    assert!("10.0.0.5:".parse::<InitialPeer>().is_err());
+    assert!("10.0.0.5[3-2]".parse::<InitialPeer>().is_err());
+    assert!("10.0.0.5[3]".parse::<InitialPeer>().is_err());
+
+    assert_eq!(
+      "tcp://10.0.0.5:7650".parse(),
+      Ok(InitialPeer::tcp("10.0.0.5", 7650))
+    );
+    assert_eq!(
+      "tcp://[fd00::1]:7650".parse(),
+      Ok(InitialPeer::tcp("fd00::1", 7650))
+    );
+    assert!("tcp://10.0.0.5".parse::<InitialPeer>().is_err());
+    assert!("tcp://10.0.0.5[1-2]".parse::<InitialPeer>().is_err());
+  }
+
+  #[test]
+  fn initial_peer_locators() {
+    let ports = PortMapping::default();
+    let localhost = Ipv4Addr::LOCALHOST.into();
+
+    assert_eq!(
+      InitialPeer::with_port("127.0.0.1", 1234)
+        .to_locators(0, &ports)
+        .unwrap(),
+      vec![Locator::from(SocketAddr::new(localhost, 1234))]
+    );
+    assert_eq!(
+      InitialPeer::with_port("::1", 1234)
+        .to_locators(0, &ports)
+        .unwrap(),
+      vec![Locator::from(SocketAddr::new(
+        Ipv6Addr::LOCALHOST.into(),
+        1234
+      ))]
+    );
+    assert_eq!(
+      InitialPeer::with_participant_ids("127.0.0.1", 0..=1)
+        .to_locators(3, &ports)
+        .unwrap(),
+      vec![
+        Locator::from(SocketAddr::new(
+          localhost,
+          ports.spdp_well_known_unicast_port(3, 0).unwrap()
+        )),
+        Locator::from(SocketAddr::new(
+          localhost,
+          ports.spdp_well_known_unicast_port(3, 1).unwrap()
+        )),
+      ]
+    );
+    assert_eq!(
+      InitialPeer::tcp("127.0.0.1", 7650)
+        .to_locators(3, &ports)
+        .unwrap(),
+      vec![tcp_locator(
+        SocketAddr::new(localhost, 7650),
+        ports.spdp_well_known_multicast_port(3).unwrap()
+      )]
+    );
+  }
+  #[test]
+  fn initial_peer_port_overflow() {
+    let ports = PortMapping::default();
+    // Participant id 60000 gives port 7410 + 2 * 60000.
+    assert!(matches!(
+      InitialPeer::with_participant_ids("127.0.0.1", 0..=60000).to_locators(0, &ports),
+      Err(Error::BadParameter { .. })
+    ));
+    // Domain 233 gives port 7400 + 250 * 233 = 65650.
+    assert!(InitialPeer::tcp("127.0.0.1", 7650)
+      .to_locators(233, &ports)
+      .is_err());
+    assert!(InitialPeer::tcp("127.0.0.1", 7650)
+      .to_locators(232, &ports)
+      .is_ok());
+  }
+}
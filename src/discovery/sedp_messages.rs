@@ -24,7 +24,7 @@ use crate::{
   },
   discovery::content_filter_property::ContentFilterProperty,
   messages::submessages::elements::{parameter::Parameter, parameter_list::ParameterList},
-  network::{constant::user_traffic_unicast_port, util::get_local_unicast_locators},
+  network::constant::USER_TRAFFIC_LISTENER_TOKEN,
   rtps::{rtps_reader_proxy::RtpsReaderProxy, rtps_writer_proxy::RtpsWriterProxy},
   security::EndpointSecurityInfo,
   serialization::{
@@ -125,6 +125,9 @@ pub struct ReaderProxy {
   pub expects_inline_qos: bool,
   pub unicast_locator_list: Vec<Locator>,
   pub multicast_locator_list: Vec<Locator>,
+  // Not in the spec ReaderProxy. RustDDS Readers announce their
+  // MaxFragmentSize in a vendor-specific parameter.
+  pub max_fragment_size: Option<u32>,
 }
 
 impl ReaderProxy {
@@ -139,6 +142,7 @@ impl ReaderProxy {
       expects_inline_qos,
       unicast_locator_list,
       multicast_locator_list,
+      max_fragment_size: None,
     }
   }
 }
@@ -150,6 +154,7 @@ impl From<RtpsReaderProxy> for ReaderProxy {
       expects_inline_qos: rtps_reader_proxy.expects_in_line_qos,
       unicast_locator_list: rtps_reader_proxy.unicast_locator_list,
       multicast_locator_list: rtps_reader_proxy.multicast_locator_list,
+      max_fragment_size: rtps_reader_proxy.max_fragment_size,
     }
   }
 }
@@ -278,6 +283,11 @@ impl SubscriptionBuiltinTopicData {
       history: None, // SubscriptionBuiltinTopicData does not contain History QoS
       resource_limits: None, // nor Resource Limits, see Figure 8.30 in RTPS spec 2.5
       lifespan: self.lifespan,
+      max_fragment_size: None,
+      flow_controller: None,
+      publish_mode: None,
+      batching: None,
+      reliability_timing: None,
     }
   }
 
@@ -395,6 +405,12 @@ impl PlCdrDeserialize for DiscoveredReaderData {
       e
     })?;
 
+    let max_fragment_size: Option<u32> = get_option_from_pl_map(
+      &pl_map,
+      ctx,
+      ParameterId::PID_RUSTDDS_MAX_FRAGMENT_SIZE,
+      "Max fragment size",
+    )?;
     let security_info: Option<EndpointSecurityInfo> = get_option_from_pl_map(
       &pl_map,
       ctx,
@@ -405,12 +421,13 @@ impl PlCdrDeserialize for DiscoveredReaderData {
     let qos = QosPolicies::from_parameter_list(ctx, &pl_map)?;
 
     Ok(DiscoveredReaderData {
-      reader_proxy: ReaderProxy::new(
-        guid,
+      reader_proxy: ReaderProxy {
+        remote_reader_guid: guid,
         expects_inline_qos,
         unicast_locator_list,
         multicast_locator_list,
-      ),
+        max_fragment_size,
+      },
       subscription_topic_data: SubscriptionBuiltinTopicData::new(
         guid,
         participant_guid,
@@ -433,6 +450,7 @@ impl PlCdrSerialize for DiscoveredReaderData {
           expects_inline_qos,
           unicast_locator_list,
           multicast_locator_list,
+          max_fragment_size,
         },
       subscription_topic_data:
         sbtd @ SubscriptionBuiltinTopicData {
@@ -506,6 +524,7 @@ impl PlCdrSerialize for DiscoveredReaderData {
         locator::repr::Locator
       );
     }
+    emit_option!(PID_RUSTDDS_MAX_FRAGMENT_SIZE, max_fragment_size, u32);
 
     // SubscriptionBuiltinTopicData
     emit_option!(PID_PARTICIPANT_GUID, participant_key, GUID);
@@ -687,6 +706,11 @@ impl PublicationBuiltinTopicData {
       history: None,         // PublicationBuiltinTopicData does not contain History QoS
       resource_limits: None, // nor Resource Limits, see Figure 8.30 in RTPS spec 2.5
       lifespan: self.lifespan,
+      max_fragment_size: None,
+      flow_controller: None,
+      publish_mode: None,
+      batching: None,
+      reliability_timing: None,
     }
   }
 
@@ -733,8 +757,11 @@ impl DiscoveredWriterData {
     dp: &DomainParticipant,
     security_info: Option<EndpointSecurityInfo>,
   ) -> Self {
-    let unicast_port = user_traffic_unicast_port(dp.domain_id(), dp.participant_id());
-    let unicast_addresses = get_local_unicast_locators(unicast_port);
+    let unicast_addresses = dp
+      .self_locators()
+      .get(&USER_TRAFFIC_LISTENER_TOKEN)
+      .cloned()
+      .unwrap_or_default();
     // TODO: Why empty vector below? No multicast?
     let writer_proxy = WriterProxy::new(writer.guid(), vec![], unicast_addresses);
     let publication_topic_data = PublicationBuiltinTopicData::new_with_qos(
@@ -1004,6 +1031,11 @@ impl HasQoSPolicy for TopicBuiltinTopicData {
       history: self.history,
       resource_limits: self.resource_limits,
       lifespan: self.lifespan,
+      max_fragment_size: None,
+      flow_controller: None,
+      publish_mode: None,
+      batching: None,
+      reliability_timing: None,
     }
   }
 }
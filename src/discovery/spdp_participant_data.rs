@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
-use mio_06::Token;
+use mio_08::Token;
 use speedy::{Readable, Writable};
 use chrono::Utc;
 use bytes::Bytes;
@@ -63,6 +63,15 @@ pub struct SpdpDiscoveredParticipantData {
 }
 
 impl SpdpDiscoveredParticipantData {
+  // Metatraffic and user traffic unicast locators, without duplicates
+  pub(crate) fn unicast_locators(&self) -> Vec<Locator> {
+    let mut locators = self.metatraffic_unicast_locators.clone();
+    locators.extend(self.default_unicast_locators.iter().copied());
+    locators.sort();
+    locators.dedup();
+    locators
+  }
+
   pub(crate) fn as_reader_proxy(
     &self,
     is_metatraffic: bool,
@@ -174,7 +183,7 @@ impl SpdpDiscoveredParticipantData {
       lease_duration: Some(lease_duration),
       manual_liveliness_count: 0,
       builtin_endpoint_qos: None,
-      entity_name: None,
+      entity_name: participant.entity_name(),
 
       // DDS Security
       identity_token: None,    // TODO: Generate(?) one
@@ -64,19 +64,21 @@
 //!
 //! # Polling multiple DataReaders
 //!
-//! There are three alternative methods to poll DataReaders (and DataWriters):
-//! mio-0.6, mio-0.8, and async. Use only one of these!
+//! There are four alternative methods to poll DataReaders (and DataWriters):
+//! mio-0.6, mio-0.8, async, and [`WaitSet`]. Use only one of these!
 //!
 //! ## `mio-0.6`
 //!
-//! RustDDS is designed to used with [mio](mio_06) version 0.6.x. DataReaders
-//! implement [`Evented`](mio_06::event::Evented) so that they can be directly
-//! registered to a [`poll`](mio_06::Poll). See example `shapes_demo`.
+//! With the `mio_06` feature, which is enabled by default, DataReaders
+//! implement [`Evented`](mio_06::event::Evented) of [mio](mio_06) version
+//! 0.6.x, so that they can be directly registered to a
+//! [`poll`](mio_06::Poll). See example `shapes_demo`.
 //!
 //! ## `mio-0.8`
 //!
 //! RustDDS DataReaders implement [`mio_08::event::Source`] for registering with
-//! mio-0.8. See example `shapes_demo_mio_08`
+//! mio-0.8. See example `shapes_demo_mio_08`. RustDDS itself runs on mio-0.8,
+//! so applications that do not use mio-0.6 can disable the `mio_06` feature.
 //!
 //!
 //! ## `async`
@@ -89,6 +91,14 @@
 //!
 //! See exampe `async_shapes_demo`.
 //!
+//! ## `WaitSet`
+//!
+//! A [`WaitSet`] blocks until one of its attached conditions triggers. It
+//! does not require an event loop of your own. Conditions are
+//! [`ReadCondition`]s on DataReaders, [`StatusCondition`]s of any Entity, and
+//! application-controlled [`GuardCondition`]s. Waiting can be done with or
+//! without `async`.
+//!
 //! # Usage Example
 //!
 //! ```
@@ -190,15 +200,16 @@ pub mod serialization;
 #[doc(inline)]
 pub use dds::{
   key::{Key, Keyed},
-  participant::DomainParticipant,
+  participant::{DomainParticipant, DomainParticipantBuilder},
   pubsub::{Publisher, Subscriber},
   qos,
   qos::{policy, QosPolicies, QosPolicyBuilder},
   readcondition::ReadCondition,
   sampleinfo::{InstanceState, NotAliveGenerationCounts, SampleInfo, SampleState, ViewState},
-  statusevents::StatusEvented,
+  statusevents::{StatusCondition, StatusEvented, StatusKind},
   topic::{Topic, TopicDescription, TopicKind},
   typedesc::TypeDesc,
+  waitset::{ConditionId, GuardCondition, ReadConditionSource, WaitSet},
   with_key::{datareader::SelectByKey, WriteOptions, WriteOptionsBuilder},
 };
 /// Needed to specify serialized data representation in case it is other than
@@ -208,8 +219,15 @@ pub use serialization::representation_identifier::RepresentationIdentifier;
 pub use serialization::{
   CDRDeserializerAdapter, CDRSerializerAdapter, CdrDeserializer, CdrSerializer,
 };
+pub use discovery::{DiscoveryMode, InitialPeer};
+pub use network::{constant::PortMapping, interface_filter::InterfaceSelector};
+pub use rtps::flow_controller::{FlowControllerSettings, FlowLimit, FlowScheduling};
 pub use structure::{
-  duration::Duration, entity::RTPSEntity, guid::GUID, sequence_number::SequenceNumber,
+  duration::Duration,
+  entity::RTPSEntity,
+  guid::{GuidPrefix, GUID},
+  locator::Locator,
+  sequence_number::SequenceNumber,
   time::Timestamp,
 };
 // re-export from a helper crate
@@ -226,6 +244,30 @@ pub mod with_key {
   pub use crate::dds::{adapters::with_key::*, with_key::*};
 }
 
+/// Pluggable transports for RTPS messages
+pub mod transport {
+  pub use crate::network::{
+    loopback_transport::{LoopbackImpairments, LoopbackNetwork, LoopbackTransport},
+    transport::{GatherBuffer, Transport, TransportContext, TransportLocators},
+  };
+}
+
 pub mod rpc {
   pub use crate::structure::rpc::*;
 }
+
+/// Check reachability of RTPS participants with RTPS PING
+pub mod ping {
+  pub use crate::rtps::ping::{ping, PingReply};
+}
+
+/// Decoding captured RTPS traffic for debugging
+pub mod debug {
+  pub use crate::{
+    network::capture::{read_capture, CapturedDatagram},
+    rtps::decoder::{
+      decode_message, DecodedMessage, DecodedParameter, DecodedPayload, DecodedSubmessage,
+      DiscoveryData, EndpointSummary, ParticipantSummary, TopicSummary,
+    },
+  };
+}
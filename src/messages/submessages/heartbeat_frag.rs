@@ -1,8 +1,19 @@
+use std::mem::size_of;
+
+use enumflags2::BitFlags;
 use speedy::{Readable, Writable};
 
-use crate::structure::{
-  guid::EntityId,
-  sequence_number::{FragmentNumber, SequenceNumber},
+use crate::{
+  messages::submessages::submessages::SubmessageHeader,
+  rtps::{Submessage, SubmessageBody},
+  structure::{
+    guid::EntityId,
+    sequence_number::{FragmentNumber, SequenceNumber},
+  },
+};
+use super::{
+  submessage::WriterSubmessage, submessage_flag::HEARTBEATFRAG_Flags,
+  submessage_kind::SubmessageKind,
 };
 
 /// When fragmenting data and until all fragments are available, the
@@ -36,6 +47,26 @@ pub struct HeartbeatFrag {
   pub count: i32,
 }
 
+impl HeartbeatFrag {
+  pub fn create_submessage(self, flags: BitFlags<HEARTBEATFRAG_Flags>) -> Submessage {
+    Submessage {
+      header: SubmessageHeader {
+        kind: SubmessageKind::HEARTBEAT_FRAG,
+        flags: flags.bits(),
+        content_length: self.len_serialized() as u16,
+      },
+      body: SubmessageBody::Writer(WriterSubmessage::HeartbeatFrag(self, flags)),
+    }
+  }
+
+  pub fn len_serialized(&self) -> usize {
+    size_of::<EntityId>() * 2
+      + size_of::<SequenceNumber>()
+      + size_of::<FragmentNumber>()
+      + size_of::<i32>()
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
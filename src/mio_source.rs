@@ -1,15 +1,16 @@
 use std::{
   io,
-  io::{Read, Write},
-  os::fd::{AsRawFd, FromRawFd, OwnedFd},
-  sync::Mutex,
+  sync::{Arc, Mutex},
+  task::Waker,
 };
 
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
-use socketpair::*;
 use mio_08::{self, *};
 
+pub mod channel;
+pub mod timer;
+
 // PollEventSource and PollEventSender are an event communcaion
 // channel. PollEventSource is a mio-0.8 event::Source for Poll,
 // so it can be Registered in mio-0.8.
@@ -17,74 +18,231 @@ use mio_08::{self, *};
 
 // This is the event receiver end. It is a "Source" in the terminology of mio.
 pub struct PollEventSource {
-  #[allow(dead_code)]
-  rec_sps: SocketpairStream, // we are storing this just to keep the socket alive
-  rec_mio_socket: Mutex<mio_08::net::TcpStream>,
+  signal: signal::Receiver,
+  task_waker: Arc<TaskWaker>,
 }
 
-// TODO: How to store the socket so that it is correctly tracked and dropped
-// when these PolEventSource /-Sender are dropped. Can we do that without
-// unsafe?
-
 pub struct PollEventSender {
-  #[allow(dead_code)]
-  send_sps: SocketpairStream,
-  send_mio_socket: Mutex<mio_08::net::TcpStream>,
+  signal: signal::Sender,
+  task_waker: Arc<TaskWaker>,
 }
 
-fn set_non_blocking(s: SocketpairStream) -> io::Result<SocketpairStream> {
-  let owned_fd = OwnedFd::from(s);
-  let std_socket = std::net::TcpStream::from(owned_fd);
-  std_socket.set_nonblocking(true)?;
-  Ok(SocketpairStream::from(OwnedFd::from(std_socket)))
+// Async task waiting for the events of a PollEventSource without a Poll. The
+// task is woken up by the next send, and has to set itself again after that.
+#[derive(Default)]
+pub struct TaskWaker {
+  waker: Mutex<Option<Waker>>,
 }
 
-pub fn make_poll_channel() -> io::Result<(PollEventSource, PollEventSender)> {
-  // This could be also a pipe or any other OS construct that can be poll()'ed in
-  // mio-0.8 And can be triggered (sent to) from the application.
-  // Socketpair is symmetric, but we decide to call one end receive and the other
-  // send. We use stream sockets, because the documentation says datagram
-  // sockets are not available on MacOS.
-  let (rec_sps, send_sps) = socketpair_stream()?;
+impl TaskWaker {
+  pub fn set(&self, waker: &Waker) {
+    *self.waker.lock().unwrap() = Some(waker.clone());
+  }
 
-  let rec_sps = set_non_blocking(rec_sps)?;
-  let send_sps = set_non_blocking(send_sps)?;
+  fn wake(&self) {
+    if let Some(waker) = self.waker.lock().unwrap().take() {
+      waker.wake();
+    }
+  }
+}
 
-  let rec_mio_socket = unsafe { mio_08::net::TcpStream::from_raw_fd(rec_sps.as_raw_fd()) };
-  let send_mio_socket = unsafe { mio_08::net::TcpStream::from_raw_fd(send_sps.as_raw_fd()) };
+pub fn make_poll_channel() -> io::Result<(PollEventSource, PollEventSender)> {
+  let (rec_signal, send_signal) = signal::pair()?;
+  let task_waker = Arc::new(TaskWaker::default());
 
   Ok((
     PollEventSource {
-      rec_sps,
-      rec_mio_socket: Mutex::new(rec_mio_socket),
+      signal: rec_signal,
+      task_waker: Arc::clone(&task_waker),
     },
     PollEventSender {
-      send_sps,
-      send_mio_socket: Mutex::new(send_mio_socket),
+      signal: send_signal,
+      task_waker,
     },
   ))
 }
 
+// The OS object that makes a PollEventSource readable. On Linux this is an
+// eventfd, so that each registered channel or timer uses one file descriptor.
+#[cfg(target_os = "linux")]
+mod signal {
+  use std::{
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    sync::Arc,
+  };
+
+  use mio_08::{event, unix::SourceFd, Interest, Registry, Token};
+
+  pub struct Receiver(Arc<OwnedFd>);
+  pub struct Sender(Arc<OwnedFd>);
+
+  pub fn pair() -> io::Result<(Receiver, Sender)> {
+    let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+    if fd < 0 {
+      return Err(io::Error::last_os_error());
+    }
+    // This is safe, because the descriptor was just created, and nothing else
+    // owns it.
+    let fd = Arc::new(unsafe { OwnedFd::from_raw_fd(fd) });
+    Ok((Receiver(Arc::clone(&fd)), Sender(fd)))
+  }
+
+  impl Sender {
+    pub fn send(&self) -> io::Result<()> {
+      let one: u64 = 1;
+      let written = unsafe { libc::write(self.0.as_raw_fd(), (&one as *const u64).cast(), 8) };
+      if written < 0 {
+        Err(io::Error::last_os_error())
+      } else {
+        Ok(())
+      }
+    }
+  }
+
+  impl Receiver {
+    // Resets the counter of the eventfd, so that it is not readable.
+    pub fn drain(&self) -> io::Result<()> {
+      let mut count: u64 = 0;
+      let read = unsafe { libc::read(self.0.as_raw_fd(), (&mut count as *mut u64).cast(), 8) };
+      if read < 0 {
+        Err(io::Error::last_os_error())
+      } else {
+        Ok(())
+      }
+    }
+  }
+
+  impl event::Source for Receiver {
+    fn register(
+      &mut self,
+      registry: &Registry,
+      token: Token,
+      interests: Interest,
+    ) -> io::Result<()> {
+      SourceFd(&self.0.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+      &mut self,
+      registry: &Registry,
+      token: Token,
+      interests: Interest,
+    ) -> io::Result<()> {
+      SourceFd(&self.0.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+      SourceFd(&self.0.as_raw_fd()).deregister(registry)
+    }
+  }
+}
+
+// Elsewhere, it is a socketpair. Socketpair is symmetric, but we decide to call
+// one end receive and the other send. We use stream sockets, because the
+// documentation says datagram sockets are not available on MacOS.
+#[cfg(not(target_os = "linux"))]
+mod signal {
+  use std::{
+    io,
+    io::{Read, Write},
+    os::fd::OwnedFd,
+    sync::Mutex,
+  };
+
+  use socketpair::*;
+  use mio_08::{event, net::TcpStream, Interest, Registry, Token};
+
+  // The mio sockets are the sole owners of the socketpair file descriptors, so
+  // they are closed exactly once, when the Receiver and Sender are dropped.
+  pub struct Receiver(Mutex<TcpStream>);
+  pub struct Sender(Mutex<TcpStream>);
+
+  fn into_non_blocking_mio(s: SocketpairStream) -> io::Result<TcpStream> {
+    let owned_fd = OwnedFd::from(s);
+    let std_socket = std::net::TcpStream::from(owned_fd);
+    std_socket.set_nonblocking(true)?;
+    Ok(TcpStream::from_std(std_socket))
+  }
+
+  pub fn pair() -> io::Result<(Receiver, Sender)> {
+    let (rec_sps, send_sps) = socketpair_stream()?;
+    Ok((
+      Receiver(Mutex::new(into_non_blocking_mio(rec_sps)?)),
+      Sender(Mutex::new(into_non_blocking_mio(send_sps)?)),
+    ))
+  }
+
+  impl Sender {
+    pub fn send(&self) -> io::Result<()> {
+      self.0.lock().unwrap().write(&[0xcc]).map(|_| ())
+    }
+  }
+
+  impl Receiver {
+    // receive and discard all available data
+    pub fn drain(&self) -> io::Result<()> {
+      let mut buf = Vec::with_capacity(16);
+      self.0.lock().unwrap().read_to_end(&mut buf).map(|_| ())
+    }
+  }
+
+  impl event::Source for Receiver {
+    fn register(
+      &mut self,
+      registry: &Registry,
+      token: Token,
+      interests: Interest,
+    ) -> io::Result<()> {
+      self
+        .0
+        .get_mut()
+        .unwrap()
+        .register(registry, token, interests)
+    }
+
+    fn reregister(
+      &mut self,
+      registry: &Registry,
+      token: Token,
+      interests: Interest,
+    ) -> io::Result<()> {
+      self
+        .0
+        .get_mut()
+        .unwrap()
+        .reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+      self.0.get_mut().unwrap().deregister(registry)
+    }
+  }
+}
+
 impl PollEventSender {
   pub fn send(&self) {
-    match self.send_mio_socket.lock().unwrap().write(&[0xcc]) {
+    match self.signal.send() {
       Ok(_b) => { // presumably wrote something
       }
       Err(e) => {
         info!("PollEventSender.send: {e}");
       }
     }
+    self.task_waker.wake();
   }
 }
 
 impl PollEventSource {
+  pub fn task_waker(&self) -> Arc<TaskWaker> {
+    Arc::clone(&self.task_waker)
+  }
+
   /// drain the sent events so that buffers do not fill up
   // and triggering can happen again. This should be called every
   // time just before acting on the events.
   pub fn drain(&self) {
-    // receive and discard all available data from recv_socket
-    let mut buf = Vec::with_capacity(16);
-    match self.rec_mio_socket.lock().unwrap().read_to_end(&mut buf) {
+    match self.signal.drain() {
       Ok(_) => (),
       Err(err) => {
         match err.kind() {
@@ -100,11 +258,7 @@ impl PollEventSource {
 
 impl event::Source for PollEventSource {
   fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
-    self
-      .rec_mio_socket
-      .lock()
-      .unwrap()
-      .register(registry, token, interests)
+    self.signal.register(registry, token, interests)
   }
 
   fn reregister(
@@ -113,14 +267,47 @@ impl event::Source for PollEventSource {
     token: Token,
     interests: Interest,
   ) -> io::Result<()> {
-    self
-      .rec_mio_socket
-      .lock()
-      .unwrap()
-      .reregister(registry, token, interests)
+    self.signal.reregister(registry, token, interests)
   }
 
   fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
-    self.rec_mio_socket.lock().unwrap().deregister(registry)
+    self.signal.deregister(registry)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use mio_08::{Events, Poll};
+
+  use super::*;
+
+  #[test]
+  fn poll_channel_signals_each_send() {
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(4);
+    let (mut source, sender) = make_poll_channel().unwrap();
+    poll
+      .registry()
+      .register(&mut source, Token(7), Interest::READABLE)
+      .unwrap();
+
+    for _ in 0..3 {
+      sender.send();
+      sender.send();
+      poll
+        .poll(&mut events, Some(Duration::from_millis(500)))
+        .unwrap();
+      assert_eq!(
+        events.iter().map(|e| e.token()).collect::<Vec<_>>(),
+        [Token(7)]
+      );
+      source.drain();
+      poll
+        .poll(&mut events, Some(Duration::from_millis(50)))
+        .unwrap();
+      assert!(events.is_empty());
+    }
   }
 }
@@ -0,0 +1,370 @@
+// Channels whose Receiver is a mio-0.8 event::Source. With the "mio_06"
+// feature, the Receiver is also a mio-0.6 Evented.
+//
+// This follows the design of mio-extras channels: Senders count the pending
+// messages, and the Receiver becomes readable when the count goes from zero to
+// one. It is no longer readable, when the count drops back to zero. Readiness
+// is edge-triggered, so a Receiver must be read until it is empty.
+//
+// The readiness signal is a PollEventSource, which is created only when the
+// Receiver is registered, so unregistered channels do not use any file
+// descriptors.
+
+use std::{
+  error, fmt, io,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc, Arc, Mutex,
+  },
+  time::Duration,
+};
+pub use std::sync::mpsc::{RecvTimeoutError, TryRecvError};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use mio_08::{event, Interest, Registry, Token};
+
+use super::{make_poll_channel, PollEventSender, PollEventSource};
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+  let (tx, rx) = mpsc::channel();
+  let (ctl_tx, ctl_rx) = control_pair();
+  (Sender { tx, ctl: ctl_tx }, Receiver { rx, ctl: ctl_rx })
+}
+
+pub fn sync_channel<T>(bound: usize) -> (SyncSender<T>, Receiver<T>) {
+  let (tx, rx) = mpsc::sync_channel(bound);
+  let (ctl_tx, ctl_rx) = control_pair();
+  (SyncSender { tx, ctl: ctl_tx }, Receiver { rx, ctl: ctl_rx })
+}
+
+pub struct Sender<T> {
+  tx: mpsc::Sender<T>,
+  ctl: SenderCtl,
+}
+
+pub struct SyncSender<T> {
+  tx: mpsc::SyncSender<T>,
+  ctl: SenderCtl,
+}
+
+pub struct Receiver<T> {
+  rx: mpsc::Receiver<T>,
+  ctl: ReceiverCtl,
+}
+
+pub enum SendError<T> {
+  Io(io::Error),
+  Disconnected(T),
+}
+
+pub enum TrySendError<T> {
+  Io(io::Error),
+  Full(T),
+  Disconnected(T),
+}
+
+impl<T> Sender<T> {
+  pub fn send(&self, t: T) -> Result<(), SendError<T>> {
+    self.tx.send(t).map_err(SendError::from)?;
+    self.ctl.inc().map_err(SendError::Io)
+  }
+}
+
+impl<T> Clone for Sender<T> {
+  fn clone(&self) -> Self {
+    Self {
+      tx: self.tx.clone(),
+      ctl: self.ctl.clone(),
+    }
+  }
+}
+
+impl<T> SyncSender<T> {
+  // Blocks while the channel is full
+  pub fn send(&self, t: T) -> Result<(), SendError<T>> {
+    self.tx.send(t).map_err(SendError::from)?;
+    self.ctl.inc().map_err(SendError::Io)
+  }
+
+  pub fn try_send(&self, t: T) -> Result<(), TrySendError<T>> {
+    self.tx.try_send(t).map_err(TrySendError::from)?;
+    self.ctl.inc().map_err(TrySendError::Io)
+  }
+}
+
+impl<T> Clone for SyncSender<T> {
+  fn clone(&self) -> Self {
+    Self {
+      tx: self.tx.clone(),
+      ctl: self.ctl.clone(),
+    }
+  }
+}
+
+impl<T> Receiver<T> {
+  pub fn try_recv(&self) -> Result<T, TryRecvError> {
+    let t = self.rx.try_recv()?;
+    self.ctl.dec();
+    Ok(t)
+  }
+
+  // Blocks, so this is for use outside of event loops.
+  pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+    let t = self.rx.recv_timeout(timeout)?;
+    self.ctl.dec();
+    Ok(t)
+  }
+}
+
+impl<T> event::Source for Receiver<T> {
+  fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+    self
+      .ctl
+      .event_source()?
+      .register(registry, token, interests)
+  }
+
+  fn reregister(
+    &mut self,
+    registry: &Registry,
+    token: Token,
+    interests: Interest,
+  ) -> io::Result<()> {
+    self
+      .ctl
+      .event_source()?
+      .reregister(registry, token, interests)
+  }
+
+  fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+    self.ctl.event_source()?.deregister(registry)
+  }
+}
+
+#[cfg(feature = "mio_06")]
+impl<T> mio_06::Evented for Receiver<T> {
+  fn register(
+    &self,
+    poll: &mio_06::Poll,
+    token: mio_06::Token,
+    interest: mio_06::Ready,
+    opts: mio_06::PollOpt,
+  ) -> io::Result<()> {
+    self
+      .ctl
+      .with_registration(|r| r.register(poll, token, interest, opts))
+  }
+
+  fn reregister(
+    &self,
+    poll: &mio_06::Poll,
+    token: mio_06::Token,
+    interest: mio_06::Ready,
+    opts: mio_06::PollOpt,
+  ) -> io::Result<()> {
+    self
+      .ctl
+      .with_registration(|r| r.reregister(poll, token, interest, opts))
+  }
+
+  fn deregister(&self, poll: &mio_06::Poll) -> io::Result<()> {
+    self
+      .ctl
+      .with_registration(|r| mio_06::Evented::deregister(r, poll))
+  }
+}
+
+// -------------------------------------------------------------------------------
+// Readiness control
+
+// Shared by the Senders and the Receiver of a channel
+struct Inner {
+  pending: AtomicUsize,
+  signals: Mutex<Signals>,
+}
+
+// Ways to make the Receiver readable. These exist only after the Receiver is
+// registered.
+#[derive(Default)]
+struct Signals {
+  event_sender: Option<PollEventSender>,
+  #[cfg(feature = "mio_06")]
+  set_readiness: Option<mio_06::SetReadiness>,
+}
+
+impl Signals {
+  fn set(&self) -> io::Result<()> {
+    if let Some(event_sender) = &self.event_sender {
+      event_sender.send();
+    }
+    #[cfg(feature = "mio_06")]
+    if let Some(set_readiness) = &self.set_readiness {
+      set_readiness.set_readiness(mio_06::Ready::readable())?;
+    }
+    Ok(())
+  }
+}
+
+struct SenderCtl {
+  inner: Arc<Inner>,
+}
+
+struct ReceiverCtl {
+  inner: Arc<Inner>,
+  event_source: Option<PollEventSource>,
+  #[cfg(feature = "mio_06")]
+  registration: Mutex<Option<mio_06::Registration>>,
+}
+
+fn control_pair() -> (SenderCtl, ReceiverCtl) {
+  let inner = Arc::new(Inner {
+    pending: AtomicUsize::new(0),
+    signals: Mutex::new(Signals::default()),
+  });
+  (
+    SenderCtl {
+      inner: Arc::clone(&inner),
+    },
+    ReceiverCtl {
+      inner,
+      event_source: None,
+      #[cfg(feature = "mio_06")]
+      registration: Mutex::new(None),
+    },
+  )
+}
+
+impl SenderCtl {
+  // A message was sent
+  fn inc(&self) -> io::Result<()> {
+    if self.inner.pending.fetch_add(1, Ordering::AcqRel) == 0 {
+      self.inner.signals.lock().unwrap().set()?;
+    }
+    Ok(())
+  }
+}
+
+impl Clone for SenderCtl {
+  fn clone(&self) -> Self {
+    Self {
+      inner: Arc::clone(&self.inner),
+    }
+  }
+}
+
+impl ReceiverCtl {
+  // A message was received
+  fn dec(&self) {
+    let first = self.inner.pending.load(Ordering::Acquire);
+    if first == 1 {
+      // The last one, so not readable anymore
+      self.unset();
+    }
+    let second = self.inner.pending.fetch_sub(1, Ordering::AcqRel);
+    if first == 1 && second > 1 {
+      // A new message was sent while readiness was cleared, so it must be set
+      // again.
+      if let Err(e) = self.inner.signals.lock().unwrap().set() {
+        info!("Channel readiness: {e}");
+      }
+    }
+  }
+
+  fn unset(&self) {
+    if let Some(event_source) = &self.event_source {
+      event_source.drain();
+    }
+    #[cfg(feature = "mio_06")]
+    if let Some(set_readiness) = &self.inner.signals.lock().unwrap().set_readiness {
+      set_readiness
+        .set_readiness(mio_06::Ready::empty())
+        .unwrap_or_else(|e| info!("Channel readiness: {e}"));
+    }
+  }
+
+  fn event_source(&mut self) -> io::Result<&mut PollEventSource> {
+    if self.event_source.is_none() {
+      let (event_source, event_sender) = make_poll_channel()?;
+      let mut signals = self.inner.signals.lock().unwrap();
+      // Messages sent before registering are waiting
+      if self.inner.pending.load(Ordering::Acquire) > 0 {
+        event_sender.send();
+      }
+      signals.event_sender = Some(event_sender);
+      self.event_source = Some(event_source);
+    }
+    Ok(self.event_source.as_mut().unwrap())
+  }
+
+  #[cfg(feature = "mio_06")]
+  fn with_registration<R>(&self, f: impl FnOnce(&mio_06::Registration) -> R) -> R {
+    let mut registration = self.registration.lock().unwrap();
+    if registration.is_none() {
+      let (new_registration, set_readiness) = mio_06::Registration::new2();
+      let mut signals = self.inner.signals.lock().unwrap();
+      if self.inner.pending.load(Ordering::Acquire) > 0 {
+        set_readiness
+          .set_readiness(mio_06::Ready::readable())
+          .unwrap_or_else(|e| info!("Channel readiness: {e}"));
+      }
+      signals.set_readiness = Some(set_readiness);
+      *registration = Some(new_registration);
+    }
+    f(registration.as_ref().unwrap())
+  }
+}
+
+// -------------------------------------------------------------------------------
+// Errors
+
+impl<T> From<mpsc::SendError<T>> for SendError<T> {
+  fn from(src: mpsc::SendError<T>) -> Self {
+    Self::Disconnected(src.0)
+  }
+}
+
+impl<T> From<mpsc::TrySendError<T>> for TrySendError<T> {
+  fn from(src: mpsc::TrySendError<T>) -> Self {
+    match src {
+      mpsc::TrySendError::Full(t) => Self::Full(t),
+      mpsc::TrySendError::Disconnected(t) => Self::Disconnected(t),
+    }
+  }
+}
+
+// The message is not formatted, so that messages do not need to be Debug.
+
+impl<T> fmt::Display for SendError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Io(e) => write!(f, "{e}"),
+      Self::Disconnected(_) => write!(f, "Disconnected"),
+    }
+  }
+}
+
+impl<T> fmt::Debug for SendError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Display::fmt(self, f)
+  }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Io(e) => write!(f, "{e}"),
+      Self::Full(_) => write!(f, "Full"),
+      Self::Disconnected(_) => write!(f, "Disconnected"),
+    }
+  }
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Display::fmt(self, f)
+  }
+}
+
+impl<T> error::Error for SendError<T> {}
+impl<T> error::Error for TrySendError<T> {}
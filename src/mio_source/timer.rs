@@ -0,0 +1,308 @@
+// Timer that is a mio-0.8 event::Source. It becomes readable when a timeout
+// expires, and the expired timeouts are then taken with `poll`.
+//
+// Readiness is edge-triggered, so `poll` must be called until it returns None.
+// Timeouts are watched by a single thread shared by all Timers, which signals
+// the Timer's PollEventSource when one expires. The PollEventSource is created
+// only when the Timer is registered.
+
+use std::{
+  cmp::Reverse,
+  collections::BinaryHeap,
+  io,
+  sync::{Arc, Condvar, Mutex, Weak},
+  thread,
+  time::{Duration, Instant},
+};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use mio_08::{event, Interest, Registry, Token};
+
+use super::{make_poll_channel, PollEventSender, PollEventSource};
+
+pub struct Timer<T> {
+  // Timeouts in order of expiry. The sequence number keeps timeouts with the
+  // same deadline in the order they were set.
+  timeouts: BinaryHeap<Reverse<(Instant, u64, Entry<T>)>>,
+  next_seq: u64,
+  signal: Arc<Signal>,
+  event_source: Option<PollEventSource>,
+}
+
+// Wrapper that makes the timeout state not take part in ordering
+struct Entry<T>(T);
+
+impl<T> PartialEq for Entry<T> {
+  fn eq(&self, _other: &Self) -> bool {
+    true
+  }
+}
+impl<T> Eq for Entry<T> {}
+impl<T> PartialOrd for Entry<T> {
+  fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+impl<T> Ord for Entry<T> {
+  fn cmp(&self, _other: &Self) -> std::cmp::Ordering {
+    std::cmp::Ordering::Equal
+  }
+}
+
+// Set when the Timer is registered
+struct Signal {
+  event_sender: Mutex<Option<PollEventSender>>,
+}
+
+impl Signal {
+  fn set(&self) {
+    if let Some(event_sender) = &*self.event_sender.lock().unwrap() {
+      event_sender.send();
+    }
+  }
+}
+
+impl<T> Default for Timer<T> {
+  fn default() -> Self {
+    Self {
+      timeouts: BinaryHeap::new(),
+      next_seq: 0,
+      signal: Arc::new(Signal {
+        event_sender: Mutex::new(None),
+      }),
+      event_source: None,
+    }
+  }
+}
+
+impl<T> Timer<T> {
+  /// `state` is returned from `poll` after `delay` has passed.
+  pub fn set_timeout(&mut self, delay: Duration, state: T) {
+    let deadline = Instant::now() + delay;
+    self
+      .timeouts
+      .push(Reverse((deadline, self.next_seq, Entry(state))));
+    self.next_seq += 1;
+    TimerThread::get().watch(deadline, Arc::downgrade(&self.signal));
+  }
+
+  /// Discards the expired timeouts and sets a new one. This is for periodic
+  /// timers that are re-armed when they expire.
+  pub fn reset_timeout(&mut self, delay: Duration, state: T) {
+    while self.poll().is_some() {}
+    self.set_timeout(delay, state);
+  }
+
+  /// Takes the next expired timeout, if any.
+  pub fn poll(&mut self) -> Option<T> {
+    if let Some(state) = self.pop_expired() {
+      return Some(state);
+    }
+    // Nothing has expired, so the Timer should not be readable. A timeout
+    // that expires while clearing the readiness is checked again.
+    if let Some(event_source) = &self.event_source {
+      event_source.drain();
+    }
+    self.pop_expired()
+  }
+
+  fn pop_expired(&mut self) -> Option<T> {
+    match self.timeouts.peek() {
+      Some(Reverse((deadline, _, _))) if *deadline <= Instant::now() => self
+        .timeouts
+        .pop()
+        .map(|Reverse((_, _, Entry(state)))| state),
+      _ => None,
+    }
+  }
+
+  fn event_source(&mut self) -> io::Result<&mut PollEventSource> {
+    if self.event_source.is_none() {
+      let (event_source, event_sender) = make_poll_channel()?;
+      let mut signal_sender = self.signal.event_sender.lock().unwrap();
+      // Timeouts set before registering may have expired already, and the
+      // timer thread could not signal them.
+      if matches!(self.timeouts.peek(), Some(Reverse((deadline, _, _))) if *deadline <= Instant::now())
+      {
+        event_sender.send();
+      }
+      *signal_sender = Some(event_sender);
+      drop(signal_sender);
+      self.event_source = Some(event_source);
+    }
+    Ok(self.event_source.as_mut().unwrap())
+  }
+}
+
+impl<T> event::Source for Timer<T> {
+  fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+    self.event_source()?.register(registry, token, interests)
+  }
+
+  fn reregister(
+    &mut self,
+    registry: &Registry,
+    token: Token,
+    interests: Interest,
+  ) -> io::Result<()> {
+    self.event_source()?.reregister(registry, token, interests)
+  }
+
+  fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+    self.event_source()?.deregister(registry)
+  }
+}
+
+// -------------------------------------------------------------------------------
+
+// The thread that signals Timers when their timeouts expire. It is started on
+// first use and runs as long as the process.
+struct TimerThread {
+  deadlines: Mutex<Deadlines>,
+  changed: Condvar,
+}
+
+#[derive(Default)]
+struct Deadlines {
+  heap: BinaryHeap<Reverse<(Instant, u64, WeakSignal)>>,
+  next_seq: u64,
+}
+
+struct WeakSignal(Weak<Signal>);
+
+impl PartialEq for WeakSignal {
+  fn eq(&self, _other: &Self) -> bool {
+    true
+  }
+}
+impl Eq for WeakSignal {}
+impl PartialOrd for WeakSignal {
+  fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+impl Ord for WeakSignal {
+  fn cmp(&self, _other: &Self) -> std::cmp::Ordering {
+    std::cmp::Ordering::Equal
+  }
+}
+
+static TIMER_THREAD: Mutex<Option<Arc<TimerThread>>> = Mutex::new(None);
+
+impl TimerThread {
+  fn get() -> Arc<Self> {
+    let mut timer_thread = TIMER_THREAD.lock().unwrap();
+    if let Some(t) = &*timer_thread {
+      return Arc::clone(t);
+    }
+    let t = Arc::new(Self {
+      deadlines: Mutex::new(Deadlines::default()),
+      changed: Condvar::new(),
+    });
+    let thread_t = Arc::clone(&t);
+    thread::Builder::new()
+      .name("RustDDS timer".to_string())
+      .spawn(move || thread_t.run())
+      .expect("Cannot start timer thread");
+    *timer_thread = Some(Arc::clone(&t));
+    t
+  }
+
+  fn watch(&self, deadline: Instant, signal: Weak<Signal>) {
+    let mut deadlines = self.deadlines.lock().unwrap();
+    let earliest = deadlines
+      .heap
+      .peek()
+      .map_or(true, |Reverse((first, _, _))| deadline < *first);
+    let seq = deadlines.next_seq;
+    deadlines.next_seq += 1;
+    deadlines
+      .heap
+      .push(Reverse((deadline, seq, WeakSignal(signal))));
+    if earliest {
+      self.changed.notify_one();
+    }
+  }
+
+  fn run(&self) {
+    let mut deadlines = self.deadlines.lock().unwrap();
+    loop {
+      let now = Instant::now();
+      let mut expired: Vec<Weak<Signal>> = Vec::new();
+      while let Some(Reverse((deadline, _, _))) = deadlines.heap.peek() {
+        if *deadline > now {
+          break;
+        }
+        if let Some(Reverse((_, _, WeakSignal(signal)))) = deadlines.heap.pop() {
+          // One signal per Timer is enough
+          if !expired.iter().any(|e| e.ptr_eq(&signal)) {
+            expired.push(signal);
+          }
+        }
+      }
+      if !expired.is_empty() {
+        drop(deadlines);
+        for signal in expired.iter().filter_map(Weak::upgrade) {
+          signal.set();
+        }
+        deadlines = self.deadlines.lock().unwrap();
+        continue;
+      }
+      deadlines = match deadlines.heap.peek() {
+        Some(Reverse((deadline, _, _))) => {
+          let wait = deadline.saturating_duration_since(now);
+          self.changed.wait_timeout(deadlines, wait).unwrap().0
+        }
+        None => self.changed.wait(deadlines).unwrap(),
+      };
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use mio_08::{Events, Poll};
+
+  use super::*;
+
+  #[test]
+  fn timer_expires_in_order() {
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(4);
+    let mut timer = Timer::default();
+    poll
+      .registry()
+      .register(&mut timer, Token(1), Interest::READABLE)
+      .unwrap();
+
+    let start = Instant::now();
+    timer.set_timeout(Duration::from_millis(60), "second");
+    timer.set_timeout(Duration::from_millis(20), "first");
+    timer.set_timeout(Duration::from_millis(60), "third");
+    assert_eq!(timer.poll(), None);
+
+    let mut expired = Vec::new();
+    while expired.len() < 3 && start.elapsed() < Duration::from_secs(2) {
+      poll
+        .poll(&mut events, Some(Duration::from_millis(500)))
+        .unwrap();
+      for event in &events {
+        assert_eq!(event.token(), Token(1));
+        while let Some(state) = timer.poll() {
+          expired.push((state, start.elapsed()));
+        }
+      }
+    }
+    let states: Vec<&str> = expired.iter().map(|(state, _)| *state).collect();
+    assert_eq!(states, ["first", "second", "third"]);
+    assert!(expired[0].1 >= Duration::from_millis(20));
+    assert!(expired[1].1 >= Duration::from_millis(60));
+
+    // Nothing more to signal
+    poll
+      .poll(&mut events, Some(Duration::from_millis(100)))
+      .unwrap();
+    assert!(events.is_empty());
+  }
+}
@@ -1,4 +1,14 @@
+pub mod capture;
 pub mod constant;
+pub mod interface_filter;
+pub mod loopback_transport;
+#[cfg(target_os = "linux")]
+pub mod shm_transport;
+pub mod tcp_transport;
+pub mod transport;
+#[cfg(target_os = "linux")]
+pub mod udp_batch;
 pub mod udp_listener;
 pub mod udp_sender;
+pub mod udp_transport;
 pub mod util;
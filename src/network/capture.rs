@@ -0,0 +1,470 @@
+//! Reading UDP datagrams from packet capture files
+//!
+//! Supports the classic pcap format and pcapng, as written by e.g. tcpdump and
+//! Wireshark, with Ethernet, Linux cooked (SLL and SLL2), raw IP and BSD
+//! loopback link layers. Only UDP over IPv4 and IPv6 is extracted. IP
+//! fragments are skipped, because they are not reassembled.
+
+use std::{
+  io,
+  net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+  time::Duration,
+};
+
+use bytes::Bytes;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+/// A UDP datagram read from a capture file
+#[derive(Debug, Clone)]
+pub struct CapturedDatagram {
+  /// Capture time, since the Unix epoch. Zero, if the capture file did not
+  /// record it.
+  pub timestamp: Duration,
+  pub source: SocketAddr,
+  pub destination: SocketAddr,
+  pub payload: Bytes,
+}
+
+/// Reads all UDP datagrams from the contents of a pcap or pcapng file
+///
+/// Packets that are not UDP are skipped. A truncated last packet ends the
+/// capture without an error, like it happens when the capturing program is
+/// interrupted.
+pub fn read_capture(capture: &[u8]) -> io::Result<Vec<CapturedDatagram>> {
+  if capture.len() < 4 {
+    return Err(invalid_data("File is too short for a packet capture"));
+  }
+  match LittleEndian::read_u32(capture) {
+    PCAPNG_SECTION_HEADER => read_pcapng(capture),
+    magic if pcap_format(magic).is_some() => read_pcap(capture),
+    _ => Err(invalid_data("Not a pcap or pcapng file")),
+  }
+}
+
+const PCAP_HEADER_SIZE: usize = 24;
+const PCAP_RECORD_HEADER_SIZE: usize = 16;
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const PCAPNG_OPTION_END: u16 = 0;
+const PCAPNG_OPTION_IF_TSRESOL: u16 = 9;
+
+// Link layer header types, from https://www.tcpdump.org/linktypes.html
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LOOP: u32 = 108;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88A8;
+
+const IP_PROTOCOL_UDP: u8 = 17;
+
+// Byte order and timestamp units per second of a pcap file
+fn pcap_format(magic: u32) -> Option<(Endian, u64)> {
+  match magic {
+    0xA1B2_C3D4 => Some((Endian::Little, 1_000_000)),
+    0xD4C3_B2A1 => Some((Endian::Big, 1_000_000)),
+    0xA1B2_3C4D => Some((Endian::Little, 1_000_000_000)),
+    0x4D3C_B2A1 => Some((Endian::Big, 1_000_000_000)),
+    _ => None,
+  }
+}
+
+fn read_pcap(capture: &[u8]) -> io::Result<Vec<CapturedDatagram>> {
+  let (endian, units_per_second) =
+    pcap_format(LittleEndian::read_u32(capture)).ok_or_else(|| invalid_data("Not a pcap file"))?;
+  let link_type = endian
+    .u32_at(capture, 20)
+    .ok_or_else(|| invalid_data("Truncated pcap file header"))?
+    & 0xFFFF; // upper bits may carry FCS information
+
+  let mut datagrams = Vec::new();
+  let mut offset = PCAP_HEADER_SIZE;
+  while offset < capture.len() {
+    let record = (|| {
+      let seconds = endian.u32_at(capture, offset)?;
+      let fraction = endian.u32_at(capture, offset + 4)?;
+      let captured_length = endian.u32_at(capture, offset + 8)? as usize;
+      let start = offset + PCAP_RECORD_HEADER_SIZE;
+      let frame = capture.get(start..start.checked_add(captured_length)?)?;
+      let timestamp = Duration::from_secs(u64::from(seconds))
+        + ticks_to_duration(u64::from(fraction), units_per_second);
+      Some((timestamp, frame))
+    })();
+    let Some((timestamp, frame)) = record else {
+      warn!("read_capture: Truncated packet at offset {offset}. Stopping.");
+      break;
+    };
+    offset += PCAP_RECORD_HEADER_SIZE + frame.len();
+    datagrams.extend(udp_datagram(link_type, frame, timestamp));
+  }
+  Ok(datagrams)
+}
+
+struct PcapngInterface {
+  link_type: u32,
+  units_per_second: u64,
+}
+
+fn read_pcapng(capture: &[u8]) -> io::Result<Vec<CapturedDatagram>> {
+  let mut datagrams = Vec::new();
+  let mut endian = Endian::Little;
+  let mut interfaces: Vec<PcapngInterface> = Vec::new();
+  let mut offset = 0;
+
+  while offset < capture.len() {
+    let block_type = endian.u32_at(capture, offset);
+    if block_type == Some(PCAPNG_SECTION_HEADER) {
+      // Each section may have its own byte order, and has its own interfaces.
+      endian = match Endian::Little.u32_at(capture, offset + 8) {
+        Some(PCAPNG_BYTE_ORDER_MAGIC) => Endian::Little,
+        Some(magic) if magic.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => Endian::Big,
+        _ => return Err(invalid_data("Bad pcapng byte order magic")),
+      };
+      interfaces.clear();
+    }
+    let block = endian
+      .u32_at(capture, offset + 4)
+      .map(|length| length as usize)
+      .filter(|length| *length >= 12 && length % 4 == 0)
+      .and_then(|length| capture.get(offset..offset.checked_add(length)?));
+    let (Some(block_type), Some(block)) = (block_type, block) else {
+      warn!("read_capture: Truncated pcapng block at offset {offset}. Stopping.");
+      break;
+    };
+    offset += block.len();
+    // Block contents without the type and length fields around it
+    let body = &block[8..block.len() - 4];
+
+    match block_type {
+      PCAPNG_INTERFACE_DESCRIPTION => {
+        let link_type = endian.u16_at(body, 0).map_or(u32::MAX, u32::from);
+        interfaces.push(PcapngInterface {
+          link_type,
+          units_per_second: pcapng_units_per_second(endian, body.get(8..).unwrap_or(&[])),
+        });
+      }
+      PCAPNG_ENHANCED_PACKET => {
+        let packet = (|| {
+          let interface = interfaces.get(endian.u32_at(body, 0)? as usize)?;
+          let ticks =
+            (u64::from(endian.u32_at(body, 4)?) << 32) | u64::from(endian.u32_at(body, 8)?);
+          let captured_length = endian.u32_at(body, 12)? as usize;
+          let frame = body.get(20..20usize.checked_add(captured_length)?)?;
+          Some((
+            interface.link_type,
+            ticks_to_duration(ticks, interface.units_per_second),
+            frame,
+          ))
+        })();
+        match packet {
+          Some((link_type, timestamp, frame)) => {
+            datagrams.extend(udp_datagram(link_type, frame, timestamp));
+          }
+          None => debug!("read_capture: Bad Enhanced Packet Block at offset {offset}"),
+        }
+      }
+      PCAPNG_SIMPLE_PACKET => {
+        // Simple Packet Blocks belong to the first interface, and have no
+        // timestamp.
+        let packet = (|| {
+          let link_type = interfaces.first()?.link_type;
+          let original_length = endian.u32_at(body, 0)? as usize;
+          let frame = body.get(4..)?;
+          Some((link_type, &frame[..frame.len().min(original_length)]))
+        })();
+        match packet {
+          Some((link_type, frame)) => {
+            datagrams.extend(udp_datagram(link_type, frame, Duration::ZERO));
+          }
+          None => debug!("read_capture: Bad Simple Packet Block at offset {offset}"),
+        }
+      }
+      _ => (), // Section header, statistics, name resolution, etc.
+    }
+  }
+  Ok(datagrams)
+}
+
+// The if_tsresol option from Interface Description Block options
+fn pcapng_units_per_second(endian: Endian, mut options: &[u8]) -> u64 {
+  let mut units_per_second = 1_000_000; // default is microseconds
+  while let (Some(code), Some(length)) = (endian.u16_at(options, 0), endian.u16_at(options, 2)) {
+    let length = usize::from(length);
+    let Some(value) = options.get(4..4 + length) else {
+      break;
+    };
+    match code {
+      PCAPNG_OPTION_END => break,
+      PCAPNG_OPTION_IF_TSRESOL if length == 1 => {
+        let exponent = u32::from(value[0] & 0x7F);
+        let base: u64 = if value[0] & 0x80 == 0 { 10 } else { 2 };
+        units_per_second = base.checked_pow(exponent).unwrap_or(units_per_second);
+      }
+      _ => (),
+    }
+    // Option values are padded to 32 bits.
+    options = options.get(4 + (length + 3) / 4 * 4..).unwrap_or(&[]);
+  }
+  units_per_second
+}
+
+fn ticks_to_duration(ticks: u64, units_per_second: u64) -> Duration {
+  let nanos = u128::from(ticks) * 1_000_000_000 / u128::from(units_per_second.max(1));
+  Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+// Link layer frame to UDP datagram, if it is one
+fn udp_datagram(link_type: u32, frame: &[u8], timestamp: Duration) -> Option<CapturedDatagram> {
+  let ip_packet = match link_type {
+    LINKTYPE_NULL | LINKTYPE_LOOP => frame.get(4..)?, // address family in host byte order
+    LINKTYPE_ETHERNET => {
+      let mut ethertype_offset = 12;
+      let mut ethertype = BigEndian::read_u16(frame.get(12..14)?);
+      while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+        ethertype_offset += 4;
+        ethertype = BigEndian::read_u16(frame.get(ethertype_offset..ethertype_offset + 2)?);
+      }
+      if ethertype != ETHERTYPE_IPV4 && ethertype != ETHERTYPE_IPV6 {
+        return None;
+      }
+      frame.get(ethertype_offset + 2..)?
+    }
+    LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => frame,
+    LINKTYPE_LINUX_SLL => frame.get(16..)?,
+    LINKTYPE_LINUX_SLL2 => frame.get(20..)?,
+    other => {
+      debug!("read_capture: Unsupported link type {other}");
+      return None;
+    }
+  };
+
+  let (source_ip, destination_ip, udp): (IpAddr, IpAddr, &[u8]) = match ip_packet.first()? >> 4 {
+    4 => {
+      let header_length = usize::from(ip_packet[0] & 0x0F) * 4;
+      let total_length = usize::from(BigEndian::read_u16(ip_packet.get(2..4)?));
+      let fragment = BigEndian::read_u16(ip_packet.get(6..8)?);
+      if *ip_packet.get(9)? != IP_PROTOCOL_UDP || header_length < 20 {
+        return None;
+      }
+      if fragment & 0x3FFF != 0 {
+        // More fragments flag or nonzero fragment offset
+        debug!("read_capture: Skipping IPv4 fragment");
+        return None;
+      }
+      let source: [u8; 4] = ip_packet.get(12..16)?.try_into().ok()?;
+      let destination: [u8; 4] = ip_packet.get(16..20)?.try_into().ok()?;
+      let end = total_length.clamp(header_length, ip_packet.len());
+      (
+        Ipv4Addr::from(source).into(),
+        Ipv4Addr::from(destination).into(),
+        ip_packet.get(header_length..end)?,
+      )
+    }
+    6 => {
+      // Extension headers are not supported.
+      if *ip_packet.get(6)? != IP_PROTOCOL_UDP {
+        return None;
+      }
+      let payload_length = usize::from(BigEndian::read_u16(ip_packet.get(4..6)?));
+      let source: [u8; 16] = ip_packet.get(8..24)?.try_into().ok()?;
+      let destination: [u8; 16] = ip_packet.get(24..40)?.try_into().ok()?;
+      let end = (40 + payload_length).min(ip_packet.len());
+      (
+        Ipv6Addr::from(source).into(),
+        Ipv6Addr::from(destination).into(),
+        ip_packet.get(40..end)?,
+      )
+    }
+    _ => return None,
+  };
+
+  let source_port = BigEndian::read_u16(udp.get(0..2)?);
+  let destination_port = BigEndian::read_u16(udp.get(2..4)?);
+  let udp_length = usize::from(BigEndian::read_u16(udp.get(4..6)?));
+  // The payload may be cut short by the capture snapshot length.
+  let payload = udp.get(8..udp_length.clamp(8, udp.len()))?;
+
+  Some(CapturedDatagram {
+    timestamp,
+    source: SocketAddr::new(source_ip, source_port),
+    destination: SocketAddr::new(destination_ip, destination_port),
+    payload: Bytes::copy_from_slice(payload),
+  })
+}
+
+#[derive(Clone, Copy)]
+enum Endian {
+  Little,
+  Big,
+}
+
+impl Endian {
+  fn u16_at(self, data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(match self {
+      Self::Little => LittleEndian::read_u16(bytes),
+      Self::Big => BigEndian::read_u16(bytes),
+    })
+  }
+
+  fn u32_at(self, data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(match self {
+      Self::Little => LittleEndian::read_u32(bytes),
+      Self::Big => BigEndian::read_u32(bytes),
+    })
+  }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Ethernet + IPv4 + UDP frame
+  fn ethernet_frame(source: SocketAddr, destination: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let (IpAddr::V4(source_ip), IpAddr::V4(destination_ip)) = (source.ip(), destination.ip())
+    else {
+      panic!("IPv4 only");
+    };
+    let mut frame = vec![0; 12]; // MAC addresses
+    frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+    frame.extend_from_slice(&[0x45, 0]); // version, header length, DSCP
+    frame.extend_from_slice(&(20 + 8 + payload.len() as u16).to_be_bytes());
+    frame.extend_from_slice(&[0, 0, 0x40, 0]); // identification, Don't Fragment
+    frame.extend_from_slice(&[64, IP_PROTOCOL_UDP, 0, 0]); // TTL, protocol, checksum
+    frame.extend_from_slice(&source_ip.octets());
+    frame.extend_from_slice(&destination_ip.octets());
+    frame.extend_from_slice(&source.port().to_be_bytes());
+    frame.extend_from_slice(&destination.port().to_be_bytes());
+    frame.extend_from_slice(&(8 + payload.len() as u16).to_be_bytes());
+    frame.extend_from_slice(&[0, 0]); // checksum
+    frame.extend_from_slice(payload);
+    frame
+  }
+
+  fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+    let mut body = body.to_vec();
+    body.resize((body.len() + 3) / 4 * 4, 0);
+    let length = (body.len() + 12) as u32;
+    let mut block = Vec::new();
+    block.extend_from_slice(&block_type.to_le_bytes());
+    block.extend_from_slice(&length.to_le_bytes());
+    block.extend_from_slice(&body);
+    block.extend_from_slice(&length.to_le_bytes());
+    block
+  }
+
+  #[test]
+  fn read_pcap_file() {
+    let source: SocketAddr = "192.168.1.2:7412".parse().unwrap();
+    let destination: SocketAddr = "239.255.0.1:7400".parse().unwrap();
+    let frame = ethernet_frame(source, destination, b"RTPS-ish");
+
+    // Big-endian microsecond pcap, Ethernet
+    let mut capture = Vec::new();
+    capture.extend_from_slice(&0xA1B2_C3D4_u32.to_be_bytes());
+    capture.extend_from_slice(&[0, 2, 0, 4]); // version 2.4
+    for field in [0, 0, 65535, LINKTYPE_ETHERNET] {
+      capture.extend_from_slice(&field.to_be_bytes());
+    }
+    for field in [
+      1_600_000_000_u32,
+      250_000,
+      frame.len() as u32,
+      frame.len() as u32,
+    ] {
+      capture.extend_from_slice(&field.to_be_bytes());
+    }
+    capture.extend_from_slice(&frame);
+    // A non-UDP frame is skipped.
+    let mut arp = vec![0; 12];
+    arp.extend_from_slice(&[0x08, 0x06, 0, 0]);
+    for field in [1_600_000_001_u32, 0, arp.len() as u32, arp.len() as u32] {
+      capture.extend_from_slice(&field.to_be_bytes());
+    }
+    capture.extend_from_slice(&arp);
+    // A truncated record ends the capture.
+    capture.extend_from_slice(&[0; 10]);
+
+    let datagrams = read_capture(&capture).unwrap();
+    assert_eq!(datagrams.len(), 1);
+    assert_eq!(datagrams[0].source, source);
+    assert_eq!(datagrams[0].destination, destination);
+    assert_eq!(&datagrams[0].payload[..], b"RTPS-ish");
+    assert_eq!(
+      datagrams[0].timestamp,
+      Duration::from_secs(1_600_000_000) + Duration::from_millis(250)
+    );
+  }
+
+  #[test]
+  fn read_pcapng_file() {
+    let source: SocketAddr = "10.0.0.1:7410".parse().unwrap();
+    let destination: SocketAddr = "10.0.0.2:7411".parse().unwrap();
+    let frame = ethernet_frame(source, destination, b"payload");
+
+    let mut capture = Vec::new();
+    let mut section_header = Vec::new();
+    section_header.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+    section_header.extend_from_slice(&[1, 0, 0, 0]); // version 1.0
+    section_header.extend_from_slice(&u64::MAX.to_le_bytes()); // section length unknown
+    capture.extend(pcapng_block(PCAPNG_SECTION_HEADER, &section_header));
+
+    let mut interface = Vec::new();
+    interface.extend_from_slice(&(LINKTYPE_ETHERNET as u16).to_le_bytes());
+    interface.extend_from_slice(&[0, 0]);
+    interface.extend_from_slice(&0_u32.to_le_bytes()); // snaplen
+    interface.extend_from_slice(&PCAPNG_OPTION_IF_TSRESOL.to_le_bytes());
+    interface.extend_from_slice(&1_u16.to_le_bytes());
+    interface.extend_from_slice(&[9, 0, 0, 0]); // nanoseconds
+    interface.extend_from_slice(&[0, 0, 0, 0]); // end of options
+    capture.extend(pcapng_block(PCAPNG_INTERFACE_DESCRIPTION, &interface));
+
+    let ticks: u64 = 1_600_000_000_123_456_789;
+    let mut packet = Vec::new();
+    packet.extend_from_slice(&0_u32.to_le_bytes()); // interface
+    packet.extend_from_slice(&((ticks >> 32) as u32).to_le_bytes());
+    packet.extend_from_slice(&(ticks as u32).to_le_bytes());
+    packet.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+    packet.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+    packet.extend_from_slice(&frame);
+    capture.extend(pcapng_block(PCAPNG_ENHANCED_PACKET, &packet));
+
+    let mut simple_packet = (frame.len() as u32).to_le_bytes().to_vec();
+    simple_packet.extend_from_slice(&frame);
+    capture.extend(pcapng_block(PCAPNG_SIMPLE_PACKET, &simple_packet));
+
+    let datagrams = read_capture(&capture).unwrap();
+    assert_eq!(datagrams.len(), 2);
+    for datagram in &datagrams {
+      assert_eq!(datagram.source, source);
+      assert_eq!(datagram.destination, destination);
+      assert_eq!(&datagram.payload[..], b"payload");
+    }
+    assert_eq!(datagrams[0].timestamp, Duration::from_nanos(ticks));
+    assert_eq!(datagrams[1].timestamp, Duration::ZERO);
+  }
+
+  #[test]
+  fn reject_other_files() {
+    assert!(read_capture(b"RTPS\x02\x04\x01\x12").is_err());
+    assert!(read_capture(&[]).is_err());
+  }
+}
@@ -1,9 +1,12 @@
-use mio_06::Token;
-use mio_extras::channel as mio_channel;
+use mio_08::Token;
 
 use crate::{
   discovery::sedp_messages::{DiscoveredReaderData, DiscoveredWriterData},
-  structure::guid::{EntityKind, GuidPrefix, GUID},
+  mio_source::channel as mio_channel,
+  structure::{
+    guid::{EntityKind, GuidPrefix, GUID},
+    locator::Locator,
+  },
 };
 
 const PTB: usize = EntityKind::POLL_TOKEN_BASE;
@@ -41,6 +44,12 @@ pub const DISCOVERY_UPDATE_NOTIFICATION_TOKEN: Token = Token(21 + PTB);
 pub const DISCOVERY_COMMAND_TOKEN: Token = Token(22 + PTB);
 pub const SPDP_LIVENESS_TOKEN: Token = Token(23 + PTB);
 
+// IPv6 counterparts of the listener tokens above
+pub const DISCOVERY_LISTENER_V6_TOKEN: Token = Token(24 + PTB);
+pub const DISCOVERY_MUL_LISTENER_V6_TOKEN: Token = Token(25 + PTB);
+pub const USER_TRAFFIC_LISTENER_V6_TOKEN: Token = Token(26 + PTB);
+pub const USER_TRAFFIC_MUL_LISTENER_V6_TOKEN: Token = Token(27 + PTB);
+
 pub const DISCOVERY_PARTICIPANT_DATA_TOKEN: Token = Token(30 + PTB);
 pub const DISCOVERY_PARTICIPANT_CLEANUP_TOKEN: Token = Token(31 + PTB);
 pub const DISCOVERY_SEND_PARTICIPANT_INFO_TOKEN: Token = Token(32 + PTB);
@@ -54,7 +63,17 @@ pub const DISCOVERY_SEND_TOPIC_INFO_TOKEN: Token = Token(39 + PTB);
 pub const DISCOVERY_PARTICIPANT_MESSAGE_TOKEN: Token = Token(40 + PTB);
 pub const DISCOVERY_PARTICIPANT_MESSAGE_TIMER_TOKEN: Token = Token(41 + PTB);
 
-pub const DPEV_ACKNACK_TIMER_TOKEN: Token = Token(50 + PTB);
+// Received messages dispatched to an event loop shard
+pub const SHARD_MESSAGE_TOKEN: Token = Token(42 + PTB);
+
+pub const BATCH_FLUSH_TIMER_TOKEN: Token = Token(48 + PTB);
+pub const ASYNC_SENDER_TOKEN: Token = Token(49 + PTB);
+
+pub const FLOW_CONTROLLER_TIMER_TOKEN: Token = Token(51 + PTB);
+
+// Transports use consecutive tokens starting from this, up to the end of the
+// fixed token range.
+pub const TRANSPORT_TOKEN_BASE: Token = Token(52 + PTB);
 
 pub struct TokenReceiverPair<T> {
   pub token: Token,
@@ -71,23 +90,79 @@ const D1: u16 = 10;
 const D2: u16 = 1;
 const D3: u16 = 11;
 
-pub fn spdp_well_known_multicast_port(domain_id: u16) -> u16 {
-  PB + DG * domain_id + D0
-}
-
-pub fn spdp_well_known_unicast_port(domain_id: u16, participant_id: u16) -> u16 {
-  PB + DG * domain_id + D1 + PG * participant_id
+/// Parameters for mapping domain id and participant id to UDP port numbers.
+///
+/// See RTPS spec v2.5 Section "9.6.2.3 Default Port Numbers" for meaning of
+/// the parameters. The `Default` implementation gives the values from the
+/// specification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PortMapping {
+  /// Port base number
+  pub pb: u16,
+  /// Domain id gain
+  pub dg: u16,
+  /// Participant id gain
+  pub pg: u16,
+  /// Offset for discovery (SPDP) multicast
+  pub d0: u16,
+  /// Offset for discovery (SPDP) unicast
+  pub d1: u16,
+  /// Offset for user traffic multicast
+  pub d2: u16,
+  /// Offset for user traffic unicast
+  pub d3: u16,
 }
 
-pub fn user_traffic_multicast_port(domain_id: u16) -> u16 {
-  PB + DG * domain_id + D2
+impl Default for PortMapping {
+  fn default() -> Self {
+    Self {
+      pb: PB,
+      dg: DG,
+      pg: PG,
+      d0: D0,
+      d1: D1,
+      d2: D2,
+      d3: D3,
+    }
+  }
 }
 
-pub fn user_traffic_unicast_port(domain_id: u16, participant_id: u16) -> u16 {
-  PB + DG * domain_id + D3 + PG * participant_id
+impl PortMapping {
+  // All ports are computed the same way: a port number that does not fit in
+  // u16 gives None.
+  fn port(&self, offset: u16, domain_id: u16, participant_id: u16) -> Option<u16> {
+    self
+      .dg
+      .checked_mul(domain_id)?
+      .checked_add(self.pb)?
+      .checked_add(offset)?
+      .checked_add(self.pg.checked_mul(participant_id)?)
+  }
+
+  /// SPDP multicast port of the domain, or `None` if it would overflow.
+  pub fn spdp_well_known_multicast_port(&self, domain_id: u16) -> Option<u16> {
+    self.port(self.d0, domain_id, 0)
+  }
+
+  /// SPDP unicast port of the participant, or `None` if it would overflow.
+  pub fn spdp_well_known_unicast_port(&self, domain_id: u16, participant_id: u16) -> Option<u16> {
+    self.port(self.d1, domain_id, participant_id)
+  }
+
+  /// User traffic multicast port of the domain, or `None` if it would
+  /// overflow.
+  pub fn user_traffic_multicast_port(&self, domain_id: u16) -> Option<u16> {
+    self.port(self.d2, domain_id, 0)
+  }
+
+  /// User traffic unicast port of the participant, or `None` if it would
+  /// overflow.
+  pub fn user_traffic_unicast_port(&self, domain_id: u16, participant_id: u16) -> Option<u16> {
+    self.port(self.d3, domain_id, participant_id)
+  }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(clippy::large_enum_variant)]
 pub(crate) enum DiscoveryNotificationType {
   ReaderUpdated {
@@ -107,6 +182,8 @@ pub(crate) enum DiscoveryNotificationType {
   },
   ParticipantLost {
     guid_prefix: GuidPrefix,
+    // Unicast locators that the participant announced
+    locators: Vec<Locator>,
   },
   AssertTopicLiveliness {
     writer_guid: GUID,
@@ -0,0 +1,205 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+
+/// Selects local network interfaces for
+/// [`allow_interfaces`](crate::DomainParticipantBuilder::allow_interfaces) and
+/// [`deny_interfaces`](crate::DomainParticipantBuilder::deny_interfaces).
+///
+/// An interface address is selected by the name of its interface, by the
+/// address itself, or by a network (CIDR) that contains it.
+///
+/// The string form accepts
+/// * `"address"`: an IPv4 or IPv6 address,
+/// * `"address/prefix-length"`: a network, e.g. `"172.16.0.0/12"`,
+/// * anything else as an interface name, e.g. `"eth0"` or `"docker0"`.
+///
+/// # Examples
+/// ```
+/// # use rustdds::InterfaceSelector;
+/// let selector: InterfaceSelector = "docker0".parse().unwrap();
+/// let selector: InterfaceSelector = "192.168.1.0/24".parse().unwrap();
+/// let selector: InterfaceSelector = "fd00::2".parse().unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterfaceSelector(Selector);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Selector {
+  Name(String),
+  Address(IpAddr),
+  Network { address: IpAddr, prefix_len: u8 },
+}
+
+impl InterfaceSelector {
+  /// Interface with the given name, with all its addresses.
+  pub fn name(name: impl Into<String>) -> Self {
+    Self(Selector::Name(name.into()))
+  }
+
+  /// Interface address equal to the given one.
+  pub fn address(address: IpAddr) -> Self {
+    Self(Selector::Address(address))
+  }
+
+  /// Interface addresses in the network of the given address and prefix
+  /// length. Prefix length is at most 32 for IPv4 and 128 for IPv6.
+  pub fn network(address: IpAddr, prefix_len: u8) -> Self {
+    Self(Selector::Network {
+      address,
+      prefix_len: prefix_len.min(max_prefix_len(&address)),
+    })
+  }
+
+  fn selects(&self, interface_name: &str, ip: &IpAddr) -> bool {
+    match &self.0 {
+      Selector::Name(name) => name == interface_name,
+      Selector::Address(address) => address == ip,
+      Selector::Network {
+        address,
+        prefix_len,
+      } => match (address, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+          let mask = u32::MAX
+            .checked_shl(32 - u32::from(*prefix_len))
+            .unwrap_or(0);
+          u32::from(*network) & mask == u32::from(*ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+          let mask = u128::MAX
+            .checked_shl(128 - u32::from(*prefix_len))
+            .unwrap_or(0);
+          u128::from(*network) & mask == u128::from(*ip) & mask
+        }
+        _ => false,
+      },
+    }
+  }
+}
+
+fn max_prefix_len(address: &IpAddr) -> u8 {
+  match address {
+    IpAddr::V4(_) => 32,
+    IpAddr::V6(_) => 128,
+  }
+}
+
+/// Error from parsing an [`InterfaceSelector`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterfaceSelectorParseError(String);
+
+impl fmt::Display for InterfaceSelectorParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Invalid network interface selector {:?}", self.0)
+  }
+}
+
+impl std::error::Error for InterfaceSelectorParseError {}
+
+impl FromStr for InterfaceSelector {
+  type Err = InterfaceSelectorParseError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let err = || InterfaceSelectorParseError(s.to_string());
+
+    if let Some((address, prefix_len)) = s.split_once('/') {
+      let address: IpAddr = address.parse().map_err(|_| err())?;
+      let prefix_len: u8 = prefix_len.parse().map_err(|_| err())?;
+      if prefix_len > max_prefix_len(&address) {
+        return Err(err());
+      }
+      Ok(Self::network(address, prefix_len))
+    } else if let Ok(address) = s.parse() {
+      Ok(Self::address(address))
+    } else if s.is_empty() || s.contains(char::is_whitespace) {
+      Err(err())
+    } else {
+      Ok(Self::name(s))
+    }
+  }
+}
+
+// Allow and deny lists of a participant. An interface address is used, if the
+// allow list is empty or selects it, and the deny list does not select it.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InterfaceFilter {
+  pub allow: Vec<InterfaceSelector>,
+  pub deny: Vec<InterfaceSelector>,
+}
+
+impl InterfaceFilter {
+  pub fn permits(&self, interface_name: &str, ip: &IpAddr) -> bool {
+    (self.allow.is_empty() || self.allow.iter().any(|s| s.selects(interface_name, ip)))
+      && !self.deny.iter().any(|s| s.selects(interface_name, ip))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::{Ipv4Addr, Ipv6Addr};
+
+  use super::*;
+
+  #[test]
+  fn interface_selector_parse() {
+    assert_eq!("eth0".parse(), Ok(InterfaceSelector::name("eth0")));
+    assert_eq!(
+      "10.0.0.5".parse(),
+      Ok(InterfaceSelector::address(IpAddr::V4(Ipv4Addr::new(
+        10, 0, 0, 5
+      ))))
+    );
+    assert_eq!(
+      "10.0.0.0/8".parse(),
+      Ok(InterfaceSelector::network(
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)),
+        8
+      ))
+    );
+    assert_eq!(
+      "fd00::/8".parse(),
+      Ok(InterfaceSelector::network(
+        IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 0)),
+        8
+      ))
+    );
+    assert!("".parse::<InterfaceSelector>().is_err());
+    assert!("eth 0".parse::<InterfaceSelector>().is_err());
+    assert!("10.0.0.0/33".parse::<InterfaceSelector>().is_err());
+    assert!("10.0.0.0/".parse::<InterfaceSelector>().is_err());
+    assert!("eth0/8".parse::<InterfaceSelector>().is_err());
+  }
+
+  #[test]
+  fn interface_filter_permits() {
+    let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+    let selector = |s: &str| s.parse::<InterfaceSelector>().unwrap();
+
+    let filter = InterfaceFilter::default();
+    assert!(filter.permits("docker0", &ip("172.17.0.1")));
+
+    let filter = InterfaceFilter {
+      allow: vec![],
+      deny: vec![selector("docker0"), selector("10.8.0.0/16")],
+    };
+    assert!(!filter.permits("docker0", &ip("172.17.0.1")));
+    assert!(!filter.permits("tun0", &ip("10.8.3.4")));
+    assert!(filter.permits("tun0", &ip("10.9.3.4")));
+    assert!(filter.permits("eth0", &ip("192.168.1.10")));
+
+    let filter = InterfaceFilter {
+      allow: vec![selector("192.168.1.0/24"), selector("fd00::2")],
+      deny: vec![selector("192.168.1.99")],
+    };
+    assert!(filter.permits("eth0", &ip("192.168.1.10")));
+    assert!(!filter.permits("eth0", &ip("192.168.1.99")));
+    assert!(!filter.permits("eth1", &ip("192.168.2.10")));
+    assert!(filter.permits("eth0", &ip("fd00::2")));
+    assert!(!filter.permits("eth0", &ip("fd00::3")));
+
+    let filter = InterfaceFilter {
+      allow: vec![selector("0.0.0.0/0")],
+      deny: vec![],
+    };
+    assert!(filter.permits("eth0", &ip("192.168.1.10")));
+    assert!(!filter.permits("eth0", &ip("fd00::2")));
+  }
+}
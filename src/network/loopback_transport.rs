@@ -0,0 +1,421 @@
+//! In-process loopback transport.
+//!
+//! Participants that use the same [`LoopbackNetwork`] exchange RTPS messages
+//! through channels, without any sockets. This makes tests with several
+//! participants in one process independent of the network configuration of
+//! the host, and of other tests running at the same time.
+//!
+//! Each transport is an endpoint of the network and gets a unicast locator of
+//! its own. The multicast locator of a domain reaches all the other endpoints
+//! of that domain. Messages can be lost, duplicated, delayed and reordered on
+//! purpose, as configured with [`LoopbackImpairments`], to exercise reliable
+//! communication.
+
+use std::{
+  collections::{BTreeMap, HashMap},
+  io,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use mio_08::{Interest, Registry, Token};
+use bytes::Bytes;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+  mio_source::{channel as mio_channel, timer::Timer},
+  network::transport::{Transport, TransportContext, TransportLocators},
+  structure::locator::{kind::LOOPBACK as LOCATOR_KIND_LOOPBACK, Locator},
+};
+
+// Locator port that addresses all endpoints of a domain
+const MULTICAST_PORT: u32 = 0;
+
+// How long a reordered message is held back, so that later messages overtake
+// it.
+const REORDER_DELAY: Duration = Duration::from_millis(20);
+
+/// Faults injected into the messages of a [`LoopbackNetwork`]. Probabilities
+/// are between 0.0 and 1.0, and apply to each message and destination
+/// separately. The default is a perfect network.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LoopbackImpairments {
+  /// Probability that a message is lost
+  pub loss: f64,
+  /// Probability that a message is delivered twice
+  pub duplication: f64,
+  /// Probability that a message is held back, so that later messages overtake
+  /// it
+  pub reordering: f64,
+  /// Delay of every message
+  pub delay: Duration,
+  /// Maximum random delay added to every message
+  pub jitter: Duration,
+}
+
+struct Endpoint {
+  domain_id: u16,
+  sender: mio_channel::Sender<(Bytes, Duration)>,
+}
+
+struct NetworkState {
+  next_endpoint_id: u32,
+  endpoints: BTreeMap<u32, Endpoint>,
+  impairments: LoopbackImpairments,
+  // None for a random seed
+  seed: Option<u64>,
+  // Random numbers of each (sender, receiver) pair of endpoints
+  links: HashMap<(u32, u32), StdRng>,
+}
+
+/// A registry of loopback transports, which can send messages to each other.
+///
+/// Give the transport factory to each participant that should be in the
+/// network, and disable the other transports to have no sockets at all:
+///
+/// ```
+/// use rustdds::{transport::LoopbackNetwork, DomainParticipantBuilder};
+///
+/// let network = LoopbackNetwork::new();
+/// let participant = DomainParticipantBuilder::new(0)
+///   .udp(false)
+///   .shared_memory(false)
+///   .transport(network.transport_factory())
+///   .build()
+///   .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct LoopbackNetwork {
+  state: Arc<Mutex<NetworkState>>,
+}
+
+impl LoopbackNetwork {
+  pub fn new() -> Self {
+    Self::from_seed(None)
+  }
+
+  /// Network whose impairments are random with the given seed.
+  ///
+  /// Each pair of endpoints has random numbers of its own, derived from the
+  /// seed and the endpoint ids, which are given out in the order the
+  /// transports are created. So when the same messages are sent between two
+  /// endpoints, the same ones are lost, regardless of the traffic between the
+  /// other endpoints. Participants decide what to send partly based on timing,
+  /// so their runs are not exactly reproducible.
+  pub fn with_seed(seed: u64) -> Self {
+    Self::from_seed(Some(seed))
+  }
+
+  fn from_seed(seed: Option<u64>) -> Self {
+    Self {
+      state: Arc::new(Mutex::new(NetworkState {
+        next_endpoint_id: MULTICAST_PORT + 1,
+        endpoints: BTreeMap::new(),
+        impairments: LoopbackImpairments::default(),
+        seed,
+        links: HashMap::new(),
+      })),
+    }
+  }
+
+  /// Change the faults injected into messages. This applies to messages sent
+  /// from now on.
+  pub fn set_impairments(&self, impairments: LoopbackImpairments) {
+    self.state.lock().unwrap().impairments = impairments;
+  }
+
+  /// Creates a new endpoint in this network.
+  pub fn create_transport(&self, context: &TransportContext) -> LoopbackTransport {
+    let (sender, receiver) = mio_channel::channel();
+    let mut state = self.state.lock().unwrap();
+    let endpoint_id = state.next_endpoint_id;
+    state.next_endpoint_id += 1;
+    state.endpoints.insert(
+      endpoint_id,
+      Endpoint {
+        domain_id: context.domain_id,
+        sender,
+      },
+    );
+    LoopbackTransport {
+      network: self.clone(),
+      endpoint_id,
+      domain_id: context.domain_id,
+      receiver,
+      timer: Timer::default(),
+    }
+  }
+
+  /// Transport factory for
+  /// [`DomainParticipantBuilder::transport`](crate::DomainParticipantBuilder::transport).
+  pub fn transport_factory(
+    &self,
+  ) -> impl Fn(&TransportContext) -> io::Result<Box<dyn Transport>> + Send + Sync + 'static {
+    let network = self.clone();
+    move |context| Ok(Box::new(network.create_transport(context)))
+  }
+
+  fn send(&self, from: u32, domain_id: u16, buffer: &[u8], to: u32) {
+    let mut state = self.state.lock().unwrap();
+    let NetworkState {
+      endpoints,
+      impairments,
+      seed,
+      links,
+      ..
+    } = &mut *state;
+    let destinations = endpoints.iter().filter(|(id, endpoint)| {
+      endpoint.domain_id == domain_id && **id != from && (to == MULTICAST_PORT || **id == to)
+    });
+    for (id, endpoint) in destinations {
+      let rng = links.entry((from, *id)).or_insert_with(|| match seed {
+        Some(seed) => StdRng::seed_from_u64(*seed ^ (u64::from(from) << 32 | u64::from(*id))),
+        None => StdRng::from_entropy(),
+      });
+      if rng.gen_bool(impairments.loss) {
+        trace!("Loopback: lost message to {id}");
+        continue;
+      }
+      let copies = if rng.gen_bool(impairments.duplication) {
+        2
+      } else {
+        1
+      };
+      for _ in 0..copies {
+        let mut delay = impairments.delay + impairments.jitter.mul_f64(rng.gen::<f64>());
+        if rng.gen_bool(impairments.reordering) {
+          delay += REORDER_DELAY;
+        }
+        // The receiver may be gone already, if its participant is shutting
+        // down.
+        endpoint
+          .sender
+          .send((Bytes::copy_from_slice(buffer), delay))
+          .unwrap_or_else(|e| debug!("Loopback: cannot send to {id}: {e:?}"));
+      }
+    }
+  }
+
+  fn remove(&self, endpoint_id: u32) {
+    let mut state = self.state.lock().unwrap();
+    state.endpoints.remove(&endpoint_id);
+    state
+      .links
+      .retain(|(from, to), _| *from != endpoint_id && *to != endpoint_id);
+  }
+}
+
+impl Default for LoopbackNetwork {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// An endpoint of a [`LoopbackNetwork`]. It is removed from the network when
+/// dropped.
+pub struct LoopbackTransport {
+  network: LoopbackNetwork,
+  endpoint_id: u32,
+  domain_id: u16,
+  receiver: mio_channel::Receiver<(Bytes, Duration)>,
+  // Delayed messages
+  timer: Timer<Bytes>,
+}
+
+impl LoopbackTransport {
+  fn locator(&self, port: u32) -> Locator {
+    let mut address = [0; 16];
+    address[..2].copy_from_slice(&self.domain_id.to_be_bytes());
+    Locator::Other {
+      kind: LOCATOR_KIND_LOOPBACK,
+      port,
+      address,
+    }
+  }
+}
+
+impl Transport for LoopbackTransport {
+  fn can_send_to(&self, locator: &Locator) -> bool {
+    match locator {
+      Locator::Other {
+        kind: LOCATOR_KIND_LOOPBACK,
+        port,
+        ..
+      } => *locator == self.locator(*port),
+      _ => false,
+    }
+  }
+
+  fn send_to_locator(&mut self, buffer: &[u8], locator: &Locator) -> io::Result<()> {
+    match locator {
+      Locator::Other { port, .. } if self.can_send_to(locator) => {
+        self
+          .network
+          .send(self.endpoint_id, self.domain_id, buffer, *port);
+        Ok(())
+      }
+      _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+    }
+  }
+
+  fn announced_locators(&self) -> TransportLocators {
+    TransportLocators {
+      metatraffic_unicast: vec![self.locator(self.endpoint_id)],
+      metatraffic_multicast: vec![self.locator(MULTICAST_PORT)],
+      default_unicast: vec![self.locator(self.endpoint_id)],
+      default_multicast: vec![self.locator(MULTICAST_PORT)],
+    }
+  }
+
+  fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+    registry.register(&mut self.receiver, token, Interest::READABLE)?;
+    registry.register(&mut self.timer, token, Interest::READABLE)
+  }
+
+  fn receive(&mut self) -> Vec<Bytes> {
+    let mut messages = Vec::new();
+    while let Ok((message, delay)) = self.receiver.try_recv() {
+      if delay.is_zero() {
+        messages.push(message);
+      } else {
+        self.timer.set_timeout(delay, message);
+      }
+    }
+    while let Some(message) = self.timer.poll() {
+      messages.push(message);
+    }
+    messages
+  }
+}
+
+impl Drop for LoopbackTransport {
+  fn drop(&mut self) {
+    self.network.remove(self.endpoint_id);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Instant;
+
+  use mio_08::{Events, Poll};
+
+  use super::*;
+
+  fn transport(network: &LoopbackNetwork, domain_id: u16) -> (LoopbackTransport, Poll) {
+    let mut transport = network.create_transport(&TransportContext {
+      domain_id,
+      participant_id: 0,
+    });
+    let poll = Poll::new().unwrap();
+    transport.register(poll.registry(), Token(0)).unwrap();
+    (transport, poll)
+  }
+
+  // Receive until `count` messages have arrived, or a second has passed.
+  fn receive(transport: &mut LoopbackTransport, poll: &mut Poll, count: usize) -> Vec<Bytes> {
+    let mut events = Events::with_capacity(4);
+    let mut messages = transport.receive();
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while messages.len() < count && Instant::now() < deadline {
+      poll
+        .poll(&mut events, Some(Duration::from_millis(10)))
+        .unwrap();
+      messages.extend(transport.receive());
+    }
+    messages
+  }
+
+  #[test]
+  fn loopback_unicast_and_multicast() {
+    let network = LoopbackNetwork::new();
+    let (mut a, _) = transport(&network, 0);
+    let (mut b, mut b_poll) = transport(&network, 0);
+    let (mut c, _) = transport(&network, 1);
+
+    let b_locator = b.announced_locators().default_unicast[0];
+    let multicast_locator = a.announced_locators().default_multicast[0];
+    assert!(a.can_send_to(&b_locator));
+    assert!(!c.can_send_to(&b_locator));
+    assert!(!c.can_send_to(&multicast_locator));
+    assert!(c.send_to_locator(&[0], &b_locator).is_err());
+
+    a.send_to_locator(&[1], &b_locator).unwrap();
+    a.send_to_locator(&[2], &multicast_locator).unwrap();
+    assert_eq!(receive(&mut b, &mut b_poll, 2), vec![vec![1], vec![2]]);
+    // Not to the sender itself, nor to another domain
+    assert!(a.receive().is_empty());
+    assert!(c.receive().is_empty());
+  }
+
+  #[test]
+  fn loopback_impairments() {
+    let network = LoopbackNetwork::with_seed(1);
+    let (mut a, _) = transport(&network, 0);
+    let (mut b, mut b_poll) = transport(&network, 0);
+    let b_locator = b.announced_locators().default_unicast[0];
+
+    network.set_impairments(LoopbackImpairments {
+      loss: 1.0,
+      ..LoopbackImpairments::default()
+    });
+    a.send_to_locator(&[1], &b_locator).unwrap();
+    assert!(receive(&mut b, &mut b_poll, 1).is_empty());
+
+    network.set_impairments(LoopbackImpairments {
+      duplication: 1.0,
+      ..LoopbackImpairments::default()
+    });
+    a.send_to_locator(&[2], &b_locator).unwrap();
+    assert_eq!(receive(&mut b, &mut b_poll, 2), vec![vec![2], vec![2]]);
+
+    network.set_impairments(LoopbackImpairments {
+      delay: Duration::from_millis(50),
+      ..LoopbackImpairments::default()
+    });
+    let sent = Instant::now();
+    a.send_to_locator(&[3], &b_locator).unwrap();
+    assert_eq!(receive(&mut b, &mut b_poll, 1), vec![vec![3]]);
+    assert!(sent.elapsed() >= Duration::from_millis(50));
+
+    // The held back message is overtaken by the next one.
+    network.set_impairments(LoopbackImpairments {
+      reordering: 1.0,
+      ..LoopbackImpairments::default()
+    });
+    a.send_to_locator(&[4], &b_locator).unwrap();
+    network.set_impairments(LoopbackImpairments::default());
+    a.send_to_locator(&[5], &b_locator).unwrap();
+    assert_eq!(receive(&mut b, &mut b_poll, 2), vec![vec![5], vec![4]]);
+  }
+
+  // The messages lost between two endpoints do not depend on the messages
+  // sent to other endpoints.
+  #[test]
+  fn loopback_seeded_links() {
+    let received_by_b = |other_traffic: bool| {
+      let network = LoopbackNetwork::with_seed(7);
+      let (mut a, _) = transport(&network, 0);
+      let (mut b, mut b_poll) = transport(&network, 0);
+      let (c, _) = transport(&network, 0);
+      network.set_impairments(LoopbackImpairments {
+        loss: 0.5,
+        ..LoopbackImpairments::default()
+      });
+      let b_locator = b.announced_locators().default_unicast[0];
+      let c_locator = c.announced_locators().default_unicast[0];
+      for i in 0..100_u8 {
+        a.send_to_locator(&[i], &b_locator).unwrap();
+        if other_traffic {
+          a.send_to_locator(&[i], &c_locator).unwrap();
+        }
+      }
+      receive(&mut b, &mut b_poll, 100)
+    };
+    let received = received_by_b(false);
+    assert!(received.len() > 10 && received.len() < 90);
+    assert_eq!(received, received_by_b(true));
+  }
+}
@@ -0,0 +1,819 @@
+//! Shared-memory transport for participants running on the same host.
+//!
+//! Each participant creates one POSIX shared memory segment that holds a ring
+//! buffer of RTPS messages, and a Unix datagram socket. Senders write a message
+//! into the ring buffer of the receiving participant and then send one byte to
+//! its socket to wake up the receiving event loop.
+//!
+//! The segment is announced as a locator of kind `LOCATOR_KIND_SHM`, next to
+//! the UDP locators. The locator address identifies the host, so that remote
+//! participants can tell if they can reach the segment. If the segment cannot
+//! be opened or written, the sender falls back to the UDP locators.
+//!
+//! A participant that crashes leaves its segment and socket behind. The
+//! segment records the pid of its owner, and segments of dead owners are
+//! removed when the next listener is created. Likewise, the write lock holds
+//! the pid of the writer, so that a lock left behind by a crashed writer can
+//! be broken.
+
+use std::{
+  cell::RefCell,
+  collections::HashMap,
+  ffi::CString,
+  io, mem,
+  os::unix::net::UnixDatagram,
+  path::PathBuf,
+  ptr,
+  sync::atomic::{AtomicU32, AtomicU64, Ordering},
+  thread,
+};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use mio_08::{event, Interest, Registry, Token};
+use bytes::Bytes;
+
+use crate::{
+  network::transport::{GatherBuffer, Transport, TransportLocators},
+  structure::locator::{kind::SHM as LOCATOR_KIND_SHM, Locator},
+};
+
+const SEGMENT_MAGIC: u32 = 0x5244_5348; // "RDSH"
+
+// Each message in the ring buffer is preceded by its length as u32.
+const RECORD_HEADER_SIZE: usize = 4;
+
+// How many times a sender tries to take the write lock before checking if its
+// holder is still alive
+const LOCK_SPIN_LIMIT: u32 = 100_000;
+
+// How many random segment names we try before giving up
+const CREATE_ATTEMPTS: usize = 8;
+
+// Segment header. This is shared between processes, so all fields are
+// atomics.
+#[repr(C)]
+struct RingHeader {
+  magic: AtomicU32,
+  capacity: AtomicU32,
+  write_lock: AtomicU32, // pid of the writer holding the lock, or 0
+  owner_pid: AtomicU32,  // pid of the listener
+  head: AtomicU64,       // total bytes written
+  tail: AtomicU64,       // total bytes consumed
+}
+
+const HEADER_SIZE: usize = mem::size_of::<RingHeader>();
+
+fn segment_name(segment_id: u32) -> CString {
+  CString::new(format!("/rustdds_shm_{segment_id:08x}")).unwrap() // no NUL bytes in there
+}
+
+fn socket_path(segment_id: u32) -> PathBuf {
+  std::env::temp_dir().join(format!("rustdds_shm_{segment_id:08x}.sock"))
+}
+
+fn current_pid() -> u32 {
+  std::process::id()
+}
+
+// Signal 0 only checks if the process exists. EPERM means that it exists, but
+// belongs to another user.
+fn process_alive(pid: u32) -> bool {
+  match libc::pid_t::try_from(pid) {
+    Ok(pid) if pid > 0 => {
+      let result = unsafe { libc::kill(pid, 0) };
+      result == 0 || io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+    }
+    _ => false,
+  }
+}
+
+fn corrupted_segment() -> io::Error {
+  io::Error::new(
+    io::ErrorKind::InvalidData,
+    "Corrupted shared memory segment",
+  )
+}
+
+// Removes the segments and sockets left behind by crashed participants. A
+// segment is stale if its owner process is gone and nobody is listening on its
+// socket. Segments of other users cannot be opened, and are left alone.
+fn remove_stale_segments() {
+  let entries = match std::fs::read_dir("/dev/shm") {
+    Ok(entries) => entries,
+    Err(e) => {
+      debug!("Cannot list shared memory segments: {e:?}");
+      return;
+    }
+  };
+  let probe = UnixDatagram::unbound().ok();
+  for entry in entries.flatten() {
+    let file_name = entry.file_name();
+    let segment_id = match file_name
+      .to_str()
+      .and_then(|name| name.strip_prefix("rustdds_shm_"))
+      .and_then(|id| u32::from_str_radix(id, 16).ok())
+    {
+      Some(id) => id,
+      None => continue,
+    };
+    let name = segment_name(segment_id);
+    let owner_pid = match Segment::open(&name) {
+      Ok(segment) => segment.header().owner_pid.load(Ordering::Relaxed),
+      // Not ours to remove, or still being created
+      Err(_) => continue,
+    };
+    let path = socket_path(segment_id);
+    let listening = probe
+      .as_ref()
+      .map_or(true, |probe| match probe.send_to(&[], &path) {
+        Ok(_) => true,
+        Err(e) => !matches!(
+          e.kind(),
+          io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+        ),
+      });
+    if !process_alive(owner_pid) && !listening {
+      info!("Removing stale shared memory segment {name:?} of process {owner_pid}");
+      unsafe { libc::shm_unlink(name.as_ptr()) };
+      std::fs::remove_file(&path).ok();
+    }
+  }
+}
+
+// Identifies this host (or at least the running kernel), so that locators
+// announced by remote hosts are not mistaken for local segments.
+fn host_id() -> [u8; 16] {
+  let mut id = String::new();
+  for path in ["/etc/machine-id", "/proc/sys/kernel/random/boot_id"] {
+    if let Ok(s) = std::fs::read_to_string(path) {
+      id.push_str(s.trim());
+    }
+  }
+  *md5::compute(id)
+}
+
+fn shm_locator(host_id: [u8; 16], segment_id: u32) -> Locator {
+  Locator::Other {
+    kind: LOCATOR_KIND_SHM,
+    port: segment_id,
+    address: host_id,
+  }
+}
+
+// A mapped shared memory segment
+#[derive(Debug)]
+struct Segment {
+  base: *mut u8,
+  map_len: usize,
+  capacity: usize,
+}
+
+// The segment is only accessed through atomics and by a single thread.
+unsafe impl Send for Segment {}
+
+impl Segment {
+  fn create(name: &CString, capacity: usize) -> io::Result<Self> {
+    if capacity < RECORD_HEADER_SIZE || capacity > u32::MAX as usize {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "Invalid shared memory segment size",
+      ));
+    }
+    let map_len = HEADER_SIZE + capacity;
+    let fd = unsafe {
+      libc::shm_open(
+        name.as_ptr(),
+        libc::O_CREAT | libc::O_EXCL | libc::O_RDWR,
+        0o600,
+      )
+    };
+    if fd < 0 {
+      return Err(io::Error::last_os_error());
+    }
+    let result = if unsafe { libc::ftruncate(fd, map_len as libc::off_t) } < 0 {
+      Err(io::Error::last_os_error())
+    } else {
+      Self::map(fd, map_len)
+    };
+    unsafe { libc::close(fd) };
+
+    match result {
+      Ok(segment) => {
+        let header = segment.header();
+        header.capacity.store(capacity as u32, Ordering::Relaxed);
+        header.write_lock.store(0, Ordering::Relaxed);
+        header.owner_pid.store(current_pid(), Ordering::Relaxed);
+        header.head.store(0, Ordering::Relaxed);
+        header.tail.store(0, Ordering::Relaxed);
+        header.magic.store(SEGMENT_MAGIC, Ordering::Release);
+        Ok(segment)
+      }
+      Err(e) => {
+        unsafe { libc::shm_unlink(name.as_ptr()) };
+        Err(e)
+      }
+    }
+  }
+
+  fn open(name: &CString) -> io::Result<Self> {
+    let fd = unsafe { libc::shm_open(name.as_ptr(), libc::O_RDWR, 0) };
+    if fd < 0 {
+      return Err(io::Error::last_os_error());
+    }
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+    let result = if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+      Err(io::Error::last_os_error())
+    } else if (stat.st_size as usize) <= HEADER_SIZE {
+      Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Shared memory segment too small",
+      ))
+    } else {
+      Self::map(fd, stat.st_size as usize)
+    };
+    unsafe { libc::close(fd) };
+
+    let segment = result?;
+    let header = segment.header();
+    if header.magic.load(Ordering::Acquire) != SEGMENT_MAGIC
+      || header.capacity.load(Ordering::Relaxed) as usize != segment.capacity
+    {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Not a RustDDS shared memory segment",
+      ));
+    }
+    Ok(segment)
+  }
+
+  fn map(fd: libc::c_int, map_len: usize) -> io::Result<Self> {
+    let base = unsafe {
+      libc::mmap(
+        ptr::null_mut(),
+        map_len,
+        libc::PROT_READ | libc::PROT_WRITE,
+        libc::MAP_SHARED,
+        fd,
+        0,
+      )
+    };
+    if base == libc::MAP_FAILED {
+      return Err(io::Error::last_os_error());
+    }
+    Ok(Self {
+      base: base.cast(),
+      map_len,
+      capacity: map_len - HEADER_SIZE,
+    })
+  }
+
+  fn header(&self) -> &RingHeader {
+    // The mapping is page-aligned and at least HEADER_SIZE long.
+    unsafe { &*self.base.cast::<RingHeader>() }
+  }
+
+  fn copy_in(&self, position: u64, bytes: &[u8]) {
+    let offset = (position % self.capacity as u64) as usize;
+    let first = bytes.len().min(self.capacity - offset);
+    unsafe {
+      let data = self.base.add(HEADER_SIZE);
+      ptr::copy_nonoverlapping(bytes.as_ptr(), data.add(offset), first);
+      ptr::copy_nonoverlapping(bytes.as_ptr().add(first), data, bytes.len() - first);
+    }
+  }
+
+  fn copy_out(&self, position: u64, bytes: &mut [u8]) {
+    let offset = (position % self.capacity as u64) as usize;
+    let first = bytes.len().min(self.capacity - offset);
+    unsafe {
+      let data = self.base.add(HEADER_SIZE);
+      ptr::copy_nonoverlapping(data.add(offset), bytes.as_mut_ptr(), first);
+      ptr::copy_nonoverlapping(data, bytes.as_mut_ptr().add(first), bytes.len() - first);
+    }
+  }
+
+  // Several processes may write to the same segment, so writers take a spin
+  // lock that lives in the segment. The lock word is the pid of the holder.
+  //
+  // If the holder has died, the lock is broken. This is safe, because a writer
+  // publishes its record by advancing head only after the record is complete,
+  // so a crashed writer leaves at most unpublished bytes behind head.
+  fn lock(&self) -> io::Result<()> {
+    let lock = &self.header().write_lock;
+    let pid = current_pid();
+    for i in 0..LOCK_SPIN_LIMIT {
+      if lock
+        .compare_exchange_weak(0, pid, Ordering::Acquire, Ordering::Relaxed)
+        .is_ok()
+      {
+        return Ok(());
+      }
+      if i % 64 == 63 {
+        thread::yield_now();
+      } else {
+        std::hint::spin_loop();
+      }
+    }
+    let holder = lock.load(Ordering::Relaxed);
+    if holder != 0
+      && !process_alive(holder)
+      && lock
+        .compare_exchange(holder, pid, Ordering::Acquire, Ordering::Relaxed)
+        .is_ok()
+    {
+      warn!("Broke shared memory segment lock held by dead process {holder}");
+      return Ok(());
+    }
+    Err(io::Error::new(
+      io::ErrorKind::TimedOut,
+      "Shared memory segment lock timed out",
+    ))
+  }
+
+  // Bytes written but not yet consumed. The header is writable by every
+  // process that can open the segment, so it is not trusted.
+  fn used(&self, head: u64, tail: u64) -> Option<usize> {
+    head
+      .checked_sub(tail)
+      .and_then(|used| usize::try_from(used).ok())
+      .filter(|used| *used <= self.capacity)
+  }
+
+  // The message is the concatenation of the segments.
+  fn push(&self, segments: &[&[u8]]) -> io::Result<()> {
+    let len: usize = segments.iter().map(|s| s.len()).sum();
+    let record_size = RECORD_HEADER_SIZE + len;
+    if record_size > self.capacity {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "Message does not fit in shared memory segment",
+      ));
+    }
+    let header = self.header();
+    self.lock()?;
+    let head = header.head.load(Ordering::Relaxed);
+    let tail = header.tail.load(Ordering::Acquire);
+    let result = match self.used(head, tail) {
+      None => Err(corrupted_segment()),
+      Some(used) if used + record_size > self.capacity => Err(io::Error::new(
+        io::ErrorKind::WouldBlock,
+        "Shared memory segment is full",
+      )),
+      Some(_) => {
+        self.copy_in(head, &(len as u32).to_le_bytes());
+        let mut position = head + RECORD_HEADER_SIZE as u64;
+        for segment in segments {
+          self.copy_in(position, segment);
+          position += segment.len() as u64;
+        }
+        header
+          .head
+          .store(head + record_size as u64, Ordering::Release);
+        Ok(())
+      }
+    };
+    header.write_lock.store(0, Ordering::Release);
+    result
+  }
+
+  // There is only one reader per segment, so this needs no lock.
+  fn pop_all(&self) -> Vec<Bytes> {
+    let header = self.header();
+    let head = header.head.load(Ordering::Acquire);
+    let mut tail = header.tail.load(Ordering::Relaxed);
+    let mut messages = Vec::new();
+    if self.used(head, tail).is_none() {
+      error!("Corrupted shared memory segment. Discarding its contents.");
+      header.tail.store(head, Ordering::Release);
+      return messages;
+    }
+    while tail < head {
+      let mut len_bytes = [0; RECORD_HEADER_SIZE];
+      self.copy_out(tail, &mut len_bytes);
+      let len = u32::from_le_bytes(len_bytes) as usize;
+      if RECORD_HEADER_SIZE + len > (head - tail) as usize {
+        error!("Corrupted shared memory segment. Discarding its contents.");
+        tail = head;
+        break;
+      }
+      let mut message = vec![0; len];
+      self.copy_out(tail + RECORD_HEADER_SIZE as u64, &mut message);
+      messages.push(Bytes::from(message));
+      tail += (RECORD_HEADER_SIZE + len) as u64;
+    }
+    header.tail.store(tail, Ordering::Release);
+    messages
+  }
+}
+
+impl Drop for Segment {
+  fn drop(&mut self) {
+    unsafe { libc::munmap(self.base.cast(), self.map_len) };
+  }
+}
+
+/// Receives messages written to our shared memory segment.
+#[derive(Debug)]
+pub struct ShmListener {
+  segment: Segment,
+  segment_name: CString,
+  notify_socket: mio_08::net::UnixDatagram,
+  socket_path: PathBuf,
+  locator: Locator,
+}
+
+impl ShmListener {
+  pub fn new(segment_size: usize) -> io::Result<Self> {
+    remove_stale_segments();
+    let host_id = host_id();
+    let mut last_error = None;
+    for _ in 0..CREATE_ATTEMPTS {
+      let segment_id: u32 = rand::random();
+      let segment_name = segment_name(segment_id);
+      let segment = match Segment::create(&segment_name, segment_size) {
+        Ok(segment) => segment,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+          last_error = Some(e);
+          continue;
+        }
+        Err(e) => return Err(e),
+      };
+      let socket_path = socket_path(segment_id);
+      let notify_socket = match mio_08::net::UnixDatagram::bind(&socket_path) {
+        Ok(s) => s,
+        Err(e) => {
+          unsafe { libc::shm_unlink(segment_name.as_ptr()) };
+          if e.kind() == io::ErrorKind::AddrInUse {
+            last_error = Some(e);
+            continue;
+          }
+          return Err(e);
+        }
+      };
+      info!("ShmListener: segment {segment_name:?} of {segment_size} bytes");
+      return Ok(Self {
+        segment,
+        segment_name,
+        notify_socket,
+        socket_path,
+        locator: shm_locator(host_id, segment_id),
+      });
+    }
+    Err(last_error.unwrap_or_else(|| io::Error::from(io::ErrorKind::AlreadyExists)))
+  }
+
+  pub fn locator(&self) -> Locator {
+    self.locator
+  }
+
+  pub fn messages(&mut self) -> Vec<Bytes> {
+    // Drain the notifications first, so that a message written after we read
+    // the ring buffer generates a new event.
+    let mut buf = [0; 16];
+    loop {
+      match self.notify_socket.recv(&mut buf) {
+        Ok(_) => (),
+        Err(e) => {
+          if e.kind() != io::ErrorKind::WouldBlock {
+            warn!("ShmListener: notification socket error: {e:?}");
+          }
+          break;
+        }
+      }
+    }
+    self.segment.pop_all()
+  }
+}
+
+impl Drop for ShmListener {
+  fn drop(&mut self) {
+    unsafe { libc::shm_unlink(self.segment_name.as_ptr()) };
+    std::fs::remove_file(&self.socket_path).unwrap_or_else(|e| {
+      warn!("ShmListener: Cannot remove {:?}: {e:?}", self.socket_path);
+    });
+  }
+}
+
+impl event::Source for ShmListener {
+  fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+    self.notify_socket.register(registry, token, interests)
+  }
+
+  fn reregister(
+    &mut self,
+    registry: &Registry,
+    token: Token,
+    interests: Interest,
+  ) -> io::Result<()> {
+    self.notify_socket.reregister(registry, token, interests)
+  }
+
+  fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+    self.notify_socket.deregister(registry)
+  }
+}
+
+/// Writes messages to the shared memory segments of other participants on
+/// this host.
+#[derive(Debug)]
+pub struct ShmSender {
+  host_id: [u8; 16],
+  notify_socket: UnixDatagram,
+  // Opened segments by segment id. None means that the segment could not be
+  // used, and we should not try again.
+  segments: RefCell<HashMap<u32, Option<Segment>>>,
+}
+
+impl ShmSender {
+  pub fn new() -> io::Result<Self> {
+    let notify_socket = UnixDatagram::unbound()?;
+    notify_socket.set_nonblocking(true)?;
+    Ok(Self {
+      host_id: host_id(),
+      notify_socket,
+      segments: RefCell::new(HashMap::new()),
+    })
+  }
+
+  fn segment_id(&self, locator: &Locator) -> Option<u32> {
+    match locator {
+      Locator::Other {
+        kind: LOCATOR_KIND_SHM,
+        port,
+        address,
+      } if *address == self.host_id => Some(*port),
+      _ => None,
+    }
+  }
+
+  /// Is the locator a shared memory segment on this host that we can use?
+  pub fn is_reachable(&self, locator: &Locator) -> bool {
+    self.segment_id(locator).map_or(false, |id| {
+      !matches!(self.segments.borrow().get(&id), Some(None))
+    })
+  }
+
+  /// Unmap the segments of the locators, e.g. when their participant is
+  /// gone. They are opened again if sent to.
+  pub fn forget(&self, locators: &[Locator]) {
+    let mut segments = self.segments.borrow_mut();
+    for segment_id in locators.iter().filter_map(|loc| self.segment_id(loc)) {
+      segments.remove(&segment_id);
+    }
+  }
+
+  /// Returns an error, if the message was not delivered and some other
+  /// transport should be used.
+  pub fn send_to_locator(&self, buffer: &[u8], locator: &Locator) -> io::Result<()> {
+    self.send_segments_to_locator(&[buffer], locator)
+  }
+
+  /// Like [`send_to_locator`](Self::send_to_locator), but the message is
+  /// copied to shared memory directly from its segments.
+  pub fn send_gather_to_locator(
+    &self,
+    message: &GatherBuffer,
+    locator: &Locator,
+  ) -> io::Result<()> {
+    let segments: Vec<&[u8]> = message.segments().iter().map(|s| s.as_ref()).collect();
+    self.send_segments_to_locator(&segments, locator)
+  }
+
+  fn send_segments_to_locator(&self, buffers: &[&[u8]], locator: &Locator) -> io::Result<()> {
+    let segment_id = self
+      .segment_id(locator)
+      .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+    let mut segments = self.segments.borrow_mut();
+    let segment = segments.entry(segment_id).or_insert_with(|| {
+      Segment::open(&segment_name(segment_id))
+        .map_err(|e| debug!("Cannot open shared memory segment {segment_id:08x}: {e:?}"))
+        .ok()
+    });
+    let segment = segment
+      .as_ref()
+      .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+
+    if let Err(e) = segment.push(buffers) {
+      if e.kind() == io::ErrorKind::InvalidData {
+        warn!("Shared memory segment {segment_id:08x}: {e}. Not using it any more.");
+        segments.insert(segment_id, None);
+      }
+      return Err(e);
+    }
+
+    match self.notify_socket.send_to(&[0], socket_path(segment_id)) {
+      // WouldBlock means that the receiver has unprocessed notifications already.
+      Ok(_) => Ok(()),
+      Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+      Err(e) => {
+        // The receiver is gone, or we cannot see its socket.
+        debug!("Shared memory segment {segment_id:08x} receiver unreachable: {e:?}");
+        segments.insert(segment_id, None);
+        Err(e)
+      }
+    }
+  }
+}
+
+/// Shared memory transport: our own segment, if it could be created, and the
+/// sender to the segments of other participants.
+#[derive(Debug)]
+pub struct ShmTransport {
+  listener: Option<ShmListener>,
+  sender: ShmSender,
+}
+
+impl ShmTransport {
+  pub fn new(segment_size: usize) -> io::Result<Self> {
+    let sender = ShmSender::new()?;
+    let listener = ShmListener::new(segment_size)
+      .map_err(|e| warn!("Shared memory segment not available: {e:?}"))
+      .ok();
+    Ok(Self { listener, sender })
+  }
+}
+
+impl Transport for ShmTransport {
+  fn can_send_to(&self, locator: &Locator) -> bool {
+    self.sender.is_reachable(locator)
+  }
+
+  fn send_to_locator(&mut self, buffer: &[u8], locator: &Locator) -> io::Result<()> {
+    self.sender.send_to_locator(buffer, locator)
+  }
+
+  fn send_gather_to_locator(
+    &mut self,
+    message: &GatherBuffer,
+    locator: &Locator,
+  ) -> io::Result<()> {
+    self.sender.send_gather_to_locator(message, locator)
+  }
+
+  fn announced_locators(&self) -> TransportLocators {
+    let locators: Vec<Locator> = self.listener.iter().map(ShmListener::locator).collect();
+    TransportLocators {
+      metatraffic_unicast: locators.clone(),
+      default_unicast: locators,
+      ..TransportLocators::default()
+    }
+  }
+
+  fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+    match &mut self.listener {
+      Some(listener) => registry.register(listener, token, Interest::READABLE),
+      None => Ok(()),
+    }
+  }
+
+  fn receive(&mut self) -> Vec<Bytes> {
+    self
+      .listener
+      .as_mut()
+      .map_or_else(Vec::new, ShmListener::messages)
+  }
+
+  // Readers on the same host are best reached through shared memory.
+  fn preferred_over_multicast(&self) -> bool {
+    true
+  }
+
+  fn participant_lost(&mut self, locators: &[Locator]) {
+    self.sender.forget(locators);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn shm_send_and_receive() {
+    let mut listener = ShmListener::new(256).unwrap();
+    let sender = ShmSender::new().unwrap();
+    let locator = listener.locator();
+    assert!(sender.is_reachable(&locator));
+
+    // Messages wrap around the end of the ring buffer.
+    for round in 0..10_u8 {
+      let messages: Vec<Vec<u8>> = (0..3_u8)
+        .map(|i| vec![round ^ i; 50 + i as usize])
+        .collect();
+      for m in &messages[..2] {
+        sender.send_to_locator(m, &locator).unwrap();
+      }
+      // The last one in segments
+      let mut gather = GatherBuffer::from(messages[2][..10].to_vec());
+      gather.push(Bytes::copy_from_slice(&messages[2][10..]));
+      sender.send_gather_to_locator(&gather, &locator).unwrap();
+      let received = listener.messages();
+      assert_eq!(received, messages);
+    }
+  }
+
+  #[test]
+  fn shm_forget_segment() {
+    let mut listener = ShmListener::new(256).unwrap();
+    let sender = ShmSender::new().unwrap();
+    let locator = listener.locator();
+    sender.send_to_locator(&[1, 2, 3], &locator).unwrap();
+    assert_eq!(sender.segments.borrow().len(), 1);
+
+    sender.forget(&[locator]);
+    assert!(sender.segments.borrow().is_empty());
+    // The segment is opened again when needed.
+    sender.send_to_locator(&[4, 5, 6], &locator).unwrap();
+    assert_eq!(listener.messages(), vec![vec![1, 2, 3], vec![4, 5, 6]]);
+  }
+
+  #[test]
+  fn shm_full_and_unreachable() {
+    let listener = ShmListener::new(64).unwrap();
+    let sender = ShmSender::new().unwrap();
+    let locator = listener.locator();
+
+    sender.send_to_locator(&[1; 40], &locator).unwrap();
+    // Not enough room left
+    assert!(sender.send_to_locator(&[2; 40], &locator).is_err());
+    // Does not fit at all
+    assert!(sender.send_to_locator(&[3; 100], &locator).is_err());
+
+    // Not on this host
+    let remote = shm_locator([0xAB; 16], 1);
+    assert!(!sender.is_reachable(&remote));
+    assert!(sender.send_to_locator(&[4], &remote).is_err());
+
+    drop(listener);
+    assert!(sender.send_to_locator(&[5], &locator).is_err());
+    assert!(!sender.is_reachable(&locator));
+  }
+
+  // The pid of a process that has exited
+  fn dead_pid() -> u32 {
+    let mut child = std::process::Command::new("true").spawn().unwrap();
+    child.wait().unwrap();
+    child.id()
+  }
+
+  #[test]
+  fn shm_lock_of_dead_writer() {
+    let mut listener = ShmListener::new(256).unwrap();
+    let sender = ShmSender::new().unwrap();
+    let locator = listener.locator();
+
+    let lock = &listener.segment.header().write_lock;
+    lock.store(current_pid(), Ordering::Relaxed);
+    assert!(sender.send_to_locator(&[1; 10], &locator).is_err());
+
+    lock.store(dead_pid(), Ordering::Relaxed);
+    sender.send_to_locator(&[2; 10], &locator).unwrap();
+    assert_eq!(listener.messages(), vec![vec![2; 10]]);
+  }
+
+  #[test]
+  fn shm_corrupted_header() {
+    let mut listener = ShmListener::new(256).unwrap();
+    let sender = ShmSender::new().unwrap();
+    let locator = listener.locator();
+
+    sender.send_to_locator(&[1; 10], &locator).unwrap();
+    listener
+      .segment
+      .header()
+      .tail
+      .store(1000, Ordering::Relaxed);
+    assert!(listener.messages().is_empty());
+
+    listener.segment.header().head.store(10, Ordering::Relaxed);
+    assert!(sender.send_to_locator(&[2; 10], &locator).is_err());
+    assert!(!sender.is_reachable(&locator));
+  }
+
+  #[test]
+  fn shm_remove_stale_segments() {
+    let segment_id: u32 = rand::random();
+    let name = segment_name(segment_id);
+    let path = socket_path(segment_id);
+    let segment = Segment::create(&name, 64).unwrap();
+    segment
+      .header()
+      .owner_pid
+      .store(dead_pid(), Ordering::Relaxed);
+    drop(std::os::unix::net::UnixDatagram::bind(&path).unwrap());
+
+    // Segments of live participants stay
+    let live = ShmListener::new(64).unwrap();
+    let live_name = live.segment_name.clone();
+
+    remove_stale_segments();
+    assert_eq!(
+      Segment::open(&name).unwrap_err().kind(),
+      io::ErrorKind::NotFound
+    );
+    assert!(!path.exists());
+    assert!(Segment::open(&live_name).is_ok());
+    assert!(live.socket_path.exists());
+  }
+}
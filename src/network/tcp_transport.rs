@@ -0,0 +1,778 @@
+//! RTPS over TCP.
+//!
+//! Framing follows the RTPS-TCP/IP variant used by Fast DDS: every RTPS
+//! message is preceded by a 14-byte header, which has the magic `"RTCP"`, the
+//! frame length including the header, a CRC and a logical port. Logical ports
+//! tell apart the kinds of traffic that share one connection. We send the CRC
+//! as zero and do not check it.
+//!
+//! The connection control protocol is simplified and does not interoperate
+//! with other implementations, so TCP locators have vendor-specific kinds that
+//! other implementations ignore. After connecting, the connecting side sends a
+//! bind frame on logical port 0. The frame lists the addresses that the
+//! connecting side listens on, so that the accepting side can send back
+//! through the same connection instead of opening a new one. This makes TCP
+//! usable when a firewall lets only one side connect to the other. Only
+//! addresses with the IP address of the connecting side are accepted, and
+//! they do not replace connections that we opened ourselves, so that a peer
+//! cannot take over the traffic to other participants.
+//!
+//! Connections are handled in a background thread. Outgoing messages are
+//! passed to it through a channel, and received messages come back through
+//! another channel, which the participant event loop polls.
+
+use std::{
+  collections::{HashMap, VecDeque},
+  fmt,
+  io::{self, Read, Write},
+  net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+  thread,
+  time::{Duration, Instant},
+};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use mio_08::{
+  net::{TcpListener, TcpStream},
+  Events, Interest, Poll, Registry, Token,
+};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::{
+  mio_source::{channel as mio_channel, channel::TrySendError},
+  network::transport::{GatherBuffer, Transport, TransportLocators},
+  structure::locator::{kind, Locator},
+};
+
+pub const TCP_HEADER_SIZE: usize = 14;
+const TCP_MAGIC: &[u8; 4] = b"RTCP";
+
+// Logical port 0 is reserved for connection control.
+const CONTROL_LOGICAL_PORT: u16 = 0;
+const BIND_MAGIC: &[u8; 4] = b"BIND";
+
+// Anything longer is taken as a framing error.
+const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+// Messages to a connection are dropped, if it has this much unsent data.
+const MAX_QUEUED_BYTES: usize = 8 * 1024 * 1024;
+
+// Do not try to reconnect to a failed destination sooner than this.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+const COMMAND_QUEUE_LENGTH: usize = 1024;
+const RECEIVED_QUEUE_LENGTH: usize = 1024;
+
+const LISTENER_TOKEN: Token = Token(0);
+const COMMAND_TOKEN: Token = Token(1);
+const FIRST_CONNECTION_TOKEN: usize = 2;
+
+/// TCP locator. The port field has the physical (TCP) port in the low 16 bits
+/// and the logical port in the high 16 bits. IPv4 addresses are in the last 4
+/// bytes of the address field.
+pub fn tcp_locator(address: SocketAddr, logical_port: u16) -> Locator {
+  let port = u32::from(logical_port) << 16 | u32::from(address.port());
+  match address.ip() {
+    IpAddr::V4(ip) => {
+      let mut address = [0; 16];
+      address[12..].copy_from_slice(&ip.octets());
+      Locator::Other {
+        kind: kind::TCP_V4,
+        port,
+        address,
+      }
+    }
+    IpAddr::V6(ip) => Locator::Other {
+      kind: kind::TCP_V6,
+      port,
+      address: ip.octets(),
+    },
+  }
+}
+
+/// Socket address and logical port of a TCP locator
+pub fn tcp_locator_address(locator: &Locator) -> Option<(SocketAddr, u16)> {
+  let (ip, port) = match locator {
+    Locator::Other {
+      kind: kind::TCP_V4,
+      port,
+      address,
+    } => {
+      let octets: [u8; 4] = address[12..].try_into().unwrap(); // exactly 4 bytes
+      (IpAddr::from(Ipv4Addr::from(octets)), *port)
+    }
+    Locator::Other {
+      kind: kind::TCP_V6,
+      port,
+      address,
+    } => (IpAddr::from(Ipv6Addr::from(*address)), *port),
+    _ => return None,
+  };
+  Some((SocketAddr::new(ip, port as u16), (port >> 16) as u16))
+}
+
+fn frame(logical_port: u16, payload: &[u8]) -> Bytes {
+  let mut frame = BytesMut::with_capacity(TCP_HEADER_SIZE + payload.len());
+  frame.put_slice(TCP_MAGIC);
+  frame.put_u32_le((TCP_HEADER_SIZE + payload.len()) as u32);
+  frame.put_u32_le(0); // CRC
+  frame.put_u16_le(logical_port);
+  frame.put_slice(payload);
+  frame.freeze()
+}
+
+fn bind_frame(addresses: &[SocketAddr]) -> Bytes {
+  let mut payload = BytesMut::new();
+  payload.put_slice(BIND_MAGIC);
+  payload.put_u16_le(addresses.len() as u16);
+  for address in addresses {
+    match address.ip() {
+      IpAddr::V4(ip) => {
+        payload.put_u8(4);
+        payload.put_slice(&ip.octets());
+      }
+      IpAddr::V6(ip) => {
+        payload.put_u8(6);
+        payload.put_slice(&ip.octets());
+      }
+    }
+    payload.put_u16_le(address.port());
+  }
+  frame(CONTROL_LOGICAL_PORT, &payload)
+}
+
+// IPv4 peers of a dual-stack listener show up as IPv4-mapped IPv6 addresses.
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+  match ip {
+    IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+    IpAddr::V4(_) => ip,
+  }
+}
+
+fn parse_bind(mut payload: &[u8]) -> Option<Vec<SocketAddr>> {
+  if payload.len() < 6 || &payload[..4] != BIND_MAGIC {
+    return None;
+  }
+  payload.advance(4);
+  let count = payload.get_u16_le();
+  let mut addresses = Vec::with_capacity(count.into());
+  for _ in 0..count {
+    let ip = match payload.first()? {
+      4 if payload.len() >= 7 => {
+        let octets: [u8; 4] = payload[1..5].try_into().unwrap();
+        payload.advance(5);
+        IpAddr::from(octets)
+      }
+      6 if payload.len() >= 19 => {
+        let octets: [u8; 16] = payload[1..17].try_into().unwrap();
+        payload.advance(17);
+        IpAddr::from(octets)
+      }
+      _ => return None,
+    };
+    addresses.push(SocketAddr::new(ip, payload.get_u16_le()));
+  }
+  Some(addresses)
+}
+
+enum TcpCommand {
+  Send {
+    destination: SocketAddr,
+    logical_port: u16,
+    message: Bytes,
+  },
+}
+
+/// Handle for sending RTPS messages over TCP. The background thread stops
+/// when this is dropped.
+pub struct TCPSender {
+  command_sender: mio_channel::SyncSender<TcpCommand>,
+  listen_port: u16,
+}
+
+impl fmt::Debug for TCPSender {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("TCPSender")
+      .field("listen_port", &self.listen_port)
+      .finish()
+  }
+}
+
+impl TCPSender {
+  /// Starts the TCP transport thread. Connections are accepted on
+  /// `listen_address`, and messages received on them for one of
+  /// `logical_ports` are passed to the returned receiver. The listening
+  /// addresses announced to remote participants are `announced_ips` with our
+  /// listening port.
+  pub fn start(
+    listen_address: SocketAddr,
+    announced_ips: &[IpAddr],
+    logical_ports: Vec<u16>,
+  ) -> io::Result<(Self, mio_channel::Receiver<Bytes>)> {
+    let mut listener = TcpListener::bind(listen_address)?;
+    let listen_port = listener.local_addr()?.port();
+    let announced: Vec<SocketAddr> = announced_ips
+      .iter()
+      .map(|ip| SocketAddr::new(*ip, listen_port))
+      .collect();
+    info!("TCP transport listening on {listen_address:?} port {listen_port}");
+
+    let (command_sender, mut command_receiver) = mio_channel::sync_channel(COMMAND_QUEUE_LENGTH);
+    let (received_sender, received_receiver) = mio_channel::sync_channel(RECEIVED_QUEUE_LENGTH);
+
+    let poll = Poll::new()?;
+    poll
+      .registry()
+      .register(&mut listener, LISTENER_TOKEN, Interest::READABLE)?;
+    poll
+      .registry()
+      .register(&mut command_receiver, COMMAND_TOKEN, Interest::READABLE)?;
+
+    let event_loop = TcpEventLoop {
+      poll,
+      listener,
+      command_receiver,
+      received_sender,
+      logical_ports,
+      bind_frame: bind_frame(&announced),
+      connections: HashMap::new(),
+      next_token: FIRST_CONNECTION_TOKEN,
+      routes: HashMap::new(),
+      reconnect_after: HashMap::new(),
+    };
+    thread::Builder::new()
+      .name(format!("RustDDS TCP port {listen_port}"))
+      .spawn(move || event_loop.run())?;
+
+    Ok((
+      Self {
+        command_sender,
+        listen_port,
+      },
+      received_receiver,
+    ))
+  }
+
+  pub fn listen_port(&self) -> u16 {
+    self.listen_port
+  }
+
+  /// Queues the message for sending. Returns an error, if the locator is not a
+  /// TCP locator, or the queue is full.
+  pub fn send_to_locator(&self, buffer: &[u8], locator: &Locator) -> io::Result<()> {
+    self.send_bytes_to_locator(Bytes::copy_from_slice(buffer), locator)
+  }
+
+  fn send_bytes_to_locator(&self, message: Bytes, locator: &Locator) -> io::Result<()> {
+    let (destination, logical_port) =
+      tcp_locator_address(locator).ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+    self
+      .command_sender
+      .try_send(TcpCommand::Send {
+        destination,
+        logical_port,
+        message,
+      })
+      .map_err(|e| match e {
+        TrySendError::Full(_) => io::Error::from(io::ErrorKind::WouldBlock),
+        TrySendError::Io(e) => e,
+        TrySendError::Disconnected(_) => io::Error::from(io::ErrorKind::NotConnected),
+      })
+  }
+}
+
+/// TCP transport: the sender handle of the background thread, and the
+/// channel of messages received from it.
+pub struct TCPTransport {
+  sender: TCPSender,
+  receiver: mio_channel::Receiver<Bytes>,
+  locators: TransportLocators,
+}
+
+impl TCPTransport {
+  pub fn new(
+    sender: TCPSender,
+    receiver: mio_channel::Receiver<Bytes>,
+    locators: TransportLocators,
+  ) -> Self {
+    Self {
+      sender,
+      receiver,
+      locators,
+    }
+  }
+}
+
+impl Transport for TCPTransport {
+  fn can_send_to(&self, locator: &Locator) -> bool {
+    locator.is_tcp()
+  }
+
+  fn send_to_locator(&mut self, buffer: &[u8], locator: &Locator) -> io::Result<()> {
+    self.sender.send_to_locator(buffer, locator)
+  }
+
+  // The message is queued to the sender thread, so it is concatenated only once.
+  fn send_gather_to_locator(
+    &mut self,
+    message: &GatherBuffer,
+    locator: &Locator,
+  ) -> io::Result<()> {
+    self
+      .sender
+      .send_bytes_to_locator(message.to_bytes(), locator)
+  }
+
+  fn announced_locators(&self) -> TransportLocators {
+    self.locators.clone()
+  }
+
+  fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+    registry.register(&mut self.receiver, token, Interest::READABLE)
+  }
+
+  fn receive(&mut self) -> Vec<Bytes> {
+    let mut messages = Vec::new();
+    while let Ok(message) = self.receiver.try_recv() {
+      messages.push(message);
+    }
+    messages
+  }
+}
+
+struct Connection {
+  stream: TcpStream,
+  // Destination, if we connected to it
+  outgoing: Option<SocketAddr>,
+  read_buffer: BytesMut,
+  write_queue: VecDeque<Bytes>,
+  write_offset: usize, // in the first queued frame
+  queued_bytes: usize,
+}
+
+struct TcpEventLoop {
+  poll: Poll,
+  listener: TcpListener,
+  command_receiver: mio_channel::Receiver<TcpCommand>,
+  received_sender: mio_channel::SyncSender<Bytes>,
+  logical_ports: Vec<u16>,
+  bind_frame: Bytes,
+  connections: HashMap<Token, Connection>,
+  next_token: usize,
+  // Which connection to use for each remote listening address
+  routes: HashMap<SocketAddr, Token>,
+  reconnect_after: HashMap<SocketAddr, Instant>,
+}
+
+impl TcpEventLoop {
+  fn run(mut self) {
+    let mut events = Events::with_capacity(64);
+    loop {
+      if let Err(e) = self.poll.poll(&mut events, Some(Duration::from_secs(1))) {
+        error!("TCP transport poll failed: {e:?}");
+        return;
+      }
+      for event in &events {
+        match event.token() {
+          LISTENER_TOKEN => self.accept(),
+          COMMAND_TOKEN => (), // handled below
+          token => {
+            if event.is_readable() {
+              self.read(token);
+            }
+            if event.is_writable() {
+              self.flush(token);
+            }
+          }
+        }
+      }
+      // This also notices when the sender is dropped.
+      if !self.handle_commands() {
+        info!("TCP transport stopping");
+        return;
+      }
+    }
+  }
+
+  // Returns false, if the sender is gone.
+  fn handle_commands(&mut self) -> bool {
+    loop {
+      match self.command_receiver.try_recv() {
+        Ok(TcpCommand::Send {
+          destination,
+          logical_port,
+          message,
+        }) => self.send(destination, logical_port, &message),
+        Err(std::sync::mpsc::TryRecvError::Empty) => return true,
+        Err(std::sync::mpsc::TryRecvError::Disconnected) => return false,
+      }
+    }
+  }
+
+  fn accept(&mut self) {
+    loop {
+      match self.listener.accept() {
+        Ok((stream, peer)) => {
+          debug!("TCP connection from {peer:?}");
+          self.add_connection(stream, None);
+        }
+        Err(e) => {
+          if e.kind() != io::ErrorKind::WouldBlock {
+            warn!("TCP accept failed: {e:?}");
+          }
+          return;
+        }
+      }
+    }
+  }
+
+  fn add_connection(
+    &mut self,
+    mut stream: TcpStream,
+    outgoing: Option<SocketAddr>,
+  ) -> Option<Token> {
+    stream.set_nodelay(true).unwrap_or_else(|e| {
+      warn!("Cannot set TCP_NODELAY: {e:?}");
+    });
+    let token = Token(self.next_token);
+    self.next_token += 1;
+    if let Err(e) =
+      self
+        .poll
+        .registry()
+        .register(&mut stream, token, Interest::READABLE | Interest::WRITABLE)
+    {
+      error!("Cannot register TCP connection: {e:?}");
+      return None;
+    }
+    self.connections.insert(
+      token,
+      Connection {
+        stream,
+        outgoing,
+        read_buffer: BytesMut::new(),
+        write_queue: VecDeque::new(),
+        write_offset: 0,
+        queued_bytes: 0,
+      },
+    );
+    Some(token)
+  }
+
+  fn connect(&mut self, destination: SocketAddr) -> Option<Token> {
+    if let Some(after) = self.reconnect_after.get(&destination) {
+      if Instant::now() < *after {
+        trace!("Not reconnecting to {destination:?} yet");
+        return None;
+      }
+    }
+    let token = match TcpStream::connect(destination) {
+      Ok(stream) => self.add_connection(stream, Some(destination)),
+      Err(e) => {
+        debug!("TCP connect to {destination:?} failed: {e:?}");
+        None
+      }
+    };
+    match token {
+      Some(token) => {
+        debug!("TCP connecting to {destination:?}");
+        self.routes.insert(destination, token);
+        let bind_frame = self.bind_frame.clone();
+        self.enqueue(token, bind_frame);
+      }
+      None => {
+        self
+          .reconnect_after
+          .insert(destination, Instant::now() + RECONNECT_DELAY);
+      }
+    }
+    token
+  }
+
+  fn send(&mut self, destination: SocketAddr, logical_port: u16, message: &[u8]) {
+    let token = match self.routes.get(&destination) {
+      Some(token) => *token,
+      None => match self.connect(destination) {
+        Some(token) => token,
+        None => return,
+      },
+    };
+    self.enqueue(token, frame(logical_port, message));
+  }
+
+  fn enqueue(&mut self, token: Token, frame: Bytes) {
+    if let Some(connection) = self.connections.get_mut(&token) {
+      if connection.queued_bytes + frame.len() > MAX_QUEUED_BYTES {
+        debug!("TCP send queue full. Dropping message.");
+        return;
+      }
+      connection.queued_bytes += frame.len();
+      connection.write_queue.push_back(frame);
+      self.flush(token);
+    }
+  }
+
+  fn flush(&mut self, token: Token) {
+    let connection = match self.connections.get_mut(&token) {
+      Some(c) => c,
+      None => return,
+    };
+    while let Some(frame) = connection.write_queue.front() {
+      match connection.stream.write(&frame[connection.write_offset..]) {
+        Ok(n) => {
+          connection.write_offset += n;
+          if connection.write_offset == frame.len() {
+            connection.queued_bytes -= frame.len();
+            connection.write_queue.pop_front();
+            connection.write_offset = 0;
+          }
+        }
+        // Still connecting, or the socket buffer is full. We get a writable event later.
+        Err(e)
+          if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::NotConnected =>
+        {
+          return
+        }
+        Err(e) => {
+          debug!("TCP write failed: {e:?}");
+          self.close(token);
+          return;
+        }
+      }
+    }
+  }
+
+  fn read(&mut self, token: Token) {
+    let mut buf = [0; 64 * 1024];
+    loop {
+      let connection = match self.connections.get_mut(&token) {
+        Some(c) => c,
+        None => return,
+      };
+      match connection.stream.read(&mut buf) {
+        Ok(0) => {
+          debug!("TCP connection closed by peer");
+          self.close(token);
+          return;
+        }
+        Ok(n) => connection.read_buffer.extend_from_slice(&buf[..n]),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+        Err(e) => {
+          debug!("TCP read failed: {e:?}");
+          self.close(token);
+          return;
+        }
+      }
+    }
+    self.handle_frames(token);
+  }
+
+  fn handle_frames(&mut self, token: Token) {
+    loop {
+      let connection = match self.connections.get_mut(&token) {
+        Some(c) => c,
+        None => return,
+      };
+      let buffer = &connection.read_buffer;
+      if buffer.len() < TCP_HEADER_SIZE {
+        return;
+      }
+      let length = u32::from_le_bytes(buffer[4..8].try_into().unwrap()) as usize;
+      if &buffer[..4] != TCP_MAGIC || !(TCP_HEADER_SIZE..=MAX_FRAME_SIZE).contains(&length) {
+        warn!("TCP framing error. Closing connection.");
+        self.close(token);
+        return;
+      }
+      if buffer.len() < length {
+        return;
+      }
+      let logical_port = u16::from_le_bytes(buffer[12..14].try_into().unwrap());
+      let frame = connection.read_buffer.split_to(length);
+      let payload = &frame[TCP_HEADER_SIZE..];
+
+      if logical_port == CONTROL_LOGICAL_PORT {
+        match parse_bind(payload) {
+          Some(addresses) => {
+            let peer = connection.stream.peer_addr().ok();
+            self.bind(token, peer, addresses);
+          }
+          None => debug!("Unknown TCP control message"),
+        }
+      } else if self.logical_ports.contains(&logical_port) {
+        // Copy, so that the message is aligned like the UDP ones.
+        match self
+          .received_sender
+          .try_send(Bytes::copy_from_slice(payload))
+        {
+          Ok(()) => (),
+          Err(TrySendError::Full(_)) => debug!("TCP receive queue full. Dropping message."),
+          Err(e) => debug!("TCP receive queue: {e:?}"),
+        }
+      } else {
+        trace!("TCP message to unknown logical port {logical_port}");
+      }
+    }
+  }
+
+  // Routes the listening addresses of the peer through its connection.
+  fn bind(&mut self, token: Token, peer: Option<SocketAddr>, addresses: Vec<SocketAddr>) {
+    debug!("TCP bind from {peer:?}: {addresses:?}");
+    let peer_ip = match peer {
+      Some(peer) => canonical_ip(peer.ip()),
+      None => return,
+    };
+    for address in addresses {
+      if canonical_ip(address.ip()) != peer_ip {
+        debug!("TCP bind of {address:?} from {peer_ip:?} ignored");
+        continue;
+      }
+      // An existing route is either our own connection, or the connection that
+      // bound the address first.
+      self.routes.entry(address).or_insert(token);
+    }
+  }
+
+  fn close(&mut self, token: Token) {
+    if let Some(mut connection) = self.connections.remove(&token) {
+      self
+        .poll
+        .registry()
+        .deregister(&mut connection.stream)
+        .unwrap_or_else(|e| debug!("TCP deregister: {e:?}"));
+      self.routes.retain(|_, t| *t != token);
+      if let Some(destination) = connection.outgoing {
+        self
+          .reconnect_after
+          .insert(destination, Instant::now() + RECONNECT_DELAY);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn receive(receiver: &mio_channel::Receiver<Bytes>, count: usize) -> Vec<Bytes> {
+    let mut messages = Vec::new();
+    for _ in 0..200 {
+      while let Ok(m) = receiver.try_recv() {
+        messages.push(m);
+      }
+      if messages.len() >= count {
+        break;
+      }
+      thread::sleep(Duration::from_millis(10));
+    }
+    messages
+  }
+
+  #[test]
+  fn tcp_locator_conversion() {
+    let address: SocketAddr = "10.1.2.3:7650".parse().unwrap();
+    let locator = tcp_locator(address, 7410);
+    assert!(locator.is_tcp());
+    assert_eq!(tcp_locator_address(&locator), Some((address, 7410)));
+
+    let address: SocketAddr = "[fd00::1]:7650".parse().unwrap();
+    assert_eq!(
+      tcp_locator_address(&tcp_locator(address, 7411)),
+      Some((address, 7411))
+    );
+
+    let udp = Locator::from(address);
+    assert_eq!(tcp_locator_address(&udp), None);
+  }
+
+  #[test]
+  fn tcp_bind_frame() {
+    let addresses: Vec<SocketAddr> = vec![
+      "127.0.0.1:1234".parse().unwrap(),
+      "[::1]:4321".parse().unwrap(),
+    ];
+    let frame = bind_frame(&addresses);
+    assert_eq!(&frame[..4], TCP_MAGIC);
+    assert_eq!(frame.len(), 14 + 4 + 2 + 7 + 19);
+    assert_eq!(parse_bind(&frame[TCP_HEADER_SIZE..]), Some(addresses));
+    assert_eq!(parse_bind(&frame[TCP_HEADER_SIZE..30]), None);
+  }
+
+  #[test]
+  fn tcp_send_and_reply() {
+    let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let (server, server_received) =
+      TCPSender::start(SocketAddr::new(localhost, 0), &[localhost], vec![10, 11]).unwrap();
+    let (client, client_received) =
+      TCPSender::start(SocketAddr::new(localhost, 0), &[localhost], vec![12]).unwrap();
+    let server_address = SocketAddr::new(localhost, server.listen_port());
+    let client_address = SocketAddr::new(localhost, client.listen_port());
+
+    let big = vec![7; 200_000];
+    client
+      .send_to_locator(&[1, 2, 3], &tcp_locator(server_address, 10))
+      .unwrap();
+    client
+      .send_to_locator(&big, &tcp_locator(server_address, 11))
+      .unwrap();
+    // Unknown logical port
+    client
+      .send_to_locator(&[4], &tcp_locator(server_address, 13))
+      .unwrap();
+    let received = receive(&server_received, 2);
+    assert_eq!(received, vec![Bytes::from(vec![1, 2, 3]), Bytes::from(big)]);
+
+    server
+      .send_to_locator(&[5, 6], &tcp_locator(client_address, 12))
+      .unwrap();
+    assert_eq!(receive(&client_received, 1), vec![Bytes::from(vec![5, 6])]);
+  }
+
+  // Reads frames from a blocking stream until one has the logical port.
+  fn read_frame(stream: &mut std::net::TcpStream, logical_port: u16) -> Option<Vec<u8>> {
+    let mut header = [0; TCP_HEADER_SIZE];
+    loop {
+      stream.read_exact(&mut header).ok()?;
+      let length = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+      let mut payload = vec![0; length - TCP_HEADER_SIZE];
+      stream.read_exact(&mut payload).ok()?;
+      if u16::from_le_bytes(header[12..14].try_into().unwrap()) == logical_port {
+        return Some(payload);
+      }
+    }
+  }
+
+  #[test]
+  fn tcp_bind_routes() {
+    let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let (server, _server_received) =
+      TCPSender::start(SocketAddr::new(localhost, 0), &[localhost], vec![10]).unwrap();
+    let server_address = SocketAddr::new(localhost, server.listen_port());
+
+    // Addresses where nobody listens, so that the server can reach them only
+    // through our connection
+    let unreachable_port = std::net::TcpListener::bind((localhost, 0))
+      .unwrap()
+      .local_addr()
+      .unwrap()
+      .port();
+    let own_address = SocketAddr::new(localhost, unreachable_port);
+    let other_host = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 7400);
+
+    let mut peer = std::net::TcpStream::connect(server_address).unwrap();
+    peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    peer
+      .write_all(&bind_frame(&[own_address, other_host]))
+      .unwrap();
+    // Let the server handle the bind frame
+    thread::sleep(Duration::from_millis(100));
+
+    // Another host is not routed through the peer.
+    server
+      .send_to_locator(&[1], &tcp_locator(other_host, 12))
+      .unwrap();
+    server
+      .send_to_locator(&[2], &tcp_locator(own_address, 12))
+      .unwrap();
+    assert_eq!(read_frame(&mut peer, 12), Some(vec![2]));
+  }
+}
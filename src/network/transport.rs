@@ -0,0 +1,395 @@
+//! Pluggable transports for RTPS messages.
+//!
+//! A [`Transport`] sends RTPS messages to the locators it understands and
+//! delivers received messages to the participant event loop. UDP, shared
+//! memory and TCP are built-in transports. Applications can add their own, e.g.
+//! an in-memory transport for tests or a serial or radio link, with
+//! [`DomainParticipantBuilder::transport`](crate::DomainParticipantBuilder::transport).
+
+use std::{
+  fmt, io,
+  sync::{Arc, Mutex},
+};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use mio_08::{Registry, Token};
+use bytes::Bytes;
+
+use crate::{network::constant::TRANSPORT_TOKEN_BASE, structure::locator::Locator};
+
+/// Locators that a transport announces to remote participants in discovery.
+///
+/// Metatraffic is the built-in discovery traffic, and default traffic is the
+/// traffic of user-defined topics.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransportLocators {
+  pub metatraffic_unicast: Vec<Locator>,
+  pub metatraffic_multicast: Vec<Locator>,
+  pub default_unicast: Vec<Locator>,
+  pub default_multicast: Vec<Locator>,
+}
+
+impl TransportLocators {
+  pub fn extend(&mut self, other: TransportLocators) {
+    self.metatraffic_unicast.extend(other.metatraffic_unicast);
+    self
+      .metatraffic_multicast
+      .extend(other.metatraffic_multicast);
+    self.default_unicast.extend(other.default_unicast);
+    self.default_multicast.extend(other.default_multicast);
+  }
+}
+
+/// A serialized RTPS message in segments, which are sent with gather I/O.
+///
+/// The message is the concatenation of the segments. The payloads of DATA and
+/// DATA_FRAG submessages are separate segments that share memory with the
+/// topic cache, so that they are not copied when sending.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GatherBuffer {
+  segments: Vec<Bytes>,
+}
+
+impl GatherBuffer {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Appends a segment. Empty segments are skipped.
+  pub fn push(&mut self, segment: Bytes) {
+    if !segment.is_empty() {
+      self.segments.push(segment);
+    }
+  }
+
+  pub fn segments(&self) -> &[Bytes] {
+    &self.segments
+  }
+
+  /// Length of the message in bytes
+  pub fn len(&self) -> usize {
+    self.segments.iter().map(Bytes::len).sum()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.segments.is_empty()
+  }
+
+  /// The message in one contiguous buffer. This copies, unless there is only
+  /// one segment.
+  pub fn to_bytes(&self) -> Bytes {
+    match self.segments.as_slice() {
+      [] => Bytes::new(),
+      [segment] => segment.clone(),
+      segments => Bytes::from(segments.concat()),
+    }
+  }
+}
+
+impl From<Bytes> for GatherBuffer {
+  fn from(buffer: Bytes) -> Self {
+    let mut gather_buffer = Self::new();
+    gather_buffer.push(buffer);
+    gather_buffer
+  }
+}
+
+impl From<Vec<u8>> for GatherBuffer {
+  fn from(buffer: Vec<u8>) -> Self {
+    Self::from(Bytes::from(buffer))
+  }
+}
+
+/// Information about the participant that a transport is created for.
+#[derive(Clone, Debug)]
+pub struct TransportContext {
+  pub domain_id: u16,
+  pub participant_id: u16,
+}
+
+/// A way to send and receive RTPS messages.
+///
+/// A transport is owned by the event loop thread of a participant, and all
+/// methods are called from that thread.
+///
+/// When a message is sent to a remote participant, the transports are tried in
+/// order of preference: user-supplied transports first, in the order they were
+/// added, then shared memory, TCP and UDP. The message is sent with the first
+/// transport that can send to some locator of the remote participant and
+/// succeeds in sending to at least one of them.
+pub trait Transport: Send {
+  /// Can this transport send to the locator? Typically this checks the
+  /// locator kind, and maybe the address.
+  fn can_send_to(&self, locator: &Locator) -> bool;
+
+  /// Send one RTPS message to the locator. An error means that the message
+  /// was not sent, so that the next transport can be tried.
+  fn send_to_locator(&mut self, buffer: &[u8], locator: &Locator) -> io::Result<()>;
+
+  /// Send one RTPS message in segments to the locator. An error means that
+  /// the message was not sent.
+  ///
+  /// The default concatenates the segments and calls
+  /// [`send_to_locator`](Self::send_to_locator). Transports can override this
+  /// to send without copying the payload, e.g. with vectored I/O.
+  fn send_gather_to_locator(
+    &mut self,
+    message: &GatherBuffer,
+    locator: &Locator,
+  ) -> io::Result<()> {
+    match message.segments() {
+      [segment] => self.send_to_locator(segment, locator),
+      _ => self.send_to_locator(&message.to_bytes(), locator),
+    }
+  }
+
+  /// Send one RTPS message to several locators, all of which this transport
+  /// can send to. An error means that the message was not sent to any of
+  /// them.
+  ///
+  /// The default calls [`send_gather_to_locator`](Self::send_gather_to_locator)
+  /// for each locator. Transports can override this to send with fewer system
+  /// calls.
+  fn send_to_locators(&mut self, message: &GatherBuffer, locators: &[Locator]) -> io::Result<()> {
+    let mut result = Err(io::Error::new(io::ErrorKind::Other, "No locators"));
+    for locator in locators {
+      match self.send_gather_to_locator(message, locator) {
+        Ok(()) => result = Ok(()),
+        Err(e) => {
+          debug!("send_to_locators: {:?}: {e:?}", locator);
+          if result.is_err() {
+            result = Err(e);
+          }
+        }
+      }
+    }
+    result
+  }
+
+  /// Send several RTPS messages to the locator, in order, e.g. the fragments
+  /// of a large sample. An error means that some of them may not have been
+  /// sent.
+  ///
+  /// The default calls [`send_gather_to_locator`](Self::send_gather_to_locator)
+  /// for each message. Transports can override this to send with fewer system
+  /// calls.
+  fn send_all_to_locator(
+    &mut self,
+    messages: &[GatherBuffer],
+    locator: &Locator,
+  ) -> io::Result<()> {
+    for message in messages {
+      self.send_gather_to_locator(message, locator)?;
+    }
+    Ok(())
+  }
+
+  /// Locators to announce in discovery, so that remote participants can send
+  /// to this transport.
+  fn announced_locators(&self) -> TransportLocators;
+
+  /// Register the receiving side with the mio-0.8 registry of the event loop.
+  /// The transport should become readable with the given token when messages
+  /// have been received. Registration is edge-triggered, so
+  /// [`receive`](Self::receive) must return all pending messages.
+  fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()>;
+
+  /// Received RTPS messages, each starting with the RTPS header.
+  fn receive(&mut self) -> Vec<Bytes>;
+
+  /// Like [`receive`](Self::receive), but each message comes with the locator
+  /// it was sent from, if the transport knows it. The source is needed to
+  /// respond to RTPS PING, which carries no reply locators. Default
+  /// implementation does not know the sources.
+  fn receive_from(&mut self) -> Vec<(Bytes, Option<Locator>)> {
+    self
+      .receive()
+      .into_iter()
+      .map(|message| (message, None))
+      .collect()
+  }
+
+  /// Should a remote reader be sent to through this transport, even if the
+  /// writer would prefer multicast? Default is `false`. This is true for
+  /// transports that reach only the local host.
+  fn preferred_over_multicast(&self) -> bool {
+    false
+  }
+
+  /// A remote participant was lost in discovery. These are the unicast
+  /// locators it had announced, so that the transport can release what it
+  /// keeps for sending to them. Default does nothing.
+  fn participant_lost(&mut self, locators: &[Locator]) {
+    let _ = locators;
+  }
+}
+
+type TransportConstructor =
+  dyn Fn(&TransportContext) -> io::Result<Box<dyn Transport>> + Send + Sync;
+
+// Constructor of a user-supplied transport, stored in the participant
+// builder until the participant is built.
+#[derive(Clone)]
+pub(crate) struct TransportFactory(pub Arc<TransportConstructor>);
+
+impl fmt::Debug for TransportFactory {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("TransportFactory")
+  }
+}
+
+// The transports of a participant, in order of preference. These are shared
+// by the event loop, Readers and Writers in the event loop thread, and by the
+// sender thread of asynchronous Writers.
+pub(crate) struct Transports {
+  transports: Vec<Mutex<Box<dyn Transport>>>,
+}
+
+impl fmt::Debug for Transports {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Transports")
+      .field("count", &self.transports.len())
+      .finish()
+  }
+}
+
+impl Transports {
+  // Number of poll tokens reserved for transports
+  pub const MAX_TRANSPORTS: usize = 12;
+
+  pub fn new(transports: Vec<Box<dyn Transport>>) -> Self {
+    Self {
+      transports: transports.into_iter().map(Mutex::new).collect(),
+    }
+  }
+
+  pub fn register(&self, registry: &Registry) -> io::Result<()> {
+    if self.transports.len() > Self::MAX_TRANSPORTS {
+      return Err(io::Error::new(io::ErrorKind::Other, "Too many transports"));
+    }
+    for (i, transport) in self.transports.iter().enumerate() {
+      transport
+        .lock()
+        .unwrap()
+        .register(registry, Token(TRANSPORT_TOKEN_BASE.0 + i))?;
+    }
+    Ok(())
+  }
+
+  pub fn is_transport_token(token: Token) -> bool {
+    (TRANSPORT_TOKEN_BASE.0..TRANSPORT_TOKEN_BASE.0 + Self::MAX_TRANSPORTS).contains(&token.0)
+  }
+
+  // Received messages of the transport that the token was registered for,
+  // and their sources, if known.
+  pub fn receive(&self, token: Token) -> Vec<(Bytes, Option<Locator>)> {
+    match self.transports.get(token.0 - TRANSPORT_TOKEN_BASE.0) {
+      Some(transport) => transport.lock().unwrap().receive_from(),
+      None => {
+        error!("No transport with token {:?}", token);
+        vec![]
+      }
+    }
+  }
+
+  pub fn can_send_to(&self, locator: &Locator) -> bool {
+    self
+      .transports
+      .iter()
+      .any(|transport| transport.lock().unwrap().can_send_to(locator))
+  }
+
+  pub fn participant_lost(&self, locators: &[Locator]) {
+    for transport in &self.transports {
+      transport.lock().unwrap().participant_lost(locators);
+    }
+  }
+
+  // Does the locator list contain a locator of a transport that should be
+  // used instead of multicast?
+  pub fn is_preferred_over_multicast(&self, ll: &[Locator]) -> bool {
+    self.transports.iter().any(|transport| {
+      let transport = transport.lock().unwrap();
+      transport.preferred_over_multicast() && ll.iter().any(|loc| transport.can_send_to(loc))
+    })
+  }
+
+  // The locator list should be that of a single participant. The message is
+  // sent to all the locators of the most preferred transport that can send to
+  // any of them. If that transport fails on all of its locators, the next
+  // transport is tried.
+  pub fn send_to_locator_list(&self, message: &GatherBuffer, ll: &[Locator]) {
+    for transport in &self.transports {
+      let mut transport = transport.lock().unwrap();
+      let locators: Vec<Locator> = ll
+        .iter()
+        .filter(|loc| transport.can_send_to(loc))
+        .copied()
+        .collect();
+      if locators.is_empty() {
+        continue;
+      }
+      match transport.send_to_locators(message, &locators) {
+        Ok(()) => return,
+        Err(e) => debug!("send_to_locator_list: {:?}: {e:?}", locators),
+      }
+      debug!("send_to_locator_list: Trying next transport for {:?}", ll);
+    }
+    trace!("send_to_locator_list: No transport for {:?}", ll);
+  }
+
+  // Several messages to a locator list, like send_to_locator_list
+  pub fn send_all_to_locator_list(&self, messages: &[GatherBuffer], ll: &[Locator]) {
+    for transport in &self.transports {
+      let mut transport = transport.lock().unwrap();
+      let locators: Vec<&Locator> = ll.iter().filter(|loc| transport.can_send_to(loc)).collect();
+      if locators.is_empty() {
+        continue;
+      }
+      let mut sent = false;
+      for loc in locators {
+        match transport.send_all_to_locator(messages, loc) {
+          Ok(()) => sent = true,
+          Err(e) => debug!("send_all_to_locator_list: {:?}: {e:?}", loc),
+        }
+      }
+      if sent {
+        return;
+      }
+      debug!(
+        "send_all_to_locator_list: Trying next transport for {:?}",
+        ll
+      );
+    }
+    trace!("send_all_to_locator_list: No transport for {:?}", ll);
+  }
+
+  pub fn send_to_locator(&self, message: &GatherBuffer, locator: &Locator) {
+    for transport in &self.transports {
+      let mut transport = transport.lock().unwrap();
+      if transport.can_send_to(locator) {
+        match transport.send_gather_to_locator(message, locator) {
+          Ok(()) => return,
+          Err(e) => debug!("send_to_locator: {:?}: {e:?}", locator),
+        }
+      }
+    }
+    // This is normal, as other implementations can define their own locator
+    // kinds. We get those from Discovery.
+    trace!("send_to_locator: No transport for {:?}", locator);
+  }
+
+  pub fn send_all_to_locator(&self, messages: &[GatherBuffer], locator: &Locator) {
+    for transport in &self.transports {
+      let mut transport = transport.lock().unwrap();
+      if transport.can_send_to(locator) {
+        match transport.send_all_to_locator(messages, locator) {
+          Ok(()) => return,
+          Err(e) => debug!("send_all_to_locator: {:?}: {e:?}", locator),
+        }
+      }
+    }
+    trace!("send_all_to_locator: No transport for {:?}", locator);
+  }
+}
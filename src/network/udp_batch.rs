@@ -0,0 +1,434 @@
+//! Batched UDP socket system calls on Linux: `recvmmsg` and `sendmmsg`, and
+//! UDP generic segmentation offload (GSO) and receive offload (GRO).
+//!
+//! These are used by the UDP transport, if enabled with
+//! [`batched_udp_io`](crate::DomainParticipantBuilder::batched_udp_io).
+//! Otherwise, and on other platforms, each datagram takes its own system call.
+
+use std::{cell::Cell, io, mem, net::SocketAddr, os::unix::io::RawFd, ptr};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use socket2::SockAddr;
+use bytes::{Bytes, BytesMut};
+
+use crate::network::transport::GatherBuffer;
+
+// Not defined by libc for glibc targets. Values are from linux/udp.h.
+const SOL_UDP: libc::c_int = 17;
+const UDP_SEGMENT: libc::c_int = 103;
+const UDP_GRO: libc::c_int = 104;
+
+// Datagrams received in one recvmmsg call
+pub const RECV_BATCH_SIZE: usize = 16;
+
+// Kernel limits of one GSO send: number of segments, and the payload size of
+// the unsegmented datagram.
+const GSO_MAX_SEGMENTS: usize = 64;
+const GSO_MAX_BYTES: usize = 65_000;
+
+// Room for one control message with an int, aligned for cmsghdr.
+#[repr(C, align(8))]
+#[derive(Clone, Copy)]
+struct ControlBuffer([u8; 64]);
+
+impl ControlBuffer {
+  fn new() -> Self {
+    Self([0; 64])
+  }
+}
+
+fn check(result: libc::c_int) -> io::Result<usize> {
+  if result < 0 {
+    Err(io::Error::last_os_error())
+  } else {
+    Ok(result as usize)
+  }
+}
+
+// With GRO enabled, the kernel may coalesce datagrams of a flow. They are
+// split again in `recv_batch`, so this must not be enabled on sockets read
+// with plain recv().
+pub fn enable_gro(fd: RawFd) -> io::Result<()> {
+  let enable: libc::c_int = 1;
+  check(unsafe {
+    libc::setsockopt(
+      fd,
+      SOL_UDP,
+      UDP_GRO,
+      ptr::addr_of!(enable).cast(),
+      mem::size_of::<libc::c_int>() as libc::socklen_t,
+    )
+  })
+  .map(|_| ())
+}
+
+// Receive the datagrams waiting in the socket, at most RECV_BATCH_SIZE, into
+// `slot_size` byte slots in `buffer`. The buffer is reused for every batch, and
+// the received datagrams are copied out of it, each aligned to 4 bytes, so
+// that they do not keep the large slots allocated. Coalesced GRO datagrams
+// are split back to the original datagrams. Each datagram comes with its
+// source address. Returns WouldBlock, if nothing was waiting.
+pub fn recv_batch(
+  fd: RawFd,
+  buffer: &mut Vec<u8>,
+  slot_size: usize,
+) -> io::Result<Vec<(Bytes, Option<SocketAddr>)>> {
+  let slots = RECV_BATCH_SIZE;
+  // Zero-filled only when allocated
+  buffer.resize(slots * slot_size, 0);
+  let mut iovecs: Vec<libc::iovec> = buffer
+    .chunks_mut(slot_size)
+    .map(|slot| libc::iovec {
+      iov_base: slot.as_mut_ptr().cast(),
+      iov_len: slot.len(),
+    })
+    .collect();
+  let mut controls = vec![ControlBuffer::new(); slots];
+  let mut sources: Vec<libc::sockaddr_storage> = vec![unsafe { mem::zeroed() }; slots];
+  let mut headers: Vec<libc::mmsghdr> = iovecs
+    .iter_mut()
+    .zip(controls.iter_mut())
+    .zip(sources.iter_mut())
+    .map(|((iovec, control), source)| {
+      let mut header: libc::mmsghdr = unsafe { mem::zeroed() };
+      header.msg_hdr.msg_name = (source as *mut libc::sockaddr_storage).cast();
+      header.msg_hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as _;
+      header.msg_hdr.msg_iov = iovec;
+      header.msg_hdr.msg_iovlen = 1;
+      header.msg_hdr.msg_control = control.0.as_mut_ptr().cast();
+      header.msg_hdr.msg_controllen = mem::size_of::<ControlBuffer>() as _;
+      header
+    })
+    .collect();
+
+  let received = check(unsafe {
+    libc::recvmmsg(
+      fd,
+      headers.as_mut_ptr(),
+      slots as _,
+      libc::MSG_DONTWAIT,
+      ptr::null_mut(),
+    )
+  });
+  let received = received?;
+  let headers = &headers[..received];
+
+  let aligned = |len: usize| (len + 3) & !3;
+  let mut copies = BytesMut::with_capacity(
+    headers
+      .iter()
+      .map(|header| aligned(header.msg_len as usize))
+      .sum(),
+  );
+  let mut ranges = Vec::with_capacity(received);
+  for (i, header) in headers.iter().enumerate() {
+    let len = header.msg_len as usize;
+    let start = copies.len();
+    copies.extend_from_slice(&buffer[i * slot_size..i * slot_size + len]);
+    copies.resize(start + aligned(len), 0);
+    ranges.push(start..start + len);
+  }
+  let copies = copies.freeze();
+
+  let mut messages = Vec::with_capacity(received);
+  for (i, (header, range)) in headers.iter().zip(ranges).enumerate() {
+    let len = range.len();
+    if header.msg_hdr.msg_flags & libc::MSG_TRUNC != 0 {
+      warn!("recv_batch: Datagram truncated to {len} bytes. Dropping.");
+      continue;
+    }
+    let datagram = copies.slice(range);
+    // This is safe, because recvmmsg initialized the address and its length.
+    let source = unsafe { SockAddr::new(sources[i], header.msg_hdr.msg_namelen) }.as_socket();
+    match gro_segment_size(&header.msg_hdr) {
+      Some(segment_size) if segment_size > 0 && segment_size < len => messages.extend(
+        (0..len)
+          .step_by(segment_size)
+          .map(|start| (datagram.slice(start..len.min(start + segment_size)), source)),
+      ),
+      _ => messages.push((datagram, source)),
+    }
+  }
+  Ok(messages)
+}
+
+fn gro_segment_size(header: &libc::msghdr) -> Option<usize> {
+  unsafe {
+    let mut cmsg = libc::CMSG_FIRSTHDR(header);
+    while !cmsg.is_null() {
+      if (*cmsg).cmsg_level == SOL_UDP && (*cmsg).cmsg_type == UDP_GRO {
+        let size = ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast::<libc::c_int>());
+        return usize::try_from(size).ok();
+      }
+      cmsg = libc::CMSG_NXTHDR(header, cmsg);
+    }
+  }
+  None
+}
+
+// Send with sendmmsg. A failed datagram is skipped, so that the rest are still
+// sent. Returns the first error.
+fn send_mmsg(fd: RawFd, headers: &mut [libc::mmsghdr]) -> io::Result<()> {
+  let mut result = Ok(());
+  let mut sent = 0;
+  while sent < headers.len() {
+    match check(unsafe {
+      libc::sendmmsg(
+        fd,
+        headers[sent..].as_mut_ptr(),
+        (headers.len() - sent) as _,
+        0,
+      )
+    }) {
+      Ok(n) => sent += n,
+      Err(e) => {
+        if result.is_ok() {
+          result = Err(e);
+        }
+        sent += 1;
+      }
+    }
+  }
+  result
+}
+
+// One datagram, gathered from the iovecs
+fn mmsghdr(address: &SockAddr, iovecs: &mut [libc::iovec]) -> libc::mmsghdr {
+  let mut header: libc::mmsghdr = unsafe { mem::zeroed() };
+  header.msg_hdr.msg_name = address.as_ptr() as *mut libc::c_void;
+  header.msg_hdr.msg_namelen = address.len();
+  header.msg_hdr.msg_iov = iovecs.as_mut_ptr();
+  header.msg_hdr.msg_iovlen = iovecs.len() as _;
+  header
+}
+
+// An iovec for each segment of the message
+fn iovecs(message: &GatherBuffer) -> Vec<libc::iovec> {
+  message
+    .segments()
+    .iter()
+    .map(|segment| libc::iovec {
+      iov_base: segment.as_ptr() as *mut libc::c_void,
+      iov_len: segment.len(),
+    })
+    .collect()
+}
+
+// Send the same datagram to each address.
+pub fn send_to_many(fd: RawFd, message: &GatherBuffer, addresses: &[SocketAddr]) -> io::Result<()> {
+  let addresses: Vec<SockAddr> = addresses.iter().map(|a| SockAddr::from(*a)).collect();
+  let mut iovecs = iovecs(message);
+  let mut headers: Vec<libc::mmsghdr> = addresses
+    .iter()
+    .map(|address| mmsghdr(address, &mut iovecs))
+    .collect();
+  send_mmsg(fd, &mut headers)
+}
+
+// Send the datagrams to the address. Runs of equal size datagrams, such as
+// fragments of a large sample, are sent with GSO, if `gso` is set. If the
+// kernel or the network interface does not support GSO, `gso` is cleared.
+pub fn send_many_to(
+  fd: RawFd,
+  buffers: &[GatherBuffer],
+  address: &SocketAddr,
+  gso: &Cell<bool>,
+) -> io::Result<()> {
+  let address = SockAddr::from(*address);
+  let mut result = Ok(());
+  let mut keep_first_error = |r: io::Result<()>| {
+    if result.is_ok() {
+      result = r;
+    }
+  };
+
+  let mut unsent = 0; // first buffer not sent yet
+  let mut next = 0;
+  while gso.get() && next < buffers.len() {
+    let run = gso_run_length(&buffers[next..]);
+    if run < 2 {
+      next += 1;
+      continue;
+    }
+    keep_first_error(send_each(fd, &buffers[unsent..next], &address));
+    unsent = next;
+    match send_segments(fd, &buffers[next..next + run], &address) {
+      Err(e) if gso_unsupported(&e) => {
+        info!("UDP GSO is not available: {e}");
+        gso.set(false);
+      }
+      r => {
+        keep_first_error(r);
+        next += run;
+        unsent = next;
+      }
+    }
+  }
+  keep_first_error(send_each(fd, &buffers[unsent..], &address));
+  result
+}
+
+fn send_each(fd: RawFd, buffers: &[GatherBuffer], address: &SockAddr) -> io::Result<()> {
+  if buffers.is_empty() {
+    return Ok(());
+  }
+  let mut iovecs: Vec<Vec<libc::iovec>> = buffers.iter().map(iovecs).collect();
+  let mut headers: Vec<libc::mmsghdr> = iovecs
+    .iter_mut()
+    .map(|iovecs| mmsghdr(address, iovecs))
+    .collect();
+  send_mmsg(fd, &mut headers)
+}
+
+// Number of buffers from the start, that can be sent as one GSO datagram. All
+// but the last segment must be of the same size, and the last may be shorter.
+fn gso_run_length(buffers: &[GatherBuffer]) -> usize {
+  let segment_size = buffers.first().map_or(0, GatherBuffer::len);
+  let mut total = 0;
+  let mut count = 0;
+  for buffer in buffers {
+    let len = buffer.len();
+    if count == GSO_MAX_SEGMENTS || total + len > GSO_MAX_BYTES || len > segment_size || len == 0 {
+      break;
+    }
+    total += len;
+    count += 1;
+    if len < segment_size {
+      break;
+    }
+  }
+  count
+}
+
+fn gso_unsupported(e: &io::Error) -> bool {
+  matches!(
+    e.raw_os_error(),
+    Some(libc::EIO | libc::EINVAL | libc::ENOPROTOOPT | libc::EOPNOTSUPP)
+  )
+}
+
+// One sendmsg with the UDP_SEGMENT option. The kernel splits the data to
+// datagrams of the size of the first buffer.
+fn send_segments(fd: RawFd, buffers: &[GatherBuffer], address: &SockAddr) -> io::Result<()> {
+  let segment_size = buffers[0].len() as u16;
+  let mut iovecs: Vec<libc::iovec> = buffers.iter().flat_map(iovecs).collect();
+  let mut control = ControlBuffer::new();
+  let mut header: libc::msghdr = unsafe { mem::zeroed() };
+  header.msg_name = address.as_ptr() as *mut libc::c_void;
+  header.msg_namelen = address.len();
+  header.msg_iov = iovecs.as_mut_ptr();
+  header.msg_iovlen = iovecs.len() as _;
+  header.msg_control = control.0.as_mut_ptr().cast();
+  unsafe {
+    header.msg_controllen = libc::CMSG_SPACE(mem::size_of::<u16>() as u32) as _;
+    let cmsg = libc::CMSG_FIRSTHDR(&header);
+    (*cmsg).cmsg_level = SOL_UDP;
+    (*cmsg).cmsg_type = UDP_SEGMENT;
+    (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<u16>() as u32) as _;
+    ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<u16>(), segment_size);
+  }
+  let total: usize = buffers.iter().map(GatherBuffer::len).sum();
+  let sent = check(unsafe { libc::sendmsg(fd, &header, 0) as libc::c_int })?;
+  if sent == total {
+    Ok(())
+  } else {
+    Err(io::Error::new(
+      io::ErrorKind::Other,
+      format!("GSO send of {total} bytes sent only {sent}"),
+    ))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    net::{Ipv4Addr, UdpSocket},
+    os::unix::io::AsRawFd,
+    time::Duration,
+  };
+
+  use super::*;
+
+  // All messages must come from `source`.
+  fn receive_all(socket: &UdpSocket, expected: usize, source: SocketAddr) -> Vec<Bytes> {
+    let mut buffer = Vec::new();
+    let mut messages = Vec::new();
+    for _ in 0..100 {
+      match recv_batch(socket.as_raw_fd(), &mut buffer, 65536) {
+        Ok(received) => messages.extend(received.into_iter().map(|(message, from)| {
+          assert_eq!(from, Some(source));
+          message
+        })),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+          if messages.len() >= expected {
+            break;
+          }
+          std::thread::sleep(Duration::from_millis(10));
+        }
+        Err(e) => panic!("recv_batch: {e:?}"),
+      }
+    }
+    messages
+  }
+
+  #[test]
+  fn gso_run_lengths() {
+    let buffers = |sizes: &[usize]| -> Vec<GatherBuffer> {
+      sizes
+        .iter()
+        .map(|s| GatherBuffer::from(vec![0; *s]))
+        .collect()
+    };
+    assert_eq!(gso_run_length(&buffers(&[100, 100, 100, 50, 100])), 4);
+    assert_eq!(gso_run_length(&buffers(&[100, 120, 100])), 1);
+    assert_eq!(gso_run_length(&buffers(&[100; 100])), GSO_MAX_SEGMENTS);
+    assert_eq!(gso_run_length(&buffers(&[20_000; 5])), 3);
+    assert_eq!(gso_run_length(&buffers(&[])), 0);
+  }
+
+  #[test]
+  fn batched_send_and_receive() {
+    let receiver = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let receiver_2 = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    enable_gro(receiver.as_raw_fd()).unwrap_or_else(|e| info!("No GRO: {e}"));
+    let sender = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let address = receiver.local_addr().unwrap();
+    let source = sender.local_addr().unwrap();
+
+    // Same message to several addresses
+    let mut hello = GatherBuffer::from(Bytes::from_static(b"hel"));
+    hello.push(Bytes::from_static(b"lo"));
+    send_to_many(
+      sender.as_raw_fd(),
+      &hello,
+      &[address, receiver_2.local_addr().unwrap()],
+    )
+    .unwrap();
+    assert_eq!(
+      receive_all(&receiver, 1, source),
+      vec![Bytes::from_static(b"hello")]
+    );
+    assert_eq!(
+      receive_all(&receiver_2, 1, source),
+      vec![Bytes::from_static(b"hello")]
+    );
+
+    // Fragments: equal size, a larger one in between, and a short last one.
+    // Each is a header and a payload segment.
+    let fragment = |i: u8, len: usize| {
+      let mut fragment = GatherBuffer::from(vec![0xff; 40]);
+      fragment.push(Bytes::from(vec![i; len - 40]));
+      fragment
+    };
+    let mut fragments: Vec<GatherBuffer> = (0..40u8).map(|i| fragment(i, 1200)).collect();
+    fragments[20] = fragment(20, 1300);
+    fragments.push(fragment(40, 300));
+    let expected: Vec<Bytes> = fragments.iter().map(GatherBuffer::to_bytes).collect();
+    for gso in [true, false] {
+      let gso = Cell::new(gso);
+      send_many_to(sender.as_raw_fd(), &fragments, &address, &gso).unwrap();
+      assert_eq!(receive_all(&receiver, fragments.len(), source), expected);
+    }
+  }
+}
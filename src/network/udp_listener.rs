@@ -1,16 +1,21 @@
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
 use std::{
   io,
   net::{IpAddr, Ipv4Addr, SocketAddr},
 };
 
-use mio_06;
+use mio_08;
 use log::{debug, error, info, trace, warn};
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 use bytes::{Bytes, BytesMut};
 
+#[cfg(target_os = "linux")]
+use crate::network::udp_batch;
 use crate::{
-  network::util::{
-    get_local_multicast_ip_addrs, get_local_multicast_locators, get_local_unicast_locators,
+  network::{
+    interface_filter::InterfaceFilter,
+    util::{get_ipv6_interface_indices, get_local_unicast_locators},
   },
   structure::locator::Locator,
 };
@@ -24,20 +29,39 @@ static_assertions::const_assert!(MESSAGE_BUFFER_ALLOCATION_CHUNK > MAX_MESSAGE_S
 /// called.
 #[derive(Debug)]
 pub struct UDPListener {
-  socket: mio_06::net::UdpSocket,
+  socket: mio_08::net::UdpSocket,
   receive_buffer: BytesMut,
-  multicast_group: Option<Ipv4Addr>,
+  multicast_group: Option<IpAddr>,
+  // IPv6 interfaces (indices) where multicast_group was joined
+  multicast_interfaces_v6: Vec<u32>,
+  // Receive with recvmmsg and GRO
+  #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+  batched: bool,
+  // Slots for recvmmsg. The datagrams are copied out of them.
+  #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+  batch_buffer: Vec<u8>,
 }
 
 impl Drop for UDPListener {
   fn drop(&mut self) {
-    if let Some(mcg) = self.multicast_group {
-      self
+    match self.multicast_group {
+      Some(IpAddr::V4(mcg)) => self
         .socket
         .leave_multicast_v4(&mcg, &Ipv4Addr::UNSPECIFIED)
         .unwrap_or_else(|e| {
           error!("leave_multicast_group: {e:?}");
-        });
+        }),
+      Some(IpAddr::V6(mcg)) => {
+        for interface in &self.multicast_interfaces_v6 {
+          self
+            .socket
+            .leave_multicast_v6(&mcg, *interface)
+            .unwrap_or_else(|e| {
+              error!("leave_multicast_group: {e:?}");
+            });
+        }
+      }
+      None => (),
     }
   }
 }
@@ -47,8 +71,25 @@ impl UDPListener {
     host: &str,
     port: u16,
     reuse_addr: bool,
-  ) -> io::Result<mio_06::net::UdpSocket> {
-    let raw_socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+  ) -> io::Result<mio_08::net::UdpSocket> {
+    let address = SocketAddr::new(
+      host
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?,
+      port,
+    );
+
+    let raw_socket = Socket::new(
+      Domain::for_address(address),
+      Type::DGRAM,
+      Some(Protocol::UDP),
+    )?;
+
+    // IPv6 sockets must not receive IPv4 traffic. That is for the IPv4 socket
+    // bound to the same port.
+    if address.is_ipv6() {
+      raw_socket.set_only_v6(true)?;
+    }
 
     // We set ReuseAddr so that other DomainParticipants on this host can
     // bind to the same multicast address and port.
@@ -66,13 +107,6 @@ impl UDPListener {
       }
     }
 
-    let address = SocketAddr::new(
-      host
-        .parse()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?,
-      port,
-    );
-
     if let Err(e) = raw_socket.bind(&SockAddr::from(address)) {
       info!("new_socket - cannot bind socket: {e:?}");
       return Err(e);
@@ -83,8 +117,7 @@ impl UDPListener {
       .set_nonblocking(true)
       .expect("Failed to set std socket to non blocking.");
 
-    let mio_socket =
-      mio_06::net::UdpSocket::from_socket(std_socket).expect("Unable to create mio socket");
+    let mio_socket = mio_08::net::UdpSocket::from_std(std_socket);
     info!(
       "UDPListener: new socket with address {:?}",
       mio_socket.local_addr()
@@ -93,12 +126,22 @@ impl UDPListener {
     Ok(mio_socket)
   }
 
-  pub fn to_locator_address(&self) -> io::Result<Vec<Locator>> {
-    let local_port = self.socket.local_addr()?.port();
+  // Locators of this listener. A listener bound to all interfaces has the
+  // addresses of the interfaces that the filter permits.
+  pub fn to_locator_address(&self, filter: &InterfaceFilter) -> io::Result<Vec<Locator>> {
+    let local_addr = self.socket.local_addr()?;
 
     match self.multicast_group {
-      Some(_ipv4_addr) => Ok(get_local_multicast_locators(local_port)),
-      None => Ok(get_local_unicast_locators(local_port)),
+      Some(group) => Ok(vec![Locator::from(SocketAddr::new(
+        group,
+        local_addr.port(),
+      ))]),
+      None if local_addr.ip().is_unspecified() => Ok(get_local_unicast_locators(
+        local_addr.port(),
+        local_addr.is_ipv6(),
+        filter,
+      )),
+      None => Ok(vec![Locator::from(local_addr)]),
     }
   }
 
@@ -109,10 +152,21 @@ impl UDPListener {
       socket: mio_socket,
       receive_buffer: BytesMut::with_capacity(MESSAGE_BUFFER_ALLOCATION_CHUNK),
       multicast_group: None,
+      multicast_interfaces_v6: Vec::new(),
+      batched: false,
+      batch_buffer: Vec::new(),
     })
   }
 
-  pub fn new_multicast(host: &str, port: u16, multicast_group: Ipv4Addr) -> io::Result<Self> {
+  // Joins the multicast group on the given interfaces, identified by their
+  // addresses. Only the interfaces with addresses of the same IP version as
+  // the group are used.
+  pub fn new_multicast_on_interfaces(
+    host: &str,
+    port: u16,
+    multicast_group: IpAddr,
+    interfaces: &[IpAddr],
+  ) -> io::Result<Self> {
     if !multicast_group.is_multicast() {
       return io::Result::Err(io::Error::new(
         io::ErrorKind::Other,
@@ -122,17 +176,33 @@ impl UDPListener {
 
     let mio_socket = Self::new_listening_socket(host, port, true)?;
 
-    for multicast_if_ipaddr in get_local_multicast_ip_addrs()? {
-      match multicast_if_ipaddr {
-        IpAddr::V4(a) => mio_socket
-          .join_multicast_v4(&multicast_group, &a)
-          .unwrap_or_else(|e| {
+    let mut multicast_interfaces_v6 = Vec::new();
+    match multicast_group {
+      IpAddr::V4(group) => {
+        for a in interfaces.iter().filter_map(|ip| match ip {
+          IpAddr::V4(a) => Some(a),
+          IpAddr::V6(_) => None,
+        }) {
+          mio_socket.join_multicast_v4(&group, a).unwrap_or_else(|e| {
             warn!(
               "join_multicast_v4 failed: {:?}. multicast_group [{:?}] interface [{:?}]",
-              e, multicast_group, a
+              e, group, a
             );
-          }),
-        IpAddr::V6(_a) => error!("UDPListener::new_multicast() not implemented for IpV6"), // TODO
+          });
+        }
+      }
+      IpAddr::V6(group) => {
+        multicast_interfaces_v6 = get_ipv6_interface_indices(interfaces);
+        for index in &multicast_interfaces_v6 {
+          mio_socket
+            .join_multicast_v6(&group, *index)
+            .unwrap_or_else(|e| {
+              warn!(
+                "join_multicast_v6 failed: {:?}. multicast_group [{:?}] interface [{:?}]",
+                e, group, index
+              );
+            });
+        }
       }
     }
 
@@ -140,14 +210,27 @@ impl UDPListener {
       socket: mio_socket,
       receive_buffer: BytesMut::with_capacity(MESSAGE_BUFFER_ALLOCATION_CHUNK),
       multicast_group: Some(multicast_group),
+      multicast_interfaces_v6,
+      batched: false,
+      batch_buffer: Vec::new(),
     })
   }
 
-  pub fn mio_socket(&mut self) -> &mut mio_06::net::UdpSocket {
+  // Receive several datagrams per system call. This is available only on
+  // Linux. GRO is used, if the kernel supports it.
+  pub fn enable_batched_receive(&mut self) {
+    #[cfg(target_os = "linux")]
+    {
+      udp_batch::enable_gro(self.socket.as_raw_fd())
+        .unwrap_or_else(|e| info!("UDP GRO is not available: {e}"));
+      self.batched = true;
+    }
+  }
+
+  pub fn mio_socket(&mut self) -> &mut mio_08::net::UdpSocket {
     &mut self.socket
   }
 
-  #[cfg(test)]
   pub fn port(&self) -> u16 {
     match self.socket.local_addr() {
       Ok(add) => add.port(),
@@ -175,6 +258,20 @@ impl UDPListener {
 
   /// Get all messages waiting in the socket.
   pub fn messages(&mut self) -> Vec<Bytes> {
+    self
+      .messages_from()
+      .into_iter()
+      .map(|(message, _source)| message)
+      .collect()
+  }
+
+  /// Get all messages waiting in the socket, with their source addresses.
+  pub fn messages_from(&mut self) -> Vec<(Bytes, Option<SocketAddr>)> {
+    #[cfg(target_os = "linux")]
+    if self.batched {
+      return self.messages_batched();
+    }
+
     let mut messages = Vec::with_capacity(4);
 
     loop {
@@ -197,8 +294,8 @@ impl UDPListener {
         "ensure_receive_buffer_capacity - {} bytes left",
         self.receive_buffer.capacity()
       );
-      let nbytes = match self.socket.recv(&mut self.receive_buffer) {
-        Ok(n) => n,
+      let (nbytes, source) = match self.socket.recv_from(&mut self.receive_buffer) {
+        Ok(received) => received,
         Err(e) => {
           self.receive_buffer.clear(); // since nothing was received
           if e.kind() == io::ErrorKind::WouldBlock {
@@ -227,13 +324,34 @@ impl UDPListener {
       // Now split away the used portion.
       let mut message = self.receive_buffer.split_to(self.receive_buffer.len());
       message.truncate(nbytes); // discard (hide) padding
-      messages.push(Bytes::from(message)); // freeze bytes and push
+      messages.push((Bytes::from(message), Some(source))); // freeze bytes and
+                                                           // push
     } // loop
 
     //unreachable!(); // But why does this cause a warning? (rustc 1.66.0)
     // Answer: https://github.com/rust-lang/rust/issues/46500
   }
 
+  #[cfg(target_os = "linux")]
+  fn messages_batched(&mut self) -> Vec<(Bytes, Option<SocketAddr>)> {
+    let mut messages = Vec::with_capacity(4);
+    loop {
+      match udp_batch::recv_batch(
+        self.socket.as_raw_fd(),
+        &mut self.batch_buffer,
+        MAX_MESSAGE_SIZE,
+      ) {
+        Ok(received) => messages.extend(received),
+        Err(e) => {
+          if e.kind() != io::ErrorKind::WouldBlock {
+            warn!("socket recvmmsg() error: {e:?}");
+          }
+          return messages;
+        }
+      }
+    }
+  }
+
   #[cfg(test)] // normally done in .drop()
   pub fn leave_multicast(&self, address: &Ipv4Addr) -> io::Result<()> {
     if address.is_multicast() {
@@ -256,7 +374,9 @@ mod tests {
   use std::{thread, time};
 
   use super::*;
-  use crate::network::udp_sender::*;
+  use crate::network::{
+    interface_filter::InterfaceFilter, udp_sender::*, util::get_local_multicast_ip_addrs,
+  };
 
   #[test]
   fn udpl_single_address() {
@@ -276,8 +396,13 @@ mod tests {
 
   #[test]
   fn udpl_multicast_address() {
-    let listener =
-      UDPListener::new_multicast("0.0.0.0", 10002, Ipv4Addr::new(239, 255, 0, 1)).unwrap();
+    let listener = UDPListener::new_multicast_on_interfaces(
+      "0.0.0.0",
+      10002,
+      Ipv4Addr::new(239, 255, 0, 1).into(),
+      &get_local_multicast_ip_addrs(&InterfaceFilter::default()).unwrap(),
+    )
+    .unwrap();
     let sender = UDPSender::new_with_random_port().unwrap();
 
     //setsockopt(sender.socket.as_raw_fd(), IpMulticastLoop, &true)
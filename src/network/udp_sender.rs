@@ -1,33 +1,143 @@
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, BorrowedFd};
 use std::{
+  cell::Cell,
   io,
-  net::{IpAddr, SocketAddr},
+  io::IoSlice,
+  net::{IpAddr, Ipv6Addr, SocketAddr},
 };
 #[cfg(test)]
 use std::net::Ipv4Addr;
 
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
-use mio_06;
+use mio_08;
+#[cfg(unix)]
+use socket2::SockRef;
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 #[cfg(windows)]
 use local_ip_address::list_afinet_netifas;
 
-use crate::{network::util::get_local_multicast_ip_addrs, structure::locator::Locator};
+#[cfg(target_os = "linux")]
+use crate::network::udp_batch;
+#[cfg(test)]
+use crate::network::{interface_filter::InterfaceFilter, util::get_local_multicast_ip_addrs};
+use crate::{
+  network::{transport::GatherBuffer, util::get_ipv6_interface_indices},
+  structure::locator::Locator,
+};
 
 // We need one multicast sender socket per interface
 
 #[derive(Debug)]
 pub struct UDPSender {
-  unicast_socket: mio_06::net::UdpSocket,
-  multicast_sockets: Vec<mio_06::net::UdpSocket>,
+  // IPv4 sockets. Unicast is None if IPv4 is disabled.
+  unicast_socket: Option<mio_08::net::UdpSocket>,
+  multicast_sockets: Vec<mio_08::net::UdpSocket>,
+  // IPv6 sockets. Unicast is None if IPv6 is disabled.
+  unicast_socket_v6: Option<mio_08::net::UdpSocket>,
+  multicast_sockets_v6: Vec<mio_08::net::UdpSocket>,
+  // Send with sendmmsg, and runs of fragments with GSO, while it works.
+  #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+  batched: bool,
+  #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+  gso: Cell<bool>,
 }
 
 impl UDPSender {
+  // IPv4 only, multicast on all interfaces
+  #[cfg(test)]
   pub fn new(sender_port: u16) -> io::Result<Self> {
+    Self::new_on_interfaces(
+      sender_port,
+      &get_local_multicast_ip_addrs(&InterfaceFilter::default())?,
+      true,
+      false,
+    )
+  }
+
+  // Sockets for the enabled IP versions. Multicast is sent on the given
+  // interfaces, identified by their addresses.
+  pub fn new_on_interfaces(
+    sender_port: u16,
+    multicast_interfaces: &[IpAddr],
+    ipv4: bool,
+    ipv6: bool,
+  ) -> io::Result<Self> {
+    let unicast_socket = if ipv4 {
+      Some(Self::new_unicast_socket_v4(sender_port)?)
+    } else {
+      None
+    };
+
+    let mut multicast_sockets = Vec::with_capacity(1);
+    for multicast_if_ipaddr in multicast_interfaces.iter().filter(|_| ipv4) {
+      let a = match multicast_if_ipaddr {
+        IpAddr::V4(a) => a,
+        IpAddr::V6(_) => continue, // see below
+      };
+      let raw_socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+      // beef: specify otput interface
+      info!(
+        "UDPSender: Multicast sender on interface {:?}",
+        multicast_if_ipaddr
+      );
+      raw_socket.set_multicast_if_v4(a)?;
+      if cfg!(windows) {
+        raw_socket.set_reuse_address(true)?;
+      } // Necessary? TODO: Check if necessary.
+      raw_socket.bind(&SockAddr::from(SocketAddr::new(*multicast_if_ipaddr, 0)))?;
+
+      raw_socket.set_multicast_loop_v4(true).unwrap_or_else(|e| {
+        error!("Cannot set multicast loop on: {e:?}");
+      });
+      multicast_sockets.push(Self::mio_socket(raw_socket)?);
+    } // end for
+
+    let unicast_socket_v6 = if ipv6 {
+      let saddr = SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), sender_port);
+      Some(Self::new_socket_v6(saddr)?)
+    } else {
+      None
+    };
+
+    let mut multicast_sockets_v6 = Vec::new();
+    // IPv6 multicast interfaces are selected by index, not address.
+    for index in get_ipv6_interface_indices(multicast_interfaces)
+      .into_iter()
+      .filter(|_| ipv6)
+    {
+      info!("UDPSender: IPv6 multicast sender on interface index {index}");
+      let raw_socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+      raw_socket.set_only_v6(true)?;
+      raw_socket.set_multicast_if_v6(index)?;
+      raw_socket.set_multicast_loop_v6(true).unwrap_or_else(|e| {
+        error!("Cannot set multicast loop on: {e:?}");
+      });
+      raw_socket.bind(&SockAddr::from(SocketAddr::new(
+        IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        0,
+      )))?;
+      multicast_sockets_v6.push(Self::mio_socket(raw_socket)?);
+    }
+
+    let sender = Self {
+      unicast_socket,
+      multicast_sockets,
+      unicast_socket_v6,
+      multicast_sockets_v6,
+      batched: false,
+      gso: Cell::new(false),
+    };
+    info!("UDPSender::new() --> {:?}", sender);
+    Ok(sender)
+  }
+
+  fn new_unicast_socket_v4(sender_port: u16) -> io::Result<mio_08::net::UdpSocket> {
     #[cfg(not(windows))]
     let unicast_socket = {
       let saddr: SocketAddr = SocketAddr::new("0.0.0.0".parse().unwrap(), sender_port);
-      mio_06::net::UdpSocket::bind(&saddr)?
+      mio_08::net::UdpSocket::bind(saddr)?
     };
 
     #[cfg(windows)]
@@ -48,7 +158,7 @@ impl UDPSender {
             )
           });
       }
-      mio_06::net::UdpSocket::from_socket(std::net::UdpSocket::from(raw_socket))?
+      Self::mio_socket(raw_socket)?
     };
 
     // We set multicasting loop on so that we can hear other DomainParticipant
@@ -59,38 +169,21 @@ impl UDPSender {
         error!("Cannot set multicast loop on: {e:?}");
       });
 
-    let mut multicast_sockets = Vec::with_capacity(1);
-    for multicast_if_ipaddr in get_local_multicast_ip_addrs()? {
-      let raw_socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
-      // beef: specify otput interface
-      info!(
-        "UDPSender: Multicast sender on interface {:?}",
-        multicast_if_ipaddr
-      );
-      match multicast_if_ipaddr {
-        IpAddr::V4(a) => {
-          raw_socket.set_multicast_if_v4(&a)?;
-          if cfg!(windows) {
-            raw_socket.set_reuse_address(true)?;
-          } // Necessary? TODO: Check if necessary.
-          raw_socket.bind(&SockAddr::from(SocketAddr::new(multicast_if_ipaddr, 0)))?;
-        }
-        IpAddr::V6(_a) => error!("UDPSender::new() not implemented for IpV6"), // TODO
-      }
+    Ok(unicast_socket)
+  }
 
-      let mc_socket = std::net::UdpSocket::from(raw_socket);
-      mc_socket.set_multicast_loop_v4(true).unwrap_or_else(|e| {
-        error!("Cannot set multicast loop on: {e:?}");
-      });
-      multicast_sockets.push(mio_06::net::UdpSocket::from_socket(mc_socket)?);
-    } // end for
+  fn new_socket_v6(saddr: SocketAddr) -> io::Result<mio_08::net::UdpSocket> {
+    let raw_socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+    raw_socket.set_only_v6(true)?;
+    raw_socket.bind(&SockAddr::from(saddr))?;
+    Self::mio_socket(raw_socket)
+  }
 
-    let sender = Self {
-      unicast_socket,
-      multicast_sockets,
-    };
-    info!("UDPSender::new() --> {:?}", sender);
-    Ok(sender)
+  // Sockets are non-blocking, so that a full send buffer does not stall the
+  // event loop.
+  fn mio_socket(raw_socket: Socket) -> io::Result<mio_08::net::UdpSocket> {
+    raw_socket.set_nonblocking(true)?;
+    Ok(mio_08::net::UdpSocket::from_std(raw_socket.into()))
   }
 
   #[cfg(test)]
@@ -98,68 +191,169 @@ impl UDPSender {
     Self::new(0)
   }
 
+  #[cfg(test)]
   pub fn send_to_locator_list(&self, buffer: &[u8], ll: &[Locator]) {
     for loc in ll {
       self.send_to_locator(buffer, loc);
     }
   }
 
-  fn send_to_udp_socket(&self, buffer: &[u8], socket: &mio_06::net::UdpSocket, addr: &SocketAddr) {
-    match socket.send_to(buffer, addr) {
+  // Send one datagram, which is the concatenation of the segments.
+  fn send_to_udp_socket(
+    &self,
+    segments: &[IoSlice],
+    socket: &mio_08::net::UdpSocket,
+    addr: &SocketAddr,
+  ) {
+    let len: usize = segments.iter().map(|s| s.len()).sum();
+    let result = match segments {
+      [buffer] => socket.send_to(buffer, *addr),
+      _ => send_to_vectored(socket, segments, addr),
+    };
+    match result {
       Ok(bytes_sent) => {
-        if bytes_sent == buffer.len() { // ok
+        if bytes_sent == len { // ok
         } else {
           error!(
             "send_to_udp_socket - send_to tried {} bytes, sent only {}",
-            buffer.len(),
-            bytes_sent
+            len, bytes_sent
           );
         }
       }
       Err(e) => {
         warn!(
           "send_to_udp_socket - send_to {} : {:?} len={}",
-          addr,
-          e,
-          buffer.len()
+          addr, e, len
         );
       }
     }
   }
 
+  // Send several datagrams per system call, and runs of equal size fragments
+  // with GSO. This is available only on Linux.
+  pub fn enable_batched_send(&mut self) {
+    #[cfg(target_os = "linux")]
+    {
+      self.batched = true;
+      self.gso.set(true);
+    }
+  }
+
+  // Sockets to send to the address with: the unicast socket, or the multicast
+  // sockets of all interfaces.
+  fn sockets_for(&self, socket_address: &SocketAddr) -> Vec<&mio_08::net::UdpSocket> {
+    let (unicast_socket, multicast_sockets) = if socket_address.is_ipv4() {
+      (&self.unicast_socket, &self.multicast_sockets)
+    } else {
+      (&self.unicast_socket_v6, &self.multicast_sockets_v6)
+    };
+    if socket_address.ip().is_multicast() {
+      multicast_sockets.iter().collect()
+    } else if let Some(socket) = unicast_socket {
+      vec![socket]
+    } else {
+      // This IP version is disabled. Normal, if the remote end announces
+      // locators of both versions.
+      trace!("sockets_for: No socket for {:?}", socket_address);
+      vec![]
+    }
+  }
+
   pub fn send_to_locator(&self, buffer: &[u8], locator: &Locator) {
-    if buffer.len() > 1500 {
-      warn!("send_to_locator: Message size = {}", buffer.len());
+    self.send_segments_to_locator(&[IoSlice::new(buffer)], locator);
+  }
+
+  // Send the message with scatter-gather I/O, without concatenating the
+  // segments first.
+  pub fn send_gather_to_locator(&self, message: &GatherBuffer, locator: &Locator) {
+    let segments: Vec<IoSlice> = message.segments().iter().map(|s| IoSlice::new(s)).collect();
+    self.send_segments_to_locator(&segments, locator);
+  }
+
+  fn send_segments_to_locator(&self, segments: &[IoSlice], locator: &Locator) {
+    let len: usize = segments.iter().map(|s| s.len()).sum();
+    if len > 1500 {
+      warn!("send_to_locator: Message size = {}", len);
     }
-    let send = |socket_address: SocketAddr| {
-      if socket_address.ip().is_multicast() {
-        for socket in &self.multicast_sockets {
-          self.send_to_udp_socket(buffer, socket, &socket_address);
-        }
-      } else {
-        self.send_to_udp_socket(buffer, &self.unicast_socket, &socket_address);
+    if let Some(socket_address) = udp_address(locator) {
+      for socket in self.sockets_for(&socket_address) {
+        self.send_to_udp_socket(segments, socket, &socket_address);
       }
-    };
+    }
+  }
 
-    match locator {
-      Locator::UdpV4(socket_address) => send(SocketAddr::from(*socket_address)),
-      Locator::UdpV6(socket_address) => send(SocketAddr::from(*socket_address)),
-      Locator::Invalid | Locator::Reserved => {
-        error!("send_to_locator: Cannot send to {:?}", locator);
+  // Send the same message to all the locators.
+  pub fn send_to_locators(&self, message: &GatherBuffer, locators: &[Locator]) {
+    #[cfg(target_os = "linux")]
+    if self.batched {
+      // One sendmmsg per socket
+      let mut destinations: Vec<(&mio_08::net::UdpSocket, Vec<SocketAddr>)> = Vec::new();
+      for socket_address in locators.iter().filter_map(udp_address) {
+        for socket in self.sockets_for(&socket_address) {
+          match destinations
+            .iter_mut()
+            .find(|(s, _)| std::ptr::eq(*s, socket))
+          {
+            Some((_, addresses)) => addresses.push(socket_address),
+            None => destinations.push((socket, vec![socket_address])),
+          }
+        }
       }
-      Locator::Other { kind, .. } =>
-      // This is normal, as other implementations can define their own kinds.
-      // We get those from Discovery.
-      {
-        trace!("send_to_locator: Unknown LocatorKind: {:?}", kind);
+      for (socket, addresses) in destinations {
+        udp_batch::send_to_many(socket.as_raw_fd(), message, &addresses).unwrap_or_else(|e| {
+          warn!(
+            "send_to_locators - sendmmsg {:?} : {:?} len={}",
+            addresses,
+            e,
+            message.len()
+          );
+        });
       }
+      return;
+    }
+
+    for locator in locators {
+      self.send_gather_to_locator(message, locator);
+    }
+  }
+
+  // Send the messages to the locator, in order.
+  pub fn send_all_to_locator(&self, messages: &[GatherBuffer], locator: &Locator) {
+    #[cfg(target_os = "linux")]
+    if self.batched {
+      if let Some(socket_address) = udp_address(locator) {
+        for socket in self.sockets_for(&socket_address) {
+          udp_batch::send_many_to(socket.as_raw_fd(), messages, &socket_address, &self.gso)
+            .unwrap_or_else(|e| {
+              warn!(
+                "send_all_to_locator - sendmmsg {} : {:?} count={}",
+                socket_address,
+                e,
+                messages.len()
+              );
+            });
+        }
+      }
+      return;
+    }
+
+    for message in messages {
+      self.send_gather_to_locator(message, locator);
     }
   }
 
   #[cfg(test)]
   pub fn send_to_all(&self, buffer: &[u8], addresses: &[SocketAddr]) {
     for address in addresses.iter() {
-      if self.unicast_socket.send_to(buffer, address).is_err() {
+      let socket = if address.is_ipv4() {
+        &self.unicast_socket
+      } else {
+        &self.unicast_socket_v6
+      };
+      if socket
+        .as_ref()
+        .map_or(true, |s| s.send_to(buffer, *address).is_err())
+      {
         debug!("Unable to send to {}", address);
       };
     }
@@ -171,7 +365,7 @@ impl UDPSender {
       let address = SocketAddr::new(IpAddr::V4(address), port);
       let mut size = 0;
       for s in self.multicast_sockets {
-        size = s.send_to(buffer, &address)?;
+        size = s.send_to(buffer, address)?;
       }
       Ok(size)
     } else {
@@ -183,8 +377,49 @@ impl UDPSender {
   }
 }
 
+// sendmsg with an iovec per segment
+#[cfg(unix)]
+fn send_to_vectored(
+  socket: &mio_08::net::UdpSocket,
+  segments: &[IoSlice],
+  addr: &SocketAddr,
+) -> io::Result<usize> {
+  // The socket outlives the borrowed descriptor.
+  let fd = unsafe { BorrowedFd::borrow_raw(socket.as_raw_fd()) };
+  SockRef::from(&fd).send_to_vectored(segments, &SockAddr::from(*addr))
+}
+
+#[cfg(not(unix))]
+fn send_to_vectored(
+  socket: &mio_08::net::UdpSocket,
+  segments: &[IoSlice],
+  addr: &SocketAddr,
+) -> io::Result<usize> {
+  let buffer: Vec<u8> = segments.iter().flat_map(|s| s.iter().copied()).collect();
+  socket.send_to(&buffer, *addr)
+}
+
+// Socket address of a UDP locator
+fn udp_address(locator: &Locator) -> Option<SocketAddr> {
+  match locator {
+    Locator::UdpV4(socket_address) => Some(SocketAddr::from(*socket_address)),
+    Locator::UdpV6(socket_address) => Some(SocketAddr::from(*socket_address)),
+    Locator::Invalid | Locator::Reserved => {
+      error!("send_to_locator: Cannot send to {:?}", locator);
+      None
+    }
+    Locator::Other { kind, .. } => {
+      // This is normal, as other implementations can define their own kinds.
+      // We get those from Discovery.
+      trace!("send_to_locator: Unknown LocatorKind: {:?}", kind);
+      None
+    }
+  }
+}
+
 #[cfg(test)]
 mod tests {
+  use bytes::Bytes;
 
   use super::*;
   use crate::network::udp_listener::*;
@@ -227,4 +462,47 @@ mod tests {
     assert_eq!(rec_data_2.len(), 6);
     assert_eq!(rec_data_2, data);
   }
+
+  #[test]
+  fn udps_ipv6_send() {
+    let listener = UDPListener::new_unicast("::1", 10401).unwrap();
+    let sender = UDPSender::new_on_interfaces(0, &[], false, true).unwrap();
+
+    let data: Vec<u8> = vec![6, 6, 6];
+
+    let locator = Locator::from(SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 10401));
+    sender.send_to_locator(&data, &locator);
+    // IPv4 is disabled, so this is not sent.
+    sender.send_to_locator(
+      &[1],
+      &Locator::from(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 10401)),
+    );
+
+    let rec_data = listener.get_message();
+
+    assert_eq!(rec_data, data);
+    assert_eq!(
+      listener
+        .to_locator_address(&InterfaceFilter::default())
+        .unwrap(),
+      vec![locator]
+    );
+  }
+
+  #[test]
+  fn udps_gather_send() {
+    let listener = UDPListener::new_unicast("127.0.0.1", 10501).unwrap();
+    let sender = UDPSender::new(11501).expect("failed to create UDPSender");
+
+    let mut message = GatherBuffer::new();
+    message.push(Bytes::from_static(&[1, 2, 3]));
+    message.push(Bytes::from(vec![4; 2000]));
+    message.push(Bytes::from_static(&[5]));
+
+    let locator = Locator::from(SocketAddr::new("127.0.0.1".parse().unwrap(), 10501));
+    sender.send_gather_to_locator(&message, &locator);
+
+    let rec_data = listener.get_message();
+    assert_eq!(rec_data, message.to_bytes());
+  }
 }
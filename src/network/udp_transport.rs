@@ -0,0 +1,97 @@
+use std::{collections::HashMap, io};
+
+use mio_08::{Interest, Registry, Token};
+use bytes::Bytes;
+
+use crate::{
+  network::{
+    transport::{GatherBuffer, Transport, TransportLocators},
+    udp_listener::UDPListener,
+    udp_sender::UDPSender,
+  },
+  structure::locator::Locator,
+};
+
+// The UDP listeners and sender of a participant. The listeners are keyed by
+// their listener tokens, but are all registered with the transport token.
+pub(crate) struct UDPTransport {
+  listeners: HashMap<Token, UDPListener>,
+  sender: UDPSender,
+  locators: TransportLocators,
+}
+
+impl UDPTransport {
+  pub fn new(
+    listeners: HashMap<Token, UDPListener>,
+    sender: UDPSender,
+    locators: TransportLocators,
+  ) -> Self {
+    Self {
+      listeners,
+      sender,
+      locators,
+    }
+  }
+}
+
+impl Transport for UDPTransport {
+  fn can_send_to(&self, locator: &Locator) -> bool {
+    locator.is_udp()
+  }
+
+  fn send_to_locator(&mut self, buffer: &[u8], locator: &Locator) -> io::Result<()> {
+    self.sender.send_to_locator(buffer, locator);
+    Ok(())
+  }
+
+  fn send_gather_to_locator(
+    &mut self,
+    message: &GatherBuffer,
+    locator: &Locator,
+  ) -> io::Result<()> {
+    self.sender.send_gather_to_locator(message, locator);
+    Ok(())
+  }
+
+  fn send_to_locators(&mut self, message: &GatherBuffer, locators: &[Locator]) -> io::Result<()> {
+    self.sender.send_to_locators(message, locators);
+    Ok(())
+  }
+
+  fn send_all_to_locator(
+    &mut self,
+    messages: &[GatherBuffer],
+    locator: &Locator,
+  ) -> io::Result<()> {
+    self.sender.send_all_to_locator(messages, locator);
+    Ok(())
+  }
+
+  fn announced_locators(&self) -> TransportLocators {
+    self.locators.clone()
+  }
+
+  fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+    for listener in self.listeners.values_mut() {
+      registry.register(listener.mio_socket(), token, Interest::READABLE)?;
+    }
+    Ok(())
+  }
+
+  fn receive(&mut self) -> Vec<Bytes> {
+    self
+      .listeners
+      .values_mut()
+      .flat_map(UDPListener::messages)
+      .collect()
+  }
+
+  fn receive_from(&mut self) -> Vec<(Bytes, Option<Locator>)> {
+    self
+      .listeners
+      .values_mut()
+      .flat_map(UDPListener::messages_from)
+      .map(|(message, source)| (message, source.map(Locator::from)))
+      .collect()
+  }
+}
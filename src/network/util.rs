@@ -5,21 +5,23 @@ use std::{
 
 use if_addrs::Interface;
 #[allow(unused_imports)]
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
 
-use crate::structure::locator::Locator;
+use crate::{network::interface_filter::InterfaceFilter, structure::locator::Locator};
 
-pub fn get_local_multicast_locators(port: u16) -> Vec<Locator> {
-  let saddr = SocketAddr::new("239.255.0.1".parse().unwrap(), port);
-  vec![Locator::from(saddr)]
-}
-
-pub fn get_local_unicast_locators(port: u16) -> Vec<Locator> {
+// Locators of all local interfaces of one IP version, that the filter permits.
+//
+// IPv6 link-local addresses are skipped, because a Locator cannot carry the
+// scope (interface) id that is needed to send to them.
+pub fn get_local_unicast_locators(port: u16, ipv6: bool, filter: &InterfaceFilter) -> Vec<Locator> {
   match if_addrs::get_if_addrs() {
     Ok(ifaces) => ifaces
       .iter()
-      .filter(|ip| !ip.is_loopback())
-      .map(|ip| Locator::from(SocketAddr::new(ip.ip(), port)))
+      .filter(|ifaddr| !ifaddr.is_loopback())
+      .filter(|ifaddr| ifaddr.ip().is_ipv6() == ipv6)
+      .filter(|ifaddr| !(ipv6 && ifaddr.is_link_local()))
+      .filter(|ifaddr| permitted(ifaddr, filter))
+      .map(|ifaddr| Locator::from(SocketAddr::new(ifaddr.ip(), port)))
       .collect(),
     Err(e) => {
       error!(
@@ -35,15 +37,89 @@ pub fn get_local_unicast_locators(port: u16) -> Vec<Locator> {
 // This is used to set up senders and listeners.
 //
 // TODO: Check that the interface actually has multicast enabled.
-// Now we just skip loopback.
+// Now we just skip loopback and interfaces that the filter does not permit.
 // Could use e.g. "interfaces" crate to do this.
-pub fn get_local_multicast_ip_addrs() -> io::Result<Vec<IpAddr>> {
+pub fn get_local_multicast_ip_addrs(filter: &InterfaceFilter) -> io::Result<Vec<IpAddr>> {
   let ifs = if_addrs::get_if_addrs()?;
   Ok(
     ifs
       .iter()
       .filter(|ifaddr| !ifaddr.is_loopback())
+      .filter(|ifaddr| permitted(ifaddr, filter))
       .map(Interface::ip)
       .collect(),
   )
 }
+
+fn permitted(ifaddr: &Interface, filter: &InterfaceFilter) -> bool {
+  let permitted = filter.permits(&ifaddr.name, &ifaddr.ip());
+  if !permitted {
+    debug!("Interface {} {} not permitted", ifaddr.name, ifaddr.ip());
+  }
+  permitted
+}
+
+// Name of the network interface that has the given address
+pub fn get_interface_name(ip: &IpAddr) -> Option<String> {
+  let ifs = if_addrs::get_if_addrs().ok()?;
+  ifs
+    .iter()
+    .find(|ifaddr| ifaddr.ip() == *ip)
+    .map(|ifaddr| ifaddr.name.clone())
+}
+
+// Index of the network interface that has the given address. IPv6 multicast
+// interfaces are selected by index instead of address.
+pub fn get_interface_index(ip: &IpAddr) -> Option<u32> {
+  match if_addrs::get_if_addrs() {
+    Ok(ifs) => ifs
+      .iter()
+      .find(|ifaddr| ifaddr.ip() == *ip)
+      .and_then(|ifaddr| ifaddr.index),
+    Err(e) => {
+      error!("Cannot get local network interfaces: {:?}", e);
+      None
+    }
+  }
+}
+
+// Indices of the interfaces that have the given IPv6 addresses. Each interface
+// is listed once, even if it has several addresses.
+pub fn get_ipv6_interface_indices(interfaces: &[IpAddr]) -> Vec<u32> {
+  let mut indices: Vec<u32> = interfaces
+    .iter()
+    .filter(|ip| ip.is_ipv6())
+    .filter_map(|ip| {
+      let index = get_interface_index(ip);
+      if index.is_none() {
+        warn!("No interface index for {ip:?}. Cannot use it for IPv6 multicast.");
+      }
+      index
+    })
+    .collect();
+  indices.sort_unstable();
+  indices.dedup();
+  indices
+}
+
+// MTU of the network interface that has the given address. This is known only
+// on Linux, where it is read from sysfs.
+pub fn get_interface_mtu(ip: &IpAddr) -> Option<usize> {
+  let ifs = if_addrs::get_if_addrs().ok()?;
+  let ifaddr = ifs.iter().find(|ifaddr| ifaddr.ip() == *ip)?;
+  interface_mtu(&ifaddr.name)
+}
+
+#[cfg(target_os = "linux")]
+fn interface_mtu(name: &str) -> Option<usize> {
+  std::fs::read_to_string(format!("/sys/class/net/{name}/mtu"))
+    .ok()?
+    .trim()
+    .parse()
+    .ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn interface_mtu(_name: &str) -> Option<usize> {
+  None
+}
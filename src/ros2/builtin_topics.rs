@@ -33,6 +33,11 @@ impl ROSDiscoveryTopic {
     lifespan: Some(Lifespan {
       duration: Duration::DURATION_INFINITE,
     }),
+    max_fragment_size: None,
+    flow_controller: None,
+    publish_mode: None,
+    batching: None,
+    reliability_timing: None,
   };
 
   const TOPIC_NAME: &'static str = "ros_discovery_info";
@@ -69,6 +74,11 @@ impl ParameterEventsTopic {
     history: Some(History::KeepLast { depth: 1 }),
     resource_limits: None,
     lifespan: None,
+    max_fragment_size: None,
+    flow_controller: None,
+    publish_mode: None,
+    batching: None,
+    reliability_timing: None,
   };
 
   const TOPIC_NAME: &'static str = "rt/parameter_events";
@@ -111,6 +121,11 @@ impl RosOutTopic {
     lifespan: Some(Lifespan {
       duration: Duration::from_secs(10),
     }),
+    max_fragment_size: None,
+    flow_controller: None,
+    publish_mode: None,
+    batching: None,
+    reliability_timing: None,
   };
 
   const TOPIC_NAME: &'static str = "rt/rosout";
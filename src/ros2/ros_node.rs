@@ -4,6 +4,7 @@ use std::{
 };
 
 use log::{error, info};
+#[cfg(feature = "mio_06")]
 use mio_06::Evented;
 use serde::{de::DeserializeOwned, Serialize};
 
@@ -257,8 +258,10 @@ impl RosParticipantInner {
   */
 }
 
+#[cfg(feature = "mio_06")]
 use mio_06 as mio;
 
+#[cfg(feature = "mio_06")]
 impl Evented for RosParticipant {
   fn register(
     &self,
@@ -295,6 +298,38 @@ impl Evented for RosParticipant {
   }
 }
 
+impl mio_08::event::Source for RosParticipant {
+  fn register(
+    &mut self,
+    registry: &mio_08::Registry,
+    token: mio_08::Token,
+    interests: mio_08::Interest,
+  ) -> std::io::Result<()> {
+    registry.register(
+      &mut self.inner.lock().unwrap().node_reader,
+      token,
+      interests,
+    )
+  }
+
+  fn reregister(
+    &mut self,
+    registry: &mio_08::Registry,
+    token: mio_08::Token,
+    interests: mio_08::Interest,
+  ) -> std::io::Result<()> {
+    registry.reregister(
+      &mut self.inner.lock().unwrap().node_reader,
+      token,
+      interests,
+    )
+  }
+
+  fn deregister(&mut self, registry: &mio_08::Registry) -> std::io::Result<()> {
+    registry.deregister(&mut self.inner.lock().unwrap().node_reader)
+  }
+}
+
 // ----------------------------------------------------------------------------------------------------
 // ----------------------------------------------------------------------------------------------------
 // ----------------------------------------------------------------------------------------------------
@@ -1,6 +1,12 @@
+pub(crate) mod async_sender;
+pub(crate) mod decoder;
 pub(crate) mod dp_event_loop;
+pub(crate) mod event_loop_shard;
+pub(crate) mod flow_controller;
 pub(crate) mod fragment_assembler;
+pub(crate) mod message_batcher;
 pub(crate) mod message_receiver;
+pub(crate) mod ping;
 pub(crate) mod reader;
 pub(crate) mod rtps_reader_proxy;
 pub(crate) mod rtps_writer_proxy;
@@ -0,0 +1,221 @@
+//! Sender thread of asynchronous DataWriters.
+//!
+//! Writers in [`Asynchronous`](crate::policy::PublishMode::Asynchronous)
+//! publish mode hand their messages to this thread instead of sending them in
+//! the event loop. While the send queue is over its limit, the Writers take no
+//! new samples from their DataWriters, prepare no more fragments of large
+//! samples, and postpone repairs. `DataWriter::write` then blocks for at most
+//! the `max_blocking_time` of Reliability QoS. When the queue has drained
+//! enough, the thread wakes up the event loop to continue.
+//!
+//! The queue itself is bounded, too. Messages that do not fit, e.g. heartbeats
+//! of many Writers, are dropped. Reliable Readers recover from that.
+
+use std::{
+  io,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc, Arc,
+  },
+  thread,
+};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::{
+  mio_source::channel as mio_channel,
+  network::transport::{GatherBuffer, Transports},
+  rtps::flow_controller::Destination,
+};
+
+// Messages in the send queue, above which Writers stop preparing fragments.
+const QUEUE_LIMIT: usize = 256;
+// The event loop is woken up, when the queue has drained to this.
+const QUEUE_LOW_WATER: usize = QUEUE_LIMIT / 2;
+// Messages that fit in the send queue. Above QUEUE_LIMIT, there is still room
+// for the heartbeats and messages that Writers were already preparing.
+const QUEUE_CAPACITY: usize = 4 * QUEUE_LIMIT;
+
+// Owned by the event loop. The thread exits when the event loop and all
+// Writers have dropped their handles.
+pub(crate) struct AsyncSender {
+  handle: AsyncSenderHandle,
+  wakeup_receiver: mio_channel::Receiver<()>,
+}
+
+impl AsyncSender {
+  pub fn new(participant_id: u16, transports: Arc<Transports>) -> io::Result<Self> {
+    let (sender, receiver) = mpsc::sync_channel::<(GatherBuffer, Destination)>(QUEUE_CAPACITY);
+    let (wakeup_sender, wakeup_receiver) = mio_channel::sync_channel::<()>(1);
+    let queued = Arc::new(AtomicUsize::new(0));
+    let thread_queued = Arc::clone(&queued);
+    thread::Builder::new()
+      .name(format!("RustDDS Participant {participant_id} async sender"))
+      .spawn(move || {
+        for (buffer, destination) in receiver {
+          match destination {
+            Destination::Locator(locator) => transports.send_to_locator(&buffer, &locator),
+            Destination::LocatorList(locators) => {
+              transports.send_to_locator_list(&buffer, &locators);
+            }
+          }
+          if thread_queued.fetch_sub(1, Ordering::AcqRel) == QUEUE_LOW_WATER + 1 {
+            // Full is fine, a wakeup is already pending.
+            let _ = wakeup_sender.try_send(());
+          }
+        }
+        debug!("Async sender of participant {participant_id} stopped");
+      })?;
+    Ok(Self {
+      handle: AsyncSenderHandle { sender, queued },
+      wakeup_receiver,
+    })
+  }
+
+  pub fn handle(&self) -> AsyncSenderHandle {
+    self.handle.clone()
+  }
+
+  pub fn has_capacity(&self) -> bool {
+    self.handle.has_capacity()
+  }
+
+  // Registered to the event loop, readable when there is room in the queue
+  // again.
+  pub fn wakeup_receiver(&mut self) -> &mut mio_channel::Receiver<()> {
+    &mut self.wakeup_receiver
+  }
+
+  pub fn clear_wakeup(&self) {
+    while self.wakeup_receiver.try_recv().is_ok() {}
+  }
+}
+
+#[derive(Clone)]
+pub(crate) struct AsyncSenderHandle {
+  sender: mpsc::SyncSender<(GatherBuffer, Destination)>,
+  queued: Arc<AtomicUsize>,
+}
+
+impl AsyncSenderHandle {
+  // Queue a message. This never blocks the event loop. If the queue is full,
+  // the message is dropped.
+  pub fn send(&self, buffer: GatherBuffer, destination: Destination) {
+    self.queued.fetch_add(1, Ordering::AcqRel);
+    match self.sender.try_send((buffer, destination)) {
+      Ok(()) => (),
+      Err(mpsc::TrySendError::Full(_)) => {
+        self.queued.fetch_sub(1, Ordering::AcqRel);
+        debug!("Async send queue is full. Dropped a message.");
+      }
+      Err(mpsc::TrySendError::Disconnected(_)) => {
+        self.queued.fetch_sub(1, Ordering::AcqRel);
+        error!("Async sender thread has stopped");
+      }
+    }
+  }
+
+  pub fn has_capacity(&self) -> bool {
+    self.queued.load(Ordering::Acquire) < QUEUE_LIMIT
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+  };
+
+  use mio_08::{Events, Interest, Poll, Registry, Token};
+  use bytes::Bytes;
+
+  use super::*;
+  use crate::{
+    network::transport::{Transport, TransportLocators},
+    structure::locator::Locator,
+  };
+
+  // Sends nothing until the gate is opened.
+  struct GatedTransport {
+    gate: Arc<Mutex<()>>,
+    sent: Arc<AtomicUsize>,
+  }
+
+  impl Transport for GatedTransport {
+    fn can_send_to(&self, _locator: &Locator) -> bool {
+      true
+    }
+    fn send_to_locator(&mut self, _buffer: &[u8], _locator: &Locator) -> io::Result<()> {
+      let _open = self.gate.lock().unwrap();
+      self.sent.fetch_add(1, Ordering::AcqRel);
+      Ok(())
+    }
+    fn announced_locators(&self) -> TransportLocators {
+      TransportLocators::default()
+    }
+    fn register(&mut self, _registry: &Registry, _token: Token) -> io::Result<()> {
+      Ok(())
+    }
+    fn receive(&mut self) -> Vec<Bytes> {
+      vec![]
+    }
+  }
+
+  #[test]
+  fn async_sender_capacity_and_wakeup() {
+    let gate = Arc::new(Mutex::new(()));
+    let sent = Arc::new(AtomicUsize::new(0));
+    let transports = Arc::new(Transports::new(vec![Box::new(GatedTransport {
+      gate: Arc::clone(&gate),
+      sent: Arc::clone(&sent),
+    })]));
+    let mut async_sender = AsyncSender::new(0, transports).unwrap();
+    let mut poll = Poll::new().unwrap();
+    poll
+      .registry()
+      .register(async_sender.wakeup_receiver(), Token(0), Interest::READABLE)
+      .unwrap();
+
+    let closed = gate.lock().unwrap();
+    let handle = async_sender.handle();
+    for _ in 0..QUEUE_LIMIT {
+      assert!(async_sender.has_capacity());
+      handle.send(
+        GatherBuffer::from(Bytes::from_static(b"message")),
+        Destination::Locator(Locator::Invalid),
+      );
+    }
+    // One message may be in the transport already, and the rest in the queue.
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(!async_sender.has_capacity());
+    // Messages beyond the capacity are dropped.
+    for _ in 0..2 * QUEUE_CAPACITY {
+      handle.send(
+        GatherBuffer::from(Bytes::from_static(b"message")),
+        Destination::Locator(Locator::Invalid),
+      );
+    }
+    let accepted = QUEUE_CAPACITY;
+
+    drop(closed);
+    let mut events = Events::with_capacity(4);
+    poll
+      .poll(&mut events, Some(Duration::from_secs(5)))
+      .unwrap();
+    assert!(!events.is_empty());
+    assert!(async_sender.has_capacity());
+    async_sender.clear_wakeup();
+
+    for _ in 0..100 {
+      if sent.load(Ordering::Acquire) >= accepted {
+        break;
+      }
+      std::thread::sleep(Duration::from_millis(10));
+    }
+    // The one in the transport made room for one more.
+    let sent = sent.load(Ordering::Acquire);
+    assert!((accepted..=accepted + 1).contains(&sent), "{sent} sent");
+  }
+}
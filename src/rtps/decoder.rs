@@ -0,0 +1,1029 @@
+//! Decoding RTPS messages for debugging
+//!
+//! [`decode_message`] turns a received RTPS message into [`DecodedMessage`],
+//! which prints in human-readable form with `Display`, and serializes with
+//! Serde, e.g. to JSON. Payloads of the built-in discovery topics are decoded
+//! with the same deserializers that Discovery uses, so the output shows what
+//! RustDDS understood of the message.
+
+use std::{fmt, io};
+
+use bytes::Bytes;
+use serde::Serialize;
+use speedy::{Endianness, Readable};
+
+use crate::{
+  dds::qos::{HasQoSPolicy, QosPolicies},
+  discovery::{
+    builtin_endpoint::BuiltinEndpointSet,
+    sedp_messages::{DiscoveredReaderData, DiscoveredTopicData, DiscoveredWriterData},
+    spdp_participant_data::SpdpDiscoveredParticipantData,
+  },
+  messages::{
+    protocol_version::ProtocolVersion,
+    submessages::{
+      elements::{parameter_list::ParameterList, serialized_payload::SerializedPayload},
+      submessages::*,
+    },
+    vendor_id::VendorId,
+  },
+  network::tcp_transport::tcp_locator_address,
+  rtps::{ping, Message, SubmessageBody},
+  serialization::pl_cdr_adapters::PlCdrDeserialize,
+  structure::{
+    duration::Duration,
+    guid::{EntityId, GuidPrefix, GUID},
+    locator::{kind as locator_kind, Locator},
+    time::Timestamp,
+  },
+  RepresentationIdentifier,
+};
+
+// Payloads of user topics are shown only up to this many bytes.
+const PAYLOAD_PREVIEW_LENGTH: usize = 64;
+
+/// Decodes an RTPS message
+///
+/// Returns an error, if `packet` is not a well-formed RTPS message. An RTPS
+/// PING is reported as an error of kind
+/// [`InvalidData`](io::ErrorKind::InvalidData), because it is not a message.
+pub fn decode_message(packet: &[u8]) -> io::Result<DecodedMessage> {
+  if ping::is_ping(packet) {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      "RTPS PING, not an RTPS message",
+    ));
+  }
+  let message = Message::read_from_buffer(&Bytes::copy_from_slice(packet))?;
+
+  Ok(DecodedMessage {
+    protocol_version: protocol_version_string(message.header.protocol_version),
+    vendor_id: vendor_id_string(message.header.vendor_id),
+    guid_prefix: guid_prefix_string(message.header.guid_prefix),
+    submessages: message
+      .submessages
+      .into_iter()
+      .map(|submessage| decode_submessage(submessage.body))
+      .collect(),
+  })
+}
+
+/// A decoded RTPS message. See [`decode_message`].
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodedMessage {
+  pub protocol_version: String,
+  pub vendor_id: String,
+  pub guid_prefix: String,
+  pub submessages: Vec<DecodedSubmessage>,
+}
+
+/// A decoded submessage. Entity ids and GUIDs are in hexadecimal.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DecodedSubmessage {
+  Data {
+    reader_id: String,
+    writer_id: String,
+    writer_sn: i64,
+    inline_qos: Option<Vec<DecodedParameter>>,
+    /// The payload is a key, not data
+    key: bool,
+    payload: Option<Box<DecodedPayload>>,
+  },
+  /// Fragments are not reassembled.
+  DataFrag {
+    reader_id: String,
+    writer_id: String,
+    writer_sn: i64,
+    inline_qos: Option<Vec<DecodedParameter>>,
+    fragment_starting_num: u32,
+    fragments_in_submessage: u16,
+    fragment_size: u16,
+    data_size: u32,
+  },
+  Gap {
+    reader_id: String,
+    writer_id: String,
+    gap_start: i64,
+    gap_list: Vec<i64>,
+  },
+  Heartbeat {
+    reader_id: String,
+    writer_id: String,
+    first_sn: i64,
+    last_sn: i64,
+    count: i32,
+    #[serde(rename = "final")]
+    final_flag: bool,
+    liveliness: bool,
+  },
+  HeartbeatFrag {
+    reader_id: String,
+    writer_id: String,
+    writer_sn: i64,
+    last_fragment_num: u32,
+    count: i32,
+  },
+  #[serde(rename = "ACKNACK")]
+  AckNack {
+    reader_id: String,
+    writer_id: String,
+    base: i64,
+    /// Sequence numbers that are negatively acknowledged
+    missing: Vec<i64>,
+    count: i32,
+    #[serde(rename = "final")]
+    final_flag: bool,
+  },
+  NackFrag {
+    reader_id: String,
+    writer_id: String,
+    writer_sn: i64,
+    base: u32,
+    /// Fragment numbers that are negatively acknowledged
+    missing: Vec<u32>,
+    count: i32,
+  },
+  InfoTs {
+    /// `None` if the timestamp was invalidated
+    timestamp: Option<String>,
+  },
+  InfoSrc {
+    protocol_version: String,
+    vendor_id: String,
+    guid_prefix: String,
+  },
+  InfoDst {
+    guid_prefix: String,
+  },
+  InfoReply {
+    unicast_locators: Vec<String>,
+    multicast_locators: Vec<String>,
+  },
+  /// DDS Security submessage. The contents are not decoded.
+  Security {
+    submessage: &'static str,
+  },
+}
+
+/// A parameter of a parameter list, i.e. inline QoS or a PL_CDR payload
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodedParameter {
+  pub id: u16,
+  /// `None` for parameter ids that RustDDS does not know, e.g. vendor-specific
+  /// ones
+  pub name: Option<&'static str>,
+  /// Value in hexadecimal
+  pub value: String,
+}
+
+/// Serialized payload of DATA
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodedPayload {
+  pub representation: String,
+  pub length: usize,
+  /// The parameter list of a PL_CDR payload
+  pub parameters: Option<Vec<DecodedParameter>>,
+  /// Decoded data of the built-in discovery topics
+  pub discovery: Option<DiscoveryData>,
+  /// Why decoding built-in topic data failed
+  pub error: Option<String>,
+  /// Beginning of other payloads, in hexadecimal
+  pub bytes: Option<String>,
+}
+
+/// Data of the built-in discovery topics
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "topic")]
+pub enum DiscoveryData {
+  #[serde(rename = "DCPSParticipant")]
+  Participant(ParticipantSummary),
+  #[serde(rename = "DCPSPublication")]
+  Publication(EndpointSummary),
+  #[serde(rename = "DCPSSubscription")]
+  Subscription(EndpointSummary),
+  #[serde(rename = "DCPSTopic")]
+  Topic(TopicSummary),
+}
+
+/// Main contents of [`SpdpDiscoveredParticipantData`]
+#[derive(Debug, Clone, Serialize)]
+pub struct ParticipantSummary {
+  pub guid: String,
+  pub protocol_version: String,
+  pub vendor_id: String,
+  pub entity_name: Option<String>,
+  pub lease_duration: Option<String>,
+  pub metatraffic_unicast_locators: Vec<String>,
+  pub metatraffic_multicast_locators: Vec<String>,
+  pub default_unicast_locators: Vec<String>,
+  pub default_multicast_locators: Vec<String>,
+  pub builtin_endpoints: Vec<&'static str>,
+}
+
+/// Main contents of [`DiscoveredWriterData`] and [`DiscoveredReaderData`]
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointSummary {
+  pub guid: String,
+  pub participant_guid: Option<String>,
+  pub topic_name: String,
+  pub type_name: String,
+  pub unicast_locators: Vec<String>,
+  pub multicast_locators: Vec<String>,
+  pub qos: Vec<String>,
+}
+
+/// Main contents of [`DiscoveredTopicData`]
+#[derive(Debug, Clone, Serialize)]
+pub struct TopicSummary {
+  pub topic_name: String,
+  pub type_name: String,
+  pub qos: Vec<String>,
+}
+
+fn decode_submessage(body: SubmessageBody) -> DecodedSubmessage {
+  match body {
+    SubmessageBody::Writer(WriterSubmessage::Data(data, flags)) => DecodedSubmessage::Data {
+      reader_id: entity_id_string(data.reader_id),
+      writer_id: entity_id_string(data.writer_id),
+      writer_sn: i64::from(data.writer_sn),
+      inline_qos: data.inline_qos.as_ref().map(decode_parameters),
+      key: flags.contains(DATA_Flags::Key),
+      payload: data.serialized_payload.as_ref().map(|payload| {
+        Box::new(decode_payload(
+          data.writer_id,
+          payload,
+          flags.contains(DATA_Flags::Key),
+        ))
+      }),
+    },
+    SubmessageBody::Writer(WriterSubmessage::DataFrag(data_frag, _flags)) => {
+      DecodedSubmessage::DataFrag {
+        reader_id: entity_id_string(data_frag.reader_id),
+        writer_id: entity_id_string(data_frag.writer_id),
+        writer_sn: i64::from(data_frag.writer_sn),
+        inline_qos: data_frag.inline_qos.as_ref().map(decode_parameters),
+        fragment_starting_num: u32::from(data_frag.fragment_starting_num),
+        fragments_in_submessage: data_frag.fragments_in_submessage,
+        fragment_size: data_frag.fragment_size,
+        data_size: data_frag.data_size,
+      }
+    }
+    SubmessageBody::Writer(WriterSubmessage::Gap(gap, _flags)) => DecodedSubmessage::Gap {
+      reader_id: entity_id_string(gap.reader_id),
+      writer_id: entity_id_string(gap.writer_id),
+      gap_start: i64::from(gap.gap_start),
+      gap_list: gap.gap_list.iter().map(i64::from).collect(),
+    },
+    SubmessageBody::Writer(WriterSubmessage::Heartbeat(heartbeat, flags)) => {
+      DecodedSubmessage::Heartbeat {
+        reader_id: entity_id_string(heartbeat.reader_id),
+        writer_id: entity_id_string(heartbeat.writer_id),
+        first_sn: i64::from(heartbeat.first_sn),
+        last_sn: i64::from(heartbeat.last_sn),
+        count: heartbeat.count,
+        final_flag: flags.contains(HEARTBEAT_Flags::Final),
+        liveliness: flags.contains(HEARTBEAT_Flags::Liveliness),
+      }
+    }
+    SubmessageBody::Writer(WriterSubmessage::HeartbeatFrag(heartbeat_frag, _flags)) => {
+      DecodedSubmessage::HeartbeatFrag {
+        reader_id: entity_id_string(heartbeat_frag.reader_id),
+        writer_id: entity_id_string(heartbeat_frag.writer_id),
+        writer_sn: i64::from(heartbeat_frag.writer_sn),
+        last_fragment_num: u32::from(heartbeat_frag.last_fragment_num),
+        count: heartbeat_frag.count,
+      }
+    }
+    SubmessageBody::Reader(ReaderSubmessage::AckNack(ack_nack, flags)) => {
+      DecodedSubmessage::AckNack {
+        reader_id: entity_id_string(ack_nack.reader_id),
+        writer_id: entity_id_string(ack_nack.writer_id),
+        base: i64::from(ack_nack.reader_sn_state.base()),
+        missing: ack_nack.reader_sn_state.iter().map(i64::from).collect(),
+        count: ack_nack.count,
+        final_flag: flags.contains(ACKNACK_Flags::Final),
+      }
+    }
+    SubmessageBody::Reader(ReaderSubmessage::NackFrag(nack_frag, _flags)) => {
+      DecodedSubmessage::NackFrag {
+        reader_id: entity_id_string(nack_frag.reader_id),
+        writer_id: entity_id_string(nack_frag.writer_id),
+        writer_sn: i64::from(nack_frag.writer_sn),
+        base: u32::from(nack_frag.fragment_number_state.base()),
+        missing: nack_frag
+          .fragment_number_state
+          .iter()
+          .map(u32::from)
+          .collect(),
+        count: nack_frag.count,
+      }
+    }
+    SubmessageBody::Interpreter(InterpreterSubmessage::InfoTimestamp(info_ts, _flags)) => {
+      DecodedSubmessage::InfoTs {
+        timestamp: info_ts.timestamp.map(timestamp_string),
+      }
+    }
+    SubmessageBody::Interpreter(InterpreterSubmessage::InfoSource(info_src, _flags)) => {
+      DecodedSubmessage::InfoSrc {
+        protocol_version: protocol_version_string(info_src.protocol_version),
+        vendor_id: vendor_id_string(info_src.vendor_id),
+        guid_prefix: guid_prefix_string(info_src.guid_prefix),
+      }
+    }
+    SubmessageBody::Interpreter(InterpreterSubmessage::InfoDestination(info_dst, _flags)) => {
+      DecodedSubmessage::InfoDst {
+        guid_prefix: guid_prefix_string(info_dst.guid_prefix),
+      }
+    }
+    SubmessageBody::Interpreter(InterpreterSubmessage::InfoReply(info_reply, _flags)) => {
+      DecodedSubmessage::InfoReply {
+        unicast_locators: locator_strings(&info_reply.unicast_locator_list),
+        multicast_locators: locator_strings(
+          info_reply.multicast_locator_list.as_deref().unwrap_or(&[]),
+        ),
+      }
+    }
+    SubmessageBody::Security(security) => DecodedSubmessage::Security {
+      submessage: match security {
+        SecuritySubmessage::SecureBody(..) => "SEC_BODY",
+        SecuritySubmessage::SecurePrefix(..) => "SEC_PREFIX",
+        SecuritySubmessage::SecurePostfix(..) => "SEC_POSTFIX",
+        SecuritySubmessage::SecureRTPSPrefix(..) => "SRTPS_PREFIX",
+        SecuritySubmessage::SecureRTPSPostfix(..) => "SRTPS_POSTFIX",
+      },
+    },
+  }
+}
+
+fn decode_parameters(parameter_list: &ParameterList) -> Vec<DecodedParameter> {
+  parameter_list
+    .parameters
+    .iter()
+    .map(|parameter| DecodedParameter {
+      id: parameter.parameter_id.value(),
+      name: parameter.parameter_id.name(),
+      value: hex_string(&parameter.value),
+    })
+    .collect()
+}
+
+fn decode_payload(
+  writer_id: EntityId,
+  payload: &SerializedPayload,
+  key_only: bool,
+) -> DecodedPayload {
+  let rep_id = payload.representation_identifier;
+  let mut decoded = DecodedPayload {
+    representation: representation_string(rep_id),
+    length: payload.value.len(),
+    parameters: None,
+    discovery: None,
+    error: None,
+    bytes: None,
+  };
+
+  let endianness = if rep_id == RepresentationIdentifier::PL_CDR_LE {
+    Endianness::LittleEndian
+  } else if rep_id == RepresentationIdentifier::PL_CDR_BE {
+    Endianness::BigEndian
+  } else {
+    let preview = &payload.value[..payload.value.len().min(PAYLOAD_PREVIEW_LENGTH)];
+    decoded.bytes = Some(hex_string(preview));
+    return decoded;
+  };
+
+  match ParameterList::read_from_buffer_with_ctx(endianness, &payload.value) {
+    Ok(parameter_list) => decoded.parameters = Some(decode_parameters(&parameter_list)),
+    Err(e) => decoded.error = Some(format!("Bad parameter list: {e}")),
+  }
+
+  // A key tells only which instance was disposed or unregistered. It is
+  // already shown as parameters.
+  if key_only || decoded.error.is_some() {
+    return decoded;
+  }
+  let discovery = match writer_id {
+    EntityId::SPDP_BUILTIN_PARTICIPANT_WRITER => {
+      SpdpDiscoveredParticipantData::from_pl_cdr_bytes(&payload.value, rep_id)
+        .map(|data| DiscoveryData::Participant(participant_summary(&data)))
+    }
+    EntityId::SEDP_BUILTIN_PUBLICATIONS_WRITER => {
+      DiscoveredWriterData::from_pl_cdr_bytes(&payload.value, rep_id)
+        .map(|data| DiscoveryData::Publication(publication_summary(&data)))
+    }
+    EntityId::SEDP_BUILTIN_SUBSCRIPTIONS_WRITER => {
+      DiscoveredReaderData::from_pl_cdr_bytes(&payload.value, rep_id)
+        .map(|data| DiscoveryData::Subscription(subscription_summary(&data)))
+    }
+    EntityId::SEDP_BUILTIN_TOPIC_WRITER => {
+      DiscoveredTopicData::from_pl_cdr_bytes(&payload.value, rep_id)
+        .map(|data| DiscoveryData::Topic(topic_summary(&data)))
+    }
+    _ => return decoded,
+  };
+  match discovery {
+    Ok(discovery) => decoded.discovery = Some(discovery),
+    Err(e) => decoded.error = Some(e.to_string()),
+  }
+  decoded
+}
+
+fn participant_summary(data: &SpdpDiscoveredParticipantData) -> ParticipantSummary {
+  ParticipantSummary {
+    guid: guid_string(data.participant_guid),
+    protocol_version: protocol_version_string(data.protocol_version),
+    vendor_id: vendor_id_string(data.vendor_id),
+    entity_name: data.entity_name.clone(),
+    lease_duration: data.lease_duration.map(duration_string),
+    metatraffic_unicast_locators: locator_strings(&data.metatraffic_unicast_locators),
+    metatraffic_multicast_locators: locator_strings(&data.metatraffic_multicast_locators),
+    default_unicast_locators: locator_strings(&data.default_unicast_locators),
+    default_multicast_locators: locator_strings(&data.default_multicast_locators),
+    builtin_endpoints: builtin_endpoint_names(data.available_builtin_endpoints),
+  }
+}
+
+fn publication_summary(data: &DiscoveredWriterData) -> EndpointSummary {
+  let topic_data = &data.publication_topic_data;
+  EndpointSummary {
+    guid: guid_string(topic_data.key),
+    participant_guid: topic_data.participant_key.map(guid_string),
+    topic_name: topic_data.topic_name.clone(),
+    type_name: topic_data.type_name.clone(),
+    unicast_locators: locator_strings(&data.writer_proxy.unicast_locator_list),
+    multicast_locators: locator_strings(&data.writer_proxy.multicast_locator_list),
+    qos: qos_strings(&topic_data.qos()),
+  }
+}
+
+fn subscription_summary(data: &DiscoveredReaderData) -> EndpointSummary {
+  let topic_data = &data.subscription_topic_data;
+  EndpointSummary {
+    guid: guid_string(topic_data.key()),
+    participant_guid: (*topic_data.participant_key()).map(guid_string),
+    topic_name: topic_data.topic_name().clone(),
+    type_name: topic_data.type_name().clone(),
+    unicast_locators: locator_strings(&data.reader_proxy.unicast_locator_list),
+    multicast_locators: locator_strings(&data.reader_proxy.multicast_locator_list),
+    qos: qos_strings(&topic_data.qos()),
+  }
+}
+
+fn topic_summary(data: &DiscoveredTopicData) -> TopicSummary {
+  TopicSummary {
+    topic_name: data.topic_data.name.clone(),
+    type_name: data.topic_data.type_name.clone(),
+    qos: qos_strings(&data.topic_data.qos()),
+  }
+}
+
+// The policies that are present
+fn qos_strings(qos: &QosPolicies) -> Vec<String> {
+  let mut strings = Vec::new();
+  let mut push = |policy: Option<String>| strings.extend(policy);
+  push(qos.durability().map(|p| format!("{p:?}")));
+  push(qos.presentation().map(|p| format!("{p:?}")));
+  push(qos.deadline().map(|p| format!("{p:?}")));
+  push(qos.latency_budget().map(|p| format!("{p:?}")));
+  push(qos.ownership().map(|p| format!("{p:?}")));
+  push(qos.liveliness().map(|p| format!("{p:?}")));
+  push(qos.time_based_filter().map(|p| format!("{p:?}")));
+  push(qos.reliability().map(|p| format!("{p:?}")));
+  push(qos.destination_order().map(|p| format!("{p:?}")));
+  push(qos.history().map(|p| format!("{p:?}")));
+  push(qos.resource_limits().map(|p| format!("{p:?}")));
+  push(qos.lifespan().map(|p| format!("{p:?}")));
+  strings
+}
+
+fn builtin_endpoint_names(endpoints: BuiltinEndpointSet) -> Vec<&'static str> {
+  [
+    (
+      BuiltinEndpointSet::PARTICIPANT_ANNOUNCER,
+      "PARTICIPANT_ANNOUNCER",
+    ),
+    (
+      BuiltinEndpointSet::PARTICIPANT_DETECTOR,
+      "PARTICIPANT_DETECTOR",
+    ),
+    (
+      BuiltinEndpointSet::PUBLICATIONS_ANNOUNCER,
+      "PUBLICATIONS_ANNOUNCER",
+    ),
+    (
+      BuiltinEndpointSet::PUBLICATIONS_DETECTOR,
+      "PUBLICATIONS_DETECTOR",
+    ),
+    (
+      BuiltinEndpointSet::SUBSCRIPTIONS_ANNOUNCER,
+      "SUBSCRIPTIONS_ANNOUNCER",
+    ),
+    (
+      BuiltinEndpointSet::SUBSCRIPTIONS_DETECTOR,
+      "SUBSCRIPTIONS_DETECTOR",
+    ),
+    (
+      BuiltinEndpointSet::PARTICIPANT_MESSAGE_DATA_WRITER,
+      "PARTICIPANT_MESSAGE_DATA_WRITER",
+    ),
+    (
+      BuiltinEndpointSet::PARTICIPANT_MESSAGE_DATA_READER,
+      "PARTICIPANT_MESSAGE_DATA_READER",
+    ),
+    (BuiltinEndpointSet::TOPICS_ANNOUNCER, "TOPICS_ANNOUNCER"),
+    (BuiltinEndpointSet::TOPICS_DETECTOR, "TOPICS_DETECTOR"),
+  ]
+  .into_iter()
+  .filter(|(bit, _name)| endpoints.contains(*bit))
+  .map(|(_bit, name)| name)
+  .collect()
+}
+
+// Formatting helpers
+
+fn hex_string(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn guid_prefix_string(guid_prefix: GuidPrefix) -> String {
+  hex_string(guid_prefix.as_ref())
+}
+
+fn entity_id_string(entity_id: EntityId) -> String {
+  hex_string(&entity_id.to_slice())
+}
+
+fn guid_string(guid: GUID) -> String {
+  format!(
+    "{}:{}",
+    guid_prefix_string(guid.prefix),
+    entity_id_string(guid.entity_id)
+  )
+}
+
+fn protocol_version_string(protocol_version: ProtocolVersion) -> String {
+  format!("{}.{}", protocol_version.major, protocol_version.minor)
+}
+
+fn vendor_id_string(vendor_id: VendorId) -> String {
+  let [a, b] = vendor_id.as_bytes();
+  match vendor_name(vendor_id) {
+    Some(name) => format!("{a:02x}.{b:02x} ({name})"),
+    None => format!("{a:02x}.{b:02x}"),
+  }
+}
+
+// From the OMG list of DDS vendor ids
+fn vendor_name(vendor_id: VendorId) -> Option<&'static str> {
+  Some(match vendor_id.as_bytes() {
+    [0x01, 0x01] => "RTI Connext DDS",
+    [0x01, 0x02] => "ADLINK OpenSplice",
+    [0x01, 0x03] => "OCI OpenDDS",
+    [0x01, 0x04] => "Milsoft",
+    [0x01, 0x05] => "Kongsberg InterCOM DDS",
+    [0x01, 0x06] => "Twin Oaks CoreDX",
+    [0x01, 0x0a] => "RTI Connext Micro",
+    [0x01, 0x0f] => "eProsima Fast DDS",
+    [0x01, 0x10] => "Eclipse Cyclone DDS",
+    [0x01, 0x11] => "GurumNetworks GurumDDS",
+    [0x01, 0x12] => "RustDDS",
+    _ => return None,
+  })
+}
+
+fn representation_string(rep_id: RepresentationIdentifier) -> String {
+  let name = match rep_id {
+    RepresentationIdentifier::CDR_BE => "CDR_BE",
+    RepresentationIdentifier::CDR_LE => "CDR_LE",
+    RepresentationIdentifier::PL_CDR_BE => "PL_CDR_BE",
+    RepresentationIdentifier::PL_CDR_LE => "PL_CDR_LE",
+    RepresentationIdentifier::CDR2_BE => "CDR2_BE",
+    RepresentationIdentifier::CDR2_LE => "CDR2_LE",
+    RepresentationIdentifier::PL_CDR2_BE => "PL_CDR2_BE",
+    RepresentationIdentifier::PL_CDR2_LE => "PL_CDR2_LE",
+    RepresentationIdentifier::D_CDR_BE => "D_CDR_BE",
+    RepresentationIdentifier::D_CDR_LE => "D_CDR_LE",
+    RepresentationIdentifier::XML => "XML",
+    other => return hex_string(&other.to_bytes()),
+  };
+  name.to_string()
+}
+
+fn locator_strings(locators: &[Locator]) -> Vec<String> {
+  locators
+    .iter()
+    .map(|locator| match locator {
+      Locator::UdpV4(address) => format!("udpv4://{address}"),
+      Locator::UdpV6(address) => format!("udpv6://{address}"),
+      Locator::Other {
+        kind: locator_kind::SHM,
+        address,
+        ..
+      } => format!("shm://{}", hex_string(address)),
+      Locator::Other {
+        kind: locator_kind::LOOPBACK,
+        address,
+        ..
+      } => format!("loopback://{}", hex_string(address)),
+      other => match tcp_locator_address(other) {
+        Some((address, logical_port)) => format!("tcp://{address}/{logical_port}"),
+        None => format!("{other:?}"),
+      },
+    })
+    .collect()
+}
+
+fn timestamp_string(timestamp: Timestamp) -> String {
+  let nanos = timestamp.duration_since(Timestamp::ZERO).to_nanoseconds();
+  chrono::DateTime::from_timestamp(
+    nanos.div_euclid(1_000_000_000),
+    nanos.rem_euclid(1_000_000_000) as u32,
+  )
+  .map_or_else(|| format!("{timestamp:?}"), |t| t.to_rfc3339())
+}
+
+fn duration_string(duration: Duration) -> String {
+  format!("{:.3}s", duration.to_nanoseconds() as f64 / 1e9)
+}
+
+// Human-readable form
+
+impl fmt::Display for DecodedMessage {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(
+      f,
+      "RTPS {} vendor {} guid_prefix {}",
+      self.protocol_version, self.vendor_id, self.guid_prefix
+    )?;
+    for submessage in &self.submessages {
+      write!(f, "{submessage}")?;
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Display for DecodedSubmessage {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Data {
+        reader_id,
+        writer_id,
+        writer_sn,
+        inline_qos,
+        key,
+        payload,
+      } => {
+        writeln!(
+          f,
+          "  DATA{} {writer_id} -> {reader_id} sn={writer_sn}",
+          if *key { "(key)" } else { "" }
+        )?;
+        if let Some(inline_qos) = inline_qos {
+          writeln!(f, "    inline QoS:")?;
+          write_parameters(f, inline_qos)?;
+        }
+        if let Some(payload) = payload {
+          write!(f, "{payload}")?;
+        }
+        Ok(())
+      }
+      Self::DataFrag {
+        reader_id,
+        writer_id,
+        writer_sn,
+        inline_qos,
+        fragment_starting_num,
+        fragments_in_submessage,
+        fragment_size,
+        data_size,
+      } => {
+        writeln!(
+          f,
+          "  DATA_FRAG {writer_id} -> {reader_id} sn={writer_sn} fragments {}..{} of {} bytes, \
+           data_size={data_size}",
+          fragment_starting_num,
+          fragment_starting_num + u32::from(*fragments_in_submessage),
+          fragment_size
+        )?;
+        if let Some(inline_qos) = inline_qos {
+          writeln!(f, "    inline QoS:")?;
+          write_parameters(f, inline_qos)?;
+        }
+        Ok(())
+      }
+      Self::Gap {
+        reader_id,
+        writer_id,
+        gap_start,
+        gap_list,
+      } => writeln!(
+        f,
+        "  GAP {writer_id} -> {reader_id} start={gap_start} list={gap_list:?}"
+      ),
+      Self::Heartbeat {
+        reader_id,
+        writer_id,
+        first_sn,
+        last_sn,
+        count,
+        final_flag,
+        liveliness,
+      } => writeln!(
+        f,
+        "  HEARTBEAT {writer_id} -> {reader_id} sn={first_sn}..={last_sn} count={count}{}{}",
+        if *final_flag { " final" } else { "" },
+        if *liveliness { " liveliness" } else { "" }
+      ),
+      Self::HeartbeatFrag {
+        reader_id,
+        writer_id,
+        writer_sn,
+        last_fragment_num,
+        count,
+      } => writeln!(
+        f,
+        "  HEARTBEAT_FRAG {writer_id} -> {reader_id} sn={writer_sn} \
+         last_fragment={last_fragment_num} count={count}"
+      ),
+      Self::AckNack {
+        reader_id,
+        writer_id,
+        base,
+        missing,
+        count,
+        final_flag,
+      } => writeln!(
+        f,
+        "  ACKNACK {reader_id} -> {writer_id} base={base} missing={missing:?} count={count}{}",
+        if *final_flag { " final" } else { "" }
+      ),
+      Self::NackFrag {
+        reader_id,
+        writer_id,
+        writer_sn,
+        base,
+        missing,
+        count,
+      } => writeln!(
+        f,
+        "  NACK_FRAG {reader_id} -> {writer_id} sn={writer_sn} base={base} missing={missing:?} \
+         count={count}"
+      ),
+      Self::InfoTs { timestamp } => writeln!(
+        f,
+        "  INFO_TS {}",
+        timestamp.as_deref().unwrap_or("(invalidated)")
+      ),
+      Self::InfoSrc {
+        protocol_version,
+        vendor_id,
+        guid_prefix,
+      } => writeln!(
+        f,
+        "  INFO_SRC {protocol_version} vendor {vendor_id} guid_prefix {guid_prefix}"
+      ),
+      Self::InfoDst { guid_prefix } => writeln!(f, "  INFO_DST guid_prefix {guid_prefix}"),
+      Self::InfoReply {
+        unicast_locators,
+        multicast_locators,
+      } => writeln!(
+        f,
+        "  INFO_REPLY unicast {unicast_locators:?} multicast {multicast_locators:?}"
+      ),
+      Self::Security { submessage } => writeln!(f, "  {submessage}"),
+    }
+  }
+}
+
+impl fmt::Display for DecodedPayload {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(
+      f,
+      "    payload {} {} bytes",
+      self.representation, self.length
+    )?;
+    match &self.discovery {
+      Some(DiscoveryData::Participant(p)) => {
+        writeln!(f, "    DCPSParticipant {}", p.guid)?;
+        writeln!(
+          f,
+          "      {} vendor {} name {:?} lease {}",
+          p.protocol_version,
+          p.vendor_id,
+          p.entity_name.as_deref().unwrap_or(""),
+          p.lease_duration.as_deref().unwrap_or("-"),
+        )?;
+        writeln!(
+          f,
+          "      metatraffic unicast {:?} multicast {:?}",
+          p.metatraffic_unicast_locators, p.metatraffic_multicast_locators
+        )?;
+        writeln!(
+          f,
+          "      default unicast {:?} multicast {:?}",
+          p.default_unicast_locators, p.default_multicast_locators
+        )?;
+        writeln!(f, "      builtin endpoints {:?}", p.builtin_endpoints)?;
+      }
+      Some(DiscoveryData::Publication(e)) => write_endpoint(f, "DCPSPublication", e)?,
+      Some(DiscoveryData::Subscription(e)) => write_endpoint(f, "DCPSSubscription", e)?,
+      Some(DiscoveryData::Topic(t)) => {
+        writeln!(f, "    DCPSTopic {:?} type {:?}", t.topic_name, t.type_name)?;
+        for policy in &t.qos {
+          writeln!(f, "      {policy}")?;
+        }
+      }
+      None => {
+        if let Some(parameters) = &self.parameters {
+          write_parameters(f, parameters)?;
+        }
+      }
+    }
+    if let Some(error) = &self.error {
+      writeln!(f, "    error: {error}")?;
+    }
+    if let Some(bytes) = &self.bytes {
+      let ellipsis = if self.length > PAYLOAD_PREVIEW_LENGTH {
+        ".."
+      } else {
+        ""
+      };
+      writeln!(f, "    {bytes}{ellipsis}")?;
+    }
+    Ok(())
+  }
+}
+
+fn write_parameters(f: &mut fmt::Formatter<'_>, parameters: &[DecodedParameter]) -> fmt::Result {
+  for parameter in parameters {
+    match parameter.name {
+      Some(name) => writeln!(f, "      {name} {}", parameter.value)?,
+      None => writeln!(f, "      0x{:04x} {}", parameter.id, parameter.value)?,
+    }
+  }
+  Ok(())
+}
+
+fn write_endpoint(
+  f: &mut fmt::Formatter<'_>,
+  topic: &str,
+  endpoint: &EndpointSummary,
+) -> fmt::Result {
+  writeln!(f, "    {topic} {}", endpoint.guid)?;
+  writeln!(
+    f,
+    "      topic {:?} type {:?}",
+    endpoint.topic_name, endpoint.type_name
+  )?;
+  if !endpoint.unicast_locators.is_empty() || !endpoint.multicast_locators.is_empty() {
+    writeln!(
+      f,
+      "      unicast {:?} multicast {:?}",
+      endpoint.unicast_locators, endpoint.multicast_locators
+    )?;
+  }
+  for policy in &endpoint.qos {
+    writeln!(f, "      {policy}")?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use speedy::Writable;
+
+  use super::*;
+  use crate::{
+    messages::header::Header,
+    structure::sequence_number::{SequenceNumber, SequenceNumberSet},
+    test::test_data::{spdp_participant_data_raw, spdp_subscription_data_raw},
+  };
+
+  #[test]
+  fn decode_spdp_participant() {
+    let decoded = decode_message(&spdp_participant_data_raw()).unwrap();
+    assert_eq!(decoded.vendor_id, "01.0f (eProsima Fast DDS)");
+    assert!(matches!(
+      decoded.submessages[0],
+      DecodedSubmessage::InfoTs { timestamp: Some(_) }
+    ));
+    let DecodedSubmessage::Data {
+      writer_id,
+      payload: Some(payload),
+      ..
+    } = &decoded.submessages[1]
+    else {
+      panic!(
+        "Expected DATA with payload, got {:?}",
+        decoded.submessages[1]
+      );
+    };
+    assert_eq!(writer_id, "000100c2");
+    assert_eq!(payload.representation, "PL_CDR_LE");
+    assert!(payload
+      .parameters
+      .as_ref()
+      .unwrap()
+      .iter()
+      .any(|p| p.name == Some("PID_PARTICIPANT_GUID")));
+    let Some(DiscoveryData::Participant(participant)) = &payload.discovery else {
+      panic!("Expected participant data, got {payload:?}");
+    };
+    assert_eq!(
+      participant.entity_name.as_deref(),
+      Some("fastrtpsParticipant")
+    );
+    assert_eq!(
+      participant.guid,
+      format!("{}:000001c1", decoded.guid_prefix)
+    );
+  }
+
+  #[test]
+  fn decode_sedp_subscription() {
+    let decoded = decode_message(&spdp_subscription_data_raw()).unwrap();
+    let subscription = decoded
+      .submessages
+      .iter()
+      .find_map(|submessage| match submessage {
+        DecodedSubmessage::Data {
+          payload: Some(payload),
+          ..
+        } => match &payload.discovery {
+          Some(DiscoveryData::Subscription(subscription)) => Some(subscription),
+          _ => None,
+        },
+        _ => None,
+      })
+      .expect("No DCPSSubscription data");
+    assert!(!subscription.topic_name.is_empty());
+    assert!(!subscription.qos.is_empty());
+  }
+
+  #[test]
+  fn decode_reliability_submessages() {
+    let guid_prefix = GuidPrefix::new(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+    let mut message = Message::new(Header::new(guid_prefix));
+    let heartbeat = Heartbeat {
+      reader_id: EntityId::SEDP_BUILTIN_PUBLICATIONS_READER,
+      writer_id: EntityId::SEDP_BUILTIN_PUBLICATIONS_WRITER,
+      first_sn: SequenceNumber::new(3),
+      last_sn: SequenceNumber::new(7),
+      count: 42,
+    };
+    message.add_submessage(
+      heartbeat
+        .create_submessage(HEARTBEAT_Flags::Endianness | HEARTBEAT_Flags::Final)
+        .unwrap(),
+    );
+    let ack_nack = AckNack {
+      reader_id: EntityId::SEDP_BUILTIN_PUBLICATIONS_READER,
+      writer_id: EntityId::SEDP_BUILTIN_PUBLICATIONS_WRITER,
+      reader_sn_state: SequenceNumberSet::from_base_and_set(
+        SequenceNumber::new(5),
+        &[SequenceNumber::new(5), SequenceNumber::new(7)]
+          .into_iter()
+          .collect(),
+      ),
+      count: 9,
+    };
+    message.add_submessage(ack_nack.create_submessage(ACKNACK_Flags::Endianness.into()));
+    let bytes = message
+      .write_to_vec_with_ctx(Endianness::LittleEndian)
+      .unwrap();
+
+    let decoded = decode_message(&bytes).unwrap();
+    assert_eq!(decoded.guid_prefix, "0102030405060708090a0b0c");
+    match &decoded.submessages[..] {
+      [DecodedSubmessage::Heartbeat {
+        writer_id,
+        first_sn: 3,
+        last_sn: 7,
+        count: 42,
+        final_flag: true,
+        liveliness: false,
+        ..
+      }, DecodedSubmessage::AckNack {
+        base: 5,
+        missing,
+        count: 9,
+        final_flag: false,
+        ..
+      }] => {
+        assert_eq!(writer_id, "000003c2");
+        assert_eq!(missing, &[5, 7]);
+      }
+      other => panic!("Unexpected {other:?}"),
+    }
+    let text = decoded.to_string();
+    assert!(text.contains("HEARTBEAT 000003c2 -> 000003c7 sn=3..=7 count=42 final"));
+    assert!(text.contains("ACKNACK 000003c7 -> 000003c2 base=5 missing=[5, 7] count=9"));
+  }
+
+  #[test]
+  fn ping_is_not_a_message() {
+    let error = decode_message(&ping::ping_message()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+  }
+}
@@ -0,0 +1,538 @@
+//! Event loop shards
+//!
+//! Optionally, the user-defined DataReaders and DataWriters of a participant
+//! are processed by a pool of shard threads instead of the participant event
+//! loop thread. Each shard runs its own [`DPEventLoop`], and the endpoints are
+//! assigned to shards by topic name, so that all local endpoints of a topic
+//! are in the same shard.
+//!
+//! The participant event loop still receives from the transports, runs the
+//! built-in discovery endpoints, and dispatches received submessages to the
+//! shards. Each shard receives everything through FIFO channels, so the
+//! submessages and commands of an endpoint are processed in order.
+
+use std::{
+  collections::{hash_map::DefaultHasher, HashMap},
+  hash::{Hash, Hasher},
+  io,
+  sync::{Arc, RwLock},
+  thread,
+};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::{
+  discovery::discovery_db::DiscoveryDB,
+  messages::submessages::submessages::{InterpreterSubmessage, ReaderSubmessage, WriterSubmessage},
+  mio_source::{channel as mio_channel, channel::TrySendError},
+  network::{constant::*, transport::Transports},
+  rtps::{
+    dp_event_loop::{DPEventLoop, DomainInfo},
+    flow_controller::FlowBudgets,
+    reader::ReaderIngredients,
+    writer::WriterIngredients,
+    Message, Submessage, SubmessageBody,
+  },
+  structure::{
+    dds_cache::DDSCache,
+    guid::{EntityId, GuidPrefix, GUID},
+  },
+};
+
+// Received messages waiting for a shard. If a shard falls this much behind,
+// further messages to it are dropped, as if a socket buffer was full.
+const SHARD_MESSAGE_QUEUE: usize = 256;
+
+// What a shard needs to run a DPEventLoop
+#[derive(Clone)]
+pub(crate) struct ShardContext {
+  pub domain_info: DomainInfo,
+  pub transports: Arc<Transports>,
+  pub flow_budgets: FlowBudgets,
+  pub ddscache: Arc<RwLock<DDSCache>>,
+  pub discovery_db: Arc<RwLock<DiscoveryDB>>,
+  pub participant_guid_prefix: GuidPrefix,
+  pub spdp_liveness_sender: mio_channel::SyncSender<GuidPrefix>,
+}
+
+struct Shard {
+  add_reader_sender: mio_channel::Sender<ReaderIngredients>,
+  remove_reader_sender: mio_channel::Sender<GUID>,
+  add_writer_sender: mio_channel::Sender<WriterIngredients>,
+  remove_writer_sender: mio_channel::Sender<GUID>,
+  discovery_update_sender: mio_channel::Sender<DiscoveryNotificationType>,
+  message_sender: mio_channel::SyncSender<Message>,
+  stop_sender: mio_channel::Sender<()>,
+  join_handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Shard {
+  fn start(index: usize, context: ShardContext) -> io::Result<Self> {
+    let (add_reader_sender, add_reader_receiver) = mio_channel::channel();
+    let (remove_reader_sender, remove_reader_receiver) = mio_channel::channel();
+    let (add_writer_sender, add_writer_receiver) = mio_channel::channel();
+    let (remove_writer_sender, remove_writer_receiver) = mio_channel::channel();
+    let (discovery_update_sender, discovery_update_receiver) = mio_channel::channel();
+    let (message_sender, message_receiver) = mio_channel::sync_channel(SHARD_MESSAGE_QUEUE);
+    let (stop_sender, stop_receiver) = mio_channel::channel();
+
+    let join_handle = thread::Builder::new()
+      .name(format!(
+        "RustDDS Participant {} event loop shard {}",
+        context.domain_info.participant_id, index
+      ))
+      .spawn(move || {
+        let dp_event_loop = DPEventLoop::new_shard(
+          context,
+          TokenReceiverPair {
+            token: ADD_READER_TOKEN,
+            receiver: add_reader_receiver,
+          },
+          TokenReceiverPair {
+            token: REMOVE_READER_TOKEN,
+            receiver: remove_reader_receiver,
+          },
+          TokenReceiverPair {
+            token: ADD_WRITER_TOKEN,
+            receiver: add_writer_receiver,
+          },
+          TokenReceiverPair {
+            token: REMOVE_WRITER_TOKEN,
+            receiver: remove_writer_receiver,
+          },
+          stop_receiver,
+          discovery_update_receiver,
+          message_receiver,
+        );
+        dp_event_loop.event_loop();
+      })?;
+
+    Ok(Self {
+      add_reader_sender,
+      remove_reader_sender,
+      add_writer_sender,
+      remove_writer_sender,
+      discovery_update_sender,
+      message_sender,
+      stop_sender,
+      join_handle: Some(join_handle),
+    })
+  }
+}
+
+// The shards of a participant, as seen from the participant event loop. With
+// no shards, everything is processed in the participant event loop.
+#[derive(Default)]
+pub(crate) struct Shards {
+  shards: Vec<Shard>,
+  // Shard of each local endpoint that is in a shard
+  local_endpoints: HashMap<EntityId, usize>,
+  // Shard of each discovered remote Writer, i.e. the shard of its topic. These
+  // are needed to dispatch DATA and HEARTBEAT to all matched Readers.
+  remote_writers: HashMap<GUID, usize>,
+}
+
+impl Shards {
+  pub fn start(count: usize, context: &ShardContext) -> io::Result<Self> {
+    let shards = (0..count)
+      .map(|index| Shard::start(index, context.clone()))
+      .collect::<io::Result<Vec<Shard>>>()?;
+    if !shards.is_empty() {
+      info!("Started {} event loop shards", shards.len());
+    }
+    Ok(Self {
+      shards,
+      local_endpoints: HashMap::new(),
+      remote_writers: HashMap::new(),
+    })
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.shards.is_empty()
+  }
+
+  // Built-in endpoints stay in the participant event loop.
+  fn shard_for(&self, entity_id: EntityId, topic_name: &str) -> Option<usize> {
+    if self.shards.is_empty() || entity_id.kind().is_built_in() {
+      return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    topic_name.hash(&mut hasher);
+    Some((hasher.finish() % self.shards.len() as u64) as usize)
+  }
+
+  // Gives the Reader back, if it should be added to the participant event
+  // loop.
+  pub fn add_reader(&mut self, ingredients: ReaderIngredients) -> Option<ReaderIngredients> {
+    let entity_id = ingredients.guid.entity_id;
+    let index = match self.shard_for(entity_id, &ingredients.topic_name) {
+      Some(index) => index,
+      None => return Some(ingredients),
+    };
+    debug!("Reader {:?} goes to shard {}", entity_id, index);
+    self.local_endpoints.insert(entity_id, index);
+    self.shards[index]
+      .add_reader_sender
+      .send(ingredients)
+      .unwrap_or_else(|e| error!("Cannot add Reader to shard {index}: {e:?}"));
+    None
+  }
+
+  // Returns false, if the Reader is not in any shard.
+  pub fn remove_reader(&mut self, guid: GUID) -> bool {
+    match self.local_endpoints.remove(&guid.entity_id) {
+      Some(index) => {
+        self.shards[index]
+          .remove_reader_sender
+          .send(guid)
+          .unwrap_or_else(|e| error!("Cannot remove Reader from shard {index}: {e:?}"));
+        true
+      }
+      None => false,
+    }
+  }
+
+  // Gives the Writer back, if it should be added to the participant event
+  // loop.
+  pub fn add_writer(&mut self, ingredients: WriterIngredients) -> Option<WriterIngredients> {
+    let entity_id = ingredients.guid.entity_id;
+    let index = match self.shard_for(entity_id, &ingredients.topic_name) {
+      Some(index) => index,
+      None => return Some(ingredients),
+    };
+    debug!("Writer {:?} goes to shard {}", entity_id, index);
+    self.local_endpoints.insert(entity_id, index);
+    self.shards[index]
+      .add_writer_sender
+      .send(ingredients)
+      .unwrap_or_else(|e| error!("Cannot add Writer to shard {index}: {e:?}"));
+    None
+  }
+
+  // Returns false, if the Writer is not in any shard.
+  pub fn remove_writer(&mut self, guid: GUID) -> bool {
+    match self.local_endpoints.remove(&guid.entity_id) {
+      Some(index) => {
+        self.shards[index]
+          .remove_writer_sender
+          .send(guid)
+          .unwrap_or_else(|e| error!("Cannot remove Writer from shard {index}: {e:?}"));
+        true
+      }
+      None => false,
+    }
+  }
+
+  // Forwards a discovery notification to the shards that it concerns. The
+  // participant event loop processes it, too.
+  pub fn discovery_notification(&mut self, notification: &DiscoveryNotificationType) {
+    use DiscoveryNotificationType::*;
+    if self.shards.is_empty() {
+      return;
+    }
+    let targets: Vec<usize> = match notification {
+      ReaderUpdated {
+        discovered_reader_data,
+      } => self
+        .shard_for(
+          discovered_reader_data
+            .reader_proxy
+            .remote_reader_guid
+            .entity_id,
+          discovered_reader_data.subscription_topic_data.topic_name(),
+        )
+        .into_iter()
+        .collect(),
+      WriterUpdated {
+        discovered_writer_data,
+      } => {
+        let writer_guid = discovered_writer_data.writer_proxy.remote_writer_guid;
+        let index = self.shard_for(
+          writer_guid.entity_id,
+          &discovered_writer_data.publication_topic_data.topic_name,
+        );
+        if let Some(index) = index {
+          self.remote_writers.insert(writer_guid, index);
+        }
+        index.into_iter().collect()
+      }
+      WriterLost { writer_guid } => {
+        self.remote_writers.remove(writer_guid);
+        (0..self.shards.len()).collect()
+      }
+      ParticipantLost { guid_prefix, .. } => {
+        self
+          .remote_writers
+          .retain(|guid, _| guid.prefix != *guid_prefix);
+        (0..self.shards.len()).collect()
+      }
+      ReaderLost { .. } => (0..self.shards.len()).collect(),
+      // Only built-in endpoints are updated.
+      ParticipantUpdated { .. } => vec![],
+      AssertTopicLiveliness { writer_guid, .. } => self
+        .local_endpoints
+        .get(&writer_guid.entity_id)
+        .copied()
+        .into_iter()
+        .collect(),
+    };
+    for index in targets {
+      self.shards[index]
+        .discovery_update_sender
+        .send(notification.clone())
+        .unwrap_or_else(|e| error!("Cannot notify shard {index}: {e:?}"));
+    }
+  }
+
+  // Sends the submessages of a received message to the shards of their target
+  // endpoints. Each shard gets the interpreter submessages, too, so that it
+  // sees the same source, destination and timestamps. Returns the part that
+  // the participant event loop should process.
+  pub fn dispatch(&self, message: Message) -> Option<Message> {
+    if self.shards.is_empty() {
+      return Some(message);
+    }
+    let mut source_guid_prefix = message.header.guid_prefix;
+    // Target of each submessage: None is the participant event loop. Interpreter
+    // submessages have no target.
+    let mut targets = Vec::with_capacity(message.submessages.len());
+    let mut in_shards = false;
+    for submessage in &message.submessages {
+      let target = match &submessage.body {
+        SubmessageBody::Interpreter(InterpreterSubmessage::InfoSource(info_source, _)) => {
+          source_guid_prefix = info_source.guid_prefix;
+          None
+        }
+        SubmessageBody::Interpreter(_) => None,
+        SubmessageBody::Writer(writer_submessage) => {
+          let (reader_id, writer_id) = writer_submessage_endpoints(writer_submessage);
+          if reader_id == EntityId::UNKNOWN {
+            self
+              .remote_writers
+              .get(&GUID::new(source_guid_prefix, writer_id))
+              .copied()
+          } else {
+            self.local_endpoints.get(&reader_id).copied()
+          }
+        }
+        SubmessageBody::Reader(reader_submessage) => self
+          .local_endpoints
+          .get(&reader_submessage_writer_id(reader_submessage))
+          .copied(),
+        SubmessageBody::Security(_) => None,
+      };
+      in_shards |= target.is_some();
+      targets.push(target);
+    }
+    if !in_shards {
+      return Some(message);
+    }
+
+    let mut parts: Vec<Option<Message>> = vec![None; self.shards.len() + 1];
+    let mut local_part = None;
+    for (submessage, target) in message.submessages.into_iter().zip(targets) {
+      let to_all = is_interpreter(&submessage);
+      for (index, part) in parts.iter_mut().enumerate() {
+        // The last part is the participant event loop.
+        let is_target = match target {
+          Some(t) => t == index,
+          None => index == self.shards.len(),
+        };
+        if to_all || is_target {
+          part
+            .get_or_insert_with(|| Message {
+              header: message.header.clone(),
+              submessages: vec![],
+            })
+            .submessages
+            .push(submessage.clone());
+        }
+      }
+    }
+    // Parts with interpreter submessages only are not sent.
+    for (index, part) in parts.into_iter().enumerate() {
+      let part = match part {
+        Some(part) if part.submessages.iter().any(|s| !is_interpreter(s)) => part,
+        _ => continue,
+      };
+      if index == self.shards.len() {
+        local_part = Some(part);
+      } else {
+        match self.shards[index].message_sender.try_send(part) {
+          Ok(()) => (),
+          Err(TrySendError::Full(_)) => {
+            info!("Event loop shard {index} is busy. Discarding message.");
+          }
+          Err(e) => warn!("Event loop shard {index} message pipe fail: {:?}", e),
+        }
+      }
+    }
+    local_part
+  }
+
+  pub fn stop(&mut self) {
+    for shard in &self.shards {
+      shard.stop_sender.send(()).unwrap_or(());
+    }
+    for shard in &mut self.shards {
+      if let Some(join_handle) = shard.join_handle.take() {
+        join_handle
+          .join()
+          .unwrap_or_else(|e| error!("Event loop shard panicked: {e:?}"));
+      }
+    }
+  }
+}
+
+impl Drop for Shards {
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
+
+fn is_interpreter(submessage: &Submessage) -> bool {
+  matches!(submessage.body, SubmessageBody::Interpreter(_))
+}
+
+// (reader_id, writer_id) of a submessage from a Writer
+fn writer_submessage_endpoints(submessage: &WriterSubmessage) -> (EntityId, EntityId) {
+  match submessage {
+    WriterSubmessage::Data(d, _) => (d.reader_id, d.writer_id),
+    WriterSubmessage::DataFrag(d, _) => (d.reader_id, d.writer_id),
+    WriterSubmessage::Gap(g, _) => (g.reader_id, g.writer_id),
+    WriterSubmessage::Heartbeat(h, _) => (h.reader_id, h.writer_id),
+    WriterSubmessage::HeartbeatFrag(h, _) => (h.reader_id, h.writer_id),
+  }
+}
+
+fn reader_submessage_writer_id(submessage: &ReaderSubmessage) -> EntityId {
+  match submessage {
+    ReaderSubmessage::AckNack(a, _) => a.writer_id,
+    ReaderSubmessage::NackFrag(n, _) => n.writer_id,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use enumflags2::BitFlags;
+  use speedy::Endianness;
+
+  use super::*;
+  use crate::{
+    messages::{
+      header::Header,
+      submessages::{
+        submessage_flag::FromEndianness,
+        submessages::{ACKNACK_Flags, AckNack, HEARTBEAT_Flags, Heartbeat},
+      },
+    },
+    rtps::MessageBuilder,
+    structure::{
+      guid::EntityKind,
+      sequence_number::{SequenceNumber, SequenceNumberSet},
+      time::Timestamp,
+    },
+  };
+
+  // A shard without a thread, and the receiving end of its messages
+  fn test_shard() -> (Shard, mio_channel::Receiver<Message>) {
+    let (message_sender, message_receiver) = mio_channel::sync_channel(8);
+    let shard = Shard {
+      add_reader_sender: mio_channel::channel().0,
+      remove_reader_sender: mio_channel::channel().0,
+      add_writer_sender: mio_channel::channel().0,
+      remove_writer_sender: mio_channel::channel().0,
+      discovery_update_sender: mio_channel::channel().0,
+      message_sender,
+      stop_sender: mio_channel::channel().0,
+      join_handle: None,
+    };
+    (shard, message_receiver)
+  }
+
+  fn heartbeat(reader_id: EntityId, writer_id: EntityId) -> Submessage {
+    Heartbeat {
+      reader_id,
+      writer_id,
+      first_sn: SequenceNumber::new(1),
+      last_sn: SequenceNumber::new(1),
+      count: 1,
+    }
+    .create_submessage(BitFlags::<HEARTBEAT_Flags>::from_endianness(
+      Endianness::LittleEndian,
+    ))
+    .unwrap()
+  }
+
+  fn kinds(message: &Message) -> Vec<&'static str> {
+    message
+      .submessages
+      .iter()
+      .map(|s| match &s.body {
+        SubmessageBody::Interpreter(_) => "interpreter",
+        SubmessageBody::Writer(_) => "writer",
+        SubmessageBody::Reader(_) => "reader",
+        SubmessageBody::Security(_) => "security",
+      })
+      .collect()
+  }
+
+  #[test]
+  fn dispatch_by_target_endpoint() {
+    let (shard_0, receiver_0) = test_shard();
+    let (shard_1, receiver_1) = test_shard();
+    let local_writer = EntityId::new([0, 0, 1], EntityKind::WRITER_NO_KEY_USER_DEFINED);
+    let remote_prefix = GuidPrefix::new(&[7; 12]);
+    let remote_writer = EntityId::new([0, 0, 2], EntityKind::WRITER_NO_KEY_USER_DEFINED);
+    let mut shards = Shards {
+      shards: vec![shard_0, shard_1],
+      local_endpoints: HashMap::new(),
+      remote_writers: HashMap::new(),
+    };
+    shards.local_endpoints.insert(local_writer, 0);
+    shards
+      .remote_writers
+      .insert(GUID::new(remote_prefix, remote_writer), 1);
+
+    // Built-in traffic stays in the participant event loop, and messages
+    // without submessages to shards are not split.
+    let builtin = MessageBuilder::new()
+      .ts_msg(Endianness::LittleEndian, Some(Timestamp::now()))
+      .add_header_and_build(remote_prefix);
+    let mut builtin = builtin;
+    builtin.add_submessage(heartbeat(
+      EntityId::SEDP_BUILTIN_PUBLICATIONS_READER,
+      EntityId::SEDP_BUILTIN_PUBLICATIONS_WRITER,
+    ));
+    let local = shards.dispatch(builtin).unwrap();
+    assert_eq!(kinds(&local), vec!["interpreter", "writer"]);
+    assert!(receiver_0.try_recv().is_err());
+    assert!(receiver_1.try_recv().is_err());
+
+    let mut message = MessageBuilder::new()
+      .ts_msg(Endianness::LittleEndian, Some(Timestamp::now()))
+      .add_header_and_build(remote_prefix);
+    // To all matched Readers of the remote Writer
+    message.add_submessage(heartbeat(EntityId::UNKNOWN, remote_writer));
+    // To a local Writer
+    message.add_submessage(
+      AckNack {
+        reader_id: EntityId::new([0, 0, 3], EntityKind::READER_NO_KEY_USER_DEFINED),
+        writer_id: local_writer,
+        reader_sn_state: SequenceNumberSet::new_empty(SequenceNumber::new(1)),
+        count: 1,
+      }
+      .create_submessage(BitFlags::<ACKNACK_Flags>::from_endianness(
+        Endianness::LittleEndian,
+      )),
+    );
+    assert!(shards.dispatch(message).is_none());
+
+    let part_0 = receiver_0.try_recv().unwrap();
+    assert_eq!(kinds(&part_0), vec!["interpreter", "reader"]);
+    assert_eq!(part_0.header, Header::new(remote_prefix));
+    let part_1 = receiver_1.try_recv().unwrap();
+    assert_eq!(kinds(&part_1), vec!["interpreter", "writer"]);
+  }
+}
@@ -0,0 +1,653 @@
+//! Flow controllers limit the rate at which DataWriters send.
+//!
+//! A flow controller is created for the participant with
+//! [`DomainParticipantBuilder::flow_controller`](crate::DomainParticipantBuilder::flow_controller),
+//! and DataWriters are assigned to it by name with the
+//! [`FlowController`](crate::policy::FlowController) QoS policy. Fresh data
+//! and repairs of those DataWriters go through the send queue of the flow
+//! controller. The queue is drained by a timer in the participant event loop
+//! as the rate limit allows.
+//!
+//! The queued bytes of each DataWriter are bounded. While a DataWriter is over
+//! the bound, it takes no new samples and postpones repairs, so that
+//! `DataWriter::write` blocks for at most the `max_blocking_time` of
+//! Reliability QoS and then fails. Periodic heartbeats do not wait in the
+//! queue, but are counted in the budget.
+//!
+//! With event loop threads, each thread has its own send queues, but the
+//! threads share the budget of a flow controller.
+
+use std::{
+  cell::RefCell,
+  collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+  io,
+  rc::Rc,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use mio_08::{Interest, Registry};
+
+use crate::{
+  mio_source::timer::Timer,
+  network::{
+    constant::FLOW_CONTROLLER_TIMER_TOKEN,
+    transport::{GatherBuffer, Transports},
+  },
+  structure::{guid::GUID, locator::Locator},
+};
+
+/// How much the DataWriters of a flow controller may send.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FlowLimit {
+  /// Average rate of `bytes_per_second`, with bursts of up to `burst_bytes`.
+  TokenBucket {
+    bytes_per_second: u64,
+    burst_bytes: u64,
+  },
+  /// At most `bytes_per_period` bytes in each `period`.
+  FixedRate {
+    bytes_per_period: u64,
+    period: Duration,
+  },
+}
+
+/// Which of the DataWriters sharing a flow controller sends next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum FlowScheduling {
+  /// Messages are sent in the order they were queued, regardless of the
+  /// DataWriter.
+  #[default]
+  Fifo,
+  /// DataWriters take turns sending one message each, so that a DataWriter
+  /// with a large backlog does not hold back the others.
+  RoundRobin,
+}
+
+/// Settings of a named flow controller. See
+/// [`DomainParticipantBuilder::flow_controller`](crate::DomainParticipantBuilder::flow_controller).
+///
+/// A message larger than the limit is sent when the full limit is available,
+/// and the excess is paid back before anything else is sent.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// # use rustdds::FlowControllerSettings;
+/// // 1 Mbit/s with bursts of 8 kB, writers taking turns
+/// let radio = FlowControllerSettings::token_bucket(125_000, 8_000).round_robin();
+/// // 64 kB every 100 ms
+/// let bulk = FlowControllerSettings::fixed_rate(64_000, Duration::from_millis(100));
+/// ```
+///
+/// All amounts and the period must be nonzero, otherwise
+/// [`DomainParticipantBuilder::build`](crate::DomainParticipantBuilder::build)
+/// fails with [`Error::BadParameter`](crate::dds::result::Error::BadParameter).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlowControllerSettings {
+  pub limit: FlowLimit,
+  pub scheduling: FlowScheduling,
+}
+
+impl FlowControllerSettings {
+  pub fn token_bucket(bytes_per_second: u64, burst_bytes: u64) -> Self {
+    Self {
+      limit: FlowLimit::TokenBucket {
+        bytes_per_second,
+        burst_bytes,
+      },
+      scheduling: FlowScheduling::Fifo,
+    }
+  }
+
+  pub fn fixed_rate(bytes_per_period: u64, period: Duration) -> Self {
+    Self {
+      limit: FlowLimit::FixedRate {
+        bytes_per_period,
+        period,
+      },
+      scheduling: FlowScheduling::Fifo,
+    }
+  }
+
+  #[must_use]
+  pub fn round_robin(mut self) -> Self {
+    self.scheduling = FlowScheduling::RoundRobin;
+    self
+  }
+
+  // Why the settings cannot be used, if so. A zero budget would never allow
+  // sending anything.
+  pub(crate) fn invalid_reason(&self) -> Option<&'static str> {
+    match self.limit {
+      FlowLimit::TokenBucket {
+        bytes_per_second: 0,
+        ..
+      } => Some("bytes_per_second is zero"),
+      FlowLimit::TokenBucket { burst_bytes: 0, .. } => Some("burst_bytes is zero"),
+      FlowLimit::FixedRate {
+        bytes_per_period: 0,
+        ..
+      } => Some("bytes_per_period is zero"),
+      FlowLimit::FixedRate { period, .. } if period.is_zero() => Some("period is zero"),
+      _ => None,
+    }
+  }
+}
+
+// Bounds of the bytes queued for one Writer. The bound is about one second of
+// traffic at the rate limit, but at least one full budget.
+const MIN_WRITER_QUEUE_BYTES: usize = 64 * 1024;
+const MAX_WRITER_QUEUE_BYTES: usize = 16 * 1024 * 1024;
+
+// Bytes that may be sent, according to a FlowLimit.
+#[derive(Debug)]
+struct Budget {
+  limit: FlowLimit,
+  // Negative after a message larger than the limit.
+  available: f64,
+  // Token bucket: when `available` was last refilled.
+  // Fixed rate: start of the current period.
+  updated: Instant,
+}
+
+impl Budget {
+  fn new(limit: FlowLimit, now: Instant) -> Self {
+    let mut budget = Self {
+      limit,
+      available: 0.0,
+      updated: now,
+    };
+    budget.available = budget.full();
+    budget
+  }
+
+  fn full(&self) -> f64 {
+    match self.limit {
+      FlowLimit::TokenBucket { burst_bytes, .. } => burst_bytes as f64,
+      FlowLimit::FixedRate {
+        bytes_per_period, ..
+      } => bytes_per_period as f64,
+    }
+  }
+
+  fn refill(&mut self, now: Instant) {
+    let elapsed = now.saturating_duration_since(self.updated);
+    match self.limit {
+      FlowLimit::TokenBucket {
+        bytes_per_second, ..
+      } => {
+        self.available =
+          (self.available + elapsed.as_secs_f64() * bytes_per_second as f64).min(self.full());
+        self.updated = now;
+      }
+      FlowLimit::FixedRate {
+        bytes_per_period,
+        period,
+      } => {
+        let periods = elapsed.as_nanos() / period.as_nanos().max(1);
+        if periods > 0 {
+          // Unused budget does not carry over, but debt from a large message is
+          // paid back from the new periods.
+          self.available =
+            (self.available.min(0.0) + periods as f64 * bytes_per_period as f64).min(self.full());
+          self.updated += period * u32::try_from(periods).unwrap_or(u32::MAX);
+        }
+      }
+    }
+  }
+
+  fn bytes_per_second(&self) -> f64 {
+    match self.limit {
+      FlowLimit::TokenBucket {
+        bytes_per_second, ..
+      } => bytes_per_second as f64,
+      FlowLimit::FixedRate {
+        bytes_per_period,
+        period,
+      } => bytes_per_period as f64 / period.as_secs_f64().max(1e-9),
+    }
+  }
+
+  // How many bytes a Writer may have queued before it is held back.
+  fn writer_queue_limit(&self) -> usize {
+    (self.bytes_per_second().max(self.full()) as usize)
+      .clamp(MIN_WRITER_QUEUE_BYTES, MAX_WRITER_QUEUE_BYTES)
+  }
+
+  fn can_send(&self, len: usize) -> bool {
+    let full = self.full();
+    self.available >= (len as f64).min(full) && self.available > 0.0
+  }
+
+  fn consume(&mut self, len: usize) {
+    self.available -= len as f64;
+  }
+
+  // How long until a message of `len` bytes can be sent.
+  fn wait_time(&self, len: usize, now: Instant) -> Duration {
+    let needed = (len as f64).min(self.full()).max(1.0) - self.available;
+    match self.limit {
+      FlowLimit::TokenBucket {
+        bytes_per_second, ..
+      } => Duration::from_secs_f64(needed.max(0.0) / (bytes_per_second.max(1) as f64)),
+      FlowLimit::FixedRate {
+        bytes_per_period,
+        period,
+      } => {
+        // Whole periods needed to pay back debt, then the start of the next one.
+        let periods = (needed.max(0.0) / (bytes_per_period.max(1) as f64))
+          .ceil()
+          .max(1.0);
+        let next = self.updated + period.mul_f64(periods);
+        next.saturating_duration_since(now)
+      }
+    }
+  }
+}
+
+// Where a queued message is sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Destination {
+  Locator(Locator),
+  // Locators of one participant. See Transports::send_to_locator_list.
+  LocatorList(Vec<Locator>),
+}
+
+#[derive(Debug)]
+struct QueuedMessage {
+  writer: GUID,
+  buffer: GatherBuffer,
+  destination: Destination,
+}
+
+// A flow controller with its send queues. This lives in the event loop
+// thread, and is shared by the Writers assigned to it.
+pub(crate) struct FlowController {
+  name: String,
+  index: usize,
+  scheduling: FlowScheduling,
+  budget: Arc<Mutex<Budget>>,
+  // Queued messages by Writer. All Writers share one queue in FIFO
+  // scheduling.
+  queues: BTreeMap<GUID, VecDeque<QueuedMessage>>,
+  // Queues with messages, in the order they take turns.
+  turns: VecDeque<GUID>,
+  // Bytes queued by each Writer
+  queued_bytes: HashMap<GUID, usize>,
+  queue_limit: usize,
+  // Writers that went below queue_limit. They are resumed from the event loop
+  // on the next timer event.
+  resumed: BTreeSet<GUID>,
+  transports: Arc<Transports>,
+  timer: Rc<RefCell<Timer<usize>>>,
+  timer_set: bool,
+}
+
+impl FlowController {
+  // Queue a message of a Writer, and send what the budget allows.
+  pub fn send(&mut self, writer: GUID, buffer: GatherBuffer, destination: Destination) {
+    let key = match self.scheduling {
+      FlowScheduling::Fifo => GUID::GUID_UNKNOWN,
+      FlowScheduling::RoundRobin => writer,
+    };
+    *self.queued_bytes.entry(writer).or_default() += buffer.len();
+    let queue = self.queues.entry(key).or_default();
+    if queue.is_empty() {
+      self.turns.push_back(key);
+    }
+    queue.push_back(QueuedMessage {
+      writer,
+      buffer,
+      destination,
+    });
+    self.drain();
+  }
+
+  // Send a message right away, ahead of the queue. It still uses the budget,
+  // so the queue waits longer.
+  pub fn send_now(&mut self, buffer: &GatherBuffer, destination: &Destination) {
+    let mut budget = self.budget.lock().unwrap();
+    budget.refill(Instant::now());
+    budget.consume(buffer.len());
+    drop(budget);
+    self.transmit(buffer, destination);
+  }
+
+  // The Writer has so much queued that it should not add more.
+  pub fn is_backlogged(&self, writer: GUID) -> bool {
+    self
+      .queued_bytes
+      .get(&writer)
+      .map_or(false, |&bytes| bytes >= self.queue_limit)
+  }
+
+  fn transmit(&self, buffer: &GatherBuffer, destination: &Destination) {
+    match destination {
+      Destination::Locator(locator) => self.transports.send_to_locator(buffer, locator),
+      Destination::LocatorList(locators) => self.transports.send_to_locator_list(buffer, locators),
+    }
+  }
+
+  // Account for a message leaving the queue.
+  fn dequeued(&mut self, writer: GUID, len: usize) {
+    if let Some(bytes) = self.queued_bytes.get_mut(&writer) {
+      let was_backlogged = *bytes >= self.queue_limit;
+      *bytes = bytes.saturating_sub(len);
+      if was_backlogged && *bytes < self.queue_limit {
+        self.resumed.insert(writer);
+        if !self.timer_set {
+          // The event loop resumes the Writer on the timer event.
+          self
+            .timer
+            .borrow_mut()
+            .set_timeout(Duration::ZERO, self.index);
+          self.timer_set = true;
+        }
+      }
+      if *bytes == 0 {
+        self.queued_bytes.remove(&writer);
+      }
+    }
+  }
+
+  fn drain(&mut self) {
+    while let Some(key) = self.turns.pop_front() {
+      let queue = match self.queues.get_mut(&key) {
+        Some(queue) => queue,
+        None => continue,
+      };
+      let len = match queue.front() {
+        Some(message) => message.buffer.len(),
+        None => {
+          self.queues.remove(&key);
+          continue;
+        }
+      };
+      let now = Instant::now();
+      let mut budget = self.budget.lock().unwrap();
+      budget.refill(now);
+      if !budget.can_send(len) {
+        let wait = budget.wait_time(len, now);
+        drop(budget);
+        self.turns.push_front(key);
+        if !self.timer_set {
+          trace!("Flow controller {:?} waits {:?}", self.name, wait);
+          self.timer.borrow_mut().set_timeout(wait, self.index);
+          self.timer_set = true;
+        }
+        return;
+      }
+      budget.consume(len);
+      drop(budget);
+
+      let message = queue.pop_front().unwrap(); // front() was Some above
+      if queue.is_empty() {
+        self.queues.remove(&key);
+      } else {
+        self.turns.push_back(key);
+      }
+      self.transmit(&message.buffer, &message.destination);
+      self.dequeued(message.writer, len);
+    }
+  }
+}
+
+// The budgets of the flow controllers of a participant. These are shared by
+// the event loop threads.
+#[derive(Clone)]
+pub(crate) struct FlowBudgets {
+  controllers: Vec<(String, FlowScheduling, Arc<Mutex<Budget>>)>,
+}
+
+impl FlowBudgets {
+  pub fn new(settings: &[(String, FlowControllerSettings)]) -> Self {
+    let now = Instant::now();
+    Self {
+      controllers: settings
+        .iter()
+        .map(|(name, settings)| {
+          (
+            name.clone(),
+            settings.scheduling,
+            Arc::new(Mutex::new(Budget::new(settings.limit.clone(), now))),
+          )
+        })
+        .collect(),
+    }
+  }
+}
+
+// The flow controllers of a participant, or of one of its event loop threads.
+pub(crate) struct FlowControllers {
+  controllers: Vec<Rc<RefCell<FlowController>>>,
+  names: HashMap<String, usize>,
+  timer: Rc<RefCell<Timer<usize>>>,
+}
+
+impl FlowControllers {
+  pub fn new(budgets: &FlowBudgets, transports: &Arc<Transports>) -> Self {
+    let timer = Rc::new(RefCell::new(Timer::default()));
+    let mut controllers = Vec::new();
+    let mut names = HashMap::new();
+    for (index, (name, scheduling, budget)) in budgets.controllers.iter().enumerate() {
+      controllers.push(Rc::new(RefCell::new(FlowController {
+        name: name.clone(),
+        index,
+        scheduling: *scheduling,
+        queue_limit: budget.lock().unwrap().writer_queue_limit(),
+        budget: Arc::clone(budget),
+        queues: BTreeMap::new(),
+        turns: VecDeque::new(),
+        queued_bytes: HashMap::new(),
+        resumed: BTreeSet::new(),
+        transports: Arc::clone(transports),
+        timer: Rc::clone(&timer),
+        timer_set: false,
+      })));
+      names.insert(name.clone(), index);
+    }
+    Self {
+      controllers,
+      names,
+      timer,
+    }
+  }
+
+  pub fn register(&self, registry: &Registry) -> io::Result<()> {
+    registry.register(
+      &mut *self.timer.borrow_mut(),
+      FLOW_CONTROLLER_TIMER_TOKEN,
+      Interest::READABLE,
+    )
+  }
+
+  pub fn get(&self, name: &str) -> Option<Rc<RefCell<FlowController>>> {
+    self
+      .names
+      .get(name)
+      .map(|&index| Rc::clone(&self.controllers[index]))
+  }
+
+  // Send from the queues of the flow controllers whose timer has expired.
+  // Returns the Writers that may take new samples again.
+  pub fn handle_timer_event(&self) -> Vec<GUID> {
+    let mut expired = Vec::new();
+    while let Some(index) = self.timer.borrow_mut().poll() {
+      expired.push(index);
+    }
+    let mut resumed = Vec::new();
+    for index in expired {
+      if let Some(controller) = self.controllers.get(index) {
+        let mut controller = controller.borrow_mut();
+        controller.timer_set = false;
+        controller.drain();
+        resumed.extend(std::mem::take(&mut controller.resumed));
+      }
+    }
+    resumed
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::structure::guid::EntityKind;
+
+  #[test]
+  fn token_bucket_budget() {
+    let start = Instant::now();
+    let mut budget = Budget::new(
+      FlowLimit::TokenBucket {
+        bytes_per_second: 1000,
+        burst_bytes: 500,
+      },
+      start,
+    );
+    assert!(budget.can_send(500));
+    budget.consume(400);
+    assert!(!budget.can_send(200));
+    assert_eq!(budget.wait_time(200, start), Duration::from_millis(100));
+
+    budget.refill(start + Duration::from_millis(100));
+    assert!(budget.can_send(200));
+
+    // Never more than the burst size
+    budget.refill(start + Duration::from_secs(10));
+    assert!(budget.can_send(500));
+    budget.consume(500);
+    assert!(!budget.can_send(1));
+
+    // Large message is sent with a full bucket, and paid back.
+    budget.refill(start + Duration::from_secs(20));
+    assert!(budget.can_send(2000));
+    budget.consume(2000);
+    assert_eq!(
+      budget.wait_time(100, start + Duration::from_secs(20)),
+      Duration::from_millis(1600)
+    );
+  }
+
+  #[test]
+  fn fixed_rate_budget() {
+    let start = Instant::now();
+    let period = Duration::from_millis(100);
+    let mut budget = Budget::new(
+      FlowLimit::FixedRate {
+        bytes_per_period: 1000,
+        period,
+      },
+      start,
+    );
+    budget.consume(800);
+    assert!(budget.can_send(200));
+    assert!(!budget.can_send(201));
+    let now = start + Duration::from_millis(30);
+    assert_eq!(budget.wait_time(201, now), Duration::from_millis(70));
+
+    // Unused budget does not accumulate.
+    budget.refill(start + Duration::from_millis(350));
+    assert!(budget.can_send(1000));
+    assert!(budget.can_send(2500)); // Large message is sent with full budget
+    budget.consume(2500);
+    // Debt of 1500 is paid back by the periods starting at 400 and 500 ms.
+    assert_eq!(
+      budget.wait_time(10, start + Duration::from_millis(350)),
+      Duration::from_millis(150)
+    );
+    budget.refill(start + Duration::from_millis(450));
+    assert!(!budget.can_send(10));
+    budget.refill(start + Duration::from_millis(500));
+    assert!(budget.can_send(500));
+    assert!(!budget.can_send(501));
+  }
+
+  #[test]
+  fn invalid_settings() {
+    assert!(FlowControllerSettings::token_bucket(1000, 0)
+      .invalid_reason()
+      .is_some());
+    assert!(FlowControllerSettings::token_bucket(0, 1000)
+      .invalid_reason()
+      .is_some());
+    assert!(
+      FlowControllerSettings::fixed_rate(0, Duration::from_millis(10))
+        .invalid_reason()
+        .is_some()
+    );
+    assert!(FlowControllerSettings::fixed_rate(1000, Duration::ZERO)
+      .invalid_reason()
+      .is_some());
+    assert!(
+      FlowControllerSettings::fixed_rate(1000, Duration::from_millis(10))
+        .invalid_reason()
+        .is_none()
+    );
+  }
+
+  #[test]
+  fn writer_queue_bound() {
+    // Budget is not refilled during the test.
+    let settings = FlowControllerSettings::fixed_rate(1000, Duration::from_secs(3600));
+    let controllers = FlowControllers::new(
+      &FlowBudgets::new(&[("slow".to_string(), settings)]),
+      &Arc::new(Transports::new(Vec::new())),
+    );
+    let controller = controllers.get("slow").unwrap();
+    let mut controller = controller.borrow_mut();
+    assert_eq!(controller.queue_limit, MIN_WRITER_QUEUE_BYTES);
+
+    let writer_a = GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED);
+    let writer_b = GUID::dummy_test_guid(EntityKind::WRITER_NO_KEY_USER_DEFINED);
+    let destination = Destination::Locator(Locator::Invalid);
+    let message = || GatherBuffer::from(vec![0_u8; 30_000]);
+
+    // The first one is sent with the full budget.
+    controller.send(writer_a, message(), destination.clone());
+    controller.send(writer_a, message(), destination.clone());
+    controller.send(writer_a, message(), destination.clone());
+    assert!(!controller.is_backlogged(writer_a));
+    controller.send(writer_a, message(), destination.clone());
+    assert!(controller.is_backlogged(writer_a));
+    assert!(!controller.is_backlogged(writer_b));
+
+    // Heartbeats go ahead of the queue, but use the budget.
+    controller.send_now(&GatherBuffer::from(vec![0_u8; 100]), &destination);
+    assert!(controller.budget.lock().unwrap().available < 0.0);
+
+    // Sending from the queue resumes the Writer.
+    controller.budget.lock().unwrap().available = 30_000.0;
+    controller.drain();
+    assert!(!controller.is_backlogged(writer_a));
+    assert!(controller.resumed.contains(&writer_a));
+  }
+
+  #[test]
+  fn shared_budget() {
+    let settings = FlowControllerSettings::fixed_rate(1000, Duration::from_secs(3600));
+    let budgets = FlowBudgets::new(&[("slow".to_string(), settings)]);
+    let transports = Arc::new(Transports::new(Vec::new()));
+    // Two event loop threads
+    let controllers_1 = FlowControllers::new(&budgets, &transports);
+    let controllers_2 = FlowControllers::new(&budgets, &transports);
+    let writer = GUID::dummy_test_guid(EntityKind::WRITER_WITH_KEY_USER_DEFINED);
+    let destination = Destination::Locator(Locator::Invalid);
+
+    let controller_1 = controllers_1.get("slow").unwrap();
+    let controller_2 = controllers_2.get("slow").unwrap();
+    controller_1.borrow_mut().send(
+      writer,
+      GatherBuffer::from(vec![0_u8; 600]),
+      destination.clone(),
+    );
+    // The other thread has only what is left.
+    controller_2
+      .borrow_mut()
+      .send(writer, GatherBuffer::from(vec![0_u8; 600]), destination);
+    assert_eq!(controller_2.borrow().queued_bytes.get(&writer), Some(&600));
+    assert!(controller_1.borrow().queued_bytes.is_empty());
+  }
+}
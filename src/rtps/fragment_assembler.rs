@@ -22,6 +22,7 @@ use crate::{
 // This is for the assembly of a single object
 struct AssemblyBuffer {
   buffer_bytes: BytesMut,
+  fragment_size: u16,
   #[allow(dead_code)] // This module is still WiP
   fragment_count: usize,
   received_bitmap: BitVec,
@@ -55,6 +56,7 @@ impl AssemblyBuffer {
 
     Self {
       buffer_bytes,
+      fragment_size,
       fragment_count,
       received_bitmap: BitVec::from_elem(fragment_count, false),
       created_time: now,
@@ -62,9 +64,14 @@ impl AssemblyBuffer {
     }
   }
 
-  pub fn insert_frags(&mut self, datafrag: &DataFrag, frag_size: u16) {
-    // TODO: Sanity checks? E.g. datafrag.fragment_size == frag_size
-    let frag_size = usize::from(frag_size); // - payload_header;
+  // Is the DataFrag fragmented the same way as the fragments we already have?
+  fn matches(&self, datafrag: &DataFrag) -> bool {
+    self.fragment_size == datafrag.fragment_size
+      && self.buffer_bytes.len() == datafrag.data_size as usize
+  }
+
+  pub fn insert_frags(&mut self, datafrag: &DataFrag) {
+    let frag_size = usize::from(self.fragment_size); // - payload_header;
     let frags_in_subm = usize::from(datafrag.fragments_in_submessage);
     let fragment_starting_num: usize = u32::from(datafrag.fragment_starting_num)
       .try_into()
@@ -126,8 +133,12 @@ impl AssemblyBuffer {
 
 // Assembles fragments from a single (remote) Writer
 // So there is only one sequence of SNs
+//
+// The fragment size is taken from the first received fragment of each sample.
+// A writer should use one constant fragment size, but RustDDS writers reduce it
+// when a reader with a smaller limit is matched, so different samples may have
+// different fragment sizes.
 pub(crate) struct FragmentAssembler {
-  fragment_size: u16, // number of bytes per fragment. Each writer must select one constant value.
   assembly_buffers: BTreeMap<SequenceNumber, AssemblyBuffer>,
 }
 
@@ -140,10 +151,8 @@ impl fmt::Debug for FragmentAssembler {
 }
 
 impl FragmentAssembler {
-  pub fn new(fragment_size: u16) -> Self {
-    debug!("new FragmentAssember. frag_size = {}", fragment_size);
+  pub fn new() -> Self {
     Self {
-      fragment_size,
       assembly_buffers: BTreeMap::new(),
     }
   }
@@ -155,14 +164,24 @@ impl FragmentAssembler {
     flags: BitFlags<DATAFRAG_Flags>,
   ) -> Option<DDSData> {
     let writer_sn = datafrag.writer_sn;
-    let frag_size = self.fragment_size;
 
     let abuf = self
       .assembly_buffers
       .entry(datafrag.writer_sn)
       .or_insert_with(|| AssemblyBuffer::new(datafrag));
 
-    abuf.insert_frags(datafrag, frag_size);
+    if !abuf.matches(datafrag) {
+      // The writer changed its fragment size while we were assembling. The
+      // fragments received so far cannot be combined with the new ones, so
+      // start over.
+      debug!(
+        "new_datafrag: Fragment size of {:?} changed to {}. Restarting assembly.",
+        writer_sn, datafrag.fragment_size
+      );
+      *abuf = AssemblyBuffer::new(datafrag);
+    }
+
+    abuf.insert_frags(datafrag);
 
     if abuf.is_complete() {
       debug!("new_datafrag: COMPLETED FRAGMENT");
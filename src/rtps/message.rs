@@ -24,6 +24,7 @@ use crate::{
     },
     vendor_id::VendorId,
   },
+  network::transport::GatherBuffer,
   rtps::{writer::Writer as RtpsWriter, Submessage, SubmessageBody},
   structure::{
     cache_change::CacheChange,
@@ -36,6 +37,9 @@ use crate::{
   RepresentationIdentifier,
 };
 
+pub(crate) const RTPS_MESSAGE_HEADER_SIZE: usize = 20;
+const SUBMESSAGE_HEADER_SIZE: usize = 4;
+
 #[derive(Debug, Clone)]
 pub struct Message {
   pub header: Header,
@@ -183,6 +187,14 @@ impl Message {
           ))
         }
 
+        SubmessageKind::HEARTBEAT_FRAG => {
+          let f = BitFlags::<HEARTBEATFRAG_Flags>::from_bits_truncate(sub_header.flags);
+          mk_w_subm(WriterSubmessage::HeartbeatFrag(
+            HeartbeatFrag::read_from_buffer_with_ctx(e, &sub_content_buffer)?,
+            f,
+          ))
+        }
+
         // interpreter submessages
         SubmessageKind::INFO_DST => {
           let f = BitFlags::<INFODESTINATION_Flags>::from_bits_truncate(sub_header.flags);
@@ -290,6 +302,29 @@ impl Message {
       submessages: vec![],
     }
   }
+
+  /// Serializes the message for sending with gather I/O. The payloads of
+  /// DATA and DATA_FRAG submessages are not copied, but referenced as
+  /// separate segments.
+  pub fn write_to_gather_buffer(
+    &self,
+    endianness: Endianness,
+  ) -> Result<GatherBuffer, speedy::Error> {
+    let mut gather_buffer = GatherBuffer::new();
+    let mut head = self.header.write_to_vec_with_ctx(endianness)?;
+    for submessage in &self.submessages {
+      match submessage.split_payload() {
+        Some((stripped, payload)) => {
+          stripped.write_to_stream_with_ctx(endianness, &mut head)?;
+          gather_buffer.push(Bytes::from(std::mem::take(&mut head)));
+          gather_buffer.push(payload);
+        }
+        None => submessage.write_to_stream_with_ctx(endianness, &mut head)?,
+      }
+    }
+    gather_buffer.push(Bytes::from(head));
+    Ok(gather_buffer)
+  }
 }
 
 impl Default for Message {
@@ -615,8 +650,12 @@ impl MessageBuilder {
     set_final_flag: bool,
     set_liveliness_flag: bool,
   ) -> Self {
-    let first = writer.first_change_sequence_number;
-    let last = writer.last_change_sequence_number;
+    // first = last + 1 announces that there is nothing available.
+    let last = writer.announced_last_sequence_number();
+    let first = min(
+      writer.first_change_sequence_number,
+      last + SequenceNumber::from(1),
+    );
 
     let heartbeat = Heartbeat {
       reader_id: reader_entityid,
@@ -643,6 +682,49 @@ impl MessageBuilder {
     self
   }
 
+  pub fn heartbeat_frag_msg(
+    mut self,
+    writer: &RtpsWriter,
+    reader_entityid: EntityId,
+    writer_sn: SequenceNumber,
+    last_fragment_num: FragmentNumber,
+    count: i32,
+  ) -> Self {
+    let heartbeat_frag = HeartbeatFrag {
+      reader_id: reader_entityid,
+      writer_id: writer.entity_id(),
+      writer_sn,
+      last_fragment_num,
+      count,
+    };
+    let flags = BitFlags::<HEARTBEATFRAG_Flags>::from_endianness(writer.endianness);
+    self
+      .submessages
+      .push(heartbeat_frag.create_submessage(flags));
+    self
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.submessages.is_empty()
+  }
+
+  /// Serialized size of the Message that would be built, including the RTPS
+  /// header.
+  pub fn len_serialized(&self) -> usize {
+    RTPS_MESSAGE_HEADER_SIZE
+      + self
+        .submessages
+        .iter()
+        .map(|s| SUBMESSAGE_HEADER_SIZE + usize::from(s.header.content_length))
+        .sum::<usize>()
+  }
+
+  /// Appends the submessages of `other` after our submessages.
+  pub fn append(mut self, mut other: Self) -> Self {
+    self.submessages.append(&mut other.submessages);
+    self
+  }
+
   pub fn add_header_and_build(self, guid_prefix: GuidPrefix) -> Message {
     Message {
       header: Header {
@@ -663,6 +745,106 @@ mod tests {
   use speedy::Writable;
 
   use super::*;
+  use crate::{
+    dds::with_key::datawriter::WriteOptions,
+    messages::submessages::elements::serialized_payload::SerializedPayload,
+  };
+
+  #[test]
+  fn message_builder_len_serialized() {
+    let guid = GUID::dummy_test_guid(EntityKind::WRITER_NO_KEY_USER_DEFINED);
+    let mut builder = MessageBuilder::new();
+    assert!(builder.is_empty());
+
+    for (i, payload_len) in [0, 5, 64, 301].into_iter().enumerate() {
+      let cache_change = CacheChange::new(
+        guid,
+        SequenceNumber::from(i as i64 + 1),
+        WriteOptions::default(),
+        DDSData::new(SerializedPayload::new(
+          RepresentationIdentifier::CDR_LE,
+          vec![0xaa; payload_len],
+        )),
+      );
+      let sample = MessageBuilder::new()
+        .ts_msg(Endianness::LittleEndian, Some(Timestamp::now()))
+        .data_msg(
+          &cache_change,
+          EntityId::UNKNOWN,
+          guid.entity_id,
+          Endianness::LittleEndian,
+        )
+        .ts_msg(Endianness::LittleEndian, None);
+      builder = builder.append(sample);
+    }
+    builder = builder.dst_submessage(Endianness::LittleEndian, GuidPrefix::UNKNOWN);
+    assert!(!builder.is_empty());
+
+    let expected_len = builder.len_serialized();
+    let message = builder.add_header_and_build(GuidPrefix::UNKNOWN);
+    assert_eq!(message.submessages.len(), 13);
+    assert_eq!(
+      message
+        .write_to_vec_with_ctx(Endianness::LittleEndian)
+        .unwrap()
+        .len(),
+      expected_len
+    );
+  }
+
+  #[test]
+  fn gather_buffer_references_payloads() {
+    let guid = GUID::dummy_test_guid(EntityKind::WRITER_NO_KEY_USER_DEFINED);
+    let cache_change = CacheChange::new(
+      guid,
+      SequenceNumber::from(1),
+      WriteOptions::default(),
+      DDSData::new(SerializedPayload::new(
+        RepresentationIdentifier::CDR_LE,
+        (0..=255).collect(),
+      )),
+    );
+    let payload = match &cache_change.data_value {
+      DDSData::Data { serialized_payload } => serialized_payload.value.clone(),
+      _ => unreachable!(),
+    };
+    let message = MessageBuilder::new()
+      .ts_msg(Endianness::LittleEndian, Some(Timestamp::now()))
+      .data_msg(
+        &cache_change,
+        EntityId::UNKNOWN,
+        guid.entity_id,
+        Endianness::LittleEndian,
+      )
+      .data_frag_msg(
+        &cache_change,
+        EntityId::UNKNOWN,
+        guid.entity_id,
+        FragmentNumber::new(2),
+        100,
+        260,
+        Endianness::LittleEndian,
+      )
+      .dst_submessage(Endianness::LittleEndian, GuidPrefix::UNKNOWN)
+      .add_header_and_build(guid.prefix);
+
+    let gather_buffer = message
+      .write_to_gather_buffer(Endianness::LittleEndian)
+      .unwrap();
+    assert_eq!(
+      gather_buffer.to_bytes(),
+      message
+        .write_to_vec_with_ctx(Endianness::LittleEndian)
+        .unwrap()
+    );
+    // head, DATA payload, middle, DATA_FRAG payload, tail. The fragment
+    // payload is offset by the 4-byte SerializedPayload header.
+    let segments = gather_buffer.segments();
+    assert_eq!(segments.len(), 5);
+    assert_eq!(segments[1].as_ptr(), payload.as_ptr());
+    assert_eq!(segments[3].as_ptr(), payload[96..].as_ptr());
+    assert_eq!(segments[3].len(), 100);
+  }
 
   #[test]
 
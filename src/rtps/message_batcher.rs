@@ -0,0 +1,215 @@
+//! Flush timing of batching Writers, and combining their batches into shared
+//! RTPS messages. See [`Batching`](crate::policy::Batching).
+
+use std::{io, sync::Arc, time::Duration};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use mio_08::{Interest, Registry};
+
+use crate::{
+  mio_source::timer::Timer,
+  network::{
+    constant::BATCH_FLUSH_TIMER_TOKEN,
+    transport::{GatherBuffer, Transports},
+  },
+  rtps::{flow_controller::Destination, message::RTPS_MESSAGE_HEADER_SIZE},
+  structure::guid::EntityId,
+};
+
+// INFO_TS with the Invalidate flag, little endian. This separates combined
+// batches, so that a timestamp in one batch does not apply to the next.
+const INFO_TS_INVALIDATE: [u8; 4] = [0x09, 0x03, 0x00, 0x00];
+
+// Batches of several Writers to the same destination
+struct CombinedMessage {
+  destination: Destination,
+  buffer: Vec<u8>,
+  max_bytes: usize,
+}
+
+// Shared by the event loop and the batching Writers in the event loop thread.
+pub(crate) struct MessageBatcher {
+  // Batch flush timeouts of Writers. Writer timers are too coarse for this.
+  flush_timer: Timer<EntityId>,
+  combined: Vec<CombinedMessage>,
+  transports: Arc<Transports>,
+}
+
+impl MessageBatcher {
+  pub fn new(transports: Arc<Transports>) -> Self {
+    Self {
+      flush_timer: Timer::default(),
+      combined: Vec::new(),
+      transports,
+    }
+  }
+
+  pub fn register(&mut self, registry: &Registry) -> io::Result<()> {
+    registry.register(
+      &mut self.flush_timer,
+      BATCH_FLUSH_TIMER_TOKEN,
+      Interest::READABLE,
+    )
+  }
+
+  pub fn schedule_flush(&mut self, writer: EntityId, delay: Duration) {
+    self.flush_timer.set_timeout(delay, writer);
+  }
+
+  // Writers whose batch should be flushed now
+  pub fn expired_flushes(&mut self) -> Vec<EntityId> {
+    let mut writers = Vec::new();
+    while let Some(writer) = self.flush_timer.poll() {
+      writers.push(writer);
+    }
+    writers
+  }
+
+  // Add a batch message of a Writer. It is sent together with batches of other
+  // Writers to the same destination, as long as the combined message fits in
+  // the `max_bytes` of all of them.
+  pub fn combine(&mut self, buffer: &[u8], destination: Destination, max_bytes: usize) {
+    let submessages = &buffer[RTPS_MESSAGE_HEADER_SIZE..];
+    let index = match self
+      .combined
+      .iter()
+      .position(|c| c.destination == destination)
+    {
+      Some(index) => index,
+      None => {
+        self.combined.push(CombinedMessage {
+          destination,
+          buffer: buffer.to_vec(),
+          max_bytes,
+        });
+        return;
+      }
+    };
+    let combined = &mut self.combined[index];
+    let limit = combined.max_bytes.min(max_bytes);
+    if combined.buffer.len() + INFO_TS_INVALIDATE.len() + submessages.len() <= limit {
+      combined.buffer.extend_from_slice(&INFO_TS_INVALIDATE);
+      combined.buffer.extend_from_slice(submessages);
+      combined.max_bytes = limit;
+    } else {
+      // Does not fit. Send what we have, and start over.
+      let full = std::mem::replace(&mut combined.buffer, buffer.to_vec());
+      combined.max_bytes = max_bytes;
+      self.send(full, &destination);
+    }
+  }
+
+  // Send the combined messages. This is called after each round of events, so
+  // batches flushed in the same round are combined.
+  pub fn send_combined(&mut self) {
+    for combined in std::mem::take(&mut self.combined) {
+      self.send(combined.buffer, &combined.destination);
+    }
+  }
+
+  fn send(&self, buffer: Vec<u8>, destination: &Destination) {
+    let message = GatherBuffer::from(buffer);
+    match destination {
+      Destination::Locator(locator) => self.transports.send_to_locator(&message, locator),
+      Destination::LocatorList(locators) => {
+        self.transports.send_to_locator_list(&message, locators);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Mutex;
+
+  use bytes::Bytes;
+  use mio_08::Token;
+  use speedy::Writable;
+
+  use super::*;
+  use crate::{
+    messages::submessages::submessages::InterpreterSubmessage,
+    network::transport::{Transport, TransportLocators},
+    rtps::{Message, MessageBuilder, SubmessageBody},
+    structure::{guid::GuidPrefix, locator::Locator, time::Timestamp},
+  };
+
+  // Sent messages and their destinations
+  type SentMessages = Arc<Mutex<Vec<(Vec<u8>, Locator)>>>;
+
+  struct RecordingTransport {
+    sent: SentMessages,
+  }
+
+  impl Transport for RecordingTransport {
+    fn can_send_to(&self, _locator: &Locator) -> bool {
+      true
+    }
+    fn send_to_locator(&mut self, buffer: &[u8], locator: &Locator) -> io::Result<()> {
+      self.sent.lock().unwrap().push((buffer.to_vec(), *locator));
+      Ok(())
+    }
+    fn announced_locators(&self) -> TransportLocators {
+      TransportLocators::default()
+    }
+    fn register(&mut self, _registry: &Registry, _token: Token) -> io::Result<()> {
+      Ok(())
+    }
+    fn receive(&mut self) -> Vec<Bytes> {
+      vec![]
+    }
+  }
+
+  fn batch_message(timestamp: Option<Timestamp>) -> Vec<u8> {
+    let message = MessageBuilder::new()
+      .ts_msg(speedy::Endianness::LittleEndian, timestamp)
+      .add_header_and_build(GuidPrefix::UNKNOWN);
+    message
+      .write_to_vec_with_ctx(speedy::Endianness::LittleEndian)
+      .unwrap()
+  }
+
+  #[test]
+  fn combine_batches_per_destination() {
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let transports = Arc::new(Transports::new(vec![Box::new(RecordingTransport {
+      sent: Arc::clone(&sent),
+    })]));
+    let mut batcher = MessageBatcher::new(transports);
+    let loc_a = Locator::from("127.0.0.1:7410".parse::<std::net::SocketAddr>().unwrap());
+    let loc_b = Locator::from("127.0.0.1:7411".parse::<std::net::SocketAddr>().unwrap());
+    let ts = Some(Timestamp::now());
+    let message = batch_message(ts);
+    let len = message.len();
+
+    batcher.combine(&message, Destination::Locator(loc_a), 1000);
+    batcher.combine(&message, Destination::Locator(loc_b), 1000);
+    batcher.combine(&message, Destination::Locator(loc_a), 1000);
+    // Does not fit into the limit of the last one
+    batcher.combine(&message, Destination::Locator(loc_a), len + 10);
+    assert_eq!(sent.lock().unwrap().len(), 1);
+    batcher.send_combined();
+
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent.len(), 3);
+    let (first, dest) = &sent[0];
+    assert_eq!(*dest, loc_a);
+    assert_eq!(
+      first.len(),
+      2 * len - RTPS_MESSAGE_HEADER_SIZE + INFO_TS_INVALIDATE.len()
+    );
+    let submessages: Vec<_> = Message::read_from_buffer(&Bytes::copy_from_slice(first))
+      .unwrap()
+      .submessages()
+      .into_iter()
+      .map(|s| match s.body {
+        SubmessageBody::Interpreter(InterpreterSubmessage::InfoTimestamp(ts, _)) => ts.timestamp,
+        other => panic!("Unexpected submessage {other:?}"),
+      })
+      .collect();
+    assert_eq!(submessages, vec![ts, None, ts]);
+    assert!(sent[1..].iter().all(|(buffer, _)| buffer.len() == len));
+    assert!(sent[1..].iter().any(|(_, dest)| *dest == loc_b));
+  }
+}
@@ -1,6 +1,5 @@
 use std::collections::{btree_map::Entry, BTreeMap};
 
-use mio_extras::{channel as mio_channel, channel::TrySendError};
 use log::{debug, info, trace, warn};
 use bytes::Bytes;
 
@@ -10,7 +9,8 @@ use crate::{
     submessages::submessages::{WriterSubmessage, *},
     vendor_id::VendorId,
   },
-  rtps::{reader::Reader, Message, SubmessageBody},
+  mio_source::{channel as mio_channel, channel::TrySendError},
+  rtps::{ping, reader::Reader, Message, SubmessageBody},
   structure::{
     entity::RTPSEntity,
     guid::{EntityId, GuidPrefix, GUID},
@@ -151,36 +151,37 @@ impl MessageReceiver {
   }
 
   pub fn handle_received_packet(&mut self, msg_bytes: &Bytes) {
+    if let Some(rtps_message) = Self::parse_packet(msg_bytes) {
+      self.handle_parsed_message(rtps_message);
+    }
+  }
+
+  // Deserializes a received packet. The sharded event loop parses once, and
+  // then dispatches the submessages.
+  pub fn parse_packet(msg_bytes: &Bytes) -> Option<Message> {
     // Check for RTPS ping message. At least RTI implementation sends these.
-    // What should we do with them? The spec does not say.
+    // The event loop answers them, because the answer goes to the source
+    // address.
     if msg_bytes.len() < RTPS_MESSAGE_HEADER_SIZE {
-      if msg_bytes.len() >= 16
-        && msg_bytes[0..4] == b"RTPS"[..]
-        && msg_bytes[9..16] == b"DDSPING"[..]
-      {
-        // TODO: Add some sensible ping message handling here.
-        info!("Received RTPS PING. Do not know how to respond.");
-        debug!("Data was {:?}", &msg_bytes);
+      if ping::is_ping(msg_bytes) {
+        debug!("Received RTPS PING. Not a message.");
       } else {
         warn!("Message is shorter than header. Cannot deserialize.");
         debug!("Data was {:?}", &msg_bytes);
       }
-      return;
+      return None;
     }
 
     // call Speedy reader
     // Bytes .clone() is cheap, so no worries
-    let rtps_message = match Message::read_from_buffer(msg_bytes) {
-      Ok(m) => m,
+    match Message::read_from_buffer(msg_bytes) {
+      Ok(m) => Some(m),
       Err(speedy_err) => {
         warn!("RTPS deserialize error {:?}", speedy_err);
         debug!("Data was {:?}", msg_bytes);
-        return;
+        None
       }
-    };
-
-    // And process message
-    self.handle_parsed_message(rtps_message);
+    }
   }
 
   // This is also called directly from dp_event_loop in case of loopback messages.
@@ -266,14 +267,14 @@ impl MessageReceiver {
             reader.handle_heartbeat_msg(
               &heartbeat,
               flags.contains(HEARTBEAT_Flags::Final),
-              mr_state.clone(),
+              &mr_state,
             );
           }
         } else if let Some(target_reader) = self.reader_mut(heartbeat.reader_id) {
           target_reader.handle_heartbeat_msg(
             &heartbeat,
             flags.contains(HEARTBEAT_Flags::Final),
-            mr_state,
+            &mr_state,
           );
         }
       }
@@ -355,7 +356,19 @@ impl MessageReceiver {
         }
       }
 
-      ReaderSubmessage::NackFrag(_, _) => {}
+      ReaderSubmessage::NackFrag(nackfrag, _) => {
+        // Handled by the Writer, like AckNack
+        match self
+          .acknack_sender
+          .try_send((self.source_guid_prefix, AckSubmessage::NackFrag(nackfrag)))
+        {
+          Ok(_) => (),
+          Err(TrySendError::Full(_)) => {
+            info!("AckNack pipe full. Looks like I am very busy. Discarding NackFrag.");
+          }
+          Err(e) => warn!("AckNack pipe fail: {:?}", e),
+        }
+      }
     }
   }
 
@@ -432,19 +445,16 @@ impl MessageReceiver {
         .map(Reader::notify_cache_change);
     }
   }
-
-  // sends 0 seqnum acknacks for those writer that haven't had any action
-  pub fn send_preemptive_acknacks(&mut self) {
-    for reader in self.available_readers.values_mut() {
-      reader.send_preemptive_acknacks();
-    }
-  }
 } // impl messageReceiver
 
 #[derive(Debug, Clone)]
 pub struct MessageReceiverState {
   pub source_guid_prefix: GuidPrefix,
+  // From INFO_REPLY. Readers respond to the locators of the writer proxy
+  // instead, so these are read only in tests.
+  #[allow(dead_code)]
   pub unicast_reply_locator_list: Vec<Locator>,
+  #[allow(dead_code)]
   pub multicast_reply_locator_list: Vec<Locator>,
   pub source_timestamp: Option<Timestamp>,
 }
@@ -462,15 +472,11 @@ impl Default for MessageReceiverState {
 
 #[cfg(test)]
 mod tests {
-  use std::{
-    rc::Rc,
-    sync::{Arc, Mutex, RwLock},
-  };
+  use std::sync::{Arc, Mutex, RwLock};
 
   use speedy::{Readable, Writable};
   use log::info;
   use serde::{Deserialize, Serialize};
-  use mio_extras::channel as mio_channel;
 
   use crate::{
     dds::{
@@ -481,7 +487,8 @@ mod tests {
     },
     messages::header::Header,
     mio_source,
-    network::udp_sender::UDPSender,
+    mio_source::channel as mio_channel,
+    network::transport::Transports,
     rtps::reader::ReaderIngredients,
     serialization::cdr_deserializer::deserialize_from_little_endian,
     structure::{dds_cache::DDSCache, guid::EntityKind},
@@ -564,8 +571,8 @@ mod tests {
 
     let mut new_reader = Reader::new(
       reader_ing,
-      Rc::new(UDPSender::new_with_random_port().unwrap()),
-      mio_extras::timer::Builder::default().build(),
+      Arc::new(Transports::new(Vec::new())),
+      crate::mio_source::timer::Timer::default(),
     );
 
     // Add info of the writer to the reader
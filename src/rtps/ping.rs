@@ -0,0 +1,279 @@
+//! RTPS PING
+//!
+//! Some DDS implementations, at least RTI Connext, send short "ping" packets
+//! to check that a locator is reachable. A ping is an RTPS header without
+//! GUID prefix, followed by the text "NDDSPING", and it is not a valid RTPS
+//! message. It carries no reply locators, so it is answered to the address it
+//! came from. The answer is a minimal RTPS message: a header and INFO_TS. The
+//! header tells who answered.
+//!
+//! The answer is larger than the ping, and the source address of a UDP ping
+//! can be forged. So that a participant cannot be used to amplify traffic
+//! towards someone else, each source gets at most one answer in 250 ms, and
+//! all sources together at most 50 answers per second.
+//!
+//! [`ping`] sends pings and waits for the answer, to check DDS-level
+//! reachability of a participant.
+
+use std::{
+  collections::HashMap,
+  io,
+  net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+  time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use speedy::{Endianness, Readable, Writable};
+
+use crate::{
+  messages::{
+    header::Header, protocol_id::ProtocolId, protocol_version::ProtocolVersion, vendor_id::VendorId,
+  },
+  rtps::{message::RTPS_MESSAGE_HEADER_SIZE, MessageBuilder},
+  structure::{guid::GuidPrefix, locator::Locator, time::Timestamp},
+};
+
+const PING_SIZE: usize = 16;
+const PING_TEXT: &[u8] = b"NDDSPING";
+
+// Pings are resent this often, until the answer arrives.
+const PING_RESEND_PERIOD: Duration = Duration::from_millis(500);
+
+// Shortest time between answers to the same source. This is shorter than
+// PING_RESEND_PERIOD, so that every ping of `ping` is answered.
+const PING_ANSWER_INTERVAL: Duration = Duration::from_millis(250);
+// Answers to all sources together
+const PING_ANSWERS_PER_SECOND: u32 = 50;
+// Sources remembered at most. While this many have been answered within
+// PING_ANSWER_INTERVAL, new sources are not answered.
+const PING_SOURCES_MAX: usize = 1024;
+
+pub(crate) fn is_ping(packet: &[u8]) -> bool {
+  packet.len() >= PING_SIZE
+    && packet.len() < RTPS_MESSAGE_HEADER_SIZE
+    && packet[0..4] == b"RTPS"[..]
+    && packet[9..16] == PING_TEXT[1..]
+}
+
+pub(crate) fn ping_message() -> Vec<u8> {
+  let mut ping = Vec::with_capacity(PING_SIZE);
+  ping.extend_from_slice(b"RTPS");
+  let ProtocolVersion { major, minor } = ProtocolVersion::THIS_IMPLEMENTATION;
+  ping.extend_from_slice(&[major, minor]);
+  ping.extend_from_slice(&VendorId::THIS_IMPLEMENTATION.as_bytes());
+  ping.extend_from_slice(PING_TEXT);
+  ping
+}
+
+// Answer to a ping from the participant `guid_prefix`
+pub(crate) fn ping_reply(guid_prefix: GuidPrefix) -> Bytes {
+  let message = MessageBuilder::new()
+    .ts_msg(Endianness::LittleEndian, Some(Timestamp::now()))
+    .add_header_and_build(guid_prefix);
+  Bytes::from(
+    message
+      .write_to_vec_with_ctx(Endianness::LittleEndian)
+      .unwrap(),
+  )
+}
+
+// Decides which pings are answered. See the module documentation.
+#[derive(Debug)]
+pub(crate) struct PingLimiter {
+  last_answer: HashMap<Locator, Instant>,
+  second_start: Instant,
+  answers_in_second: u32,
+}
+
+impl PingLimiter {
+  pub fn new(now: Instant) -> Self {
+    Self {
+      last_answer: HashMap::new(),
+      second_start: now,
+      answers_in_second: 0,
+    }
+  }
+
+  // Should a ping from `source` be answered now?
+  pub fn allow(&mut self, source: Locator, now: Instant) -> bool {
+    if now.saturating_duration_since(self.second_start) >= Duration::from_secs(1) {
+      self.second_start = now;
+      self.answers_in_second = 0;
+    }
+    if self.answers_in_second >= PING_ANSWERS_PER_SECOND {
+      return false;
+    }
+    let recent =
+      |answered: &Instant| now.saturating_duration_since(*answered) < PING_ANSWER_INTERVAL;
+    if self.last_answer.get(&source).map_or(false, recent) {
+      return false;
+    }
+    if self.last_answer.len() >= PING_SOURCES_MAX {
+      self.last_answer.retain(|_, answered| recent(answered));
+      if self.last_answer.len() >= PING_SOURCES_MAX {
+        return false;
+      }
+    }
+    self.last_answer.insert(source, now);
+    self.answers_in_second += 1;
+    true
+  }
+}
+
+/// Answer to [`ping`]
+#[derive(Debug, Clone)]
+pub struct PingReply {
+  /// Address that answered. Normally this is the pinged address.
+  pub source: SocketAddr,
+  /// From sending the first ping to receiving the answer
+  pub round_trip_time: Duration,
+  /// The participant that answered
+  pub guid_prefix: GuidPrefix,
+  pub vendor_id: VendorId,
+  pub protocol_version: ProtocolVersion,
+}
+
+/// Pings an RTPS participant over UDP, and waits for the answer at most
+/// `timeout`.
+///
+/// `address` is a unicast locator of the participant, e.g. the metatraffic
+/// unicast locator announced in discovery. Any RTPS message coming back to the
+/// ping socket is taken as the answer, so this works also with
+/// implementations that answer pings with other messages. Returns
+/// [`TimedOut`](io::ErrorKind::TimedOut), if nothing arrives in time.
+pub fn ping(address: SocketAddr, timeout: Duration) -> io::Result<PingReply> {
+  let bind_address = if address.is_ipv4() {
+    SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+  } else {
+    SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+  };
+  let socket = UdpSocket::bind(bind_address)?;
+  let ping = ping_message();
+  let start = Instant::now();
+  let deadline = start + timeout;
+  let mut buffer = [0; 64 * 1024];
+
+  loop {
+    let now = Instant::now();
+    if now >= deadline {
+      return Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("No answer from {address} in {timeout:?}"),
+      ));
+    }
+    socket.send_to(&ping, address)?;
+
+    let resend_at = (now + PING_RESEND_PERIOD).min(deadline);
+    loop {
+      let now = Instant::now();
+      if now >= resend_at {
+        break;
+      }
+      socket.set_read_timeout(Some(resend_at - now))?;
+      let (len, source) = match socket.recv_from(&mut buffer) {
+        Ok(received) => received,
+        Err(e)
+          if matches!(
+            e.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+          ) =>
+        {
+          break;
+        }
+        Err(e) => return Err(e),
+      };
+      match parse_reply(&buffer[..len]) {
+        Some(header) => {
+          return Ok(PingReply {
+            source,
+            round_trip_time: start.elapsed(),
+            guid_prefix: header.guid_prefix,
+            vendor_id: header.vendor_id,
+            protocol_version: header.protocol_version,
+          })
+        }
+        None => debug!("ping: Not an RTPS message from {source}. Ignoring."),
+      }
+    }
+  }
+}
+
+fn parse_reply(packet: &[u8]) -> Option<Header> {
+  if packet.len() < RTPS_MESSAGE_HEADER_SIZE {
+    return None;
+  }
+  Header::read_from_buffer(&packet[..RTPS_MESSAGE_HEADER_SIZE])
+    .ok()
+    .filter(|header| header.protocol_id == ProtocolId::PROTOCOL_RTPS)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    network::constant::DISCOVERY_LISTENER_TOKEN, rtps::Message, structure::locator::Locator,
+    DomainParticipant, RTPSEntity,
+  };
+
+  #[test]
+  fn ping_and_reply_format() {
+    let ping = ping_message();
+    assert_eq!(ping.len(), PING_SIZE);
+    assert!(is_ping(&ping));
+    assert!(!is_ping(b"RTPS\x02\x04\x01\x12NDDSPONG"));
+
+    let guid_prefix = GuidPrefix::new(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+    let reply = ping_reply(guid_prefix);
+    assert!(!is_ping(&reply));
+    let header = parse_reply(&reply).unwrap();
+    assert_eq!(header.guid_prefix, guid_prefix);
+    assert_eq!(header.vendor_id, VendorId::THIS_IMPLEMENTATION);
+    // The answer is a valid RTPS message.
+    let message = Message::read_from_buffer(&reply).unwrap();
+    assert_eq!(message.submessages.len(), 1);
+  }
+
+  #[test]
+  fn participant_answers_ping() {
+    let participant = DomainParticipant::new(63).unwrap();
+    let address = participant
+      .self_locators()
+      .remove(&DISCOVERY_LISTENER_TOKEN)
+      .unwrap_or_default()
+      .into_iter()
+      .filter_map(|locator| match locator {
+        Locator::UdpV4(address) => Some(SocketAddr::from(address)),
+        _ => None,
+      })
+      .next()
+      .expect("No UDPv4 discovery locator");
+
+    let reply = ping(address, Duration::from_secs(5)).unwrap();
+    assert_eq!(reply.guid_prefix, participant.guid().prefix);
+    assert_eq!(reply.vendor_id, VendorId::THIS_IMPLEMENTATION);
+    assert!(reply.round_trip_time < Duration::from_secs(5));
+  }
+
+  #[test]
+  fn ping_answer_limits() {
+    let start = Instant::now();
+    let mut limiter = PingLimiter::new(start);
+    let source = |i: u16| Locator::from(SocketAddr::from((Ipv4Addr::LOCALHOST, 7400 + i)));
+
+    assert!(limiter.allow(source(0), start));
+    assert!(!limiter.allow(source(0), start + Duration::from_millis(100)));
+    assert!(limiter.allow(source(1), start + Duration::from_millis(100)));
+    // Resent pings are answered.
+    assert!(limiter.allow(source(0), start + PING_RESEND_PERIOD));
+
+    // Many sources in one second
+    let later = start + Duration::from_secs(10);
+    let answered = (0..200)
+      .filter(|i| limiter.allow(source(*i), later))
+      .count();
+    assert_eq!(answered, PING_ANSWERS_PER_SECOND as usize);
+    assert!(limiter.allow(source(199), later + Duration::from_secs(1)));
+  }
+}
@@ -1,17 +1,16 @@
 use std::{
-  collections::BTreeMap,
+  cmp::min,
+  collections::{BTreeMap, BTreeSet},
   fmt, iter,
-  rc::Rc,
-  sync::{Arc, Mutex, MutexGuard},
+  sync::{Arc, Mutex, RwLock, RwLockWriteGuard},
   task::Waker,
   time::Duration as StdDuration,
 };
 
-use mio_06::Token;
-use mio_extras::{channel as mio_channel, timer::Timer};
+use mio_08::Token;
 use log::{debug, error, info, trace, warn};
 use enumflags2::BitFlags;
-use speedy::{Endianness, Writable};
+use speedy::Endianness;
 
 use crate::{
   dds::{
@@ -34,7 +33,8 @@ use crate::{
     vendor_id::VendorId,
   },
   mio_source,
-  network::udp_sender::UDPSender,
+  mio_source::{channel as mio_channel, timer::Timer},
+  network::transport::Transports,
   rtps::{message_receiver::MessageReceiverState, rtps_writer_proxy::RtpsWriterProxy, Message},
   structure::{
     cache_change::{CacheChange, ChangeKind},
@@ -42,7 +42,7 @@ use crate::{
     entity::RTPSEntity,
     guid::{EntityId, GuidPrefix, GUID},
     locator::Locator,
-    sequence_number::{FragmentNumberSet, SequenceNumber, SequenceNumberSet},
+    sequence_number::{FragmentNumber, FragmentNumberSet, SequenceNumber, SequenceNumberSet},
     time::Timestamp,
   },
 };
@@ -50,6 +50,8 @@ use crate::{
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum TimedEvent {
   DeadlineMissedCheck,
+  HeartbeatResponse { from_writer: GUID },
+  PreemptiveAckNack,
 }
 
 // Some pieces necessary to contruct a reader.
@@ -59,8 +61,8 @@ pub(crate) struct ReaderIngredients {
   pub notification_sender: mio_channel::SyncSender<()>,
   pub status_sender: StatusChannelSender<DataReaderStatus>,
   pub topic_name: String,
-  pub(crate) topic_cache_handle: Arc<Mutex<TopicCache>>, /* A handle to the topic cache in DDS
-                                                          * cache */
+  pub(crate) topic_cache_handle: Arc<RwLock<TopicCache>>, /* A handle to the topic cache in DDS
+                                                           * cache */
   pub qos_policy: QosPolicies,
   pub data_reader_command_receiver: mio_channel::Receiver<ReaderCommand>,
   pub(crate) data_reader_waker: Arc<Mutex<Option<Waker>>>,
@@ -88,7 +90,7 @@ pub(crate) struct Reader {
   // Should the instant be sent?
   notification_sender: mio_channel::SyncSender<()>,
   status_sender: StatusChannelSender<DataReaderStatus>,
-  udp_sender: Rc<UDPSender>,
+  transports: Arc<Transports>,
 
   is_stateful: bool, // is this StatefulReader or Statelessreader as per RTPS spec
   // Currently we support only stateful behaviour.
@@ -97,7 +99,7 @@ pub(crate) struct Reader {
   // So stateful must be true whenever we are Reliable.
   reliability: policy::Reliability,
   // Reader stores a pointer to a mutex on the topic cache
-  topic_cache: Arc<Mutex<TopicCache>>,
+  topic_cache: Arc<RwLock<TopicCache>>,
 
   #[cfg(test)]
   seqnum_instant_map: BTreeMap<SequenceNumber, Timestamp>,
@@ -108,6 +110,7 @@ pub(crate) struct Reader {
   my_guid: GUID,
 
   heartbeat_response_delay: StdDuration,
+  preemptive_acknack_period: StdDuration,
 
   // TODO: Implement (use) this
   #[allow(dead_code)]
@@ -130,11 +133,11 @@ pub(crate) struct Reader {
 impl Reader {
   pub fn new(
     i: ReaderIngredients,
-    udp_sender: Rc<UDPSender>,
-    timed_event_timer: Timer<TimedEvent>,
+    transports: Arc<Transports>,
+    mut timed_event_timer: Timer<TimedEvent>,
   ) -> Self {
     // Verify that the topic cache corresponds to the topic of the Reader
-    let topic_cache_name = i.topic_cache_handle.lock().unwrap().topic_name();
+    let topic_cache_name = i.topic_cache_handle.read().unwrap().topic_name();
     if i.topic_name != topic_cache_name {
       panic!(
         "Topic name = {} and topic cache name = {} not equal when creating a Reader",
@@ -142,16 +145,23 @@ impl Reader {
       );
     }
 
+    let timing = i.qos_policy.reliability_timing().unwrap_or_default();
+    let reliability = i
+      .qos_policy
+      .reliability() // use qos specification
+      .unwrap_or(policy::Reliability::BestEffort); // or default to BestEffort
+    let preemptive_acknack_period = timing.preemptive_acknack_period.to_std();
+    if matches!(reliability, policy::Reliability::Reliable { .. }) {
+      timed_event_timer.set_timeout(preemptive_acknack_period, TimedEvent::PreemptiveAckNack);
+    }
+
     Self {
       notification_sender: i.notification_sender,
       status_sender: i.status_sender,
-      udp_sender,
+      transports,
       is_stateful: true, // Do not change this before stateless functionality is implemented.
 
-      reliability: i
-        .qos_policy
-        .reliability() // use qos specification
-        .unwrap_or(policy::Reliability::BestEffort), // or default to BestEffort
+      reliability,
       topic_cache: i.topic_cache_handle,
       topic_name: i.topic_name,
       qos_policy: i.qos_policy,
@@ -160,7 +170,8 @@ impl Reader {
       seqnum_instant_map: BTreeMap::new(),
       my_guid: i.guid,
 
-      heartbeat_response_delay: StdDuration::new(0, 500_000_000), // 0,5sec
+      heartbeat_response_delay: timing.heartbeat_response_delay.to_std(),
+      preemptive_acknack_period,
       heartbeat_supression_duration: StdDuration::new(0, 0),
       received_hearbeat_count: 0,
       matched_writers: BTreeMap::new(),
@@ -274,6 +285,16 @@ impl Reader {
           self.handle_requested_deadline_event();
           self.set_requested_deadline_check_timer(); // re-prime timer
         }
+        TimedEvent::HeartbeatResponse {
+          from_writer: writer_guid,
+        } => self.handle_heartbeat_response_timeout(writer_guid),
+        TimedEvent::PreemptiveAckNack => {
+          self.send_preemptive_acknacks();
+          self.timed_event_timer.set_timeout(
+            self.preemptive_acknack_period,
+            TimedEvent::PreemptiveAckNack,
+          );
+        }
       }
     }
   }
@@ -310,7 +331,7 @@ impl Reader {
   // TODO Used for test/debugging purposes
   #[cfg(test)]
   pub fn history_cache_change_data(&self, sequence_number: SequenceNumber) -> Option<DDSData> {
-    let topic_cache = self.acquire_the_topic_cache_guard();
+    let topic_cache = self.topic_cache.read().unwrap();
     let cc = self
       .seqnum_instant_map
       .get(&sequence_number)
@@ -694,7 +715,7 @@ impl Reader {
     &mut self,
     heartbeat: &Heartbeat,
     final_flag_set: bool,
-    mr_state: MessageReceiverState,
+    mr_state: &MessageReceiverState,
   ) -> bool {
     let writer_guid =
       GUID::new_with_prefix_and_id(mr_state.source_guid_prefix, heartbeat.writer_id);
@@ -727,6 +748,7 @@ impl Reader {
       );
     }
 
+    let heartbeat_response_delay = self.heartbeat_response_delay;
     let writer_proxy = if let Some(wp) = self.matched_writer_mut(writer_guid) {
       wp
     } else {
@@ -734,9 +756,6 @@ impl Reader {
       return false;
     };
 
-    let mut mr_state = mr_state;
-    mr_state.unicast_reply_locator_list = writer_proxy.unicast_locator_list.clone();
-
     if heartbeat.count <= writer_proxy.received_heartbeat_count {
       // This heartbeat was already seen an processed.
       return false;
@@ -746,16 +765,56 @@ impl Reader {
     // remove fragmented changes until first_sn.
     writer_proxy.irrelevant_changes_up_to(heartbeat.first_sn);
 
-    //let received_before = writer_proxy.all_ackable_before();
+    if heartbeat_response_delay.is_zero() {
+      return self.send_heartbeat_response(writer_guid, heartbeat, final_flag_set);
+    }
+    // Respond later to the latest HEARTBEAT. Repairs or new data may arrive in
+    // the meantime. If an earlier one requested a response, it is still due.
+    let final_flag_set = final_flag_set
+      && writer_proxy
+        .delayed_heartbeat
+        .as_ref()
+        .map_or(true, |(_, final_flag)| *final_flag);
+    let previous = writer_proxy
+      .delayed_heartbeat
+      .replace((heartbeat.clone(), final_flag_set));
+    if previous.is_none() {
+      self.timed_event_timer.set_timeout(
+        heartbeat_response_delay,
+        TimedEvent::HeartbeatResponse {
+          from_writer: writer_guid,
+        },
+      );
+    }
+    false
+  } // fn
+
+  // The timer of a delayed HEARTBEAT response has expired.
+  fn handle_heartbeat_response_timeout(&mut self, writer_guid: GUID) {
+    let delayed = self
+      .matched_writer_mut(writer_guid)
+      .and_then(|wp| wp.delayed_heartbeat.take());
+    if let Some((heartbeat, final_flag_set)) = delayed {
+      self.send_heartbeat_response(writer_guid, &heartbeat, final_flag_set);
+    }
+  }
+
+  // Sends ACKNACK and NACK_FRAGs in response to HEARTBEAT, if needed. Returns
+  // true, if ACKNACK was sent.
+  fn send_heartbeat_response(
+    &mut self,
+    writer_guid: GUID,
+    heartbeat: &Heartbeat,
+    final_flag_set: bool,
+  ) -> bool {
     let reader_id = self.entity_id();
-    // this is duplicate code from above, but needed, because we need another
-    // mutable borrow. TODO: Maybe could be written in some sensible way.
     let writer_proxy = if let Some(wp) = self.matched_writer_mut(writer_guid) {
       wp
     } else {
       error!("Writer proxy disappeared 2!");
       return false;
     };
+    let reply_locators = writer_proxy.unicast_locator_list.clone();
 
     // See if ACKNACK is needed, and generate one.
     let missing_seqnums = writer_proxy.missing_seqnums(heartbeat.first_sn, heartbeat.last_sn);
@@ -860,9 +919,9 @@ impl Reader {
           fflags,
           nackfrags,
           InfoDestination {
-            guid_prefix: mr_state.source_guid_prefix,
+            guid_prefix: writer_guid.prefix,
           },
-          &mr_state.unicast_reply_locator_list,
+          &reply_locators,
         );
       }
 
@@ -870,9 +929,9 @@ impl Reader {
         flags,
         response_ack_nack,
         InfoDestination {
-          guid_prefix: mr_state.source_guid_prefix,
+          guid_prefix: writer_guid.prefix,
         },
-        &mr_state.unicast_reply_locator_list,
+        &reply_locators,
       );
 
       return true;
@@ -936,7 +995,7 @@ impl Reader {
     }
 
     // Get the topic cache
-    let mut tc = self.acquire_the_topic_cache_guard();
+    let mut tc = self.acquire_the_topic_cache_write_guard();
     tc.mark_reliably_received_before(writer_guid, all_ackable_before);
 
     // TODO: If receiving GAP actually moved the reliably received mark forward
@@ -944,14 +1003,87 @@ impl Reader {
     // from our Datareader (DDS Spec Section 2.2.4.1)
   }
 
+  // HEARTBEAT_FRAG tells which fragments of a sample the Writer has sent so
+  // far. Respond with NACK_FRAG for the ones we are missing.
   pub fn handle_heartbeatfrag_msg(
     &mut self,
     heartbeatfrag: &HeartbeatFrag,
-    _mr_state: &MessageReceiverState,
+    mr_state: &MessageReceiverState,
   ) {
-    info!(
-      "HeartbeatFrag handling not implemented. topic={:?}   {:?}",
-      self.topic_name, heartbeatfrag
+    let writer_guid =
+      GUID::new_with_prefix_and_id(mr_state.source_guid_prefix, heartbeatfrag.writer_id);
+
+    if self.reliability == policy::Reliability::BestEffort {
+      debug!(
+        "HEARTBEAT_FRAG from {:?}, but this Reader is BestEffort. Ignoring. topic={:?} reader={:?}",
+        writer_guid, self.topic_name, self.my_guid
+      );
+      return;
+    }
+
+    let reader_id = self.entity_id();
+    let writer_proxy = if let Some(wp) = self.matched_writer_mut(writer_guid) {
+      wp
+    } else {
+      info!(
+        "HEARTBEAT_FRAG from {:?}, but no writer proxy available. topic={:?} reader={:?}",
+        writer_guid, self.topic_name, self.my_guid
+      );
+      return;
+    };
+
+    if heartbeatfrag.count <= writer_proxy.received_heartbeatfrag_count {
+      // This heartbeat was already seen and processed.
+      return;
+    }
+    writer_proxy.received_heartbeatfrag_count = heartbeatfrag.count;
+
+    let writer_sn = heartbeatfrag.writer_sn;
+    if writer_proxy.should_ignore_change(writer_sn) {
+      // Already received, or not relevant.
+      return;
+    }
+
+    let last_fragment_num = heartbeatfrag.last_fragment_num;
+    let missing_frags: BTreeSet<FragmentNumber> = if writer_proxy.is_partially_received(writer_sn) {
+      writer_proxy
+        .missing_frags_for(writer_sn)
+        .take_while(|f| *f <= last_fragment_num)
+        .collect()
+    } else {
+      // Nothing received yet of this sample
+      FragmentNumber::range_inclusive(
+        FragmentNumber::new(1),
+        min(last_fragment_num, FragmentNumber::new(256)),
+      )
+      .collect()
+    };
+    let first_missing = match missing_frags.iter().next() {
+      Some(&first) => first,
+      None => return, // We have all that is available.
+    };
+    // Limit the set to maximum that can be sent in a NackFrag submessage.
+    let missing_frags = missing_frags
+      .range(..first_missing + FragmentNumber::new(256))
+      .copied()
+      .collect();
+
+    let nackfrag = NackFrag {
+      reader_id,
+      writer_id: heartbeatfrag.writer_id,
+      writer_sn,
+      fragment_number_state: FragmentNumberSet::from_base_and_set(first_missing, &missing_frags),
+      count: writer_proxy.next_ack_nack_sequence_number(),
+    };
+    let reply_locators = writer_proxy.unicast_locator_list.clone();
+
+    self.send_nackfrags_to(
+      BitFlags::<NACKFRAG_Flags>::from_flag(NACKFRAG_Flags::Endianness),
+      vec![nackfrag],
+      InfoDestination {
+        guid_prefix: mr_state.source_guid_prefix,
+      },
+      &reply_locators,
     );
   }
 
@@ -990,7 +1122,7 @@ impl Reader {
     let cache_change = CacheChange::new(writer_guid, writer_sn, write_options, data);
 
     // Get the topic cache
-    let mut tc = self.acquire_the_topic_cache_guard();
+    let mut tc = self.acquire_the_topic_cache_write_guard();
 
     tc.add_change(&receive_timestamp, cache_change);
     self.matched_writer(writer_guid).map(|wp| {
@@ -1049,10 +1181,10 @@ impl Reader {
     message.add_submessage(acknack.create_submessage(flags));
 
     let bytes = message
-      .write_to_vec_with_ctx(Endianness::LittleEndian)
+      .write_to_gather_buffer(Endianness::LittleEndian)
       .unwrap();
     self
-      .udp_sender
+      .transports
       .send_to_locator_list(&bytes, dst_localtor_list);
   }
 
@@ -1080,10 +1212,10 @@ impl Reader {
     }
 
     let bytes = message
-      .write_to_vec_with_ctx(Endianness::LittleEndian)
+      .write_to_gather_buffer(Endianness::LittleEndian)
       .unwrap();
     self
-      .udp_sender
+      .transports
       .send_to_locator_list(&bytes, dst_locator_list);
   }
 
@@ -1122,8 +1254,8 @@ impl Reader {
     &self.topic_name
   }
 
-  fn acquire_the_topic_cache_guard(&self) -> MutexGuard<TopicCache> {
-    self.topic_cache.lock().unwrap_or_else(|e| {
+  fn acquire_the_topic_cache_write_guard(&self) -> RwLockWriteGuard<'_, TopicCache> {
+    self.topic_cache.write().unwrap_or_else(|e| {
       panic!(
         "The topic cache of topic {} is poisoned. Error: {}",
         &self.topic_name, e
@@ -1151,6 +1283,7 @@ impl fmt::Debug for Reader {
       .field("topic_name", &self.topic_name)
       .field("my_guid", &self.my_guid)
       .field("heartbeat_response_delay", &self.heartbeat_response_delay)
+      .field("preemptive_acknack_period", &self.preemptive_acknack_period)
       .field("received_hearbeat_count", &self.received_hearbeat_count)
       .finish()
   }
@@ -1162,7 +1295,7 @@ mod tests {
 
   use crate::{
     dds::{
-      qos::policy::Reliability,
+      qos::policy::{Reliability, ReliabilityTiming},
       statusevents::{sync_status_channel, DataReaderStatus},
       typedesc::TypeDesc,
       with_key::datawriter::WriteOptions,
@@ -1220,8 +1353,8 @@ mod tests {
     };
     let mut reader = Reader::new(
       reader_ing,
-      Rc::new(UDPSender::new(0).unwrap()),
-      mio_extras::timer::Builder::default().build(),
+      Arc::new(Transports::new(Vec::new())),
+      crate::mio_source::timer::Timer::default(),
     );
 
     // 2. Add info of a matched writer to the reader
@@ -1300,8 +1433,8 @@ mod tests {
     };
     let mut reader = Reader::new(
       reader_ing,
-      Rc::new(UDPSender::new(0).unwrap()),
-      mio_extras::timer::Builder::default().build(),
+      Arc::new(Transports::new(Vec::new())),
+      crate::mio_source::timer::Timer::default(),
     );
 
     // 2. Add info of a matched writer to the reader
@@ -1335,7 +1468,7 @@ mod tests {
     reader.handle_data_msg(data.clone(), data_flags, &mr_state);
 
     // 5. Verify that the reader sent the data to the topic cache
-    let topic_cache = topic_cache_handle.lock().unwrap();
+    let topic_cache = topic_cache_handle.read().unwrap();
 
     let cc_from_chache = topic_cache
       .get_change(reader.seqnum_instant_map.get(&sequence_num).unwrap())
@@ -1401,8 +1534,8 @@ mod tests {
     };
     let mut reader = Reader::new(
       reader_ing,
-      Rc::new(UDPSender::new(0).unwrap()),
-      mio_extras::timer::Builder::default().build(),
+      Arc::new(Transports::new(Vec::new())),
+      crate::mio_source::timer::Timer::default(),
     );
 
     // 2. Add info of a matched writer to the reader
@@ -1430,7 +1563,7 @@ mod tests {
       last_sn: SequenceNumber::new(0),
       count: 1,
     };
-    assert!(!reader.handle_heartbeat_msg(&hb_new, true, mr_state.clone())); // should be false, no ack
+    assert!(!reader.handle_heartbeat_msg(&hb_new, true, &mr_state)); // should be false, no ack
 
     // 4. Send the first proper heartbeat, reader should respond with acknack
     let hb_one = Heartbeat {
@@ -1440,12 +1573,12 @@ mod tests {
       last_sn: SequenceNumber::new(1),
       count: 2,
     };
-    assert!(reader.handle_heartbeat_msg(&hb_one, false, mr_state.clone())); // Should send an ack_nack
+    assert!(reader.handle_heartbeat_msg(&hb_one, false, &mr_state)); // Should send an ack_nack
 
     // 5. Send a duplicate of the first heartbeat, reader should not respond with
     // acknack
     let hb_one2 = hb_one.clone();
-    assert!(!reader.handle_heartbeat_msg(&hb_one2, false, mr_state.clone())); // No acknack
+    assert!(!reader.handle_heartbeat_msg(&hb_one2, false, &mr_state)); // No acknack
 
     // 6. Send a second proper heartbeat, reader should respond with acknack
     let hb_2 = Heartbeat {
@@ -1455,7 +1588,7 @@ mod tests {
       last_sn: SequenceNumber::new(3),  // writer has written 3 samples
       count: 3,
     };
-    assert!(reader.handle_heartbeat_msg(&hb_2, false, mr_state)); // Should send an ack_nack
+    assert!(reader.handle_heartbeat_msg(&hb_2, false, &mr_state)); // Should send an ack_nack
 
     // 7. Count of acknack sent should be 2
     // The count is verified from the writer proxy
@@ -1465,6 +1598,80 @@ mod tests {
     assert_eq!(writer_proxy.sent_ack_nack_count, 2);
   }
 
+  #[test]
+  fn reader_delays_heartbeat_response() {
+    let dds_cache = Arc::new(RwLock::new(DDSCache::new()));
+    let topic_name = "test_name";
+    let qos = QosPolicyBuilder::new()
+      .reliability(Reliability::Reliable {
+        max_blocking_time: Duration::from_millis(100),
+      })
+      .reliability_timing(ReliabilityTiming {
+        heartbeat_response_delay: Duration::from_millis(10),
+        ..ReliabilityTiming::default()
+      })
+      .build();
+
+    let topic_cache_handle = dds_cache.write().unwrap().add_new_topic(
+      topic_name.to_string(),
+      TypeDesc::new("test_type".to_string()),
+      &qos,
+    );
+    let (notification_sender, _notification_receiver) = mio_channel::sync_channel::<()>(100);
+    let (_notification_event_source, notification_event_sender) =
+      mio_source::make_poll_channel().unwrap();
+    let (status_sender, _status_receiver) = sync_status_channel::<DataReaderStatus>(4).unwrap();
+    let (_reader_command_sender, reader_command_receiver) =
+      mio_channel::sync_channel::<ReaderCommand>(10);
+
+    let reader_ing = ReaderIngredients {
+      guid: GUID::dummy_test_guid(EntityKind::READER_NO_KEY_USER_DEFINED),
+      notification_sender,
+      status_sender,
+      topic_name: topic_name.to_string(),
+      topic_cache_handle,
+      qos_policy: qos.clone(),
+      data_reader_command_receiver: reader_command_receiver,
+      data_reader_waker: Arc::new(Mutex::new(None)),
+      poll_event_sender: notification_event_sender,
+    };
+    let mut reader = Reader::new(
+      reader_ing,
+      Arc::new(Transports::new(Vec::new())),
+      crate::mio_source::timer::Timer::default(),
+    );
+
+    let writer_guid = GUID::dummy_test_guid(EntityKind::WRITER_NO_KEY_USER_DEFINED);
+    let mr_state = MessageReceiverState {
+      source_guid_prefix: writer_guid.prefix,
+      ..Default::default()
+    };
+    reader.matched_writer_add(writer_guid, EntityId::UNKNOWN, vec![], vec![], &qos);
+
+    let heartbeat = |count, last_sn| Heartbeat {
+      reader_id: reader.entity_id(),
+      writer_id: writer_guid.entity_id,
+      first_sn: SequenceNumber::new(1),
+      last_sn: SequenceNumber::new(last_sn),
+      count,
+    };
+    let hb_1 = heartbeat(1, 1);
+    let hb_2 = heartbeat(2, 2);
+
+    // Responses wait for the timer, and a later HEARTBEAT replaces an earlier
+    // one, but its final flag does not cancel the response.
+    assert!(!reader.handle_heartbeat_msg(&hb_1, false, &mr_state));
+    assert!(!reader.handle_heartbeat_msg(&hb_2, true, &mr_state));
+    let writer_proxy = reader.matched_writer(writer_guid).unwrap();
+    assert_eq!(writer_proxy.sent_ack_nack_count, 0);
+    assert_eq!(writer_proxy.delayed_heartbeat, Some((hb_2, false)));
+
+    reader.handle_heartbeat_response_timeout(writer_guid);
+    let writer_proxy = reader.matched_writer(writer_guid).unwrap();
+    assert_eq!(writer_proxy.sent_ack_nack_count, 1);
+    assert!(writer_proxy.delayed_heartbeat.is_none());
+  }
+
   #[test]
   fn reader_handles_gaps() {
     // 1. Create a reader
@@ -1505,8 +1712,8 @@ mod tests {
     };
     let mut reader = Reader::new(
       reader_ing,
-      Rc::new(UDPSender::new(0).unwrap()),
-      mio_extras::timer::Builder::default().build(),
+      Arc::new(Transports::new(Vec::new())),
+      crate::mio_source::timer::Timer::default(),
     );
 
     // 2. Add info of a matched writer to the reader
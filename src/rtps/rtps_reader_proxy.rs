@@ -1,4 +1,7 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::{
+  collections::{BTreeMap, BTreeSet, VecDeque},
+  time::{Duration, Instant},
+};
 
 use bit_vec::BitVec;
 #[allow(unused_imports)]
@@ -15,7 +18,7 @@ use crate::{
     sequence_number::{FragmentNumber, FragmentNumberSet, SequenceNumber},
   },
 };
-use super::reader::ReaderIngredients;
+use super::{reader::ReaderIngredients, writer::max_fragment_size};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 /// ReaderProxy class represents the information an RTPS StatefulWriter
@@ -53,6 +56,13 @@ pub(crate) struct RtpsReaderProxy {
   pub repair_mode: bool,
   pub qos: QosPolicies,
   pub frags_requested: BTreeMap<SequenceNumber, BitVec>,
+  /// Largest sample or fragment the Reader wants to receive, if it has
+  /// announced one. This is a RustDDS extension.
+  pub max_fragment_size: Option<u32>,
+  // Count of the latest NackFrag processed, to detect duplicates.
+  pub received_nackfrag_count: Option<i32>,
+  // NACK suppression: samples sent to the Reader recently, oldest first.
+  pub recently_sent: VecDeque<(Instant, SequenceNumber)>,
 }
 
 impl RtpsReaderProxy {
@@ -69,6 +79,9 @@ impl RtpsReaderProxy {
       repair_mode: false,
       qos,
       frags_requested: BTreeMap::new(),
+      received_nackfrag_count: None,
+      recently_sent: VecDeque::new(),
+      max_fragment_size: None,
     }
   }
 
@@ -97,6 +110,12 @@ impl RtpsReaderProxy {
       repair_mode: false,
       qos: reader.qos_policy.clone(),
       frags_requested: BTreeMap::new(),
+      received_nackfrag_count: None,
+      recently_sent: VecDeque::new(),
+      max_fragment_size: Some(max_fragment_size(
+        &reader.qos_policy,
+        domain_participant.max_fragment_size(),
+      ) as u32),
     }
   }
 
@@ -134,6 +153,9 @@ impl RtpsReaderProxy {
       repair_mode: false,
       qos: discovered_reader_data.subscription_topic_data.qos(),
       frags_requested: BTreeMap::new(),
+      received_nackfrag_count: None,
+      recently_sent: VecDeque::new(),
+      max_fragment_size: discovered_reader_data.reader_proxy.max_fragment_size,
     }
   }
 
@@ -165,9 +187,24 @@ impl RtpsReaderProxy {
         }
       }
 
-      AckSubmessage::NackFrag(_nack_frag) => {
-        // TODO
-        error!("NACKFRAG not implemented");
+      AckSubmessage::NackFrag(nack_frag) => {
+        if self
+          .received_nackfrag_count
+          .map_or(false, |count| nack_frag.count <= count)
+        {
+          // Already processed, e.g. received over another transport.
+          return;
+        }
+        self.received_nackfrag_count = Some(nack_frag.count);
+
+        if nack_frag.writer_sn < SequenceNumber::new(1) || nack_frag.writer_sn > last_available {
+          warn!(
+            "ReaderProxy {:?} asks for fragments of {:?} but I have only up to {:?}. NACKFRAG = {:?}",
+            self.remote_reader_guid, nack_frag.writer_sn, last_available, nack_frag
+          );
+          return;
+        }
+        self.mark_frags_requested(nack_frag.writer_sn, &nack_frag.fragment_number_state);
       }
     }
   }
@@ -184,6 +221,38 @@ impl RtpsReaderProxy {
     self.unsent_changes.insert(sequence_number);
   }
 
+  /// Remembers that a sample was sent to the Reader at `now`, so that
+  /// requests for it can be suppressed for `suppression` time.
+  pub fn sample_sent(
+    &mut self,
+    sequence_number: SequenceNumber,
+    now: Instant,
+    suppression: Duration,
+  ) {
+    if suppression.is_zero() {
+      return;
+    }
+    self.forget_sent_before(now, suppression);
+    self.recently_sent.push_back((now, sequence_number));
+  }
+
+  /// Removes the samples sent less than `suppression` ago from the requested
+  /// samples. They may still be on their way to the Reader.
+  pub fn suppress_recent_nacks(&mut self, now: Instant, suppression: Duration) {
+    self.forget_sent_before(now, suppression);
+    for (_, sequence_number) in &self.recently_sent {
+      self.unsent_changes.remove(sequence_number);
+    }
+  }
+
+  fn forget_sent_before(&mut self, now: Instant, suppression: Duration) {
+    while self.recently_sent.front().map_or(false, |(sent, _)| {
+      now.saturating_duration_since(*sent) >= suppression
+    }) {
+      self.recently_sent.pop_front();
+    }
+  }
+
   pub fn acked_up_to_before(&self) -> SequenceNumber {
     self.all_acked_before
   }
@@ -200,27 +269,30 @@ impl RtpsReaderProxy {
   }
 
   pub fn mark_frags_requested(&mut self, seq_num: SequenceNumber, frag_nums: &FragmentNumberSet) {
-    let req_set = self
-      .frags_requested
-      .entry(seq_num)
-      .or_insert_with(|| BitVec::with_capacity(64)); // default capacity out of hat
-
-    if let Some(max_fn_requested) = req_set.iter().next_back() {
-      // allocate more space if needed
-      let max_fn_requested = usize::from(max_fn_requested);
-      if max_fn_requested > req_set.len() {
-        let growth_need = max_fn_requested - req_set.len();
-        req_set.grow(growth_need, false);
-      }
-      for f in frag_nums.iter() {
-        // -1 because FragmentNumbers start at 1
-        req_set.set(usize::from(f) - 1, true);
+    let max_fn_requested = match frag_nums.iter().next_back() {
+      Some(max_fn) => usize::from(max_fn),
+      None => {
+        debug!(
+          "mark_frags_requested: Empty set in NackFrag. reader={:?} SN={:?}",
+          self.remote_reader_guid, seq_num
+        );
+        return;
       }
-    } else {
-      warn!(
-        "mark_frags_requested: Empty set in NackFrag??? reader={:?} SN={:?}",
-        self.remote_reader_guid, seq_num
-      );
+    };
+    let req_set = self.frags_requested.entry(seq_num).or_default();
+
+    // allocate more space if needed
+    if max_fn_requested > req_set.len() {
+      let growth_need = max_fn_requested - req_set.len();
+      req_set.grow(growth_need, false);
+    }
+    // FragmentNumbers start at 1, so zero is not a valid request
+    for f in frag_nums.iter().map(usize::from).filter(|&f| f > 0) {
+      // -1 because FragmentNumbers start at 1
+      req_set.set(f - 1, true);
+    }
+    if req_set.none() {
+      self.frags_requested.remove(&seq_num);
     }
   }
 
@@ -312,3 +384,93 @@ impl Iterator for FragBitVecIterator {
 //     }
 //   }
 // }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    messages::submessages::submessages::{AckNack, NackFrag},
+    structure::{guid::EntityKind, sequence_number::SequenceNumberSet},
+  };
+
+  fn nack_frag(writer_sn: i64, frags: &[u32], count: i32) -> AckSubmessage {
+    let frags = frags.iter().map(|&f| FragmentNumber::new(f)).collect();
+    AckSubmessage::NackFrag(NackFrag {
+      reader_id: EntityId::UNKNOWN,
+      writer_id: EntityId::UNKNOWN,
+      writer_sn: SequenceNumber::new(writer_sn),
+      fragment_number_state: FragmentNumberSet::from_base_and_set(FragmentNumber::new(1), &frags),
+      count,
+    })
+  }
+
+  fn requested(proxy: &RtpsReaderProxy) -> Vec<(i64, u32)> {
+    proxy
+      .frags_requested_iterator()
+      .map(|(sn, f)| (i64::from(sn), u32::from(f)))
+      .collect()
+  }
+
+  #[test]
+  fn reader_proxy_nack_frag() {
+    let mut proxy = RtpsReaderProxy::new(
+      GUID::dummy_test_guid(EntityKind::READER_NO_KEY_USER_DEFINED),
+      QosPolicies::qos_none(),
+    );
+    let last_available = SequenceNumber::new(5);
+
+    proxy.handle_ack_nack(&nack_frag(2, &[3, 40], 0), last_available);
+    assert_eq!(requested(&proxy), vec![(2, 3), (2, 40)]);
+
+    // Duplicate count is ignored
+    proxy.mark_frag_sent(SequenceNumber::new(2), &FragmentNumber::new(3));
+    proxy.handle_ack_nack(&nack_frag(2, &[3, 40], 0), last_available);
+    assert_eq!(requested(&proxy), vec![(2, 40)]);
+
+    // Sample that the writer does not have
+    proxy.handle_ack_nack(&nack_frag(6, &[1], 1), last_available);
+    assert_eq!(requested(&proxy), vec![(2, 40)]);
+
+    proxy.mark_frag_sent(SequenceNumber::new(2), &FragmentNumber::new(40));
+    assert!(!proxy.repair_frags_requested());
+    assert!(proxy.frags_requested.is_empty());
+  }
+
+  #[test]
+  fn reader_proxy_nack_suppression() {
+    let mut proxy = RtpsReaderProxy::new(
+      GUID::dummy_test_guid(EntityKind::READER_NO_KEY_USER_DEFINED),
+      QosPolicies::qos_none(),
+    );
+    let last_available = SequenceNumber::new(5);
+    let suppression = Duration::from_millis(50);
+    let sent = Instant::now();
+    let acknack = AckSubmessage::AckNack(AckNack {
+      reader_id: EntityId::UNKNOWN,
+      writer_id: EntityId::UNKNOWN,
+      reader_sn_state: SequenceNumberSet::from_base_and_set(
+        SequenceNumber::new(2),
+        &[2, 3].iter().map(|&sn| SequenceNumber::new(sn)).collect(),
+      ),
+      count: 1,
+    });
+
+    proxy.sample_sent(SequenceNumber::new(3), sent, suppression);
+
+    // Too soon: SN 3 may still be on its way.
+    proxy.handle_ack_nack(&acknack, last_available);
+    proxy.suppress_recent_nacks(sent + Duration::from_millis(10), suppression);
+    assert_eq!(
+      proxy.unsent_changes,
+      BTreeSet::from_iter([SequenceNumber::new(2)])
+    );
+
+    proxy.handle_ack_nack(&acknack, last_available);
+    proxy.suppress_recent_nacks(sent + Duration::from_millis(60), suppression);
+    assert_eq!(
+      proxy.unsent_changes,
+      BTreeSet::from_iter([SequenceNumber::new(2), SequenceNumber::new(3)])
+    );
+    assert!(proxy.recently_sent.is_empty());
+  }
+}
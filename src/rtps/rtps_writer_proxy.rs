@@ -8,7 +8,7 @@ use log::{debug, error, info, trace, warn};
 use crate::{
   dds::ddsdata::DDSData,
   discovery::sedp_messages::DiscoveredWriterData,
-  messages::submessages::submessages::{DATAFRAG_Flags, DataFrag},
+  messages::submessages::submessages::{DATAFRAG_Flags, DataFrag, Heartbeat},
   rtps::fragment_assembler::FragmentAssembler,
   structure::{
     guid::{EntityId, GUID},
@@ -67,8 +67,11 @@ pub(crate) struct RtpsWriterProxy {
 
   // The changes map is cleaned on heartbeat messages. The changes no longer available are dropped.
   pub received_heartbeat_count: i32,
+  pub received_heartbeatfrag_count: i32,
 
   pub sent_ack_nack_count: i32,
+  // Latest HEARTBEAT and its final flag, while the response is delayed.
+  pub delayed_heartbeat: Option<(Heartbeat, bool)>,
 
   ack_base: SequenceNumber, // We can ACK everything before this number.
   // ack_base can be increased from N-1 to N, if we receive DATA with SequenceNumber N-1
@@ -96,7 +99,9 @@ impl RtpsWriterProxy {
       remote_group_entity_id,
       changes: BTreeMap::new(),
       received_heartbeat_count: 0,
+      received_heartbeatfrag_count: 0,
       sent_ack_nack_count: 0,
+      delayed_heartbeat: None,
       // Sequence numbering must start at 1.
       // Therefore, we can ACK all sequence numbers below 1 even before receiving anything.
       ack_base: SequenceNumber::new(1),
@@ -323,7 +328,9 @@ impl RtpsWriterProxy {
       multicast_locator_list,
       changes: BTreeMap::new(),
       received_heartbeat_count: 0,
+      received_heartbeatfrag_count: 0,
       sent_ack_nack_count: 0,
+      delayed_heartbeat: None,
       ack_base: SequenceNumber::default(),
       last_received_sequence_number: SequenceNumber::new(0),
       last_received_timestamp: Timestamp::INVALID,
@@ -339,7 +346,7 @@ impl RtpsWriterProxy {
     if let Some(ref mut fa) = self.fragment_assembler {
       fa.new_datafrag(datafrag, flags)
     } else {
-      let mut fa = FragmentAssembler::new(datafrag.fragment_size);
+      let mut fa = FragmentAssembler::new();
       //TODO: Test that the fragment size is not zero
       let ret = fa.new_datafrag(datafrag, flags);
       self.fragment_assembler = Some(fa);
@@ -1,4 +1,5 @@
 use speedy::{Context, Writable, Writer};
+use bytes::Bytes;
 
 use crate::messages::submessages::{
   submessage::{ReaderSubmessage, SecuritySubmessage, WriterSubmessage},
@@ -35,6 +36,35 @@ impl<C: Context> Writable<C> for Submessage {
   }
 }
 
+impl Submessage {
+  /// Splits off the payload of a DATA or DATA_FRAG submessage. The payload is
+  /// the last element of these submessages, so writing the remaining
+  /// submessage followed by the payload gives the original serialization.
+  /// The submessage header is kept as is.
+  pub(crate) fn split_payload(&self) -> Option<(Self, Bytes)> {
+    let mut body = self.body.clone();
+    let payload = match &mut body {
+      SubmessageBody::Writer(WriterSubmessage::Data(data, _)) => {
+        std::mem::take(&mut data.serialized_payload.as_mut()?.value)
+      }
+      SubmessageBody::Writer(WriterSubmessage::DataFrag(data_frag, _)) => {
+        std::mem::take(&mut data_frag.serialized_payload)
+      }
+      _ => return None,
+    };
+    if payload.is_empty() {
+      return None;
+    }
+    Some((
+      Self {
+        header: self.header,
+        body,
+      },
+      payload,
+    ))
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use bytes::Bytes;
@@ -1,21 +1,22 @@
 use std::{
-  cmp::max,
-  collections::{BTreeMap, BTreeSet, HashSet},
+  cell::{Cell, RefCell},
+  cmp::{max, min},
+  collections::{BTreeMap, BTreeSet, HashSet, VecDeque},
   iter::FromIterator,
   ops::Bound::Included,
   rc::Rc,
-  sync::{Arc, Mutex, MutexGuard},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
+  },
+  time::Instant,
 };
 use core::task::Waker;
 
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 use speedy::{Endianness, Writable};
-use mio_extras::{
-  channel::{self as mio_channel, TrySendError},
-  timer::Timer,
-};
-use mio_06::Token;
+use mio_08::Token;
 
 use crate::{
   dds::{
@@ -29,9 +30,15 @@ use crate::{
     with_key::datawriter::WriteOptions,
   },
   messages::submessages::submessages::AckSubmessage,
-  network::udp_sender::UDPSender,
+  mio_source::{
+    channel::{self as mio_channel, TrySendError},
+    timer::Timer,
+  },
+  network::transport::{GatherBuffer, Transports},
   rtps::{
-    dp_event_loop::{NACK_RESPONSE_DELAY, NACK_SUPPRESSION_DURATION},
+    async_sender::AsyncSenderHandle,
+    flow_controller::{Destination, FlowController},
+    message_batcher::MessageBatcher,
     rtps_reader_proxy::RtpsReaderProxy,
     Message, MessageBuilder,
   },
@@ -47,6 +54,21 @@ use crate::{
   },
 };
 
+// Limits of the fragment size, i.e. the largest payload that is sent in one
+// DATA submessage. Fragment size is an u16 in DATA_FRAG, and a fragment with
+// its RTPS headers must fit into one UDP datagram.
+pub(crate) const MIN_FRAGMENT_SIZE: usize = 256;
+pub(crate) const MAX_FRAGMENT_SIZE: usize = 64_000;
+
+// Fragment size of a DataWriter or DataReader: MaxFragmentSize QoS, if set,
+// otherwise the participant default.
+pub(crate) fn max_fragment_size(qos: &QosPolicies, participant_default: usize) -> usize {
+  qos
+    .max_fragment_size()
+    .map_or(participant_default, |m| m.bytes as usize)
+    .clamp(MIN_FRAGMENT_SIZE, MAX_FRAGMENT_SIZE)
+}
+
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum DeliveryMode {
   Unicast,
@@ -68,10 +90,13 @@ pub(crate) struct WriterIngredients {
   pub writer_command_receiver: mio_channel::Receiver<WriterCommand>,
   pub writer_command_receiver_waker: Arc<Mutex<Option<Waker>>>,
   pub topic_name: String,
-  pub(crate) topic_cache_handle: Arc<Mutex<TopicCache>>, /* A handle to the topic cache in DDS
-                                                          * cache */
+  pub(crate) topic_cache_handle: Arc<RwLock<TopicCache>>, /* A handle to the topic cache in DDS
+                                                           * cache */
   pub qos_policies: QosPolicies,
   pub status_sender: StatusChannelSender<DataWriterStatus>,
+  pub publications_suspended: Arc<AtomicBool>, // shared with Publisher
+  pub data_max_size_serialized: usize,         // from QoS or participant default
+  pub max_message_size: usize,                 // participant default fragment size
 }
 
 impl WriterIngredients {
@@ -112,9 +137,39 @@ impl AckWaiter {
 
 const EPSILON_DELAY: Duration = Duration::from_nanos(10_000);
 
+// A reliable Writer streaming a large sample piggybacks a HEARTBEAT_FRAG on
+// every this many DATA_FRAGs, so that Readers can request lost fragments with
+// NACK_FRAG before the whole sample has been sent.
+const HEARTBEAT_FRAG_INTERVAL: u32 = 16;
+
+// In asynchronous publish mode, a Writer prepares at most this many fragments
+// in one turn, and then the other asynchronous Writers get their turns.
+const ASYNC_FRAGMENTS_PER_TURN: u32 = 16;
+
+// Largest number of DATAFRAG messages handed to the transports at once
+const FRAGMENT_SEND_BATCH_SIZE: usize = 64;
+
+// In asynchronous publish mode, a Writer takes no new samples from its
+// DataWriter while this many are waiting to be sent. DataWriter::write then
+// blocks.
+const ASYNC_MAX_QUEUED_SAMPLES: usize = 4;
+
+// While the flow controller or asynchronous send queue of a Writer is full,
+// repairs are tried again after this delay.
+const SEND_QUEUE_REPAIR_DELAY: std::time::Duration = std::time::Duration::from_millis(20);
+
+// A sample waiting to be sent in asynchronous publish mode
+struct AsyncSample {
+  sequence_number: SequenceNumber,
+  next_fragment: u32,
+}
+
 pub(crate) struct Writer {
   pub endianness: Endianness,
   pub heartbeat_message_counter: i32,
+  // Count for HEARTBEAT_FRAG submessages. This is a Cell, because DATA_FRAGs
+  // are sent while the topic cache is borrowed.
+  heartbeat_frag_counter: Cell<i32>,
   /// Configures the mode in which the
   ///Writer operates. If
   ///pushMode==true, then the Writer
@@ -130,6 +185,9 @@ pub(crate) struct Writer {
   ///availability of data by sending a
   ///Heartbeat Message.
   pub heartbeat_period: Option<Duration>,
+  /// Heartbeat period while some reliable Reader has not acknowledged all
+  /// samples.
+  pub fast_heartbeat_period: Option<Duration>,
   /// duration to launch cahche change remove from DDSCache
   pub cache_cleaning_period: Duration,
   ///Protocol tuning parameter that
@@ -146,8 +204,6 @@ pub(crate) struct Writer {
   ///acknowledgments that arrive ‘too
   ///soon’ after the corresponding
   ///change is sent.
-  // TODO: use this
-  #[allow(dead_code)]
   pub nack_suppression_duration: std::time::Duration,
   ///Internal counter used to assign
   ///increasing sequence number to
@@ -167,7 +223,15 @@ pub(crate) struct Writer {
   // Note: Writer can choose the max size at initialization, but is not allowed to change it later.
   // RTPS spec v2.5 Section 8.4.14.1.1:
   // "The fragment size must be fixed for a given Writer and is identical for all remote Readers"
+  //
+  // This comes from the MaxFragmentSize QoS or the participant default. A
+  // matched Reader that announces a lower limit only gets a warning, see
+  // check_reader_fragment_size().
   pub data_max_size_serialized: usize,
+  // Size budget for RTPS Messages that pack several samples together. This is
+  // the participant's default fragment size, which is derived from the path
+  // MTU, so that a batch fits into one datagram without IP fragmentation.
+  max_message_size: usize,
 
   my_guid: GUID,
   pub(crate) writer_command_receiver: mio_channel::Receiver<WriterCommand>,
@@ -178,10 +242,26 @@ pub(crate) struct Writer {
   matched_readers_count_total: i32, // all matches, never decremented
   requested_incompatible_qos_count: i32, // how many times a Reader requested incompatible QoS
   //message: Option<Message>,
-  udp_sender: Rc<UDPSender>,
+  transports: Arc<Transports>,
+  // If set, all messages are sent through the queue of this flow controller.
+  flow_controller: Option<Rc<RefCell<FlowController>>>,
+  // Asynchronous publish mode: messages are sent by the sender thread, and
+  // large samples are sent in turns with other Writers.
+  async_sender: Option<AsyncSenderHandle>,
+  async_samples: VecDeque<AsyncSample>,
+  // Batching QoS: small samples are collected into `batch`, and sent in one
+  // message. The batcher gives flush timeouts, and combines batches of
+  // several Writers.
+  batching: Option<policy::Batching>,
+  message_batcher: Option<Rc<RefCell<MessageBatcher>>>,
+  batch: MessageBuilder,
+  batch_samples: Vec<SequenceNumber>,
+  // INFO_TS in effect at the end of the batch
+  batch_timestamp: Option<Timestamp>,
+  batch_flush_scheduled: bool,
 
   // Writer can read/write to one topic only, and it stores a pointer to a mutex on the topic cache
-  topic_cache: Arc<Mutex<TopicCache>>,
+  topic_cache: Arc<RwLock<TopicCache>>,
   /// Writer can only read/write to this topic DDSHistoryCache.
   my_topic_name: String,
 
@@ -215,6 +295,13 @@ pub(crate) struct Writer {
   status_sender: StatusChannelSender<DataWriterStatus>,
   //offered_deadline_status: OfferedDeadlineMissedStatus,
   ack_waiter: Option<AckWaiter>,
+
+  /// Set by the Publisher, when it has suspended publications. New samples
+  /// are stored to the history cache, but sending them is deferred until
+  /// resume.
+  publications_suspended: Arc<AtomicBool>,
+  /// Samples written while suspended, in order of writing.
+  suspended_changes: Vec<SequenceNumber>,
 }
 //#[derive(Clone)]
 pub enum WriterCommand {
@@ -227,17 +314,23 @@ pub enum WriterCommand {
   WaitForAcknowledgments {
     all_acked: StatusChannelSender<()>,
   },
+  // From Publisher::resume_publications, so that samples written while
+  // suspended are sent without waiting for the next write.
+  ResumePublications,
   //ResetOfferedDeadlineMissedStatus { writer_guid: GUID },
 }
 
 impl Writer {
   pub fn new(
     i: WriterIngredients,
-    udp_sender: Rc<UDPSender>,
+    transports: Arc<Transports>,
+    flow_controller: Option<Rc<RefCell<FlowController>>>,
+    async_sender: Option<AsyncSenderHandle>,
+    message_batcher: Option<Rc<RefCell<MessageBatcher>>>,
     mut timed_event_timer: Timer<TimedEvent>,
   ) -> Self {
     // Verify that the topic cache corresponds to the topic of the Reader
-    let topic_cache_name = i.topic_cache_handle.lock().unwrap().topic_name();
+    let topic_cache_name = i.topic_cache_handle.read().unwrap().topic_name();
     if i.topic_name != topic_cache_name {
       panic!(
         "Topic name = {} and topic cache name = {} not equal when creating a Writer",
@@ -245,12 +338,13 @@ impl Writer {
       );
     }
 
+    let timing = i.qos_policies.reliability_timing().unwrap_or_default();
     let heartbeat_period = i
       .qos_policies
       .reliability
       .and_then(|reliability| {
         if matches!(reliability, Reliability::Reliable { .. }) {
-          Some(Duration::from_secs(1))
+          Some(timing.heartbeat_period)
         } else {
           None
         }
@@ -261,14 +355,18 @@ impl Writer {
           i.qos_policies.liveliness
         {
           let std_dur = lease_duration;
-          std_dur / 3
+          // Explicitly configured heartbeats may be more frequent.
+          match i.qos_policies.reliability_timing() {
+            Some(_) => min(hbp, std_dur / 3),
+            None => std_dur / 3,
+          }
         } else {
           hbp
         }
       });
+    let fast_heartbeat_period = heartbeat_period.map(|hbp| min(hbp, timing.fast_heartbeat_period));
 
-    // TODO: Configuration value
-    let cache_cleaning_period = Duration::from_secs(2 * 60);
+    let cache_cleaning_period = timing.cache_cleaning_period;
 
     // Start periodic Heartbeat
     if let Some(period) = heartbeat_period {
@@ -283,25 +381,35 @@ impl Writer {
     Self {
       endianness: Endianness::LittleEndian,
       heartbeat_message_counter: 1,
+      heartbeat_frag_counter: Cell::new(1),
       push_mode: true,
       heartbeat_period,
+      fast_heartbeat_period,
       cache_cleaning_period,
-      nack_response_delay: NACK_RESPONSE_DELAY, // default value from dp_event_loop
-      nackfrag_response_delay: NACK_RESPONSE_DELAY, // default value from dp_event_loop
+      nack_response_delay: timing.nack_response_delay.to_std(),
+      nackfrag_response_delay: timing.nack_response_delay.to_std(),
       repairfrags_continue_delay: std::time::Duration::from_millis(1),
-      nack_suppression_duration: NACK_SUPPRESSION_DURATION,
+      nack_suppression_duration: timing.nack_suppression_duration.to_std(),
       first_change_sequence_number: SequenceNumber::from(1), // first = 1, last = 0
       last_change_sequence_number: SequenceNumber::from(0),  // means we have nothing to write
-      data_max_size_serialized: 1024,
-      // ^^ TODO: Maybe a smarter selection would be in order.
-      // We should get the minimum over all outgoing interfaces.
+      data_max_size_serialized: i.data_max_size_serialized,
+      max_message_size: i.max_message_size,
       my_guid: i.guid,
       writer_command_receiver: i.writer_command_receiver,
       writer_command_receiver_waker: i.writer_command_receiver_waker,
       readers: BTreeMap::new(),
       matched_readers_count_total: 0,
       requested_incompatible_qos_count: 0,
-      udp_sender,
+      transports,
+      flow_controller,
+      async_sender,
+      async_samples: VecDeque::new(),
+      batching: i.qos_policies.batching(),
+      message_batcher,
+      batch: MessageBuilder::new(),
+      batch_samples: Vec::new(),
+      batch_timestamp: None,
+      batch_flush_scheduled: false,
       topic_cache: i.topic_cache_handle,
       my_topic_name: i.topic_name,
       sequence_number_to_instant: BTreeMap::new(),
@@ -311,6 +419,8 @@ impl Writer {
       status_sender: i.status_sender,
       //offered_deadline_status: OfferedDeadlineMissedStatus::new(),
       ack_waiter: None,
+      publications_suspended: i.publications_suspended,
+      suspended_changes: Vec::new(),
     }
   }
 
@@ -362,7 +472,13 @@ impl Writer {
           self.handle_heartbeat_tick(false);
           // ^^ false = This is automatic heartbeat by timer, not manual by application
           // call.
-          if let Some(period) = self.heartbeat_period {
+          // Adaptive heartbeat rate: faster while some Reader is behind
+          let period = if self.reliable_readers_behind() {
+            self.fast_heartbeat_period
+          } else {
+            self.heartbeat_period
+          };
+          if let Some(period) = period {
             self
               .timed_event_timer
               .set_timeout(std::time::Duration::from(period), TimedEvent::Heartbeat);
@@ -375,6 +491,14 @@ impl Writer {
             TimedEvent::CacheCleaning,
           );
         }
+        repair @ (TimedEvent::SendRepairData { .. } | TimedEvent::SendRepairFrags { .. })
+          if self.send_queue_full() =>
+        {
+          // Repairs would only pile up in the send queue.
+          self
+            .timed_event_timer
+            .set_timeout(SEND_QUEUE_REPAIR_DELAY, repair);
+        }
         TimedEvent::SendRepairData {
           to_reader: reader_guid,
         } => {
@@ -438,19 +562,38 @@ impl Writer {
   // --------------------------------------------------------------
   // --------------------------------------------------------------
   // --------------------------------------------------------------
+  // The fragment size is fixed for the lifetime of the Writer, so a Reader that
+  // has announced a lower limit gets larger fragments than it asked for.
+  fn check_reader_fragment_size(&self, reader_proxy: &RtpsReaderProxy) {
+    if let Some(limit) = reader_proxy.max_fragment_size {
+      if (limit as usize) < self.data_max_size_serialized {
+        warn!(
+          "Reader {:?} accepts fragments of at most {} bytes, but this Writer sends {} bytes. \
+           Set MaxFragmentSize QoS on the DataWriter to reach it. topic={:?}",
+          reader_proxy.remote_reader_guid,
+          limit,
+          self.data_max_size_serialized,
+          self.topic_name()
+        );
+      }
+    }
+  }
+
   fn num_frags_and_frag_size(&self, payload_size: usize) -> (u32, u16) {
-    let fragment_size = self.data_max_size_serialized as u32; //TODO: overflow check
+    let fragment_size = self.data_max_size_serialized as u32; // fits in u16, see MAX_FRAGMENT_SIZE
     let data_size = payload_size as u32; //TODO: overflow check
                                          // Formula from RTPS spec v2.5 Section "8.3.8.3.5 Logical Interpretation"
     let num_frags = (data_size / fragment_size) + u32::from(data_size % fragment_size != 0); // rounding up
     debug!("Fragmenting {data_size} to {num_frags} x {fragment_size}");
-    // TODO: Check fragment_size overflow
     (num_frags, fragment_size as u16)
   }
 
   // Receive new data samples from the DDS DataWriter
   pub fn process_writer_command(&mut self) {
-    while let Ok(cc) = self.writer_command_receiver.try_recv() {
+    if !self.publications_suspended() {
+      self.send_suspended_changes();
+    }
+    while let Some(cc) = self.receive_writer_command() {
       match cc {
         WriterCommand::DDSData {
           ddsdata,
@@ -477,6 +620,40 @@ impl Writer {
           let timestamp =
             self.insert_to_history_cache(ddsdata, write_options.clone(), sequence_number);
 
+          if self.publications_suspended() {
+            // Sample is now in history cache. It will be sent when publications
+            // are resumed.
+            self.suspended_changes.push(sequence_number);
+            continue;
+          }
+          // Publications may have been resumed while we were processing.
+          // Samples written before this one go first.
+          self.send_suspended_changes();
+
+          if fragmentation_needed {
+            // Keep the order of samples.
+            self.flush_batch();
+          }
+
+          if self.async_sender.is_some()
+            && self.push_mode
+            && (fragmentation_needed || !self.async_samples.is_empty())
+          {
+            // Large samples are sent a few fragments at a time by
+            // send_async_fragments. Smaller samples wait behind them, so that
+            // samples are sent in order.
+            self.async_samples.push_back(AsyncSample {
+              sequence_number,
+              next_fragment: 1,
+            });
+            continue;
+          }
+
+          if self.batching.is_some() && self.push_mode && !fragmentation_needed {
+            self.add_to_batch(sequence_number, timestamp);
+            continue;
+          }
+
           self.increase_heartbeat_counter();
 
           if !fragmentation_needed {
@@ -523,41 +700,17 @@ impl Writer {
               &data_hb_message,
               &mut self.readers.values(),
             );
+            if self.push_mode {
+              self.sample_pushed(sequence_number);
+            }
           } else {
             // Large payload, must fragment.
             if let Some(cache_change) = self.acquire_the_topic_cache_guard().get_change(&timestamp)
             {
               let data_size = cache_change.data_value.payload_size();
-              let (num_frags, fragment_size) = self.num_frags_and_frag_size(data_size);
 
               if self.push_mode {
-                // loop over fragments
-                for frag_num in FragmentNumber::range_inclusive(
-                  FragmentNumber::new(1),
-                  FragmentNumber::new(num_frags),
-                ) {
-                  let mut message_builder = MessageBuilder::new();
-                  if let Some(src_ts) = cache_change.write_options.source_timestamp {
-                    message_builder = message_builder.ts_msg(self.endianness, Some(src_ts));
-                  }
-
-                  message_builder = message_builder.data_frag_msg(
-                    cache_change,
-                    EntityId::UNKNOWN,      // reader
-                    self.my_guid.entity_id, // writer
-                    frag_num,
-                    fragment_size,
-                    data_size.try_into().unwrap(),
-                    self.endianness,
-                  );
-
-                  // TODO: some sort of queuing is needed
-                  self.send_message_to_readers(
-                    DeliveryMode::Multicast,
-                    &message_builder.add_header_and_build(self.my_guid.prefix),
-                    &mut self.readers.values(),
-                  );
-                } // end for
+                self.send_data_frags_to_readers(cache_change, data_size, 1, u32::MAX);
               }
               // Regardless of push mode, we send a Heartbeat
               let final_flag = false; // false = request that readers acknowledge with ACKNACK.
@@ -578,9 +731,18 @@ impl Writer {
                 self.topic_name(),
               );
             }
+            if self.push_mode {
+              self.fragments_pushed(sequence_number);
+            }
           } // end if large payload
         }
 
+        WriterCommand::ResumePublications => {
+          if !self.publications_suspended() {
+            self.send_suspended_changes();
+          }
+        }
+
         // WriterCommand::ResetOfferedDeadlineMissedStatus { writer_guid: _, } => {
         //   self.reset_offered_deadline_missed_status();
         // }
@@ -618,6 +780,473 @@ impl Writer {
         }
       }
     }
+
+    if self.batch_flush_delay().is_zero() {
+      // No latency budget: send what was written while we were busy.
+      self.flush_batch();
+    }
+  }
+
+  // Send a large sample as DATAFRAGs to all readers, at most `max_count`
+  // fragments starting from `first`. Returns the last fragment number sent.
+  fn send_data_frags_to_readers(
+    &self,
+    cache_change: &CacheChange,
+    data_size: usize,
+    first: u32,
+    max_count: u32,
+  ) -> u32 {
+    let (num_frags, fragment_size) = self.num_frags_and_frag_size(data_size);
+    let last = min(num_frags, first.saturating_add(max_count - 1));
+
+    // Fragments are sent in batches, so that a transport can send them with
+    // fewer system calls.
+    let mut fragment_messages = Vec::with_capacity(FRAGMENT_SEND_BATCH_SIZE);
+
+    // loop over fragments
+    for frag_num in
+      FragmentNumber::range_inclusive(FragmentNumber::new(first), FragmentNumber::new(last))
+    {
+      let mut message_builder = MessageBuilder::new();
+      if let Some(src_ts) = cache_change.write_options.source_timestamp {
+        message_builder = message_builder.ts_msg(self.endianness, Some(src_ts));
+      }
+
+      message_builder = message_builder.data_frag_msg(
+        cache_change,
+        EntityId::UNKNOWN,      // reader
+        self.my_guid.entity_id, // writer
+        frag_num,
+        fragment_size,
+        data_size.try_into().unwrap(),
+        self.endianness,
+      );
+
+      // The last fragment is followed by a regular HEARTBEAT.
+      if self.is_reliable()
+        && u32::from(frag_num) % HEARTBEAT_FRAG_INTERVAL == 0
+        && u32::from(frag_num) < num_frags
+      {
+        let count = self.heartbeat_frag_counter.get();
+        self.heartbeat_frag_counter.set(count + 1);
+        message_builder = message_builder.heartbeat_frag_msg(
+          self,
+          EntityId::UNKNOWN,
+          cache_change.sequence_number,
+          frag_num,
+          count,
+        );
+      }
+
+      let message = message_builder.add_header_and_build(self.my_guid.prefix);
+      fragment_messages.push(message.write_to_gather_buffer(self.endianness).unwrap());
+      if fragment_messages.len() == FRAGMENT_SEND_BATCH_SIZE {
+        self.send_messages_to_readers(&fragment_messages);
+        fragment_messages.clear();
+      }
+    } // end for
+    if !fragment_messages.is_empty() {
+      self.send_messages_to_readers(&fragment_messages);
+    }
+    last
+  }
+
+  // New samples are not taken while too many are waiting to be sent, in
+  // asynchronous publish mode or in the send queues.
+  fn receive_writer_command(&self) -> Option<WriterCommand> {
+    if self.async_samples.len() >= ASYNC_MAX_QUEUED_SAMPLES || self.send_queue_full() {
+      return None;
+    }
+    self.writer_command_receiver.try_recv().ok()
+  }
+
+  // The flow controller queue or the asynchronous send queue has no room for
+  // more from this Writer.
+  fn send_queue_full(&self) -> bool {
+    self
+      .flow_controller
+      .as_ref()
+      .map_or(false, |fc| fc.borrow().is_backlogged(self.my_guid))
+      || self
+        .async_sender
+        .as_ref()
+        .map_or(false, |async_sender| !async_sender.has_capacity())
+  }
+
+  pub fn is_async(&self) -> bool {
+    self.async_sender.is_some()
+  }
+
+  pub fn has_async_samples(&self) -> bool {
+    !self.async_samples.is_empty()
+  }
+
+  // Asynchronous publish mode: send the next fragments of queued samples, at
+  // most ASYNC_FRAGMENTS_PER_TURN. A sample that is not fragmented counts as
+  // one. Returns true, if there is more to send.
+  pub fn send_async_fragments(&mut self) -> bool {
+    enum Progress {
+      Sent(u32), // fragments sent, sample incomplete
+      Complete(u32),
+      Missing,
+    }
+
+    let mut budget = ASYNC_FRAGMENTS_PER_TURN;
+    let mut completed_any = false;
+    while budget > 0 {
+      let (sequence_number, next_fragment) = match self.async_samples.front() {
+        Some(sample) => (sample.sequence_number, sample.next_fragment),
+        None => break,
+      };
+
+      let cache_change_timestamp = self.sequence_number_to_instant(sequence_number);
+      let progress = match cache_change_timestamp.and_then(|ts| {
+        self
+          .acquire_the_topic_cache_guard()
+          .get_change(&ts)
+          .cloned()
+      }) {
+        None => Progress::Missing,
+        Some(cache_change) => {
+          let data_size = cache_change.data_value.payload_size();
+          if data_size <= self.data_max_size_serialized {
+            let mut message_builder = MessageBuilder::new();
+            if let Some(src_ts) = cache_change.write_options.source_timestamp {
+              message_builder = message_builder.ts_msg(self.endianness, Some(src_ts));
+            }
+            let message = message_builder
+              .data_msg(
+                &cache_change,
+                EntityId::UNKNOWN,
+                self.my_guid.entity_id,
+                self.endianness,
+              )
+              .add_header_and_build(self.my_guid.prefix);
+            self.send_message_to_readers(
+              DeliveryMode::Multicast,
+              &message,
+              &mut self.readers.values(),
+            );
+            Progress::Complete(1)
+          } else {
+            let (num_frags, _) = self.num_frags_and_frag_size(data_size);
+            let last =
+              self.send_data_frags_to_readers(&cache_change, data_size, next_fragment, budget);
+            if last == num_frags {
+              Progress::Complete(last + 1 - next_fragment)
+            } else {
+              Progress::Sent(last + 1 - next_fragment)
+            }
+          }
+        }
+      };
+
+      match progress {
+        Progress::Sent(count) => {
+          if let Some(sample) = self.async_samples.front_mut() {
+            sample.next_fragment = next_fragment + count;
+          }
+          budget = budget.saturating_sub(count);
+        }
+        Progress::Complete(count) => {
+          self.async_samples.pop_front();
+          self.fragments_pushed(sequence_number);
+          self.increase_heartbeat_counter();
+          let hb_message = MessageBuilder::new()
+            .heartbeat_msg(self, EntityId::UNKNOWN, false, false)
+            .add_header_and_build(self.my_guid.prefix);
+          self.send_message_to_readers(
+            DeliveryMode::Multicast,
+            &hb_message,
+            &mut self.readers.values(),
+          );
+          budget = budget.saturating_sub(count);
+          completed_any = true;
+        }
+        Progress::Missing => {
+          debug!(
+            "send_async_fragments: {:?} no longer in cache. topic={:?}",
+            sequence_number, self.my_topic_name
+          );
+          self.async_samples.pop_front();
+        }
+      }
+    }
+
+    if completed_any {
+      // There is room for more samples from the DataWriter.
+      self.process_writer_command();
+    }
+    self.has_async_samples()
+  }
+
+  // The fragments of a large sample are on their way to the readers, so it is
+  // not resent in full when a reader ACKNACKs. Lost fragments are requested
+  // with NACK_FRAG, and a reader that got none of them NACKs the whole sample.
+  fn fragments_pushed(&mut self, sequence_number: SequenceNumber) {
+    for reader_proxy in self.readers.values_mut() {
+      reader_proxy.unsent_changes.remove(&sequence_number);
+    }
+    self.sample_pushed(sequence_number);
+  }
+
+  // NACK suppression: remember when the sample was sent to the Readers.
+  fn sample_pushed(&mut self, sequence_number: SequenceNumber) {
+    if self.nack_suppression_duration.is_zero() {
+      return;
+    }
+    let now = Instant::now();
+    for reader_proxy in self.readers.values_mut() {
+      reader_proxy.sample_sent(sequence_number, now, self.nack_suppression_duration);
+    }
+  }
+
+  fn publications_suspended(&self) -> bool {
+    self.publications_suspended.load(Ordering::Acquire)
+  }
+
+  // Last sequence number announced in HEARTBEATs. Samples held back by
+  // suspended publications are not announced, or readers would request them.
+  pub(crate) fn announced_last_sequence_number(&self) -> SequenceNumber {
+    match self.suspended_changes.first() {
+      Some(first_suspended) => *first_suspended - SequenceNumber::from(1),
+      None => self.last_change_sequence_number,
+    }
+  }
+
+  // Flush samples that were written while publications were suspended.
+  // Instead of one message per sample, DATA submessages are packed into as few
+  // messages as possible, each at most max_message_size bytes, and a
+  // single HEARTBEAT is appended to the last one.
+  fn send_suspended_changes(&mut self) {
+    let suspended_changes = std::mem::take(&mut self.suspended_changes);
+    if suspended_changes.is_empty() {
+      return;
+    }
+    self.increase_heartbeat_counter();
+
+    let mut batch = MessageBuilder::new();
+    let mut fragmented = Vec::new();
+    let mut pushed = Vec::new();
+    if self.push_mode {
+      let topic_cache = self.acquire_the_topic_cache_guard();
+      // INFO_TS currently in effect in the batch.
+      let mut batch_timestamp = None;
+
+      for sequence_number in suspended_changes {
+        let cache_change = match self
+          .sequence_number_to_instant(sequence_number)
+          .and_then(|ts| topic_cache.get_change(&ts))
+        {
+          Some(cc) => cc,
+          None => {
+            // Readers will get a GAP for this, if they ask.
+            debug!(
+              "send_suspended_changes: {:?} no longer in cache. topic={:?}",
+              sequence_number,
+              self.topic_name()
+            );
+            continue;
+          }
+        };
+
+        let data_size = cache_change.data_value.payload_size();
+        if data_size > self.data_max_size_serialized {
+          // Large payload cannot be batched. Keep the order of samples by
+          // sending out what we have so far.
+          if !batch.is_empty() {
+            self.send_batch_to_readers(std::mem::take(&mut batch));
+            batch_timestamp = None;
+          }
+          self.send_data_frags_to_readers(cache_change, data_size, 1, u32::MAX);
+          fragmented.push(sequence_number);
+          continue;
+        }
+
+        let src_ts = cache_change.write_options.source_timestamp;
+        let mut sample = self.batch_sample(cache_change, batch_timestamp);
+        if !batch.is_empty()
+          && batch.len_serialized() + sample.len_serialized() > self.max_message_size
+        {
+          self.send_batch_to_readers(std::mem::take(&mut batch));
+          // New message starts without timestamp, so it must be repeated.
+          sample = self.batch_sample(cache_change, None);
+        }
+        batch = batch.append(sample);
+        batch_timestamp = src_ts;
+        pushed.push(sequence_number);
+      }
+    }
+    for sequence_number in fragmented {
+      self.fragments_pushed(sequence_number);
+    }
+    for sequence_number in pushed {
+      self.sample_pushed(sequence_number);
+    }
+
+    let final_flag = false; // false = request that readers acknowledge with ACKNACK.
+    let liveliness_flag = false; // Side-effect of writing new data.
+    let heartbeat =
+      MessageBuilder::new().heartbeat_msg(self, EntityId::UNKNOWN, final_flag, liveliness_flag);
+    if !batch.is_empty()
+      && batch.len_serialized() + heartbeat.len_serialized() > self.max_message_size
+    {
+      self.send_batch_to_readers(std::mem::take(&mut batch));
+    }
+    self.send_batch_to_readers(batch.append(heartbeat));
+  }
+
+  fn send_batch_to_readers(&self, batch: MessageBuilder) {
+    self.send_message_to_readers(
+      DeliveryMode::Multicast,
+      &batch.add_header_and_build(self.my_guid.prefix),
+      &mut self.readers.values(),
+    );
+  }
+
+  // INFO_TS, if needed, and DATA of a sample in a batch. `batch_timestamp` is
+  // the INFO_TS in effect in the batch so far. Timestamp has to go before Data
+  // to have effect on Data, and a sample without source timestamp must
+  // invalidate the previous one.
+  fn batch_sample(
+    &self,
+    cache_change: &CacheChange,
+    batch_timestamp: Option<Timestamp>,
+  ) -> MessageBuilder {
+    let src_ts = cache_change.write_options.source_timestamp;
+    let mut sample = MessageBuilder::new();
+    if src_ts != batch_timestamp {
+      sample = sample.ts_msg(self.endianness, src_ts);
+    }
+    sample.data_msg(
+      cache_change,
+      EntityId::UNKNOWN,      // reader
+      self.my_guid.entity_id, // writer
+      self.endianness,
+    )
+  }
+
+  // Batching QoS: samples wait at most for the latency budget.
+  fn batch_flush_delay(&self) -> std::time::Duration {
+    self
+      .qos_policies
+      .latency_budget()
+      .map_or(std::time::Duration::ZERO, |lb| lb.duration.into())
+  }
+
+  // Batching QoS: add a sample to the batch. The batch is sent when it is
+  // full, or when the first sample has waited for the flush delay.
+  fn add_to_batch(&mut self, sequence_number: SequenceNumber, timestamp: Timestamp) {
+    let batching = match self.batching {
+      Some(batching) => batching,
+      None => return,
+    };
+    let (sample, src_ts, flush_first) = {
+      let topic_cache = self.acquire_the_topic_cache_guard();
+      let cache_change = match topic_cache.get_change(&timestamp) {
+        Some(cache_change) => cache_change,
+        None => {
+          error!(
+            "add_to_batch: The dog ate my CacheChange {:?} topic={:?}",
+            sequence_number,
+            self.topic_name(),
+          );
+          return;
+        }
+      };
+      let src_ts = cache_change.write_options.source_timestamp;
+      let sample = self.batch_sample(cache_change, self.batch_timestamp);
+      if !self.batch.is_empty()
+        && self.batch.len_serialized() + sample.len_serialized() > batching.max_bytes as usize
+      {
+        // New message starts without timestamp.
+        (self.batch_sample(cache_change, None), src_ts, true)
+      } else {
+        (sample, src_ts, false)
+      }
+    };
+    if flush_first {
+      self.flush_batch();
+    }
+    self.batch = std::mem::take(&mut self.batch).append(sample);
+    self.batch_samples.push(sequence_number);
+    self.batch_timestamp = src_ts;
+
+    if self.batch_samples.len() >= batching.max_samples as usize {
+      self.flush_batch();
+    } else if !self.batch_flush_scheduled {
+      let delay = self.batch_flush_delay();
+      if !delay.is_zero() {
+        if let Some(message_batcher) = &self.message_batcher {
+          message_batcher
+            .borrow_mut()
+            .schedule_flush(self.my_guid.entity_id, delay);
+          self.batch_flush_scheduled = true;
+        }
+      }
+    }
+  }
+
+  // The flush delay of the batch has expired.
+  pub fn batch_flush_timeout(&mut self) {
+    self.batch_flush_scheduled = false;
+    self.flush_batch();
+  }
+
+  pub fn combines_batches(&self) -> bool {
+    self.batching.map_or(false, |b| b.across_writers)
+  }
+
+  // Another Writer is flushing its batch. Go along, so that the batches can
+  // share messages. This is before our flush delay, so it is never late.
+  pub fn flush_batch_with_others(&mut self) {
+    if self.combines_batches() {
+      self.flush_batch();
+    }
+  }
+
+  // Send the samples in the batch, and a HEARTBEAT.
+  pub fn flush_batch(&mut self) {
+    let max_bytes = match self.batching {
+      Some(batching) if !self.batch_samples.is_empty() => batching.max_bytes as usize,
+      _ => return,
+    };
+    for sequence_number in std::mem::take(&mut self.batch_samples) {
+      self.sample_pushed(sequence_number);
+    }
+    self.batch_timestamp = None;
+    self.increase_heartbeat_counter();
+
+    let mut batch = std::mem::take(&mut self.batch);
+    let final_flag = false; // false = request that readers acknowledge with ACKNACK.
+    let liveliness_flag = false; // Side-effect of writing new data.
+    let heartbeat =
+      MessageBuilder::new().heartbeat_msg(self, EntityId::UNKNOWN, final_flag, liveliness_flag);
+    if batch.len_serialized() + heartbeat.len_serialized() > max_bytes {
+      self.send_batch(batch, max_bytes);
+      batch = MessageBuilder::new();
+    }
+    self.send_batch(batch.append(heartbeat), max_bytes);
+  }
+
+  fn send_batch(&self, batch: MessageBuilder, max_bytes: usize) {
+    match &self.message_batcher {
+      Some(message_batcher)
+        if self.combines_batches()
+          && self.flow_controller.is_none()
+          && self.async_sender.is_none() =>
+      {
+        let buffer = batch
+          .add_header_and_build(self.my_guid.prefix)
+          .write_to_vec_with_ctx(self.endianness)
+          .unwrap();
+        let mut message_batcher = message_batcher.borrow_mut();
+        for destination in self.destinations(DeliveryMode::Multicast, &mut self.readers.values()) {
+          message_batcher.combine(&buffer, destination, max_bytes);
+        }
+      }
+      _ => self.send_batch_to_readers(batch),
+    }
   }
 
   fn insert_to_history_cache(
@@ -656,7 +1285,7 @@ impl Writer {
     // timestamp taken here is used as a unique(!) key in the DDSCache.
     let timestamp = Timestamp::now();
     self
-      .acquire_the_topic_cache_guard()
+      .acquire_the_topic_cache_write_guard()
       .add_change(&timestamp, new_cache_change);
 
     // keeping table of instant sequence number pairs
@@ -678,6 +1307,14 @@ impl Writer {
   // --------------------------------------------------------------
   // --------------------------------------------------------------
 
+  // Some reliable Reader has not acknowledged all samples.
+  fn reliable_readers_behind(&self) -> bool {
+    self.readers.values().any(|rp| {
+      matches!(rp.qos().reliability(), Some(Reliability::Reliable { .. }))
+        && rp.all_acked_before <= self.last_change_sequence_number
+    })
+  }
+
   /// This is called periodically.
   pub fn handle_heartbeat_tick(&mut self, is_manual_assertion: bool) {
     // Reliable Stateless Writer will set the final flag.
@@ -686,6 +1323,10 @@ impl Writer {
     let final_flag = false;
     let liveliness_flag = is_manual_assertion; // RTPS spec "8.3.7.5 Heartbeat"
 
+    if !self.publications_suspended() {
+      self.send_suspended_changes();
+    }
+
     trace!(
       "heartbeat tick in topic {:?} have {} readers",
       self.topic_name(),
@@ -699,7 +1340,7 @@ impl Writer {
     if self
       .readers
       .values()
-      .all(|rp| self.last_change_sequence_number < rp.all_acked_before)
+      .all(|rp| self.announced_last_sequence_number() < rp.all_acked_before)
     {
       trace!("heartbeat tick: all readers have all available data.");
     } else {
@@ -713,11 +1354,15 @@ impl Writer {
         self.topic_name(),
         hb_message
       );
-      self.send_message_to_readers(
-        DeliveryMode::Multicast,
-        &hb_message,
-        &mut self.readers.values(),
-      );
+      let buffer = hb_message.write_to_gather_buffer(self.endianness).unwrap();
+      for destination in self.destinations(DeliveryMode::Multicast, &mut self.readers.values()) {
+        // Heartbeats do not wait behind a flow controller backlog, so that
+        // Readers keep hearing from this Writer.
+        match &self.flow_controller {
+          Some(flow_controller) => flow_controller.borrow_mut().send_now(&buffer, &destination),
+          None => self.send_to(&buffer, destination),
+        }
+      }
     }
   }
 
@@ -754,9 +1399,12 @@ impl Writer {
 
         self.update_ack_waiters(reader_guid, Some(an.reader_sn_state.base()));
 
+        let nack_suppression_duration = self.nack_suppression_duration;
         if let Some(reader_proxy) = self.lookup_readerproxy_mut(reader_guid) {
           // Mark requested SNs as "unsent changes"
           reader_proxy.handle_ack_nack(ack_submessage, last_seq);
+          // except those that were sent too recently to have arrived
+          reader_proxy.suppress_recent_nacks(Instant::now(), nack_suppression_duration);
 
           let reader_guid = reader_proxy.remote_reader_guid; // copy to avoid double mut borrow
                                                              // Sanity Check: if the reader asked for something we did not even advertise
@@ -809,16 +1457,29 @@ impl Writer {
       AckSubmessage::NackFrag(ref nackfrag) => {
         // NackFrag is negative acknowledgement only, i.e. requesting missing fragments.
 
+        let last_seq = self.last_change_sequence_number;
         let reader_guid = GUID::new(reader_guid_prefix, nackfrag.reader_id);
-        if let Some(reader_proxy) = self.lookup_readerproxy_mut(reader_guid) {
-          reader_proxy.mark_frags_requested(nackfrag.writer_sn, &nackfrag.fragment_number_state);
+        let repair_needed = match self.lookup_readerproxy_mut(reader_guid) {
+          Some(reader_proxy) => {
+            reader_proxy.handle_ack_nack(ack_submessage, last_seq);
+            reader_proxy.repair_frags_requested()
+          }
+          None => {
+            debug!(
+              "NACKFRAG from unknown reader {:?}. topic={:?}",
+              reader_guid, self.my_topic_name
+            );
+            false
+          }
+        };
+        if repair_needed {
+          self.timed_event_timer.set_timeout(
+            self.nackfrag_response_delay,
+            TimedEvent::SendRepairFrags {
+              to_reader: reader_guid,
+            },
+          );
         }
-        self.timed_event_timer.set_timeout(
-          self.nackfrag_response_delay,
-          TimedEvent::SendRepairFrags {
-            to_reader: reader_guid,
-          },
-        );
       }
     }
   }
@@ -891,7 +1552,24 @@ impl Writer {
     let mut sending_data = false;
     let mut sending_gap = false;
     let mut trigger_send_repair_frags = false;
-    if let Some(&unsent_sn) = reader_proxy.unsent_changes.iter().next() {
+    // Samples written while publications are suspended are not repaired
+    // either. They will be sent on resume.
+    // Samples waiting to be sent in asynchronous mode are not repaired yet.
+    let first_suspended = [
+      self.suspended_changes.first(),
+      self.async_samples.front().map(|s| &s.sequence_number),
+      self.batch_samples.first(),
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+    .copied();
+    if let Some(&unsent_sn) = reader_proxy
+      .unsent_changes
+      .iter()
+      .next()
+      .filter(|&&sn| first_suspended.map_or(true, |first| sn < first))
+    {
       // There are unsent changes.
       if let Some(timestamp) = self.sequence_number_to_instant(unsent_sn) {
         // Try to find the cache change from topic cache
@@ -905,9 +1583,8 @@ impl Writer {
               self.my_guid.entity_id, // writer
               self.endianness,
             );
-            // TODO: Here we are cloning the entire payload. We need to rewrite
-            // the transmit path to avoid copying.
             sending_data = true;
+            reader_proxy.sample_sent(unsent_sn, Instant::now(), self.nack_suppression_duration);
           } else {
             // Large data: arrange DATAFRAGs to be sent
             let (num_frags, _frag_size) =
@@ -959,6 +1636,15 @@ impl Writer {
 
     // if we have DATA or GAP to send, then build message and send
     if sending_data || sending_gap {
+      if reader_proxy.unsent_changes.is_empty() {
+        // Last repair: piggyback a HEARTBEAT, so that the Reader acknowledges
+        // or asks for more without waiting for the next periodic one.
+        self.increase_heartbeat_counter();
+        let final_flag = false; // false = request that readers acknowledge with ACKNACK.
+        let liveliness_flag = false;
+        partial_message =
+          partial_message.heartbeat_msg(self, reader_guid.entity_id, final_flag, liveliness_flag);
+      }
       let data_gap_msg = partial_message.add_header_and_build(self.my_guid.prefix);
       self.send_message_to_readers(
         DeliveryMode::Unicast,
@@ -1007,15 +1693,28 @@ impl Writer {
             message_builder = message_builder.ts_msg(self.endianness, Some(src_ts));
           }
 
-          let fragment_size: u32 = self.data_max_size_serialized as u32; //TODO: overflow check
+          let (num_frags, fragment_size) =
+            self.num_frags_and_frag_size(cache_change.data_value.payload_size());
           let data_size: u32 = cache_change.data_value.payload_size() as u32; //TODO: overflow check
 
+          if u32::from(frag_num) > num_frags {
+            // The Reader may have assumed a different fragment size, or the
+            // request is just bogus.
+            debug!(
+              "handle_repair_frags_send_worker: {:?} has only {} fragments, {:?} requested. \
+               reader={:?} topic={:?}",
+              seq_num, num_frags, frag_num, reader_proxy.remote_reader_guid, self.my_topic_name
+            );
+            reader_proxy.mark_frag_sent(seq_num, &frag_num);
+            continue;
+          }
+
           message_builder = message_builder.data_frag_msg(
             cache_change,
             reader_proxy.remote_reader_guid.entity_id, // reader
             self.my_guid.entity_id,                    // writer
             frag_num,
-            fragment_size as u16, // TODO: overflow check
+            fragment_size,
             data_size,
             self.endianness,
           );
@@ -1024,7 +1723,7 @@ impl Writer {
           self.send_message_to_readers(
             DeliveryMode::Unicast,
             &message_builder.add_header_and_build(self.my_guid.prefix),
-            &mut self.readers.values(),
+            &mut std::iter::once(&*reader_proxy),
           );
         } else {
           error!(
@@ -1068,7 +1767,7 @@ impl Writer {
     // as far as this Writer is concerned.
     if let Some(&keep_instant) = self.sequence_number_to_instant.get(&first_keeper) {
       self
-        .acquire_the_topic_cache_guard()
+        .acquire_the_topic_cache_write_guard()
         .remove_changes_before(keep_instant);
     } else {
       // if we end up with SequenceNumber(1), it may be due to "max()" above,
@@ -1100,54 +1799,132 @@ impl Writer {
     message: &Message,
     readers: &mut dyn Iterator<Item = &RtpsReaderProxy>,
   ) {
+    let buffer = message.write_to_gather_buffer(self.endianness).unwrap();
+    for destination in self.destinations(preferred_mode, readers) {
+      self.send_to(&buffer, destination);
+    }
+  }
+
+  // Send several messages, in order, to all readers.
+  fn send_messages_to_readers(&self, buffers: &[GatherBuffer]) {
+    for destination in self.destinations(DeliveryMode::Multicast, &mut self.readers.values()) {
+      if self.flow_controller.is_some() || self.async_sender.is_some() {
+        for buffer in buffers {
+          self.send_to(buffer, destination.clone());
+        }
+      } else {
+        match destination {
+          Destination::Locator(locator) => self.transports.send_all_to_locator(buffers, &locator),
+          Destination::LocatorList(locators) => {
+            self.transports.send_all_to_locator_list(buffers, &locators);
+          }
+        }
+      }
+    }
+  }
+
+  // Where to send a message, so that it reaches the readers.
+  fn destinations(
+    &self,
+    preferred_mode: DeliveryMode,
+    readers: &mut dyn Iterator<Item = &RtpsReaderProxy>,
+  ) -> Vec<Destination> {
     // TODO: This is a stupid transmit algorithm. We should compute a preferred
     // unicast and multicast locators for each reader only on every reader update,
     // and not find it dynamically on every message.
-    let buffer = message.write_to_vec_with_ctx(self.endianness).unwrap();
+    let mut destinations = Vec::new();
     let mut already_sent_to = BTreeSet::new();
 
+    // The locators of one reader are sent to as a list, so that a locator of
+    // a preferred transport, e.g. shared memory, can replace the UDP locators
+    // of the same participant. The SPDP reader proxy has an unknown GUID prefix and
+    // collects the locators of many participants, so there each locator is
+    // sent to separately.
     macro_rules! send_unless_sent_and_mark {
-      ($locs:expr) => {
-        for loc in $locs.iter() {
-          if already_sent_to.contains(loc) {
-            trace!("Already sent to {:?}", loc);
-          } else {
-            self.udp_sender.send_to_locator(&buffer, loc);
-            already_sent_to.insert(loc.clone());
+      ($reader:expr, $locs:expr) => {{
+        let unsent: Vec<Locator> = $locs
+          .iter()
+          .filter(|loc| !already_sent_to.contains(*loc))
+          .cloned()
+          .collect();
+        if unsent.is_empty() {
+          trace!("Already sent to {:?}", $locs);
+        } else if $reader.remote_reader_guid.prefix == GuidPrefix::UNKNOWN {
+          for loc in &unsent {
+            destinations.push(Destination::Locator(*loc));
           }
+        } else {
+          destinations.push(Destination::LocatorList(unsent.clone()));
         }
-      };
+        already_sent_to.extend(unsent);
+      }};
     }
 
     for reader in readers {
+      // The unicast locators of the SPDP reader proxy are initial peers and
+      // participants that we may not reach by multicast, so it is sent to on
+      // both lists.
+      if reader.remote_reader_guid.prefix == GuidPrefix::UNKNOWN {
+        send_unless_sent_and_mark!(reader, reader.multicast_locator_list);
+        send_unless_sent_and_mark!(reader, reader.unicast_locator_list);
+        continue;
+      }
+      // Some transports, e.g. shared memory to readers on the same host, are
+      // better than multicast even if multicast is preferred.
+      if self
+        .transports
+        .is_preferred_over_multicast(&reader.unicast_locator_list)
+      {
+        send_unless_sent_and_mark!(reader, reader.unicast_locator_list);
+        continue;
+      }
       match (
         preferred_mode,
         reader
           .unicast_locator_list
           .iter()
-          .find(|l| Locator::is_udp(l)),
+          .find(|l| self.transports.can_send_to(l)),
         reader
           .multicast_locator_list
           .iter()
-          .find(|l| Locator::is_udp(l)),
+          .find(|l| self.transports.can_send_to(l)),
       ) {
         (DeliveryMode::Multicast, _, Some(_mc_locator)) => {
-          send_unless_sent_and_mark!(reader.multicast_locator_list);
+          send_unless_sent_and_mark!(reader, reader.multicast_locator_list);
         }
         (DeliveryMode::Unicast, Some(_uc_locator), _) => {
-          send_unless_sent_and_mark!(reader.unicast_locator_list)
+          send_unless_sent_and_mark!(reader, reader.unicast_locator_list);
         }
         (_delivery_mode, _, Some(_mc_locator)) => {
-          send_unless_sent_and_mark!(reader.multicast_locator_list);
+          send_unless_sent_and_mark!(reader, reader.multicast_locator_list);
         }
         (_delivery_mode, Some(_uc_locator), _) => {
-          send_unless_sent_and_mark!(reader.unicast_locator_list)
+          send_unless_sent_and_mark!(reader, reader.unicast_locator_list);
         }
         (_delivery_mode, None, None) => {
           warn!("send_message_to_readers: No locators for {:?}", reader);
         }
       } // match
     }
+    destinations
+  }
+
+  // Send now, or queue to the flow controller or the asynchronous sender.
+  fn send_to(&self, buffer: &GatherBuffer, destination: Destination) {
+    if let Some(flow_controller) = &self.flow_controller {
+      flow_controller
+        .borrow_mut()
+        .send(self.my_guid, buffer.clone(), destination);
+    } else if let Some(async_sender) = &self.async_sender {
+      async_sender.send(buffer.clone(), destination);
+    } else {
+      match destination {
+        Destination::Locator(locator) => self.transports.send_to_locator(buffer, &locator),
+        Destination::LocatorList(locators) => {
+          self.transports.send_to_locator_list(buffer, &locators);
+        }
+      }
+    }
   }
 
   // Send status to DataWriter or however is listening
@@ -1216,13 +1993,17 @@ impl Writer {
   // return 1 if it was new ( = count of added reader proxies)
   fn matched_reader_update(&mut self, reader_proxy: RtpsReaderProxy) -> i32 {
     let (to_insert, count_change) = match self.readers.remove(&reader_proxy.remote_reader_guid) {
-      None => (reader_proxy, 1),
+      None => {
+        self.check_reader_fragment_size(&reader_proxy);
+        (reader_proxy, 1)
+      }
       Some(existing_reader) => (
         RtpsReaderProxy {
           is_active: existing_reader.is_active,
           all_acked_before: existing_reader.all_acked_before,
           unsent_changes: existing_reader.unsent_changes,
           repair_mode: existing_reader.repair_mode,
+          received_nackfrag_count: existing_reader.received_nackfrag_count,
           ..reader_proxy
         },
         0,
@@ -1288,8 +2069,17 @@ impl Writer {
     &self.my_topic_name
   }
 
-  fn acquire_the_topic_cache_guard(&self) -> MutexGuard<TopicCache> {
-    self.topic_cache.lock().unwrap_or_else(|e| {
+  fn acquire_the_topic_cache_guard(&self) -> RwLockReadGuard<TopicCache> {
+    self.topic_cache.read().unwrap_or_else(|e| {
+      panic!(
+        "The topic cache of topic {} is poisoned. Error: {}",
+        &self.my_topic_name, e
+      )
+    })
+  }
+
+  fn acquire_the_topic_cache_write_guard(&self) -> RwLockWriteGuard<'_, TopicCache> {
+    self.topic_cache.write().unwrap_or_else(|e| {
       panic!(
         "The topic cache of topic {} is poisoned. Error: {}",
         &self.my_topic_name, e
@@ -1,7 +1,7 @@
 use std::{io, io::Write, marker::PhantomData};
 
 use serde::{ser, Serialize};
-use bytes::Bytes;
+use bytes::{BufMut, Bytes, BytesMut};
 use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
 
 use crate::{
@@ -116,6 +116,10 @@ where
     to_writer::<D, BO, &mut Vec<u8>>(&mut buffer, value)?;
     Ok(Bytes::from(buffer))
   }
+
+  fn to_buffer(value: &D, buffer: &mut BytesMut) -> Result<()> {
+    to_writer::<D, BO, _>(buffer.writer(), value)
+  }
 }
 
 impl<D, BO> with_key::SerializerAdapter<D> for CDRSerializerAdapter<D, BO>
@@ -640,12 +644,37 @@ mod tests {
   use log::info;
   use serde::{Deserialize, Serialize};
   use serde_repr::{Deserialize_repr, Serialize_repr};
-
-  use crate::serialization::{
-    cdr_deserializer::deserialize_from_little_endian,
-    cdr_serializer::{to_big_endian_binary, to_little_endian_binary},
+  use bytes::BytesMut;
+
+  use crate::{
+    dds::adapters::no_key::SerializerAdapter,
+    serialization::{
+      cdr_deserializer::deserialize_from_little_endian,
+      cdr_serializer::{to_big_endian_binary, to_little_endian_binary, CDRSerializerAdapter},
+    },
   };
 
+  #[test]
+  fn cdr_adapter_to_buffer_matches_to_bytes() {
+    #[derive(Serialize)]
+    struct Sample {
+      a: u8,
+      b: u64,
+      c: String,
+    }
+    let sample = Sample {
+      a: 1,
+      b: 2,
+      c: "three".to_string(),
+    };
+    let mut buffer = BytesMut::new();
+    CDRSerializerAdapter::<Sample>::to_buffer(&sample, &mut buffer).unwrap();
+    assert_eq!(
+      buffer.freeze(),
+      CDRSerializerAdapter::<Sample>::to_bytes(&sample).unwrap()
+    );
+  }
+
   #[test]
 
   fn cdr_serialize_and_deserializesequence_of_structs() {
@@ -2,7 +2,7 @@ use std::{
   cmp::max,
   collections::{BTreeMap, HashMap},
   ops::Bound::{Excluded, Included},
-  sync::{Arc, Mutex},
+  sync::{Arc, RwLock},
 };
 
 #[allow(unused_imports)]
@@ -28,13 +28,13 @@ use super::cache_change::CacheChange;
 /// same TopicCache can be serialized/deserialized same way. Topic/TopicCache is
 /// identified by its name, which must be unique in the whole Domain.
 ///
-/// More specifically, the DDSCache stores handles (Arcs) to mutexes protecting
+/// More specifically, the DDSCache stores handles (Arcs) to RwLocks protecting
 /// the actual TopicCaches. For a given topic, the Reader/Writer and
 /// DataReader/DataWriter get a clone of the handle and
 /// interact with the TopicCache through this handle.
 #[derive(Debug, Default)]
 pub struct DDSCache {
-  topic_caches: HashMap<String, Arc<Mutex<TopicCache>>>,
+  topic_caches: HashMap<String, Arc<RwLock<TopicCache>>>,
 }
 
 impl DDSCache {
@@ -51,12 +51,12 @@ impl DDSCache {
     topic_name: String,
     topic_data_type: TypeDesc,
     qos: &QosPolicies,
-  ) -> Arc<Mutex<TopicCache>> {
+  ) -> Arc<RwLock<TopicCache>> {
     let topic_cache_handle = self
       .topic_caches
       .entry(topic_name.clone())
-      .and_modify(|tc| tc.lock().unwrap().update_keep_limits(qos))
-      .or_insert(Arc::new(Mutex::new(TopicCache::new(
+      .and_modify(|tc| tc.write().unwrap().update_keep_limits(qos))
+      .or_insert(Arc::new(RwLock::new(TopicCache::new(
         topic_name,
         topic_data_type,
         qos,
@@ -67,7 +67,7 @@ impl DDSCache {
 
   // This function is currently not used
   #[allow(dead_code)]
-  pub(crate) fn get_existing_topic_cache(&self, topic_name: &str) -> Arc<Mutex<TopicCache>> {
+  pub(crate) fn get_existing_topic_cache(&self, topic_name: &str) -> Arc<RwLock<TopicCache>> {
     // Return a clone of the pointer to the mutex on an existing topic cache
     // The program panics if the topic cache does not exist
     self
@@ -362,17 +362,27 @@ impl TopicCache {
 #[cfg(test)]
 mod tests {
   use std::{
-    sync::{Arc, RwLock},
+    sync::{
+      atomic::{AtomicBool, Ordering},
+      Arc, RwLock,
+    },
     thread,
+    time::{Duration, Instant},
   };
 
   use super::DDSCache;
   use crate::{
     dds::{
-      ddsdata::DDSData, qos::QosPolicies, typedesc::TypeDesc, with_key::datawriter::WriteOptions,
+      ddsdata::DDSData,
+      qos::{policy::History, QosPolicies, QosPolicyBuilder},
+      typedesc::TypeDesc,
+      with_key::datawriter::WriteOptions,
     },
     messages::submessages::elements::serialized_payload::SerializedPayload,
-    structure::{cache_change::CacheChange, guid::GUID, sequence_number::SequenceNumber},
+    structure::{
+      cache_change::CacheChange, guid::GUID, sequence_number::SequenceNumber, time::Timestamp,
+    },
+    RepresentationIdentifier,
   };
 
   #[test]
@@ -398,7 +408,7 @@ mod tests {
       DDSData::new(SerializedPayload::default()),
     );
     topic_cache_handle
-      .lock()
+      .write()
       .unwrap()
       .add_change(&crate::Timestamp::now(), change1);
 
@@ -419,11 +429,11 @@ mod tests {
       );
 
       topic_cache_handle2
-        .lock()
+        .write()
         .unwrap()
         .add_change(&crate::Timestamp::now(), change2);
       topic_cache_handle2
-        .lock()
+        .write()
         .unwrap()
         .add_change(&crate::Timestamp::now(), change3);
     })
@@ -433,7 +443,7 @@ mod tests {
     // Verify that there are 3 cache changes in the topic cache
     assert_eq!(
       topic_cache_handle
-        .lock()
+        .read()
         .unwrap()
         .get_changes_in_range_best_effort(
           crate::Timestamp::now() - crate::Duration::from_secs(23),
@@ -443,4 +453,122 @@ mod tests {
       3
     );
   }
+
+  // Latency of inserting changes, as the event loop does, while DataReaders
+  // poll the cache. This compares the earlier access pattern, where readers
+  // locked the cache exclusively and deserialized while holding the lock, to
+  // the current one. The samples are 64 KiB, so that deserializing them takes
+  // longer than inserting. Then the tail latency of inserting is dominated by
+  // waiting for a DataReader that deserializes while holding the lock.
+  #[test]
+  #[ignore = "benchmark: cargo test --release topic_cache_contention -- --ignored --nocapture"]
+  fn topic_cache_contention() {
+    for shared_readers in [false, true] {
+      let mut latencies = insert_latencies(shared_readers);
+      latencies.sort();
+      let percentile = |p: f64| latencies[((latencies.len() - 1) as f64 * p) as usize];
+      println!(
+        "{}: p50 {:?} p99 {:?} p99.9 {:?} max {:?}",
+        if shared_readers {
+          "shared readers   "
+        } else {
+          "exclusive readers"
+        },
+        percentile(0.5),
+        percentile(0.99),
+        percentile(0.999),
+        latencies.last().unwrap(),
+      );
+    }
+  }
+
+  fn insert_latencies(shared_readers: bool) -> Vec<Duration> {
+    const READERS: usize = 4;
+    const INSERTS: i64 = 10_000;
+    const PAYLOAD_SIZE: usize = 64 * 1024;
+
+    let qos = QosPolicyBuilder::new()
+      .history(History::KeepLast { depth: 1000 })
+      .build();
+    let topic_cache = DDSCache::new().add_new_topic(
+      "contention".to_string(),
+      TypeDesc::new("Bytes".to_string()),
+      &qos,
+    );
+    let stop = Arc::new(AtomicBool::new(false));
+
+    // Stand-in for deserialization: decode a sequence of 32-bit integers.
+    fn deserialize(cc: &CacheChange) -> u64 {
+      let size = cc.data_value.payload_size();
+      let decoded: Vec<u32> = cc
+        .data_value
+        .bytes_slice(0, size)
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+      decoded.iter().map(|x| u64::from(*x)).sum()
+    }
+
+    let readers: Vec<_> = (0..READERS)
+      .map(|_| {
+        let topic_cache = topic_cache.clone();
+        let stop = stop.clone();
+        thread::spawn(move || {
+          let mut last_read = Timestamp::ZERO;
+          let mut checksum = 0;
+          while !stop.load(Ordering::Relaxed) {
+            if shared_readers {
+              let changes: Vec<(Timestamp, CacheChange)> = topic_cache
+                .read()
+                .unwrap()
+                .get_changes_in_range_best_effort(last_read, Timestamp::now())
+                .map(|(ts, cc)| (ts, cc.clone()))
+                .collect();
+              for (ts, cc) in changes {
+                checksum += deserialize(&cc);
+                last_read = ts;
+              }
+            } else {
+              let topic_cache = topic_cache.write().unwrap();
+              for (ts, cc) in
+                topic_cache.get_changes_in_range_best_effort(last_read, Timestamp::now())
+              {
+                checksum += deserialize(cc);
+                last_read = ts;
+              }
+            }
+            // DataReaders poll when notified of new data.
+            thread::sleep(Duration::from_micros(100));
+          }
+          checksum
+        })
+      })
+      .collect();
+
+    let mut latencies = Vec::new();
+    for sn in 1..=INSERTS {
+      let change = CacheChange::new(
+        GUID::GUID_UNKNOWN,
+        SequenceNumber::new(sn),
+        WriteOptions::default(),
+        DDSData::new(SerializedPayload::new(
+          RepresentationIdentifier::CDR_LE,
+          vec![sn as u8; PAYLOAD_SIZE],
+        )),
+      );
+      let start = Instant::now();
+      topic_cache
+        .write()
+        .unwrap()
+        .add_change(&Timestamp::now(), change);
+      latencies.push(start.elapsed());
+      thread::sleep(Duration::from_micros(20));
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    for reader in readers {
+      reader.join().unwrap();
+    }
+    latencies
+  }
 }
@@ -3,7 +3,7 @@ use std::{fmt, hash::Hash, ops::RangeBounds};
 use speedy::{Context, Readable, Reader, Writable, Writer};
 use serde::{Deserialize, Serialize};
 use cdr_encoding_size::*;
-use mio_06::Token;
+use mio_08::Token;
 use log::warn;
 use static_assertions as sa;
 
@@ -569,7 +569,7 @@ impl fmt::Debug for GUID {
 #[cfg(test)]
 mod tests {
   use speedy::Endianness;
-  use mio_06::Token;
+  use mio_08::Token;
   use log::info;
   use byteorder::BigEndian;
 
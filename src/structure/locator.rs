@@ -6,11 +6,22 @@ pub use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
 use speedy::{Context, Readable, Reader, Writable, Writer};
 
-mod kind {
+pub(crate) mod kind {
   pub const INVALID: i32 = -1;
   pub const RESERVED: i32 = 0;
   pub const UDP_V4: i32 = 1;
   pub const UDP_V6: i32 = 2;
+  // Vendor-specific kind for our shared memory transport. The high bytes are
+  // the Atostek vendor id. Other implementations ignore locators of this kind.
+  pub const SHM: i32 = 0x0112_0001;
+  // Vendor-specific kind for the in-process loopback transport
+  pub const LOOPBACK: i32 = 0x0112_0002;
+  // Vendor-specific kinds for our TCP transport. Its connection control
+  // messages differ from the RTPS-TCP/IP PSM, so we do not announce the
+  // standard TCPv4 (4) and TCPv6 (8) kinds, which other implementations would
+  // try to use.
+  pub const TCP_V4: i32 = 0x0112_0003;
+  pub const TCP_V6: i32 = 0x0112_0004;
 }
 
 const INVALID_PORT: u16 = 0;
@@ -33,6 +44,26 @@ impl Locator {
   pub fn is_udp(&self) -> bool {
     matches!(self, Self::UdpV4(_) | Self::UdpV6(_))
   }
+
+  pub fn is_tcp(&self) -> bool {
+    matches!(
+      self,
+      Self::Other {
+        kind: kind::TCP_V4 | kind::TCP_V6,
+        ..
+      }
+    )
+  }
+
+  pub fn is_shm(&self) -> bool {
+    matches!(
+      self,
+      Self::Other {
+        kind: kind::SHM,
+        ..
+      }
+    )
+  }
 }
 
 impl From<Locator> for SocketAddr {
@@ -102,6 +102,90 @@ impl ParameterId {
   // Section 7.4.1.6 "New DCPSParticipantSecure Builtin Topic"
   // Table 13
   pub const PID_IDENTITY_STATUS_TOKEN: Self = Self { value: 0x1006 };
+
+  // Vendor-specific parameters of RustDDS. Their meaning depends on the vendor
+  // id, so they are interpreted only when sent by a RustDDS participant.
+
+  // MaxFragmentSize QoS of a DataReader: the largest sample or fragment it
+  // wants to receive
+  pub const PID_RUSTDDS_MAX_FRAGMENT_SIZE: Self = Self { value: 0x8001 };
+}
+
+impl ParameterId {
+  pub fn value(&self) -> u16 {
+    self.value
+  }
+
+  /// Name of a known parameter id, e.g. "PID_TOPIC_NAME"
+  pub fn name(&self) -> Option<&'static str> {
+    Some(match *self {
+      Self::PID_PAD => "PID_PAD",
+      Self::PID_SENTINEL => "PID_SENTINEL",
+      Self::PID_USER_DATA => "PID_USER_DATA",
+      Self::PID_TOPIC_NAME => "PID_TOPIC_NAME",
+      Self::PID_TYPE_NAME => "PID_TYPE_NAME",
+      Self::PID_GROUP_DATA => "PID_GROUP_DATA",
+      Self::PID_TOPIC_DATA => "PID_TOPIC_DATA",
+      Self::PID_DURABILITY => "PID_DURABILITY",
+      Self::PID_DURABILITY_SERVICE => "PID_DURABILITY_SERVICE",
+      Self::PID_DEADLINE => "PID_DEADLINE",
+      Self::PID_LATENCY_BUDGET => "PID_LATENCY_BUDGET",
+      Self::PID_LIVELINESS => "PID_LIVELINESS",
+      Self::PID_RELIABILITY => "PID_RELIABILITY",
+      Self::PID_LIFESPAN => "PID_LIFESPAN",
+      Self::PID_DESTINATION_ORDER => "PID_DESTINATION_ORDER",
+      Self::PID_HISTORY => "PID_HISTORY",
+      Self::PID_RESOURCE_LIMITS => "PID_RESOURCE_LIMITS",
+      Self::PID_OWNERSHIP => "PID_OWNERSHIP",
+      Self::PID_OWNERSHIP_STRENGTH => "PID_OWNERSHIP_STRENGTH",
+      Self::PID_PRESENTATION => "PID_PRESENTATION",
+      Self::PID_PARTITION => "PID_PARTITION",
+      Self::PID_TIME_BASED_FILTER => "PID_TIME_BASED_FILTER",
+      Self::PID_TRANSPORT_PRIO => "PID_TRANSPORT_PRIO",
+      Self::PID_PROTOCOL_VERSION => "PID_PROTOCOL_VERSION",
+      Self::PID_VENDOR_ID => "PID_VENDOR_ID",
+      Self::PID_UNICAST_LOCATOR => "PID_UNICAST_LOCATOR",
+      Self::PID_MULTICAST_LOCATOR => "PID_MULTICAST_LOCATOR",
+      Self::PID_MULTICAST_IPADDRESS => "PID_MULTICAST_IPADDRESS",
+      Self::PID_DEFAULT_UNICAST_LOCATOR => "PID_DEFAULT_UNICAST_LOCATOR",
+      Self::PID_DEFAULT_MULTICAST_LOCATOR => "PID_DEFAULT_MULTICAST_LOCATOR",
+      Self::PID_METATRAFFIC_UNICAST_LOCATOR => "PID_METATRAFFIC_UNICAST_LOCATOR",
+      Self::PID_METATRAFFIC_MULTICAST_LOCATOR => "PID_METATRAFFIC_MULTICAST_LOCATOR",
+      Self::PID_DEFAULT_UNICAST_IPADDRESS => "PID_DEFAULT_UNICAST_IPADDRESS",
+      Self::PID_DEFAULT_UNICAST_PORT => "PID_DEFAULT_UNICAST_PORT",
+      Self::PID_METATRAFFIC_UNICAST_IPADDRESS => "PID_METATRAFFIC_UNICAST_IPADDRESS",
+      Self::PID_METATRAFFIC_UNICAST_PORT => "PID_METATRAFFIC_UNICAST_PORT",
+      Self::PID_METATRAFFIC_MULTICAST_IPADDRESS => "PID_METATRAFFIC_MULTICAST_IPADDRESS",
+      Self::PID_METATRAFFIC_MULTICAST_PORT => "PID_METATRAFFIC_MULTICAST_PORT",
+      Self::PID_EXPECTS_INLINE_QOS => "PID_EXPECTS_INLINE_QOS",
+      Self::PID_PARTICIPANT_MANUAL_LIVELINESS_COUNT => "PID_PARTICIPANT_MANUAL_LIVELINESS_COUNT",
+      Self::PID_PARTICIPANT_BUILTIN_ENDPOINTS => "PID_PARTICIPANT_BUILTIN_ENDPOINTS",
+      Self::PID_PARTICIPANT_LEASE_DURATION => "PID_PARTICIPANT_LEASE_DURATION",
+      Self::PID_CONTENT_FILTER_PROPERTY => "PID_CONTENT_FILTER_PROPERTY",
+      Self::PID_PARTICIPANT_GUID => "PID_PARTICIPANT_GUID",
+      Self::PID_GROUP_GUID => "PID_GROUP_GUID",
+      Self::PID_GROUP_ENTITYID => "PID_GROUP_ENTITYID",
+      Self::PID_BUILTIN_ENDPOINT_SET => "PID_BUILTIN_ENDPOINT_SET",
+      Self::PID_ENDPOINT_GUID => "PID_ENDPOINT_GUID",
+      Self::PID_BUILTIN_ENDPOINT_QOS => "PID_BUILTIN_ENDPOINT_QOS",
+      Self::PID_PROPERTY_LIST => "PID_PROPERTY_LIST",
+      Self::PID_TYPE_MAX_SIZE_SERIALIZED => "PID_TYPE_MAX_SIZE_SERIALIZED",
+      Self::PID_ENTITY_NAME => "PID_ENTITY_NAME",
+      Self::PID_KEY_HASH => "PID_KEY_HASH",
+      Self::PID_STATUS_INFO => "PID_STATUS_INFO",
+      Self::PID_SERVICE_INSTANCE_NAME => "PID_SERVICE_INSTANCE_NAME",
+      Self::PID_RELATED_ENTITY_GUID => "PID_RELATED_ENTITY_GUID",
+      Self::PID_TOPIC_ALIASES => "PID_TOPIC_ALIASES",
+      Self::PID_RELATED_SAMPLE_IDENTITY => "PID_RELATED_SAMPLE_IDENTITY",
+      Self::PID_IDENTITY_TOKEN => "PID_IDENTITY_TOKEN",
+      Self::PID_PERMISSIONS_TOKEN => "PID_PERMISSIONS_TOKEN",
+      Self::PID_DATA_TAGS => "PID_DATA_TAGS",
+      Self::PID_ENDPOINT_SECURITY_INFO => "PID_ENDPOINT_SECURITY_INFO",
+      Self::PID_PARTICIPANT_SECURITY_INFO => "PID_PARTICIPANT_SECURITY_INFO",
+      Self::PID_IDENTITY_STATUS_TOKEN => "PID_IDENTITY_STATUS_TOKEN",
+      _ => return None,
+    })
+  }
 }
 
 #[cfg(test)]
@@ -403,6 +487,12 @@ mod tests {
       le = [0x60, 0x00],
       be = [0x00, 0x60]
   },
+  {
+      pid_rustdds_max_fragment_size,
+      ParameterId::PID_RUSTDDS_MAX_FRAGMENT_SIZE,
+      le = [0x01, 0x80],
+      be = [0x80, 0x01]
+  },
   {
       pid_entity_name,
       ParameterId::PID_ENTITY_NAME,
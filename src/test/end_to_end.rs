@@ -0,0 +1,948 @@
+// End-to-end tests: two DomainParticipants in the same process, a DataWriter
+// in one and a DataReader in the other, and samples going between them
+// through the transports being tested.
+
+use std::{
+  collections::{BTreeSet, HashMap},
+  io,
+  net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+  },
+  time::{Duration, Instant},
+};
+
+use mio_08::{Interest, Registry, Token};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  dds::{
+    no_key::{datareader::DataReader, datawriter::DataWriter},
+    participant::{DomainParticipant, DomainParticipantBuilder},
+    qos::{policy, QosPolicies},
+    result::Error,
+    statusevents::{DataWriterStatus, StatusEvented, StatusKind},
+    topic::TopicKind,
+  },
+  discovery::DiscoveryMode,
+  messages::submessages::submessages::WriterSubmessage,
+  mio_source::channel as mio_channel,
+  network::{
+    constant::{PortMapping, DISCOVERY_LISTENER_TOKEN, USER_TRAFFIC_LISTENER_TOKEN},
+    loopback_transport::{LoopbackImpairments, LoopbackNetwork},
+    tcp_transport::tcp_locator_address,
+    transport::{Transport, TransportContext, TransportLocators},
+  },
+  rtps::{flow_controller::FlowControllerSettings, submessage::*, Message},
+  serialization::{cdr_deserializer::CDRDeserializerAdapter, cdr_serializer::CDRSerializerAdapter},
+  structure::{entity::RTPSEntity, guid::EntityId, locator::Locator},
+};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+struct Sample {
+  a: i32,
+  data: Vec<u8>,
+}
+
+impl Sample {
+  fn new(a: i32) -> Self {
+    Self {
+      a,
+      data: Vec::new(),
+    }
+  }
+
+  fn with_data(a: i32, data: Vec<u8>) -> Self {
+    Self { a, data }
+  }
+}
+
+type Writer = DataWriter<Sample, CDRSerializerAdapter<Sample>>;
+type Reader = DataReader<Sample, CDRDeserializerAdapter<Sample>>;
+
+// Long enough for discovery and repairs on a loaded machine. The waits end as
+// soon as they succeed.
+const TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+fn reliable_qos() -> QosPolicies {
+  QosPolicies::builder()
+    .reliability(policy::Reliability::Reliable {
+      max_blocking_time: crate::Duration::from_millis(100),
+    })
+    .history(policy::History::KeepAll)
+    .build()
+}
+
+// Participant on localhost that finds its peer with unicast SPDP
+fn localhost_builder(domain_id: u16, participant_id: u16, peer: &str) -> DomainParticipantBuilder {
+  DomainParticipantBuilder::new(domain_id)
+    .participant_id(participant_id)
+    .bind_address(IpAddr::V4(Ipv4Addr::LOCALHOST))
+    .multicast(false)
+    .shared_memory(false)
+    .initial_peers(vec![peer.parse().unwrap()])
+    .spdp_announcement_period(Duration::from_millis(100))
+}
+
+// Participant without sockets. A transport must be added.
+fn socketless_builder(domain_id: u16) -> DomainParticipantBuilder {
+  DomainParticipantBuilder::new(domain_id)
+    .udp(false)
+    .shared_memory(false)
+    .spdp_announcement_period(Duration::from_millis(100))
+}
+
+fn create_topic(dp: &DomainParticipant, name: &str, qos: &QosPolicies) -> crate::Topic {
+  dp.create_topic(
+    name.to_string(),
+    "Sample".to_string(),
+    qos,
+    TopicKind::NoKey,
+  )
+  .unwrap()
+}
+
+// A DataWriter in `dp_writer` and a DataReader in `dp_reader` on the same
+// topic. `writer_qos` is added to `qos` for the writer.
+fn writer_reader_pair(
+  dp_writer: &DomainParticipant,
+  dp_reader: &DomainParticipant,
+  topic_name: &str,
+  qos: &QosPolicies,
+  writer_qos: Option<QosPolicies>,
+) -> (Writer, Reader) {
+  let writer = dp_writer
+    .create_publisher(qos)
+    .unwrap()
+    .create_datawriter_no_key(
+      &create_topic(dp_writer, topic_name, qos),
+      writer_qos.map(|w| qos.modify_by(&w)),
+    )
+    .unwrap();
+  let reader = dp_reader
+    .create_subscriber(qos)
+    .unwrap()
+    .create_datareader_no_key(&create_topic(dp_reader, topic_name, qos), None)
+    .unwrap();
+  (writer, reader)
+}
+
+// Writes samples with `a` = 0 until the reader receives one, so that the
+// endpoints have surely matched. These samples are skipped by
+// `wait_for_samples`.
+fn wait_until_received(writer: &Writer, reader: &mut Reader) {
+  let start = Instant::now();
+  while start.elapsed() < TIMEOUT {
+    writer.write(Sample::new(0), None).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    if reader.take_next_sample().unwrap().is_some() {
+      return;
+    }
+  }
+  panic!("Reader did not receive anything from writer");
+}
+
+// Waits until the writer reports a matched reader, and some more for the
+// reader to match the writer.
+fn wait_until_matched(writer: &mut Writer) {
+  // Status events are delivered only after they have been asked for.
+  let _condition = writer
+    .status_condition(StatusKind::PublicationMatched.into())
+    .unwrap();
+  let start = Instant::now();
+  while !matches!(
+    writer.try_recv_status(),
+    Some(DataWriterStatus::PublicationMatched { .. })
+  ) {
+    assert!(start.elapsed() < TIMEOUT, "Writer did not match");
+    std::thread::sleep(POLL_INTERVAL);
+  }
+  std::thread::sleep(Duration::from_millis(200));
+}
+
+// Takes samples until `count` samples have been received, skipping the ones
+// written by `wait_until_received`. Returns the samples in the order they were
+// received.
+fn wait_for_samples(reader: &mut Reader, count: usize) -> Vec<Sample> {
+  let start = Instant::now();
+  let mut received = Vec::new();
+  while received.len() < count && start.elapsed() < TIMEOUT {
+    while let Some(sample) = reader.take_next_sample().unwrap() {
+      if sample.value().a != 0 {
+        received.push(sample.into_value());
+      }
+    }
+    std::thread::sleep(POLL_INTERVAL);
+  }
+  received
+}
+
+// Transport whose outgoing messages are observed by a test
+struct RecordingTransport<F> {
+  inner: Box<dyn Transport>,
+  // Returns false to drop the message
+  record: F,
+}
+
+impl<F> Transport for RecordingTransport<F>
+where
+  F: FnMut(&[u8]) -> bool + Send,
+{
+  fn can_send_to(&self, locator: &Locator) -> bool {
+    self.inner.can_send_to(locator)
+  }
+
+  fn send_to_locator(&mut self, buffer: &[u8], locator: &Locator) -> io::Result<()> {
+    if (self.record)(buffer) {
+      self.inner.send_to_locator(buffer, locator)
+    } else {
+      Ok(())
+    }
+  }
+
+  fn announced_locators(&self) -> TransportLocators {
+    self.inner.announced_locators()
+  }
+
+  fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+    self.inner.register(registry, token)
+  }
+
+  fn receive(&mut self) -> Vec<Bytes> {
+    self.inner.receive()
+  }
+}
+
+// Loopback transport factory that passes the sent messages to `record`
+fn recording_loopback<F>(
+  network: &LoopbackNetwork,
+  record: F,
+) -> impl Fn(&TransportContext) -> io::Result<Box<dyn Transport>> + Send + Sync + 'static
+where
+  F: FnMut(&[u8]) -> bool + Clone + Send + Sync + 'static,
+{
+  let loopback = network.transport_factory();
+  move |context| {
+    Ok(Box::new(RecordingTransport {
+      inner: loopback(context)?,
+      record: record.clone(),
+    }))
+  }
+}
+
+fn submessages(buffer: &[u8]) -> Vec<Submessage> {
+  Message::read_from_buffer(&Bytes::copy_from_slice(buffer))
+    .map(|message| message.submessages())
+    .unwrap_or_default()
+}
+
+#[test]
+fn discovery_server() {
+  // Clients a and b talk only to server s, but must still find each other's
+  // endpoints, and then communicate directly.
+  let builder = |participant_id, discovery_mode| {
+    DomainParticipantBuilder::new(44)
+      .participant_id(participant_id)
+      .bind_address(IpAddr::V4(Ipv4Addr::LOCALHOST))
+      .multicast(false)
+      .spdp_announcement_period(Duration::from_millis(100))
+      .discovery_mode(discovery_mode)
+  };
+  let client = || DiscoveryMode::Client {
+    servers: vec!["127.0.0.1[0-0]".parse().unwrap()],
+  };
+  let _dp_s = builder(0, DiscoveryMode::Server)
+    .build()
+    .expect("Failed to create server");
+  let dp_a = builder(1, client())
+    .build()
+    .expect("Failed to create participant");
+  let dp_b = builder(2, client())
+    .build()
+    .expect("Failed to create participant");
+
+  let (writer, mut reader) = writer_reader_pair(
+    &dp_a,
+    &dp_b,
+    "discovery_server",
+    &QosPolicies::qos_none(),
+    None,
+  );
+  wait_until_received(&writer, &mut reader);
+
+  // a learned about b only through the server
+  assert!(dp_a
+    .discovery_db()
+    .read()
+    .unwrap()
+    .find_participant_proxy(dp_b.guid().prefix)
+    .is_some());
+}
+
+#[test]
+fn ipv6_only() {
+  let localhost = IpAddr::V6(Ipv6Addr::LOCALHOST);
+  let builder = |participant_id, peer: &str| {
+    localhost_builder(48, participant_id, peer)
+      .ipv4(false)
+      .ipv6(true)
+      .bind_address(localhost)
+  };
+  let dp_a = builder(0, "[::1][1-1]")
+    .build()
+    .expect("Failed to create participant");
+  let dp_b = builder(1, "[::1][0-0]")
+    .build()
+    .expect("Failed to create participant");
+
+  let ports = PortMapping::default();
+  let self_locators = dp_a.self_locators();
+  assert_eq!(
+    self_locators[&DISCOVERY_LISTENER_TOKEN],
+    vec![Locator::from(SocketAddr::new(
+      localhost,
+      ports.spdp_well_known_unicast_port(48, 0).unwrap()
+    ))]
+  );
+  assert_eq!(
+    self_locators[&USER_TRAFFIC_LISTENER_TOKEN],
+    vec![Locator::from(SocketAddr::new(
+      localhost,
+      ports.user_traffic_unicast_port(48, 0).unwrap()
+    ))]
+  );
+
+  let (writer, mut reader) =
+    writer_reader_pair(&dp_a, &dp_b, "ipv6_only", &QosPolicies::qos_none(), None);
+  wait_until_received(&writer, &mut reader);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn shared_memory() {
+  // Nothing listens on the announced UDP address, so all traffic after SPDP
+  // must go through shared memory.
+  let builder = |participant_id, peer: &str| {
+    localhost_builder(51, participant_id, peer)
+      .announce_addresses(vec![IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))])
+      .shared_memory(true)
+      .shared_memory_segment_size(256 * 1024)
+  };
+  let dp_a = builder(0, "127.0.0.1[1-1]")
+    .build()
+    .expect("Failed to create participant");
+  let dp_b = builder(1, "127.0.0.1[0-0]")
+    .build()
+    .expect("Failed to create participant");
+
+  let self_locators = dp_a.self_locators();
+  for token in [DISCOVERY_LISTENER_TOKEN, USER_TRAFFIC_LISTENER_TOKEN] {
+    assert_eq!(
+      self_locators[&token].iter().filter(|l| l.is_shm()).count(),
+      1
+    );
+  }
+
+  let (writer, mut reader) =
+    writer_reader_pair(&dp_a, &dp_b, "shared_memory", &reliable_qos(), None);
+  wait_until_received(&writer, &mut reader);
+}
+
+#[test]
+fn batched_udp_io() {
+  let builder =
+    |participant_id, peer: &str| localhost_builder(61, participant_id, peer).batched_udp_io(true);
+  let dp_a = builder(0, "127.0.0.1[1-1]")
+    .build()
+    .expect("Failed to create participant");
+  let dp_b = builder(1, "127.0.0.1[0-0]")
+    .build()
+    .expect("Failed to create participant");
+
+  let (writer, mut reader) =
+    writer_reader_pair(&dp_a, &dp_b, "batched_udp_io", &reliable_qos(), None);
+  wait_until_received(&writer, &mut reader);
+
+  // A large sample goes out as a batch of fragments, small samples as single
+  // datagrams.
+  let large: Vec<u8> = (0..500_000).map(|i: u32| i as u8).collect();
+  let mut samples = vec![Sample::with_data(1, large)];
+  samples.extend((2..12).map(Sample::new));
+  for sample in &samples {
+    writer.write(sample.clone(), None).unwrap();
+  }
+
+  // The large sample may be completed only after repairs
+  let mut received = wait_for_samples(&mut reader, samples.len());
+  received.sort_by_key(|s| s.a);
+  assert_eq!(received, samples);
+}
+
+#[test]
+fn event_loop_threads() {
+  let builder =
+    |participant_id, peer: &str| localhost_builder(62, participant_id, peer).event_loop_threads(2);
+  let dp_a = builder(0, "127.0.0.1[1-1]")
+    .build()
+    .expect("Failed to create participant");
+  let dp_b = builder(1, "127.0.0.1[0-0]")
+    .build()
+    .expect("Failed to create participant");
+
+  // Several topics, so that endpoints go to different shards. Each topic
+  // has a remote reader, and the first one a reader in the same participant.
+  let qos = reliable_qos();
+  let names = ["threads_0", "threads_1", "threads_2", "threads_3"];
+  let (writers, mut readers): (Vec<_>, Vec<_>) = names
+    .iter()
+    .map(|name| writer_reader_pair(&dp_a, &dp_b, name, &qos, None))
+    .unzip();
+  readers.push(
+    dp_a
+      .create_subscriber(&qos)
+      .unwrap()
+      .create_datareader_no_key(&create_topic(&dp_a, names[0], &qos), None)
+      .unwrap(),
+  );
+  let writer_of_reader = [0, 1, 2, 3, 0];
+
+  for (reader, writer_index) in readers.iter_mut().zip(writer_of_reader) {
+    wait_until_received(&writers[writer_index], reader);
+  }
+
+  for (i, writer) in writers.iter().enumerate() {
+    for a in 1..=20 {
+      writer.write(Sample::new(100 * i as i32 + a), None).unwrap();
+    }
+  }
+
+  // Samples of each writer arrive in order
+  for (reader, writer_index) in readers.iter_mut().zip(writer_of_reader) {
+    let received: Vec<i32> = wait_for_samples(reader, 20)
+      .into_iter()
+      .map(|s| s.a)
+      .collect();
+    let expected: Vec<i32> = (1..=20).map(|a| 100 * writer_index as i32 + a).collect();
+    assert_eq!(received, expected);
+  }
+}
+
+#[test]
+fn tcp() {
+  // Multicast is off and the only initial peer is over TCP, so everything
+  // starts from TCP. Unicast traffic then prefers TCP over UDP.
+  let builder = |participant_id| {
+    DomainParticipantBuilder::new(52)
+      .participant_id(participant_id)
+      .bind_address(IpAddr::V4(Ipv4Addr::LOCALHOST))
+      .multicast(false)
+      .shared_memory(false)
+      .tcp(0)
+      .spdp_announcement_period(Duration::from_millis(100))
+  };
+  let dp_a = builder(0).build().expect("Failed to create participant");
+  let tcp_port = dp_a.self_locators()[&USER_TRAFFIC_LISTENER_TOKEN]
+    .iter()
+    .find_map(tcp_locator_address)
+    .map(|(address, _)| address.port())
+    .expect("No TCP locator");
+  let dp_b = builder(1)
+    .initial_peers(vec![format!("tcp://127.0.0.1:{tcp_port}").parse().unwrap()])
+    .build()
+    .expect("Failed to create participant");
+
+  let qos = QosPolicies::builder()
+    .reliability(policy::Reliability::Reliable {
+      max_blocking_time: crate::Duration::from_millis(100),
+    })
+    .build();
+  let (writer, mut reader) = writer_reader_pair(&dp_b, &dp_a, "tcp", &qos, None);
+  wait_until_received(&writer, &mut reader);
+}
+
+// Passes messages between the participants of a test through channels.
+struct ChannelTransport {
+  locator: Locator,
+  receiver: mio_channel::Receiver<Bytes>,
+  peers: Arc<Mutex<HashMap<Locator, mio_channel::Sender<Bytes>>>>,
+  sent: Arc<AtomicUsize>,
+}
+
+impl Transport for ChannelTransport {
+  fn can_send_to(&self, locator: &Locator) -> bool {
+    self.peers.lock().unwrap().contains_key(locator)
+  }
+
+  fn send_to_locator(&mut self, buffer: &[u8], locator: &Locator) -> io::Result<()> {
+    let peers = self.peers.lock().unwrap();
+    let peer = peers
+      .get(locator)
+      .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+    peer
+      .send(Bytes::copy_from_slice(buffer))
+      .map_err(|_| io::Error::from(io::ErrorKind::NotConnected))?;
+    self.sent.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+  }
+
+  fn announced_locators(&self) -> TransportLocators {
+    TransportLocators {
+      metatraffic_unicast: vec![self.locator],
+      default_unicast: vec![self.locator],
+      ..TransportLocators::default()
+    }
+  }
+
+  fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+    registry.register(&mut self.receiver, token, Interest::READABLE)
+  }
+
+  fn receive(&mut self) -> Vec<Bytes> {
+    let mut messages = Vec::new();
+    while let Ok(message) = self.receiver.try_recv() {
+      messages.push(message);
+    }
+    messages
+  }
+}
+
+#[test]
+fn user_transport() {
+  let peers = Arc::new(Mutex::new(HashMap::new()));
+  let sent = Arc::new(AtomicUsize::new(0));
+
+  // Nothing listens on the announced UDP address, so all traffic after SPDP
+  // must go through the channel transport.
+  let builder = |participant_id, peer: &str| {
+    let peers = peers.clone();
+    let sent = sent.clone();
+    localhost_builder(53, participant_id, peer)
+      .announce_addresses(vec![IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))])
+      .transport(move |context| {
+        let locator = Locator::Other {
+          kind: 0x7fff_0001,
+          port: context.participant_id.into(),
+          address: [0; 16],
+        };
+        let (sender, receiver) = mio_channel::channel();
+        peers.lock().unwrap().insert(locator, sender);
+        Ok(Box::new(ChannelTransport {
+          locator,
+          receiver,
+          peers: peers.clone(),
+          sent: sent.clone(),
+        }))
+      })
+  };
+  let dp_a = builder(0, "127.0.0.1[1-1]")
+    .build()
+    .expect("Failed to create participant");
+  let dp_b = builder(1, "127.0.0.1[0-0]")
+    .build()
+    .expect("Failed to create participant");
+
+  assert!(dp_a.self_locators()[&USER_TRAFFIC_LISTENER_TOKEN]
+    .iter()
+    .any(|l| matches!(
+      l,
+      Locator::Other {
+        kind: 0x7fff_0001,
+        port: 0,
+        ..
+      }
+    )));
+
+  let qos = QosPolicies::builder()
+    .reliability(policy::Reliability::Reliable {
+      max_blocking_time: crate::Duration::from_millis(100),
+    })
+    .build();
+  let (writer, mut reader) = writer_reader_pair(&dp_a, &dp_b, "user_transport", &qos, None);
+  wait_until_received(&writer, &mut reader);
+  assert!(sent.load(Ordering::Relaxed) > 0);
+}
+
+#[test]
+fn loopback() {
+  // No sockets, and a network that loses, duplicates and reorders messages.
+  // Reliable communication must still deliver every sample exactly once.
+  let network = LoopbackNetwork::with_seed(54);
+  let builder = || socketless_builder(54).transport(network.transport_factory());
+  let dp_a = builder().build().expect("Failed to create participant");
+  let dp_b = builder().build().expect("Failed to create participant");
+  assert!(dp_a.self_locators().values().flatten().all(|l| !l.is_udp()));
+
+  network.set_impairments(LoopbackImpairments {
+    loss: 0.2,
+    duplication: 0.1,
+    reordering: 0.1,
+    jitter: Duration::from_millis(5),
+    ..LoopbackImpairments::default()
+  });
+
+  let qos = reliable_qos().modify_by(
+    &QosPolicies::builder()
+      .durability(policy::Durability::TransientLocal)
+      .build(),
+  );
+  let (writer, mut reader) = writer_reader_pair(&dp_a, &dp_b, "loopback", &qos, None);
+
+  for a in 1..=20 {
+    writer.write(Sample::new(a), None).unwrap();
+  }
+  // Lost messages are repaired after heartbeats, which takes a while.
+  // Reordered messages are delivered in the order they were received.
+  let mut received: Vec<i32> = wait_for_samples(&mut reader, 20)
+    .into_iter()
+    .map(|s| s.a)
+    .collect();
+  received.sort_unstable();
+  assert_eq!(received, (1..=20).collect::<Vec<_>>());
+}
+
+#[test]
+fn fragment_size() {
+  let network = LoopbackNetwork::new();
+  // Size of the largest message sent
+  let max_sent = Arc::new(AtomicUsize::new(0));
+  let recording_max_sent = max_sent.clone();
+  let dp_a = socketless_builder(55)
+    .transport(recording_loopback(&network, move |buffer| {
+      recording_max_sent.fetch_max(buffer.len(), Ordering::Relaxed);
+      true
+    }))
+    .build()
+    .expect("Failed to create participant");
+  assert_eq!(dp_a.max_fragment_size(), 64_000);
+  // The reader announces a small limit, but the fragment size of the writer
+  // is fixed.
+  let dp_b = socketless_builder(55)
+    .max_fragment_size(1000)
+    .transport(network.transport_factory())
+    .build()
+    .expect("Failed to create participant");
+
+  let qos = reliable_qos().modify_by(
+    &QosPolicies::builder()
+      .durability(policy::Durability::TransientLocal)
+      .build(),
+  );
+  let writer_qos = QosPolicies::builder()
+    .max_fragment_size(policy::MaxFragmentSize { bytes: 20_000 })
+    .build();
+  let (writer, mut reader) = writer_reader_pair(&dp_a, &dp_b, "fragments", &qos, Some(writer_qos));
+
+  let sample = Sample::with_data(1, (0..50_000).map(|i| (i % 251) as u8).collect());
+  writer.write(sample.clone(), None).unwrap();
+  assert_eq!(wait_for_samples(&mut reader, 1), vec![sample]);
+  let max_sent = max_sent.load(Ordering::Relaxed);
+  assert!(
+    (20_000..21_000).contains(&max_sent),
+    "Sent a message of {max_sent} bytes"
+  );
+}
+
+#[test]
+fn fragment_repair() {
+  #[derive(Default)]
+  struct FragCounts {
+    data_frags: usize,
+    heartbeat_frags: usize,
+    dropped: BTreeSet<u32>,
+  }
+
+  // Drops the first transmission of some fragments, and counts what was
+  // sent.
+  let network = LoopbackNetwork::new();
+  let counts = Arc::new(Mutex::new(FragCounts::default()));
+  let recording_counts = counts.clone();
+  let dp_a = socketless_builder(56)
+    .transport(recording_loopback(&network, move |buffer| {
+      let mut counts = recording_counts.lock().unwrap();
+      let mut drop = false;
+      for submessage in submessages(buffer) {
+        match submessage.body {
+          SubmessageBody::Writer(WriterSubmessage::DataFrag(df, _)) => {
+            counts.data_frags += 1;
+            let frag_num = u32::from(df.fragment_starting_num);
+            if (frag_num == 3 || frag_num == 40) && counts.dropped.insert(frag_num) {
+              drop = true;
+            }
+          }
+          SubmessageBody::Writer(WriterSubmessage::HeartbeatFrag(..)) => {
+            counts.heartbeat_frags += 1;
+          }
+          _ => (),
+        }
+      }
+      !drop
+    }))
+    .build()
+    .expect("Failed to create participant");
+  let dp_b = socketless_builder(56)
+    .transport(network.transport_factory())
+    .build()
+    .expect("Failed to create participant");
+
+  let writer_qos = QosPolicies::builder()
+    .max_fragment_size(policy::MaxFragmentSize { bytes: 1000 })
+    .build();
+  let (mut writer, mut reader) = writer_reader_pair(
+    &dp_a,
+    &dp_b,
+    "fragment_repair",
+    &reliable_qos(),
+    Some(writer_qos),
+  );
+  // The sample must be streamed to the reader, not repaired from a heartbeat.
+  wait_until_matched(&mut writer);
+
+  // 50 fragments of 1000 bytes
+  let sample = Sample::with_data(1, (0..49_000).map(|i| (i % 251) as u8).collect());
+  writer.write(sample.clone(), None).unwrap();
+  assert_eq!(wait_for_samples(&mut reader, 1), vec![sample]);
+
+  let counts = counts.lock().unwrap();
+  assert_eq!(counts.dropped, BTreeSet::from([3, 40]));
+  assert!(
+    counts.heartbeat_frags >= 3,
+    "{} HEARTBEAT_FRAGs",
+    counts.heartbeat_frags
+  );
+  // Only the lost fragments are sent again.
+  assert!(
+    counts.data_frags < 60,
+    "{} DATA_FRAGs sent for 50 fragments",
+    counts.data_frags
+  );
+}
+
+#[test]
+fn flow_controller() {
+  let network = LoopbackNetwork::new();
+  let dp_a = socketless_builder(57)
+    .transport(network.transport_factory())
+    .flow_controller(
+      "slow",
+      FlowControllerSettings::token_bucket(20_000, 2_000).round_robin(),
+    )
+    .build()
+    .expect("Failed to create participant");
+  let dp_b = socketless_builder(57)
+    .transport(network.transport_factory())
+    .build()
+    .expect("Failed to create participant");
+  assert!(matches!(
+    socketless_builder(57)
+      .transport(network.transport_factory())
+      .flow_controller("stuck", FlowControllerSettings::token_bucket(20_000, 0))
+      .build(),
+    Err(Error::BadParameter { .. })
+  ));
+
+  let qos = reliable_qos();
+  let unknown_qos = qos.modify_by(
+    &QosPolicies::builder()
+      .flow_controller(policy::FlowController {
+        name: "fast".to_string(),
+      })
+      .build(),
+  );
+  assert!(matches!(
+    dp_a
+      .create_publisher(&qos)
+      .unwrap()
+      .create_datawriter_no_key::<Sample, CDRSerializerAdapter<Sample>>(
+        &create_topic(&dp_a, "flow_controller", &qos),
+        Some(unknown_qos)
+      ),
+    Err(Error::BadParameter { .. })
+  ));
+
+  let writer_qos = QosPolicies::builder()
+    .max_fragment_size(policy::MaxFragmentSize { bytes: 1000 })
+    .flow_controller(policy::FlowController {
+      name: "slow".to_string(),
+    })
+    .build();
+  let (mut writer, mut reader) =
+    writer_reader_pair(&dp_a, &dp_b, "flow_controller", &qos, Some(writer_qos));
+  wait_until_matched(&mut writer);
+
+  // About 12 kB in fragments, of which 2 kB fit into the burst. The rest
+  // takes at least 0.5 s at 20 kB/s.
+  let sample = Sample::with_data(1, (0..12_000).map(|i| (i % 251) as u8).collect());
+  let start = Instant::now();
+  writer.write(sample.clone(), None).unwrap();
+  assert_eq!(wait_for_samples(&mut reader, 1), vec![sample]);
+  let elapsed = start.elapsed();
+  assert!(
+    elapsed >= Duration::from_millis(450),
+    "Sample received in {elapsed:?}"
+  );
+}
+
+#[test]
+fn async_publish_mode() {
+  let network = LoopbackNetwork::new();
+  let participant = || {
+    socketless_builder(58)
+      .transport(network.transport_factory())
+      .build()
+      .expect("Failed to create participant")
+  };
+  let dp_a = participant();
+  let dp_b = participant();
+
+  let qos = QosPolicies::builder()
+    .reliability(policy::Reliability::Reliable {
+      max_blocking_time: crate::Duration::from_secs(5),
+    })
+    .history(policy::History::KeepAll)
+    .build();
+  let writer_qos = QosPolicies::builder()
+    .max_fragment_size(policy::MaxFragmentSize { bytes: 1000 })
+    .publish_mode(policy::PublishMode::Asynchronous)
+    .build();
+  let (mut writer, mut reader) =
+    writer_reader_pair(&dp_a, &dp_b, "async_publish", &qos, Some(writer_qos));
+  wait_until_matched(&mut writer);
+
+  // Large and small samples, more than the Writer queues at a time, so
+  // that write() has to wait for the sender.
+  let samples: Vec<Sample> = (1..=12)
+    .map(|i| Sample::with_data(i, vec![i as u8; if i % 3 == 2 { 10 } else { 100_000 }]))
+    .collect();
+  for sample in &samples {
+    writer.write(sample.clone(), None).unwrap();
+  }
+  assert_eq!(wait_for_samples(&mut reader, samples.len()), samples);
+}
+
+#[test]
+fn batching() {
+  // Records the writers of the DATA submessages of each message.
+  let network = LoopbackNetwork::new();
+  let messages = Arc::new(Mutex::new(Vec::<Vec<EntityId>>::new()));
+  let recorded_messages = messages.clone();
+  let dp_a = socketless_builder(59)
+    .transport(recording_loopback(&network, move |buffer| {
+      let writers = submessages(buffer)
+        .into_iter()
+        .filter_map(|submessage| match submessage.body {
+          SubmessageBody::Writer(WriterSubmessage::Data(data, _)) => Some(data.writer_id),
+          _ => None,
+        })
+        .collect();
+      recorded_messages.lock().unwrap().push(writers);
+      true
+    }))
+    .build()
+    .expect("Failed to create participant");
+  let dp_b = socketless_builder(59)
+    .transport(network.transport_factory())
+    .build()
+    .expect("Failed to create participant");
+
+  let writer_qos = QosPolicies::builder()
+    .latency_budget(policy::LatencyBudget {
+      duration: crate::Duration::from_millis(50),
+    })
+    .batching(policy::Batching {
+      max_bytes: 8000,
+      max_samples: 20,
+      across_writers: true,
+    })
+    .build();
+  let (mut writers, mut readers): (Vec<_>, Vec<_>) = ["batching_1", "batching_2"]
+    .iter()
+    .map(|name| {
+      writer_reader_pair(
+        &dp_a,
+        &dp_b,
+        name,
+        &reliable_qos(),
+        Some(writer_qos.clone()),
+      )
+    })
+    .unzip();
+  for writer in &mut writers {
+    wait_until_matched(writer);
+  }
+
+  let samples: Vec<Sample> = (1..=10)
+    .map(|i| Sample::with_data(i, vec![i as u8; 100]))
+    .collect();
+  for sample in &samples {
+    for writer in &writers {
+      writer.write(sample.clone(), None).unwrap();
+    }
+  }
+  for reader in &mut readers {
+    assert_eq!(wait_for_samples(reader, samples.len()), samples);
+  }
+
+  let writer_ids: Vec<EntityId> = writers.iter().map(|w| w.guid().entity_id).collect();
+  let messages = messages.lock().unwrap();
+  let data_messages: Vec<_> = messages
+    .iter()
+    .filter(|writers| writers.iter().any(|w| writer_ids.contains(w)))
+    .collect();
+  // Samples of both writers were sent in less messages than samples, and
+  // batches of the writers were combined.
+  assert!(data_messages.len() < samples.len());
+  assert!(data_messages
+    .iter()
+    .any(|writers| writer_ids.iter().all(|w| writers.contains(w))));
+}
+
+#[test]
+fn resume_publications_in_batches() {
+  // Sizes of the messages with DATA submessages
+  let network = LoopbackNetwork::new();
+  let data_message_sizes = Arc::new(Mutex::new(Vec::new()));
+  let recorded_sizes = data_message_sizes.clone();
+  let dp_a = socketless_builder(63)
+    .max_fragment_size(4000)
+    .transport(recording_loopback(&network, move |buffer| {
+      if submessages(buffer).iter().any(|submessage| {
+        matches!(
+          submessage.body,
+          SubmessageBody::Writer(WriterSubmessage::Data(..))
+        )
+      }) {
+        recorded_sizes.lock().unwrap().push(buffer.len());
+      }
+      true
+    }))
+    .build()
+    .expect("Failed to create participant");
+  let dp_b = socketless_builder(63)
+    .transport(network.transport_factory())
+    .build()
+    .expect("Failed to create participant");
+
+  let (mut writer, mut reader) =
+    writer_reader_pair(&dp_a, &dp_b, "resume_batches", &reliable_qos(), None);
+  wait_until_matched(&mut writer);
+
+  writer.publisher().suspend_publications().unwrap();
+  let samples: Vec<Sample> = (1..=40)
+    .map(|i| Sample::with_data(i, vec![i as u8; 200]))
+    .collect();
+  for sample in &samples {
+    writer.write(sample.clone(), None).unwrap();
+  }
+  data_message_sizes.lock().unwrap().clear();
+  writer.publisher().resume_publications().unwrap();
+  assert_eq!(wait_for_samples(&mut reader, samples.len()), samples);
+
+  // Batches are packed up to the participant's fragment size, which is larger
+  // than an Ethernet frame here.
+  let sizes = data_message_sizes.lock().unwrap();
+  assert!(sizes.iter().all(|size| *size <= 4000), "{sizes:?}");
+  assert!(sizes.iter().any(|size| *size > 1500), "{sizes:?}");
+}
@@ -1,5 +1,6 @@
 #![cfg(test)]
 
+mod end_to_end;
 pub(crate) mod random_data;
 pub(crate) mod shape_type;
 pub(crate) mod test_data;
@@ -219,6 +219,7 @@ pub(crate) fn reader_proxy_data() -> Option<ReaderProxy> {
       "0.0.0.0".parse().unwrap(),
       13579,
     ))],
+    max_fragment_size: Some(24000),
   };
 
   Some(reader_proxy)
@@ -6,7 +6,7 @@ use crate::{
   structure::{guid::EntityId, sequence_number::SequenceNumber},
 };
 /*
-use mio_extras::channel as mio_channel;
+use crate::mio_source::channel as mio_channel;
 use crate::dds::interfaces::{IDataReader};
 use crate::dds::traits::key::Keyed;
 use crate::dds::traits::key::Key;